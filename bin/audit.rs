@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use exchanges::{commitment::Sha256Hasher, journal::Journal};
+
+/// Re-executes a binary journal and checks it against the state roots of the live exchange.
+///
+/// The state roots file has one `<seq> <hex SHA-256 root>` pair per line; blank lines and
+/// lines starting with `#` are ignored. Exits with status 1 at the first divergence.
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, journal, roots] = args.as_slice() else {
+        eprintln!("usage: audit <journal> <state roots>");
+        std::process::exit(2);
+    };
+
+    let bytes = std::fs::read(journal).with_context(|| format!("Failed to read {}", journal))?;
+    let journal =
+        Journal::from_bytes(&bytes).with_context(|| format!("Invalid journal {}", journal))?;
+    let text =
+        std::fs::read_to_string(roots).with_context(|| format!("Failed to read {}", roots))?;
+    let mut state_roots = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let root = parse_line(line).with_context(|| format!("{}:{}", roots, i + 1))?;
+        state_roots.push(root);
+    }
+
+    match journal.audit(&Sha256Hasher, &state_roots)? {
+        None => println!(
+            "{} commands and {} state roots verified",
+            journal.len(),
+            state_roots.len()
+        ),
+        Some(divergence) => {
+            println!(
+                "Divergence at sequence {}: {}",
+                divergence.seq, divergence.reason
+            );
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+fn parse_line(line: &str) -> Result<(u64, [u8; 32])> {
+    let Some((seq, hex)) = line.split_once(char::is_whitespace) else {
+        return Err(anyhow::anyhow!("Expected a sequence number and a root"));
+    };
+    let seq = seq.parse().context("Invalid sequence number")?;
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(anyhow::anyhow!("Expected a 64 digit hex root"));
+    }
+    let mut root = [0; 32];
+    for (byte, digits) in root.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).unwrap();
+        *byte = u8::from_str_radix(digits, 16).context("Invalid hex root")?;
+    }
+    Ok((seq, root))
+}
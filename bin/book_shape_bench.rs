@@ -0,0 +1,28 @@
+use exchanges::{book_shape::BookShape, orderbook::BookBackend};
+
+fn main() {
+    println!(
+        "{:<12} {:<7} {:>12} {:>12} {:>12} {:>12}",
+        "shape", "backend", "insert", "take", "cancel", "sweep"
+    );
+    for shape in BookShape::presets() {
+        let backends = [
+            ("btree", BookBackend::BTree),
+            ("ladder", shape.ladder_backend()),
+        ];
+        let mut fastest: Option<(&str, std::time::Duration)> = None;
+        for (name, backend) in backends {
+            let timings = shape.measure(backend);
+            println!(
+                "{:<12} {:<7} {:>12?} {:>12?} {:>12?} {:>12?}",
+                shape.name, name, timings.insert, timings.take, timings.cancel, timings.sweep
+            );
+            if fastest.is_none_or(|(_, total)| timings.total() < total) {
+                fastest = Some((name, timings.total()));
+            }
+        }
+        if let Some((name, _)) = fastest {
+            println!("{:<12} use {}", shape.name, name);
+        }
+    }
+}
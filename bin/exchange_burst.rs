@@ -1,6 +1,7 @@
 use anyhow::Result;
 use exchanges::{
     asset::Asset,
+    clock::MonotonicClock,
     exchange::Exchange,
     market::{Market, Pair},
     order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
@@ -10,6 +11,7 @@ use std::time::Instant;
 fn main() -> Result<()> {
     // Create exchange and market
     let mut exchange = Exchange::new();
+    exchange.clock = Some(Box::new(MonotonicClock::new()));
     let pair = Pair {
         numeraire: Asset::new("USD"),
         base: Asset::new("BTC"),
@@ -43,36 +45,36 @@ fn main() -> Result<()> {
 
     let start = Instant::now();
 
-    // Create and process some orders
+    // Create and process some orders. The exchange assigns their IDs and timestamps.
     let orders = vec![
         Order::new(
-            OrderId::new(1),
+            OrderId::default(),
             Price::new(50_000),
             Quantity::new(2),
             Side::Ask,
             trader1.clone(),
-            Timestamp::new(1),
+            Timestamp::default(),
         ),
         Order::new(
-            OrderId::new(2),
+            OrderId::default(),
             Price::new(49_000),
             Quantity::new(3),
             Side::Ask,
             trader3.clone(),
-            Timestamp::new(2),
+            Timestamp::default(),
         ),
         Order::new(
-            OrderId::new(3),
+            OrderId::default(),
             Price::new(51_000),
             Quantity::new(4),
             Side::Bid,
             trader2.clone(),
-            Timestamp::new(3),
+            Timestamp::default(),
         ),
     ];
 
     for order in orders {
-        exchange.post_order(order, pair)?;
+        exchange.place_order(order, pair)?;
     }
 
     let duration = start.elapsed();
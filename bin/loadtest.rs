@@ -0,0 +1,91 @@
+use exchanges::{
+    asset::Asset,
+    exchange::Exchange,
+    market::{Market, Pair},
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    phase_timing::PhaseTimings,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::time::{Duration, Instant};
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(11);
+    let count = 100_000;
+    let pair = Pair {
+        numeraire: Asset::new("USD"),
+        base: Asset::new("BTC"),
+    };
+    let accounts: Vec<AccountId> = (0..100)
+        .map(|i| AccountId::new(format!("trader{}", i)))
+        .collect();
+
+    let mut exchange = Exchange::new();
+    exchange.add_market(Market::new(pair));
+    for account in &accounts {
+        exchange.add_balance(account.clone(), pair.numeraire, 1_000_000_000);
+        exchange.add_balance(account.clone(), pair.base, 10_000_000);
+    }
+    exchange.phase_timings = Some(PhaseTimings::default());
+
+    // Limit orders around a fixed mid, so about half of them trade on arrival
+    let orders: Vec<Order> = (1..=count)
+        .map(|i| {
+            let side = if rng.random_bool(0.5) {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            Order::new(
+                OrderId::new(i),
+                Price::new(rng.random_range(90..110)),
+                Quantity::new(rng.random_range(1..100)),
+                side,
+                accounts[rng.random_range(0..accounts.len())].clone(),
+                Timestamp::new(i),
+            )
+        })
+        .collect();
+
+    let start = Instant::now();
+    let mut rejected = 0;
+    let mut trades = 0;
+    for order in orders {
+        match exchange.post_order(order, pair) {
+            Ok(report) => trades += report.fills.len(),
+            Err(_) => rejected += 1,
+        }
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{} orders in {:?} ({:.0} orders/s), {} trades, {} rejected",
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64(),
+        trades,
+        rejected
+    );
+
+    let timings = exchange.phase_timings.unwrap();
+    let share = |phase: Duration| 100.0 * phase.as_secs_f64() / timings.total().as_secs_f64();
+    println!("{:<12} {:>12} {:>8}", "phase", "per order", "share");
+    for (name, phase) in [
+        ("validation", timings.validation),
+        ("matching", timings.matching),
+        ("settlement", timings.settlement),
+    ] {
+        println!(
+            "{:<12} {:>12?} {:>7.1}%",
+            name,
+            timings.per_order(phase),
+            share(phase)
+        );
+    }
+    // Order ID bookkeeping, posting triggered stops and re-pricing pegs
+    let untimed = elapsed.saturating_sub(timings.total());
+    println!(
+        "{:<12} {:>12?} {:>8}",
+        "untimed",
+        timings.per_order(untimed),
+        ""
+    );
+}
@@ -0,0 +1,105 @@
+use exchanges::{
+    matching::MatchingEngine,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    orderbook::BookBackend,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+/// Counts every allocation and reallocation made through it.
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let warmup = 100_000;
+    let measured = 100_000;
+    let accounts: Vec<AccountId> = (0..100)
+        .map(|i| AccountId::new(format!("trader{}", i)))
+        .collect();
+
+    // Limit orders around a fixed mid, so the book settles into a steady shape
+    let orders: Vec<Order> = (0..warmup + measured)
+        .map(|i| {
+            let side = if rng.random_bool(0.5) {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            Order::new(
+                OrderId::new(i),
+                Price::new(rng.random_range(90..110)),
+                Quantity::new(rng.random_range(1..100)),
+                side,
+                accounts[rng.random_range(0..accounts.len())].clone(),
+                Timestamp::new(i),
+            )
+        })
+        .collect();
+
+    let backends = [
+        ("btree", BookBackend::BTree),
+        (
+            "ladder",
+            BookBackend::Ladder {
+                min_price: Price::new(90),
+                tick_size: 1,
+                num_ticks: 20,
+            },
+        ),
+    ];
+
+    for (name, backend) in backends {
+        let mut engine = MatchingEngine::with_backend(backend);
+        let mut orders = orders.iter().cloned();
+        for order in orders.by_ref().take(warmup as usize) {
+            engine.process_order_buffered(order);
+        }
+
+        // Only the matching itself is measured: the orders are cloned up front
+        let batch: Vec<Order> = orders.collect();
+        let mut trades = 0;
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        for order in batch {
+            trades += engine.process_order_buffered(order).len();
+        }
+        let duration = start.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        println!(
+            "[{}] {} orders after {} warm-up orders in {:?} ({} trades)",
+            name, measured, warmup, duration, trades
+        );
+        println!(
+            "[{}] {} allocations, {:.4} per order",
+            name,
+            allocations,
+            allocations as f64 / measured as f64
+        );
+    }
+}
@@ -1,39 +1,82 @@
 use exchanges::{
     matching::MatchingEngine,
     order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    orderbook::BookBackend,
 };
 use rand::{Rng, rng};
 use std::time::Instant;
 
 fn main() {
-    let mut engine = MatchingEngine::new();
     let mut rng = rng();
-    let start = Instant::now();
     let num_orders = 100_000;
 
-    // Generate and process random orders
-    for i in 0..num_orders {
-        let side = if rng.random_bool(0.5) {
-            Side::Bid
-        } else {
-            Side::Ask
-        };
-        let price = Price::new(rng.random_range(90..110));
-        let quantity = Quantity::new(rng.random_range(1..100));
+    // Generate random orders
+    let orders: Vec<Order> = (0..num_orders)
+        .map(|i| {
+            let side = if rng.random_bool(0.5) {
+                Side::Bid
+            } else {
+                Side::Ask
+            };
+            let price = Price::new(rng.random_range(90..110));
+            let quantity = Quantity::new(rng.random_range(1..100));
+            let account_id = AccountId::new(format!("trader{}", rng.random_range(1..100)));
 
-        let order = Order::new(
-            OrderId::new(i),
-            price,
-            quantity,
-            side,
-            AccountId::new(format!("trader{}", rng.random_range(1..100))),
-            Timestamp::new(i),
-        );
+            // One order in ten is a market order, protected so thin books cannot give absurd fills
+            if i % 10 == 0 {
+                return Order {
+                    protection_price: Some(price),
+                    ..Order::market(
+                        OrderId::new(i),
+                        quantity,
+                        side,
+                        account_id,
+                        Timestamp::new(i),
+                    )
+                };
+            }
+            Order::new(
+                OrderId::new(i),
+                price,
+                quantity,
+                side,
+                account_id,
+                Timestamp::new(i),
+            )
+        })
+        .collect();
 
-        engine.process_order(order);
-    }
+    let backends = [
+        ("btree", BookBackend::BTree),
+        (
+            "ladder",
+            BookBackend::Ladder {
+                min_price: Price::new(90),
+                tick_size: 1,
+                num_ticks: 20,
+            },
+        ),
+    ];
+
+    for (name, backend) in backends {
+        let mut engine = MatchingEngine::with_backend(backend);
+        let mut trades = 0;
+        let start = Instant::now();
+
+        // Count trades as they execute rather than collecting them per order
+        for order in orders.iter().cloned() {
+            engine.process_order_with(order, |_| trades += 1);
+        }
 
-    let duration = start.elapsed();
-    println!("Processed {} orders in {:?}", num_orders, duration);
-    println!("Average time per order: {:?}", duration / num_orders as u32);
+        let duration = start.elapsed();
+        println!(
+            "[{}] Processed {} orders in {:?} ({} trades)",
+            name, num_orders, duration, trades
+        );
+        println!(
+            "[{}] Average time per order: {:?}",
+            name,
+            duration / num_orders as u32
+        );
+    }
 }
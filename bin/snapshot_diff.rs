@@ -0,0 +1,25 @@
+use anyhow::{Context, Result};
+use exchanges::snapshot::Snapshot;
+
+/// Prints the changes between two binary snapshot files.
+///
+/// Exits with status 1 if the snapshots differ.
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, before, after] = args.as_slice() else {
+        eprintln!("usage: snapshot_diff <before> <after>");
+        std::process::exit(2);
+    };
+
+    let load = |path: &str| -> Result<Snapshot> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+        Snapshot::from_bytes(&bytes).with_context(|| format!("Invalid snapshot {}", path))
+    };
+    let diff = load(before)?.diff(&load(after)?);
+
+    print!("{}", diff);
+    if !diff.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
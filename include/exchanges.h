@@ -0,0 +1,82 @@
+/* C interface of the exchanges crate. See src/ffi.rs for the full documentation. */
+#ifndef EXCHANGES_H
+#define EXCHANGES_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct ExExchange ExExchange;
+
+typedef enum {
+    EX_OK = 0,
+    EX_NULL_POINTER = 1,
+    EX_INVALID_ARGUMENT = 2,
+    EX_REJECTED = 3,
+} ExStatus;
+
+enum {
+    EX_SIDE_BID = 0,
+    EX_SIDE_ASK = 1,
+};
+
+typedef struct {
+    uint64_t ask_order_id;
+    uint64_t bid_order_id;
+    uint64_t price;
+    uint64_t quantity;
+    uint64_t trade_id;
+    uint64_t sequence;
+} ExTrade;
+
+typedef enum {
+    EX_EVENT_ACCOUNT_CLOSED = 0,
+    EX_EVENT_ORDER_EXPIRED = 1,
+    EX_EVENT_STOP_REJECTED = 2,
+    EX_EVENT_PEG_CANCELLED = 3,
+    EX_EVENT_BALANCE_ALERT = 4,
+    EX_EVENT_SELF_TRADE_PREVENTED = 5,
+    EX_EVENT_CIRCUIT_BREAKER_TRIPPED = 6,
+    EX_EVENT_IMPLIED_HEDGE_SHORTFALL = 7,
+} ExEventKind;
+
+typedef struct {
+    ExEventKind kind;
+    /* Only valid during the callback. Empty for market events. */
+    const char *account_id;
+    /* Zero for account and market events. */
+    uint64_t order_id;
+} ExEvent;
+
+typedef void (*ExTradeCallback)(void *user_data, const ExTrade *trade);
+typedef void (*ExEventCallback)(void *user_data, const ExEvent *event);
+
+ExExchange *ex_exchange_new(void);
+void ex_exchange_free(ExExchange *handle);
+const char *ex_last_error(const ExExchange *handle);
+
+ExStatus ex_set_trade_callback(ExExchange *handle, ExTradeCallback callback, void *user_data);
+ExStatus ex_set_event_callback(ExExchange *handle, ExEventCallback callback, void *user_data);
+
+ExStatus ex_add_market(ExExchange *handle, const char *numeraire, const char *base);
+ExStatus ex_deposit(ExExchange *handle, const char *account_id, const char *asset,
+                    uint64_t amount);
+ExStatus ex_balance(const ExExchange *handle, const char *account_id, const char *asset,
+                    uint64_t *out);
+ExStatus ex_post_limit_order(ExExchange *handle, const char *numeraire, const char *base,
+                             uint64_t order_id, int32_t side, uint64_t price,
+                             uint64_t quantity, const char *account_id, uint64_t timestamp);
+ExStatus ex_cancel_order(ExExchange *handle, const char *numeraire, const char *base,
+                         uint64_t order_id, int32_t side, uint64_t price);
+ExStatus ex_reduce_order(ExExchange *handle, const char *numeraire, const char *base,
+                         uint64_t order_id, int32_t side, uint64_t price, uint64_t quantity);
+ExStatus ex_close_account(ExExchange *handle, const char *account_id,
+                          uint64_t dust_threshold);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EXCHANGES_H */
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use crate::{
     asset::Asset,
     order::{AccountId, OrderId, Price, Quantity, Side},
+    order_defaults::OrderDefaults,
 };
 
 pub struct Account {
@@ -10,6 +11,8 @@ pub struct Account {
     pub balances: HashMap<Asset, Quantity>,
     // Orders are stored in a map of asset to a map of order id to (side, price). The quantity can change and is only fulfilled when the order is cancelled.
     pub orders: HashMap<Asset, HashMap<OrderId, (Side, Price)>>,
+    /// Instructions applied to the account's orders that leave them unset.
+    pub order_defaults: OrderDefaults,
 }
 
 impl Account {
@@ -18,6 +21,72 @@ impl Account {
             id,
             balances: HashMap::new(),
             orders: HashMap::new(),
+            order_defaults: OrderDefaults::default(),
         }
     }
 }
+
+/// A level of an account's available balance worth notifying about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceThreshold {
+    /// Breached while the balance is strictly below the amount.
+    Below(u64),
+    /// Breached while the balance is strictly above the amount.
+    Above(u64),
+}
+
+impl BalanceThreshold {
+    pub fn is_breached(self, balance: u64) -> bool {
+        match self {
+            BalanceThreshold::Below(amount) => balance < amount,
+            BalanceThreshold::Above(amount) => balance > amount,
+        }
+    }
+}
+
+/// Accounts owned by the exchange itself, created when the exchange is bootstrapped.
+///
+/// System accounts cannot trade, withdraw or be closed; funds only leave them through
+/// `Exchange::system_transfer`, or as loans of the lending pool, so every movement stays in
+/// the ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemAccount {
+    /// Credited with the trading fees collected by every market.
+    Fees,
+    /// Backstop for losses that cannot be recovered from the account that caused them.
+    Insurance,
+    /// Credited with the dust swept from closed accounts.
+    Dust,
+    /// Operating funds of the exchange.
+    Treasury,
+    /// Inventory lent to short sellers.
+    Lending,
+}
+
+impl SystemAccount {
+    pub const ALL: [SystemAccount; 5] = [
+        SystemAccount::Fees,
+        SystemAccount::Insurance,
+        SystemAccount::Dust,
+        SystemAccount::Treasury,
+        SystemAccount::Lending,
+    ];
+
+    pub fn id(self) -> AccountId {
+        let name = match self {
+            SystemAccount::Fees => "fees",
+            SystemAccount::Insurance => "insurance",
+            SystemAccount::Dust => "dust",
+            SystemAccount::Treasury => "treasury",
+            SystemAccount::Lending => "lending",
+        };
+        AccountId::new(name.to_string())
+    }
+
+    /// Returns the system account with the given ID, if any
+    pub fn from_id(account_id: &AccountId) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|system| system.id() == *account_id)
+    }
+}
@@ -1,16 +1,51 @@
 use crate::{
     account::Account,
     asset::Asset,
+    ledger::{Direction, Ledger},
     order::{AccountId, Quantity},
+    order_defaults::OrderDefaults,
 };
 use anyhow::Result;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ops::{Add, Sub},
 };
 
+/// A balance whose cached value disagrees with the ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discrepancy {
+    pub account_id: AccountId,
+    pub asset: Asset,
+    /// The balance recomputed from the ledger.
+    pub ledger: i128,
+    /// The balance held in the account.
+    pub cached: u64,
+}
+
+/// The result of reconciling cached balances against the ledger.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ReconciliationReport {
+    /// Number of account and asset pairs compared.
+    pub checked: usize,
+    /// Every mismatch found, sorted by account and asset.
+    pub discrepancies: Vec<Discrepancy>,
+    /// Number of discrepancies fixed by resetting the cached balance to the ledger.
+    pub repaired: usize,
+}
+
+impl ReconciliationReport {
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+#[derive(Default)]
 pub struct AccountManager {
     accounts: HashMap<AccountId, Account>,
+    /// Every balance movement, in order.
+    ledger: Ledger,
+    /// IDs of closed accounts, which can never be used again.
+    closed: HashSet<AccountId>,
     // todo: add overall positions and risk limits later.
 }
 
@@ -68,9 +103,22 @@ impl AccountManager {
     pub fn new() -> Self {
         Self {
             accounts: HashMap::new(),
+            ledger: Ledger::new(),
+            closed: HashSet::new(),
         }
     }
 
+    /// Create an empty account if it does not exist yet
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account to open
+    pub fn open_account(&mut self, account_id: AccountId) {
+        self.accounts
+            .entry(account_id.clone())
+            .or_insert(Account::new(account_id));
+    }
+
     /// Add a balance to an account
     ///
     /// # Arguments
@@ -85,6 +133,10 @@ impl AccountManager {
             .or_insert(Account::new(account_id));
         let balance = account.balances.entry(asset).or_insert(Quantity::new(0));
         *balance = balance.add(Quantity::new(amount));
+        if amount > 0 {
+            self.ledger
+                .record(&account.id, asset, Direction::Credit, amount);
+        }
     }
 
     /// Remove a balance from an account
@@ -110,6 +162,38 @@ impl AccountManager {
         }
         let new_balance = balance.sub(Quantity::new(amount));
         account.balances.insert(asset, new_balance);
+        if amount > 0 {
+            self.ledger
+                .record(&account.id, asset, Direction::Debit, amount);
+        }
+        Ok(())
+    }
+
+    /// Get an account, or `None` if it was never opened
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    pub fn get_account(&self, account_id: &AccountId) -> Option<&Account> {
+        self.accounts.get(account_id)
+    }
+
+    /// Set the instructions applied to an account's orders that leave them unset
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `defaults` - The new defaults, replacing the previous ones
+    pub fn set_order_defaults(
+        &mut self,
+        account_id: &AccountId,
+        defaults: OrderDefaults,
+    ) -> Result<()> {
+        let account = self
+            .accounts
+            .get_mut(account_id)
+            .ok_or(anyhow::anyhow!("Account not found"))?;
+        account.order_defaults = defaults;
         Ok(())
     }
 
@@ -124,6 +208,142 @@ impl AccountManager {
             .accounts
             .get(&account_id)
             .ok_or(anyhow::anyhow!("Account not found"))?;
-        Ok(account.balances.get(&asset).unwrap().get())
+        Ok(account.balances.get(&asset).map_or(0, |q| q.get()))
+    }
+
+    /// Iterate over every balance of every account, in no particular order
+    pub fn balances(&self) -> impl Iterator<Item = (&AccountId, Asset, u64)> {
+        self.accounts.iter().flat_map(|(account_id, account)| {
+            account
+                .balances
+                .iter()
+                .map(move |(asset, quantity)| (account_id, *asset, quantity.get()))
+        })
+    }
+
+    /// The ledger of every balance movement
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Returns true if the account was closed
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    pub fn is_closed(&self, account_id: &AccountId) -> bool {
+        self.closed.contains(account_id)
+    }
+
+    /// Close an account, archiving its ledger entries and returning how many were archived
+    ///
+    /// The caller is responsible for emptying the account first: any remaining balances
+    /// are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account to close
+    pub fn close_account(&mut self, account_id: &AccountId) -> Result<usize> {
+        self.accounts
+            .remove(account_id)
+            .ok_or(anyhow::anyhow!("Account not found"))?;
+        self.closed.insert(account_id.clone());
+        Ok(self.ledger.archive(account_id))
+    }
+
+    /// Recompute every balance from the ledger and compare it with the cached balances
+    ///
+    /// With `repair`, mismatching cached balances are reset to the ledger value. Balances
+    /// the ledger puts below zero cannot be represented and are only reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `repair` - Whether to fix the discrepancies found
+    pub fn reconcile(&mut self, repair: bool) -> ReconciliationReport {
+        let mut expected = self.ledger.net_balances();
+        let mut report = ReconciliationReport::default();
+        for (account_id, account) in self.accounts.iter_mut() {
+            for (asset, cached) in account.balances.iter_mut() {
+                let ledger = expected.remove(&(account_id.clone(), *asset)).unwrap_or(0);
+                report.checked += 1;
+                if ledger == cached.get() as i128 {
+                    continue;
+                }
+                report.discrepancies.push(Discrepancy {
+                    account_id: account_id.clone(),
+                    asset: *asset,
+                    ledger,
+                    cached: cached.get(),
+                });
+                if repair && let Ok(ledger) = u64::try_from(ledger) {
+                    *cached = Quantity::new(ledger);
+                    report.repaired += 1;
+                }
+            }
+        }
+        // Ledger balances of accounts or assets missing from the cache
+        for ((account_id, asset), ledger) in expected {
+            report.checked += 1;
+            if ledger == 0 {
+                continue;
+            }
+            report.discrepancies.push(Discrepancy {
+                account_id: account_id.clone(),
+                asset,
+                ledger,
+                cached: 0,
+            });
+            if repair && let Ok(ledger) = u64::try_from(ledger) {
+                self.accounts
+                    .entry(account_id.clone())
+                    .or_insert(Account::new(account_id))
+                    .balances
+                    .insert(asset, Quantity::new(ledger));
+                report.repaired += 1;
+            }
+        }
+        report
+            .discrepancies
+            .sort_by(|a, b| (&a.account_id, a.asset.symbol).cmp(&(&b.account_id, b.asset.symbol)));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile_reports_and_repairs() {
+        let usd = Asset::new("USD");
+        let alice = AccountId::new("alice".to_string());
+        let mut manager = AccountManager::new();
+        manager.add_balance(alice.clone(), usd, 100);
+        manager.remove_balance(alice.clone(), usd, 30).unwrap();
+        assert!(manager.reconcile(false).is_clean());
+
+        // Corrupt the cache behind the ledger's back
+        manager
+            .accounts
+            .get_mut(&alice)
+            .unwrap()
+            .balances
+            .insert(usd, Quantity::new(75));
+        let report = manager.reconcile(false);
+        assert_eq!(
+            report.discrepancies,
+            vec![Discrepancy {
+                account_id: alice.clone(),
+                asset: usd,
+                ledger: 70,
+                cached: 75,
+            }]
+        );
+        assert_eq!(report.repaired, 0);
+        assert_eq!(manager.get_balance(alice.clone(), usd).unwrap(), 75);
+
+        assert_eq!(manager.reconcile(true).repaired, 1);
+        assert_eq!(manager.get_balance(alice, usd).unwrap(), 70);
+        assert!(manager.reconcile(false).is_clean());
     }
 }
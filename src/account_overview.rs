@@ -0,0 +1,188 @@
+//! Everything an account needs to see about itself, gathered in one query.
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    exchange::Exchange,
+    market::{FeeSchedule, Pair},
+    matching::Trade,
+    order::{AccountId, Order},
+};
+
+/// Number of recent trades reported per market.
+pub const RECENT_TRADES: usize = 50;
+
+/// The funds an account holds in one asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetBalance {
+    pub asset: Asset,
+    /// Balance that can be withdrawn or used by new orders.
+    pub available: u64,
+    /// Balance held by resting orders, not included in `available`.
+    pub locked: u64,
+}
+
+/// An account's balances, orders, positions, trades and fees.
+///
+/// Markets are listed in symbol order (base, then numeraire) and assets in symbol order, so
+/// two overviews of the same state compare equal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountOverview {
+    pub account_id: AccountId,
+    /// Every asset with an available or locked balance.
+    pub balances: Vec<AssetBalance>,
+    /// Resting orders, then pending stop orders, of each market.
+    pub open_orders: Vec<(Pair, Order)>,
+    /// Non-zero positions.
+    pub positions: Vec<(Pair, i64)>,
+    /// The last `RECENT_TRADES` trades of the account in each market, oldest first.
+    pub recent_trades: Vec<(Pair, Trade)>,
+    /// The fees the account pays in each market.
+    pub fees: Vec<(Pair, FeeSchedule)>,
+}
+
+impl Exchange {
+    /// Get an overview of an account
+    ///
+    /// Everything is read from the same state, so the parts are consistent with each other:
+    /// an order's hold shows up in `locked` exactly when the order is in `open_orders`.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    pub fn account_overview(&self, account_id: &AccountId) -> Result<AccountOverview> {
+        let account = self
+            .account_manager
+            .get_account(account_id)
+            .ok_or(anyhow::anyhow!("Account not found"))?;
+        let mut pairs: Vec<Pair> = self.markets.keys().copied().collect();
+        pairs.sort_by_key(|pair| (pair.base.symbol, pair.numeraire.symbol));
+
+        let mut assets: Vec<Asset> = account
+            .balances
+            .keys()
+            .copied()
+            .chain(pairs.iter().flat_map(|pair| [pair.base, pair.numeraire]))
+            .collect();
+        assets.sort_by_key(|asset| asset.symbol);
+        assets.dedup();
+        let balances = assets
+            .into_iter()
+            .map(|asset| AssetBalance {
+                asset,
+                available: account.balances.get(&asset).map_or(0, |q| q.get()),
+                locked: self.locked_balance(account_id, asset),
+            })
+            .filter(|balance| balance.available > 0 || balance.locked > 0)
+            .collect();
+
+        let mut overview = AccountOverview {
+            account_id: account_id.clone(),
+            balances,
+            open_orders: Vec::new(),
+            positions: Vec::new(),
+            recent_trades: Vec::new(),
+            fees: Vec::new(),
+        };
+        for pair in pairs {
+            let market = &self.markets[&pair];
+            overview.open_orders.extend(
+                market
+                    .resting_orders()
+                    .chain(market.pending_stops())
+                    .filter(|order| order.account_id == *account_id)
+                    .map(|order| (pair, order.clone())),
+            );
+
+            let position = self.position(account_id, pair);
+            if position != 0 {
+                overview.positions.push((pair, position));
+            }
+
+            let trades: Vec<&Trade> = market.private_trades(account_id).collect();
+            let recent = &trades[trades.len().saturating_sub(RECENT_TRADES)..];
+            overview
+                .recent_trades
+                .extend(recent.iter().map(|trade| (pair, (*trade).clone())));
+
+            overview.fees.push((pair, market.config.fees));
+        }
+        Ok(overview)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        market::Market,
+        order::{OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_account_overview() {
+        let (usd, btc) = (Asset::new("USD"), Asset::new("BTC"));
+        let pair = Pair {
+            numeraire: usd,
+            base: btc,
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), btc, 10);
+        exchange.add_balance(bob.clone(), usd, 1_000);
+        let order = |id: u64, price: u64, side: Side, account_id: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(3),
+                side,
+                account_id.clone(),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, 100, Side::Ask, &alice), pair)
+            .unwrap();
+        exchange
+            .post_order(order(2, 100, Side::Bid, &bob), pair)
+            .unwrap();
+        exchange
+            .post_order(order(3, 110, Side::Ask, &alice), pair)
+            .unwrap();
+
+        let overview = exchange.account_overview(&alice).unwrap();
+        assert_eq!(
+            overview.balances,
+            vec![
+                AssetBalance {
+                    asset: btc,
+                    available: 4,
+                    locked: 3,
+                },
+                AssetBalance {
+                    asset: usd,
+                    available: 300,
+                    locked: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            overview.open_orders,
+            vec![(pair, order(3, 110, Side::Ask, &alice))]
+        );
+        assert_eq!(overview.positions, vec![(pair, -3)]);
+        assert_eq!(overview.recent_trades.len(), 1);
+        assert_eq!(overview.recent_trades[0].1.ask_account_id, alice);
+        assert_eq!(overview.fees, vec![(pair, FeeSchedule::default())]);
+
+        assert!(
+            exchange
+                .account_overview(&AccountId::new("carol".to_string()))
+                .is_err()
+        );
+    }
+}
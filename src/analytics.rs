@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use crate::{
+    market::Pair,
+    matching::{Liquidity, Trade},
+    order::{AccountId, Side},
+};
+
+/// A fill of the tracked account, with the mid price at the time of the fill.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MakerFill {
+    pub time: u64,
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    pub liquidity: Liquidity,
+    pub mid: Option<f64>,
+}
+
+/// Quoting performance of an account in one market.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MakerReport {
+    pub pair: Pair,
+    /// Number of fills in which the account provided liquidity.
+    pub maker_fills: usize,
+    /// Base quantity traded as maker.
+    pub maker_volume: u64,
+    /// Net base position after each fill, maker or taker.
+    pub inventory: Vec<(u64, i64)>,
+    /// Average distance between the fill price and the mid at the time of the fill, in the
+    /// account's favour, weighted by quantity.
+    pub spread_capture: f64,
+    /// Average move of the mid against the account over the report horizon after each maker
+    /// fill, weighted by quantity. Positive values mean the account was picked off.
+    pub adverse_selection: f64,
+}
+
+/// Tracks the fills of a market-making account and the mid prices of its markets, and
+/// derives inventory, spread capture and adverse selection reports from them.
+#[derive(Debug)]
+pub struct MakerAnalytics {
+    account_id: AccountId,
+    /// How far after a fill the mid is sampled to measure adverse selection.
+    horizon: u64,
+    mids: HashMap<Pair, Vec<(u64, f64)>>,
+    fills: HashMap<Pair, Vec<MakerFill>>,
+}
+
+impl MakerAnalytics {
+    pub fn new(account_id: AccountId, horizon: u64) -> Self {
+        Self {
+            account_id,
+            horizon,
+            mids: HashMap::new(),
+            fills: HashMap::new(),
+        }
+    }
+
+    /// Record the best prices of a market. Mids are only recorded when both sides exist.
+    ///
+    /// Times must be recorded in non-decreasing order.
+    pub fn record_book(
+        &mut self,
+        pair: Pair,
+        time: u64,
+        best_bid: Option<u64>,
+        best_ask: Option<u64>,
+    ) {
+        if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
+            self.mids
+                .entry(pair)
+                .or_default()
+                .push((time, (bid as f64 + ask as f64) / 2.0));
+        }
+    }
+
+    /// Record a trade. Trades the tracked account did not take part in are ignored.
+    pub fn record_trade(&mut self, pair: Pair, time: u64, trade: &Trade) {
+        let side = if trade.bid_account_id == self.account_id {
+            Side::Bid
+        } else if trade.ask_account_id == self.account_id {
+            Side::Ask
+        } else {
+            return;
+        };
+        let mid = self.mid_at(pair, time);
+        self.fills.entry(pair).or_default().push(MakerFill {
+            time,
+            side,
+            price: trade.price.get(),
+            quantity: trade.quantity.get(),
+            liquidity: trade.liquidity(side),
+            mid,
+        });
+    }
+
+    /// The fills recorded in a market, oldest first.
+    pub fn fills(&self, pair: Pair) -> &[MakerFill] {
+        self.fills.get(&pair).map_or(&[], |fills| fills.as_slice())
+    }
+
+    /// Build the report of a market from everything recorded so far.
+    pub fn report(&self, pair: Pair) -> MakerReport {
+        let mut inventory = Vec::new();
+        let mut position: i64 = 0;
+        let mut maker_fills = 0;
+        let mut maker_volume = 0;
+        let mut capture = 0.0;
+        let mut capture_qty = 0;
+        let mut adverse = 0.0;
+        let mut adverse_qty = 0;
+
+        for fill in self.fills(pair) {
+            let sign = match fill.side {
+                Side::Bid => 1.0,
+                Side::Ask => -1.0,
+            };
+            position += sign as i64 * fill.quantity as i64;
+            inventory.push((fill.time, position));
+
+            if fill.liquidity != Liquidity::Maker {
+                continue;
+            }
+            maker_fills += 1;
+            maker_volume += fill.quantity;
+            let Some(mid) = fill.mid else {
+                continue;
+            };
+            let quantity = fill.quantity as f64;
+            capture += sign * (mid - fill.price as f64) * quantity;
+            capture_qty += fill.quantity;
+            if let Some(later) = self.mid_at(pair, fill.time + self.horizon) {
+                adverse += sign * (mid - later) * quantity;
+                adverse_qty += fill.quantity;
+            }
+        }
+
+        MakerReport {
+            pair,
+            maker_fills,
+            maker_volume,
+            inventory,
+            spread_capture: average(capture, capture_qty),
+            adverse_selection: average(adverse, adverse_qty),
+        }
+    }
+
+    /// The most recent mid recorded at or before `time`.
+    fn mid_at(&self, pair: Pair, time: u64) -> Option<f64> {
+        let mids = self.mids.get(&pair)?;
+        let index = mids.partition_point(|(t, _)| *t <= time);
+        index.checked_sub(1).map(|i| mids[i].1)
+    }
+}
+
+fn average(total: f64, quantity: u64) -> f64 {
+    if quantity == 0 {
+        0.0
+    } else {
+        total / quantity as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        matching::TradeId,
+        order::{OrderId, Price, Quantity},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_maker_report() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let mm = AccountId::new("mm".to_string());
+        let other = AccountId::new("other".to_string());
+        let trade =
+            |bid: &AccountId, ask: &AccountId, price: u64, qty: u64, aggressor: Side| Trade {
+                id: TradeId::default(),
+                sequence: 0,
+                ask_order_id: OrderId::new(1),
+                bid_order_id: OrderId::new(2),
+                ask_account_id: ask.clone(),
+                bid_account_id: bid.clone(),
+                price: Price::new(price),
+                quantity: Quantity::new(qty),
+                aggressor: Some(aggressor),
+                ask_client_order_id: None,
+                bid_client_order_id: None,
+                ask_tag: None,
+                bid_tag: None,
+            };
+
+        let mut analytics = MakerAnalytics::new(mm.clone(), 5);
+        analytics.record_book(pair, 0, Some(99), Some(101));
+        // Bought 10 at 99 from a seller hitting the bid: captured 1 below the mid of 100
+        analytics.record_trade(pair, 1, &trade(&mm, &other, 99, 10, Side::Ask));
+        // The mid then falls to 98: the buy was adversely selected by 2
+        analytics.record_book(pair, 4, Some(97), Some(99));
+        // Sold 4 at 99 taking liquidity: counts for inventory only
+        analytics.record_trade(pair, 7, &trade(&other, &mm, 99, 4, Side::Ask));
+        // Someone else's trade is ignored
+        analytics.record_trade(pair, 8, &trade(&other, &other, 98, 1, Side::Bid));
+
+        let report = analytics.report(pair);
+        assert_eq!(report.maker_fills, 1);
+        assert_eq!(report.maker_volume, 10);
+        assert_eq!(report.inventory, vec![(1, 10), (7, 6)]);
+        assert_eq!(report.spread_capture, 1.0);
+        assert_eq!(report.adverse_selection, 2.0);
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    collections::HashSet,
+    sync::{Mutex, OnceLock},
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Asset {
     pub symbol: &'static str,
@@ -7,4 +12,25 @@ impl Asset {
     pub fn new(symbol: &'static str) -> Self {
         Self { symbol }
     }
+
+    /// Creates an asset from a symbol only known at runtime (e.g. parsed from a file).
+    ///
+    /// Each distinct symbol is leaked once and reused afterwards, so interning the same
+    /// symbol repeatedly does not grow memory.
+    pub fn intern(symbol: &str) -> Self {
+        static SYMBOLS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut symbols = SYMBOLS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let symbol = match symbols.get(symbol) {
+            Some(symbol) => *symbol,
+            None => {
+                let leaked: &'static str = Box::leak(symbol.to_string().into_boxed_str());
+                symbols.insert(leaked);
+                leaked
+            }
+        };
+        Self { symbol }
+    }
 }
@@ -0,0 +1,708 @@
+//! Opening and closing auctions with published imbalances and imbalance-offset orders.
+//!
+//! While a market's opening auction is open the market is in pre-open: continuous trading
+//! stops, limit orders rest on the book without matching, so the book may cross, and
+//! triggered stops wait. At the open the auction uncrosses, continuous trading starts from an
+//! uncrossed book, and the waiting stops are posted.
+//!
+//! While a market's closing auction is open, continuous trading carries on and auction
+//! orders are collected beside the book without matching. At the close the auction uncrosses.
+//!
+//! Either uncross trades the book and the auction orders at the single price that executes
+//! the most quantity.
+
+use anyhow::Result;
+
+use crate::{
+    exchange::Exchange,
+    market::Pair,
+    matching::{Trade, TradeId},
+    order::{Order, OrderId, OrderType, Price, Quantity, Side, Timestamp},
+    reject::RejectReason,
+};
+
+/// The state of a closing auction, as published during its call period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Imbalance {
+    /// The price the auction would uncross at now, or `None` if nothing crosses.
+    pub indicative_price: Option<Price>,
+    /// The quantity that would trade at the indicative price.
+    pub paired_quantity: Quantity,
+    /// The quantity left unmatched at the indicative price on the imbalance side.
+    pub imbalance_quantity: Quantity,
+    /// The side with more interest at the indicative price, or `None` if balanced.
+    pub imbalance_side: Option<Side>,
+}
+
+/// Which auction a market is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuctionKind {
+    /// The call before continuous trading starts, during which orders rest without matching.
+    Opening,
+    /// The call alongside continuous trading, with orders collected beside the book.
+    Closing,
+}
+
+/// An open auction and the orders it collected beside the book, in arrival order.
+#[derive(Debug)]
+pub(crate) struct Auction {
+    pub(crate) kind: AuctionKind,
+    orders: Vec<Order>,
+    /// Imbalance-offset orders, which only trade against the imbalance.
+    offsets: Vec<Order>,
+}
+
+/// Where an order taking part in an uncross comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Book,
+    Auction,
+}
+
+#[derive(Debug)]
+struct Participant {
+    order: Order,
+    source: Source,
+    remaining: u64,
+}
+
+impl Auction {
+    fn new(kind: AuctionKind) -> Self {
+        Self {
+            kind,
+            orders: Vec::new(),
+            offsets: Vec::new(),
+        }
+    }
+}
+
+impl Exchange {
+    /// Put a market in pre-open by opening its opening auction
+    ///
+    /// Until `uncross_opening_auction`, orders posted to the market rest on its book without
+    /// matching. Only good-till-cancelled limit orders without a stop, peg, minimum quantity
+    /// or all-or-none condition are accepted, and no odd lots in segregated markets.
+    /// Cancels, reductions and expiry work as usual. The pre-open state is not part of
+    /// snapshots.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market
+    pub fn open_opening_auction(&mut self, pair: Pair) -> Result<()> {
+        self.open_auction(pair, AuctionKind::Opening)
+    }
+
+    /// Open the closing auction of a market
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market
+    pub fn open_closing_auction(&mut self, pair: Pair) -> Result<()> {
+        self.open_auction(pair, AuctionKind::Closing)
+    }
+
+    fn open_auction(&mut self, pair: Pair, kind: AuctionKind) -> Result<()> {
+        if !self.markets.contains_key(&pair) {
+            return Err(anyhow::anyhow!("Market not found"));
+        }
+        if self.auctions.contains_key(&pair) {
+            return Err(anyhow::anyhow!("Auction already open"));
+        }
+        self.auctions.insert(pair, Auction::new(kind));
+        Ok(())
+    }
+
+    /// The auction a market is running, if any.
+    pub fn auction_kind(&self, pair: Pair) -> Option<AuctionKind> {
+        self.auctions.get(&pair).map(|auction| auction.kind)
+    }
+
+    /// Whether a market is in pre-open, collecting its opening auction.
+    pub fn is_pre_open(&self, pair: Pair) -> bool {
+        self.auction_kind(pair) == Some(AuctionKind::Opening)
+    }
+
+    /// Post a limit order to the open closing auction of a market
+    ///
+    /// The order's hold is taken right away. It does not match until the auction uncrosses,
+    /// and whatever it does not fill then is cancelled. Auction orders are not part of
+    /// snapshots.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order to post
+    /// * `pair` - The market of the auction
+    pub fn post_auction_order(&mut self, order: Order, pair: Pair) -> Result<()> {
+        self.collect_auction_order(order, pair, false)
+    }
+
+    /// Post an imbalance-offset order to the open closing auction of a market
+    ///
+    /// Offsets must be on the opposite side of the published imbalance. At the uncross they
+    /// only trade against what is left of the imbalance, at the auction price, in arrival
+    /// order, and never move the price. Offsets whose limit does not accept the auction price,
+    /// or that are on the wrong side of the final imbalance, do not trade.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order to post
+    /// * `pair` - The market of the auction
+    pub fn post_imbalance_offset(&mut self, order: Order, pair: Pair) -> Result<()> {
+        self.collect_auction_order(order, pair, true)
+    }
+
+    /// Cancel an order of an open closing auction and release its hold
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The ID of the auction or offset order
+    /// * `pair` - The market of the auction
+    pub fn cancel_auction_order(&mut self, order_id: OrderId, pair: Pair) -> Result<()> {
+        let auction = self
+            .auctions
+            .get_mut(&pair)
+            .filter(|auction| auction.kind == AuctionKind::Closing)
+            .ok_or(anyhow::anyhow!("No closing auction open"))?;
+        let order = [&mut auction.orders, &mut auction.offsets]
+            .into_iter()
+            .find_map(|orders| {
+                let index = orders.iter().position(|order| order.id == order_id)?;
+                Some(orders.remove(index))
+            })
+            .ok_or(RejectReason::UnknownOrder)?;
+        self.release_auction_hold(&order, order.quantity.get(), pair);
+        Ok(())
+    }
+
+    /// Get the imbalance of the open auction of a market
+    ///
+    /// The imbalance is computed from the book and the auction orders; offsets are left out,
+    /// since they can only reduce it. During pre-open its indicative price is the price the
+    /// market would open at.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market of the auction
+    pub fn auction_imbalance(&self, pair: Pair) -> Option<Imbalance> {
+        let auction = self.auctions.get(&pair)?;
+        let (bids, asks) = self.auction_interest(pair, auction);
+        Some(imbalance(&bids, &asks))
+    }
+
+    /// Close the opening auction of a market, uncross it at `timestamp` and start continuous
+    /// trading
+    ///
+    /// The book trades at the price that executes the most quantity, then leaves the smallest
+    /// imbalance, as for `uncross_closing_auction`, which leaves it uncrossed. Returns the
+    /// trades of the uncross, followed by those of any stops it triggered, including those
+    /// triggered before or during pre-open. The uncross price becomes the reference price of
+    /// the market's circuit breaker.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market of the auction
+    /// * `timestamp` - The time of the uncross
+    pub fn uncross_opening_auction(
+        &mut self,
+        pair: Pair,
+        timestamp: Timestamp,
+    ) -> Result<Vec<Trade>> {
+        let auction = self
+            .take_auction(pair, AuctionKind::Opening)
+            .ok_or(anyhow::anyhow!("No opening auction open"))?;
+        if let Some(market) = self.markets.get_mut(&pair) {
+            market.reset_circuit_breaker();
+        }
+        Ok(self.uncross(pair, auction, timestamp))
+    }
+
+    /// Close the closing auction of a market and uncross it at `timestamp`
+    ///
+    /// Book and auction orders willing to trade at the auction price are filled in price,
+    /// then time priority; the auction price maximises the executed quantity, then
+    /// minimises the imbalance, and is the lowest such price otherwise. Offsets then fill
+    /// against the remaining imbalance. All trades are at the auction price and have no
+    /// aggressor. Partly filled book orders keep resting with their priority, while unfilled
+    /// auction and offset orders are cancelled. Returns the trades of the uncross, followed
+    /// by those of any stops it triggered.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market of the auction
+    /// * `timestamp` - The time of the uncross
+    pub fn uncross_closing_auction(
+        &mut self,
+        pair: Pair,
+        timestamp: Timestamp,
+    ) -> Result<Vec<Trade>> {
+        let auction = self
+            .take_auction(pair, AuctionKind::Closing)
+            .ok_or(anyhow::anyhow!("No closing auction open"))?;
+        Ok(self.uncross(pair, auction, timestamp))
+    }
+
+    fn take_auction(&mut self, pair: Pair, kind: AuctionKind) -> Option<Auction> {
+        if self.auction_kind(pair) != Some(kind) {
+            return None;
+        }
+        self.auctions.remove(&pair)
+    }
+
+    fn uncross(&mut self, pair: Pair, auction: Auction, timestamp: Timestamp) -> Vec<Trade> {
+        let (mut bids, mut asks) = self.auction_interest(pair, &auction);
+        let imbalance = imbalance(&bids, &asks);
+
+        let mut fills = Vec::new();
+        let mut offsets: Vec<Participant> = auction
+            .offsets
+            .into_iter()
+            .map(|order| Participant {
+                remaining: order.quantity.get(),
+                order,
+                source: Source::Auction,
+            })
+            .collect();
+        if let Some(price) = imbalance.indicative_price {
+            fills = match_at(&mut bids, &mut asks, price);
+            let accepts = |order: &Order| match order.side {
+                Side::Bid => order.price >= price,
+                Side::Ask => order.price <= price,
+            };
+            let offsetting: Vec<&mut Participant> = offsets
+                .iter_mut()
+                .filter(|offset| {
+                    Some(offset.order.side.opposite()) == imbalance.imbalance_side
+                        && accepts(&offset.order)
+                })
+                .collect();
+            match imbalance.imbalance_side {
+                Some(Side::Bid) => fills.extend(pair_fills(
+                    bids.iter_mut().filter(|bid| accepts(&bid.order)).collect(),
+                    offsetting,
+                    price,
+                )),
+                Some(Side::Ask) => fills.extend(pair_fills(
+                    offsetting,
+                    asks.iter_mut().filter(|ask| accepts(&ask.order)).collect(),
+                    price,
+                )),
+                None => {}
+            }
+        }
+
+        for participant in bids.iter().chain(&asks).chain(&offsets) {
+            let order = &participant.order;
+            match participant.source {
+                Source::Book if participant.remaining == 0 => {
+                    let market = self.markets.get_mut(&pair).unwrap();
+                    market.cancel_order(order.id, order.side, order.price);
+                }
+                Source::Book if participant.remaining < order.quantity.get() => {
+                    let market = self.markets.get_mut(&pair).unwrap();
+                    let remaining = Quantity::new(participant.remaining);
+                    market.reduce_order(order.id, order.side, order.price, remaining);
+                }
+                Source::Book => {}
+                Source::Auction => self.release_auction_hold(order, participant.remaining, pair),
+            }
+        }
+
+        let stop_trades = self.settle_uncross(pair, &mut fills, timestamp);
+        let trades = fills.into_iter().map(|(trade, _)| trade);
+        trades.chain(stop_trades).collect()
+    }
+
+    fn collect_auction_order(&mut self, order: Order, pair: Pair, offset: bool) -> Result<()> {
+        let market = self
+            .markets
+            .get(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        if self.auction_kind(pair) != Some(AuctionKind::Closing) {
+            return Err(anyhow::anyhow!("No closing auction open"));
+        }
+        if order.order_type != OrderType::Limit || order.stop_price.is_some() || order.peg.is_some()
+        {
+            return Err(anyhow::anyhow!("Auction orders must be plain limit orders"));
+        }
+        if order.quantity.get() == 0 {
+            return Err(anyhow::anyhow!("Quantity must be positive"));
+        }
+        if !market.supports_price(order.price) {
+            return Err(anyhow::anyhow!("Price not supported by market"));
+        }
+        if offset {
+            let imbalance = self.auction_imbalance(pair).unwrap();
+            if imbalance.imbalance_side != Some(order.side.opposite()) {
+                return Err(anyhow::anyhow!(
+                    "Imbalance offset orders must offset the published imbalance"
+                ));
+            }
+        }
+        let (asset, amount) = Self::checked_hold_for(&order, pair, market.config.fees)
+            .ok_or(RejectReason::HoldOverflow)?;
+        self.remove_balance(order.account_id.clone(), asset, amount)?;
+        self.record_order_id(order.id, pair);
+        let auction = self.auctions.get_mut(&pair).unwrap();
+        if offset {
+            auction.offsets.push(order);
+        } else {
+            auction.orders.push(order);
+        }
+        Ok(())
+    }
+
+    fn release_auction_hold(&mut self, order: &Order, remaining: u64, pair: Pair) {
+        let unfilled = Order {
+            quantity: Quantity::new(remaining),
+            ..order.clone()
+        };
+        let fees = self.markets[&pair].config.fees;
+        let (asset, amount) = Self::hold_for(&unfilled, pair, fees);
+        if amount > 0 {
+            self.add_balance(unfilled.account_id, asset, amount);
+        }
+    }
+
+    /// The bids and asks of the book and the auction, each in priority order. Odd lots of
+    /// segregated markets do not take part.
+    fn auction_interest(
+        &self,
+        pair: Pair,
+        auction: &Auction,
+    ) -> (Vec<Participant>, Vec<Participant>) {
+        let book = self.markets[&pair].matching_engine.orderbook();
+        let resting = book
+            .get_bids()
+            .flat_map(|(_, orders)| orders.iter())
+            .chain(book.get_asks().flat_map(|(_, orders)| orders.iter()))
+            .map(|order| (order, Source::Book));
+        let collected = auction.orders.iter().map(|order| (order, Source::Auction));
+        let (mut bids, mut asks): (Vec<Participant>, Vec<Participant>) = resting
+            .chain(collected)
+            .map(|(order, source)| Participant {
+                order: order.clone(),
+                source,
+                remaining: order.quantity.get(),
+            })
+            .partition(|participant| participant.order.side == Side::Bid);
+        bids.sort_by_key(|p| {
+            (
+                std::cmp::Reverse(p.order.price),
+                p.order.timestamp,
+                p.order.id,
+            )
+        });
+        asks.sort_by_key(|p| (p.order.price, p.order.timestamp, p.order.id));
+        (bids, asks)
+    }
+}
+
+/// The imbalance of bids and asks in priority order.
+fn imbalance(bids: &[Participant], asks: &[Participant]) -> Imbalance {
+    let mut best: Option<(Price, u64, u64, u64)> = None;
+    for price in bids.iter().chain(asks).map(|p| p.order.price) {
+        let demand: u64 = bids
+            .iter()
+            .filter(|bid| bid.order.price >= price)
+            .map(|bid| bid.remaining)
+            .sum();
+        let supply: u64 = asks
+            .iter()
+            .filter(|ask| ask.order.price <= price)
+            .map(|ask| ask.remaining)
+            .sum();
+        let paired = demand.min(supply);
+        if paired == 0 {
+            continue;
+        }
+        let rank = |(price, paired, demand, supply): (Price, u64, u64, u64)| {
+            (std::cmp::Reverse(paired), demand.abs_diff(supply), price)
+        };
+        let candidate = (price, paired, demand, supply);
+        if best.is_none_or(|best| rank(candidate) < rank(best)) {
+            best = Some(candidate);
+        }
+    }
+    match best {
+        Some((price, paired, demand, supply)) => Imbalance {
+            indicative_price: Some(price),
+            paired_quantity: Quantity::new(paired),
+            imbalance_quantity: Quantity::new(demand.abs_diff(supply)),
+            imbalance_side: match demand.cmp(&supply) {
+                std::cmp::Ordering::Greater => Some(Side::Bid),
+                std::cmp::Ordering::Less => Some(Side::Ask),
+                std::cmp::Ordering::Equal => None,
+            },
+        },
+        None => Imbalance {
+            indicative_price: None,
+            paired_quantity: Quantity::new(0),
+            imbalance_quantity: Quantity::new(0),
+            imbalance_side: None,
+        },
+    }
+}
+
+/// Fills the bids and asks that accept `price` against each other in priority order.
+fn match_at(
+    bids: &mut [Participant],
+    asks: &mut [Participant],
+    price: Price,
+) -> Vec<(Trade, Price)> {
+    pair_fills(
+        bids.iter_mut()
+            .filter(|bid| bid.order.price >= price)
+            .collect(),
+        asks.iter_mut()
+            .filter(|ask| ask.order.price <= price)
+            .collect(),
+        price,
+    )
+}
+
+/// Pairs bids with asks in the given order until either side runs out, returning each trade
+/// with the limit price of its bid.
+fn pair_fills(
+    mut bids: Vec<&mut Participant>,
+    mut asks: Vec<&mut Participant>,
+    price: Price,
+) -> Vec<(Trade, Price)> {
+    let mut fills = Vec::new();
+    let (mut b, mut a) = (0, 0);
+    while b < bids.len() && a < asks.len() {
+        let quantity = bids[b].remaining.min(asks[a].remaining);
+        if quantity > 0 {
+            let (bid, ask) = (&bids[b].order, &asks[a].order);
+            fills.push((
+                Trade {
+                    id: TradeId::default(),
+                    sequence: 0,
+                    ask_order_id: ask.id,
+                    bid_order_id: bid.id,
+                    ask_account_id: ask.account_id.clone(),
+                    bid_account_id: bid.account_id.clone(),
+                    price,
+                    quantity: Quantity::new(quantity),
+                    aggressor: None,
+                    ask_client_order_id: ask.client_order_id.clone(),
+                    bid_client_order_id: bid.client_order_id.clone(),
+                    ask_tag: ask.tag.clone(),
+                    bid_tag: bid.tag.clone(),
+                },
+                bid.price,
+            ));
+            bids[b].remaining -= quantity;
+            asks[a].remaining -= quantity;
+        }
+        if bids[b].remaining == 0 {
+            b += 1;
+        }
+        if asks[a].remaining == 0 {
+            a += 1;
+        }
+    }
+    fills
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::Market,
+        order::{AccountId, TimeInForce},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_closing_auction_imbalance_and_offsets() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        for name in ["alice", "bob", "carol"] {
+            exchange.add_balance(account(name), pair.base, 100);
+            exchange.add_balance(account(name), pair.numeraire, 10_000);
+        }
+        let order = |id: u64, price: u64, quantity: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+
+        exchange
+            .post_order(order(1, 101, 4, Side::Ask, "alice"), pair)
+            .unwrap();
+        assert!(
+            exchange
+                .post_auction_order(order(2, 102, 10, Side::Bid, "bob"), pair)
+                .is_err()
+        );
+        exchange.open_closing_auction(pair).unwrap();
+        exchange
+            .post_auction_order(order(2, 102, 10, Side::Bid, "bob"), pair)
+            .unwrap();
+        exchange
+            .post_auction_order(order(3, 100, 2, Side::Ask, "alice"), pair)
+            .unwrap();
+
+        // 6 of the 10 bid pair up at 102; 4 are left over on the bid side
+        let imbalance = exchange.auction_imbalance(pair).unwrap();
+        assert_eq!(imbalance.indicative_price, Some(Price::new(101)));
+        assert_eq!(imbalance.paired_quantity, Quantity::new(6));
+        assert_eq!(imbalance.imbalance_quantity, Quantity::new(4));
+        assert_eq!(imbalance.imbalance_side, Some(Side::Bid));
+
+        // Offsets must be asks, and only fill the imbalance
+        assert!(
+            exchange
+                .post_imbalance_offset(order(4, 101, 3, Side::Bid, "carol"), pair)
+                .is_err()
+        );
+        exchange
+            .post_imbalance_offset(order(5, 100, 5, Side::Ask, "carol"), pair)
+            .unwrap();
+        assert_eq!(exchange.auction_imbalance(pair), Some(imbalance));
+
+        let trades = exchange
+            .uncross_closing_auction(pair, Timestamp::new(10))
+            .unwrap();
+        assert!(trades.iter().all(|trade| trade.price == Price::new(101)));
+        assert!(trades.iter().all(|trade| trade.aggressor.is_none()));
+        let filled = |id: u64| -> u64 {
+            trades
+                .iter()
+                .filter(|t| {
+                    t.ask_order_id == OrderId::new(id) || t.bid_order_id == OrderId::new(id)
+                })
+                .map(|t| t.quantity.get())
+                .sum()
+        };
+        assert_eq!((filled(1), filled(2), filled(3), filled(5)), (4, 10, 2, 4));
+
+        // Carol sold 4 of her 5 offset and got the unfilled one back
+        assert_eq!(
+            exchange.get_balance(account("carol"), pair.base).unwrap(),
+            96
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("carol"), pair.numeraire)
+                .unwrap(),
+            10_404
+        );
+        assert_eq!(
+            exchange.get_balance(account("bob"), pair.base).unwrap(),
+            110
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("bob"), pair.numeraire)
+                .unwrap(),
+            8_990
+        );
+        assert_eq!(exchange.locked_balance(&account("alice"), pair.base), 0);
+        assert!(exchange.auction_imbalance(pair).is_none());
+    }
+
+    #[test]
+    fn test_opening_auction_uncrosses_pre_open_book() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        for name in ["alice", "bob", "carol", "dave"] {
+            exchange.add_balance(account(name), pair.base, 100);
+            exchange.add_balance(account(name), pair.numeraire, 10_000);
+        }
+        let order = |id: u64, price: u64, quantity: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+
+        exchange.open_opening_auction(pair).unwrap();
+        assert!(exchange.is_pre_open(pair));
+        assert!(exchange.open_closing_auction(pair).is_err());
+        for order in [
+            order(1, 100, 4, Side::Ask, "alice"),
+            order(2, 103, 3, Side::Bid, "bob"),
+            order(3, 101, 4, Side::Bid, "carol"),
+            order(4, 101, 2, Side::Ask, "dave"),
+            order(5, 102, 3, Side::Ask, "dave"),
+        ] {
+            let report = exchange.post_order(order, pair).unwrap();
+            assert!(report.fills.is_empty());
+        }
+        let error = exchange
+            .post_order(
+                Order {
+                    time_in_force: Some(TimeInForce::Ioc),
+                    ..order(6, 103, 1, Side::Bid, "bob")
+                },
+                pair,
+            )
+            .unwrap_err();
+        assert_eq!(RejectReason::of(&error), Some(RejectReason::PreOpen));
+
+        // The book is crossed until the open. Both 100 and 101 would have traded more than
+        // 102 or 103; 101 trades 6 and leaves the smaller imbalance
+        let book = exchange.orderbook(pair).unwrap();
+        assert!(book.bids[0].price > book.asks[0].price);
+        let imbalance = exchange.auction_imbalance(pair).unwrap();
+        assert_eq!(imbalance.indicative_price, Some(Price::new(101)));
+        assert_eq!(imbalance.paired_quantity, Quantity::new(6));
+        assert_eq!(
+            (imbalance.imbalance_quantity, imbalance.imbalance_side),
+            (Quantity::new(1), Some(Side::Bid))
+        );
+        assert!(
+            exchange
+                .uncross_closing_auction(pair, Timestamp::new(10))
+                .is_err()
+        );
+
+        let trades = exchange
+            .uncross_opening_auction(pair, Timestamp::new(10))
+            .unwrap();
+        assert!(trades.iter().all(|trade| trade.price == Price::new(101)));
+        let traded: u64 = trades.iter().map(|trade| trade.quantity.get()).sum();
+        assert_eq!(traded, 6);
+        assert!(!exchange.is_pre_open(pair));
+        let book = exchange.orderbook(pair).unwrap();
+        assert_eq!(
+            (book.bids[0].price, book.bids[0].quantity),
+            (Price::new(101), Quantity::new(1))
+        );
+        assert_eq!(book.asks[0].price, Price::new(102));
+        // Bob bought 3 at 101 and got back what he held at 103
+        assert_eq!(
+            exchange
+                .get_balance(account("bob"), pair.numeraire)
+                .unwrap(),
+            9_697
+        );
+
+        // Continuous trading has started
+        let report = exchange
+            .post_order(order(7, 102, 1, Side::Bid, "bob"), pair)
+            .unwrap();
+        assert_eq!(report.fills[0].price, Price::new(102));
+    }
+}
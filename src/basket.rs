@@ -0,0 +1,36 @@
+use anyhow::Result;
+
+use crate::asset::Asset;
+
+/// A basket instrument: a token backed by a fixed weighted set of constituent assets.
+///
+/// One unit of `token` is created by depositing `amount` of every constituent, and redeemed
+/// for the same amounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Basket {
+    pub token: Asset,
+    pub constituents: Vec<(Asset, u64)>,
+}
+
+impl Basket {
+    pub fn new(token: Asset, constituents: Vec<(Asset, u64)>) -> Self {
+        Self {
+            token,
+            constituents,
+        }
+    }
+
+    /// Returns the constituent amounts backing `units` of the basket token, or an error if
+    /// one of them does not fit in a `u64`.
+    pub fn constituents_for(&self, units: u64) -> Result<Vec<(Asset, u64)>> {
+        self.constituents
+            .iter()
+            .map(|(asset, amount)| {
+                let total = amount
+                    .checked_mul(units)
+                    .ok_or(anyhow::anyhow!("Basket units too large"))?;
+                Ok((*asset, total))
+            })
+            .collect()
+    }
+}
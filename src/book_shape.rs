@@ -0,0 +1,214 @@
+//! Synthetic books of configurable shape, for measuring how each book backend copes with
+//! them.
+//!
+//! The `book_shape_bench` binary runs the presets below against both backends; use it to
+//! pick the backend of a market whose book is expected to look like one of them.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    matching::MatchingEngine,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    orderbook::BookBackend,
+};
+
+/// The shape of a synthetic book, centred on a mid price with a one-gap spread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookShape {
+    pub name: String,
+    /// Number of occupied bid levels.
+    pub bid_levels: u64,
+    /// Number of occupied ask levels. Differs from `bid_levels` for skewed books.
+    pub ask_levels: u64,
+    /// Resting orders at each occupied level.
+    pub orders_per_level: u64,
+    /// Ticks between occupied levels. Large gaps make sparse books.
+    pub level_gap: u64,
+}
+
+/// Average time per operation on a book of some shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShapeTimings {
+    /// Posting a resting order.
+    pub insert: Duration,
+    /// Posting an order that fills against the best ask.
+    pub take: Duration,
+    /// Cancelling a resting bid.
+    pub cancel: Duration,
+    /// Posting one order that sweeps every ask level.
+    pub sweep: Duration,
+}
+
+impl ShapeTimings {
+    /// Total of the per-operation averages, used to rank backends.
+    pub fn total(&self) -> Duration {
+        self.insert + self.take + self.cancel + self.sweep
+    }
+}
+
+impl BookShape {
+    pub fn new(name: &str, levels: u64, orders_per_level: u64, level_gap: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            bid_levels: levels,
+            ask_levels: levels,
+            orders_per_level,
+            level_gap: level_gap.max(1),
+        }
+    }
+
+    /// A range of typical shapes: deep and thin books, a skewed book, and books with many
+    /// levels or many orders per level.
+    pub fn presets() -> Vec<BookShape> {
+        vec![
+            BookShape::new("deep", 500, 20, 1),
+            BookShape::new("thin", 20, 2, 50),
+            BookShape {
+                ask_levels: 20,
+                ..BookShape::new("skewed", 1_000, 5, 1)
+            },
+            BookShape::new("many-levels", 5_000, 1, 1),
+            BookShape::new("many-orders", 5, 2_000, 1),
+        ]
+    }
+
+    fn mid_price(&self) -> u64 {
+        (self.bid_levels + 1) * self.level_gap
+    }
+
+    /// The resting orders of the book, each of quantity one, interleaving the sides level by
+    /// level.
+    pub fn orders(&self) -> Vec<Order> {
+        let mid = self.mid_price();
+        let mut orders = Vec::new();
+        for level in 0..self.bid_levels.max(self.ask_levels) {
+            let offset = (level + 1) * self.level_gap;
+            for (side, levels, price) in [
+                (Side::Bid, self.bid_levels, mid - offset),
+                (Side::Ask, self.ask_levels, mid + offset),
+            ] {
+                if level >= levels {
+                    continue;
+                }
+                for _ in 0..self.orders_per_level {
+                    let id = orders.len() as u64 + 1;
+                    orders.push(Order::new(
+                        OrderId::new(id),
+                        Price::new(price),
+                        Quantity::new(1),
+                        side,
+                        AccountId::new("maker".to_string()),
+                        Timestamp::new(id),
+                    ));
+                }
+            }
+        }
+        orders
+    }
+
+    /// The smallest ladder holding every price of the book.
+    pub fn ladder_backend(&self) -> BookBackend {
+        let highest = self.mid_price() + self.ask_levels * self.level_gap;
+        BookBackend::Ladder {
+            min_price: Price::new(0),
+            tick_size: 1,
+            num_ticks: highest as usize + 1,
+        }
+    }
+
+    /// Build the book with the given backend.
+    pub fn build(&self, backend: BookBackend) -> MatchingEngine {
+        let mut engine = MatchingEngine::with_backend(backend);
+        for order in self.orders() {
+            engine.process_order(order);
+        }
+        engine
+    }
+
+    /// Time inserting the book, taking half of its asks one order at a time, then cancelling
+    /// every bid. The sweep is timed on a fresh copy of the book.
+    pub fn measure(&self, backend: BookBackend) -> ShapeTimings {
+        let orders = self.orders();
+        let mut engine = MatchingEngine::with_backend(backend);
+        let start = Instant::now();
+        for order in orders.iter().cloned() {
+            engine.process_order(order);
+        }
+        let insert = average(start.elapsed(), orders.len());
+
+        let takes = (self.ask_levels * self.orders_per_level).div_ceil(2);
+        let highest = Price::new(self.mid_price() + self.ask_levels * self.level_gap);
+        let start = Instant::now();
+        for i in 0..takes {
+            let id = orders.len() as u64 + 1 + i;
+            engine.process_order(Order::new(
+                OrderId::new(id),
+                highest,
+                Quantity::new(1),
+                Side::Bid,
+                AccountId::new("taker".to_string()),
+                Timestamp::new(id),
+            ));
+        }
+        let take = average(start.elapsed(), takes as usize);
+
+        let bids: Vec<&Order> = orders.iter().filter(|o| o.side == Side::Bid).collect();
+        let start = Instant::now();
+        for order in &bids {
+            engine.cancel_order(order.id, order.side, order.price);
+        }
+        let cancel = average(start.elapsed(), bids.len());
+
+        let mut engine = self.build(backend);
+        let id = orders.len() as u64 + 1;
+        let sweep = Order::new(
+            OrderId::new(id),
+            highest,
+            Quantity::new(self.ask_levels * self.orders_per_level),
+            Side::Bid,
+            AccountId::new("taker".to_string()),
+            Timestamp::new(id),
+        );
+        let start = Instant::now();
+        engine.process_order(sweep);
+        let sweep = start.elapsed();
+
+        ShapeTimings {
+            insert,
+            take,
+            cancel,
+            sweep,
+        }
+    }
+}
+
+fn average(elapsed: Duration, operations: usize) -> Duration {
+    elapsed / operations.max(1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shapes_build_identically_on_both_backends() {
+        let shape = BookShape {
+            ask_levels: 2,
+            ..BookShape::new("test", 4, 3, 5)
+        };
+        assert_eq!(shape.orders().len(), 18);
+
+        for backend in [BookBackend::BTree, shape.ladder_backend()] {
+            let engine = shape.build(backend);
+            let book = engine.orderbook();
+            assert_eq!(book.get_best_bid(), Some(20));
+            assert_eq!(book.get_best_ask(), Some(30));
+            assert_eq!(book.get_bids().count(), 4);
+            assert_eq!(book.get_asks().count(), 2);
+            assert!(
+                book.get_asks()
+                    .all(|(_, orders)| orders.len() as u64 == shape.orders_per_level)
+            );
+        }
+    }
+}
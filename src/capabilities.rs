@@ -0,0 +1,212 @@
+//! What an exchange supports, for clients that adapt to it rather than assume it.
+//!
+//! `Exchange::capabilities` lists the versions of the persisted formats this build writes
+//! and, for every market, the orders it currently accepts and how it matches them. The
+//! report reflects the market's state at the time, so a market in pre-open reports only the
+//! orders it takes until its opening auction uncrosses.
+
+use crate::{
+    auction::AuctionKind,
+    circuit_breaker::CircuitBreaker,
+    exchange::Exchange,
+    market::{MarketConfig, Pair},
+    migration::Format,
+    order::{OrderType, StopTrigger, TimeInForce},
+    price_band::PriceBand,
+};
+
+/// What one market accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketCapabilities {
+    pub pair: Pair,
+    /// Order types the market accepts.
+    pub order_types: Vec<OrderType>,
+    /// Times in force the market accepts.
+    pub time_in_force: Vec<TimeInForce>,
+    /// Prices stop orders can be triggered by, empty if stop orders are not accepted.
+    pub stop_triggers: Vec<StopTrigger>,
+    /// Whether pegged orders are accepted.
+    pub pegged_orders: bool,
+    /// Whether post-only orders are accepted.
+    pub post_only: bool,
+    /// Whether minimum-quantity and all-or-none orders are accepted.
+    pub conditional_quantities: bool,
+    /// Whether asks may borrow the base they lack from the lending pool.
+    pub short_selling: bool,
+    /// The auction the market is running, if any.
+    pub auction: Option<AuctionKind>,
+    /// The circuit breaker that halts the market, if it has one.
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// The bands limit prices must fall within, applied in order.
+    pub price_bands: Vec<PriceBand>,
+    /// The book backend and its price range, fees, lot handling and allocation. A custom
+    /// policy installed with `Market::set_policy` is not reflected in `allocation`.
+    pub config: MarketConfig,
+}
+
+/// A point-in-time report on what an exchange supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Version of the crate serving the exchange.
+    pub crate_version: &'static str,
+    /// Every persisted format with the version this build writes. Every earlier version of
+    /// a format can still be read.
+    pub formats: Vec<(Format, u32)>,
+    /// Every market, by numeraire then base symbol.
+    pub markets: Vec<MarketCapabilities>,
+}
+
+impl Capabilities {
+    /// What the market of a pair accepts, if it is listed.
+    pub fn market(&self, pair: Pair) -> Option<&MarketCapabilities> {
+        self.markets.iter().find(|market| market.pair == pair)
+    }
+}
+
+impl Exchange {
+    /// Report what the exchange and each of its markets support.
+    pub fn capabilities(&self) -> Capabilities {
+        let mut markets: Vec<MarketCapabilities> = self
+            .markets
+            .values()
+            .map(|market| {
+                let auction = self.auction_kind(market.pair);
+                // Pre-open only takes plain limit orders that can rest until the uncross
+                let pre_open = auction == Some(AuctionKind::Opening);
+                let (order_types, time_in_force, stop_triggers) = match pre_open {
+                    true => (vec![OrderType::Limit], vec![TimeInForce::Gtc], vec![]),
+                    false => (
+                        vec![OrderType::Limit, OrderType::Market],
+                        vec![TimeInForce::Gtc, TimeInForce::Ioc],
+                        vec![StopTrigger::LastTrade, StopTrigger::Index],
+                    ),
+                };
+                MarketCapabilities {
+                    pair: market.pair,
+                    order_types,
+                    time_in_force,
+                    stop_triggers,
+                    pegged_orders: !pre_open,
+                    post_only: !pre_open,
+                    conditional_quantities: !pre_open,
+                    short_selling: self.allows_short_selling(market.pair),
+                    auction,
+                    circuit_breaker: market.circuit_breaker(),
+                    price_bands: market.price_bands().to_vec(),
+                    config: market.config,
+                }
+            })
+            .collect();
+        markets.sort_by_key(|market| (market.pair.numeraire.symbol, market.pair.base.symbol));
+        Capabilities {
+            crate_version: env!("CARGO_PKG_VERSION"),
+            formats: [Format::Snapshot, Format::Witness, Format::Journal]
+                .into_iter()
+                .map(|format| (format, format.current_version()))
+                .collect(),
+            markets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::{Market, OddLots},
+        migration::read_header,
+        order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+        reject::RejectReason,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_capabilities_follow_market_state() {
+        let btc = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let eth = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let config = MarketConfig {
+            odd_lots: OddLots::Segregated { round_lot: 10 },
+            ..MarketConfig::default()
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(eth, config));
+        exchange.add_market(Market::new(btc));
+        exchange.set_short_selling(eth, true);
+        exchange.open_opening_auction(btc).unwrap();
+
+        let capabilities = exchange.capabilities();
+        assert_eq!(capabilities.crate_version, env!("CARGO_PKG_VERSION"));
+        let pairs: Vec<Pair> = capabilities.markets.iter().map(|m| m.pair).collect();
+        assert_eq!(pairs, vec![btc, eth]);
+        // Clients can check a file's version before loading it
+        let snapshot = exchange.snapshot().to_bytes();
+        let (version, _) = read_header(Format::Snapshot, &snapshot).unwrap();
+        assert_eq!(capabilities.formats[0], (Format::Snapshot, version));
+
+        let eth_market = capabilities.market(eth).unwrap();
+        assert_eq!(
+            eth_market.order_types,
+            vec![OrderType::Limit, OrderType::Market]
+        );
+        assert_eq!(eth_market.stop_triggers.len(), 2);
+        assert!(eth_market.pegged_orders && eth_market.short_selling);
+        assert_eq!(eth_market.config, config);
+        assert_eq!(eth_market.auction, None);
+        assert_eq!(eth_market.circuit_breaker, None);
+        assert!(eth_market.price_bands.is_empty());
+
+        // In pre-open the report matches what order entry accepts
+        let btc_market = capabilities.market(btc).unwrap();
+        assert_eq!(btc_market.order_types, vec![OrderType::Limit]);
+        assert_eq!(btc_market.time_in_force, vec![TimeInForce::Gtc]);
+        assert!(btc_market.stop_triggers.is_empty() && !btc_market.pegged_orders);
+        assert!(!btc_market.post_only);
+        assert_eq!(btc_market.auction, Some(AuctionKind::Opening));
+        let alice = AccountId::new("alice".to_string());
+        exchange.add_balance(alice.clone(), btc.numeraire, 100);
+        let market_order = Order::market(
+            OrderId::new(1),
+            Quantity::new(1),
+            Side::Bid,
+            alice.clone(),
+            Timestamp::new(1),
+        );
+        let error = exchange.post_order(market_order, btc).unwrap_err();
+        assert_eq!(RejectReason::of(&error), Some(RejectReason::PreOpen));
+        let limit = Order::new(
+            OrderId::new(2),
+            Price::new(10),
+            Quantity::new(1),
+            Side::Bid,
+            alice,
+            Timestamp::new(2),
+        );
+        exchange.post_order(limit, btc).unwrap();
+
+        exchange
+            .uncross_opening_auction(btc, Timestamp::new(3))
+            .unwrap();
+        let btc_market = exchange.capabilities().market(btc).cloned().unwrap();
+        assert_eq!(
+            btc_market.time_in_force,
+            vec![TimeInForce::Gtc, TimeInForce::Ioc]
+        );
+        assert_eq!(btc_market.auction, None);
+        assert!(
+            exchange
+                .capabilities()
+                .market(Pair {
+                    numeraire: eth.base,
+                    base: btc.base
+                })
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Circuit breakers: halting a market whose price moves too far too fast.
+//!
+//! A market with a `CircuitBreaker` compares the price of every trade it records with its
+//! reference price, the price it last traded at when the breaker's window began, or the
+//! first price it traded at within the window. A trade more than `max_move_bps` away from
+//! the reference trips the breaker, and the exchange halts the market by putting it back in
+//! pre-open: continuous orders are rejected, limit orders rest without matching, and stops
+//! triggered before the halt wait until the market reopens. `uncross_opening_auction`
+//! reopens it, and its uncross price becomes the new reference.
+//!
+//! The order whose trades tripped the breaker completes; the halt applies from the next
+//! order on, so an order group cannot have two legs in a market with a breaker. A market
+//! running its closing auction is not halted. Breakers are not part of snapshots.
+
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use crate::{
+    event::ExchangeEvent,
+    exchange::Exchange,
+    market::Pair,
+    order::{Price, Timestamp},
+};
+
+/// How far and how fast a market's price may move before the market is halted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// The largest move from the reference price allowed, in basis points of it.
+    pub max_move_bps: u64,
+    /// How far back the reference price is taken from, in timestamp units.
+    pub window: u64,
+}
+
+impl CircuitBreaker {
+    /// Returns true if `price` is further from `reference` than the breaker allows.
+    pub fn is_breached(&self, reference: Price, price: Price) -> bool {
+        let moved = reference.get().abs_diff(price.get()) as u128;
+        moved * 10_000 > reference.get() as u128 * self.max_move_bps as u128
+    }
+}
+
+/// A market's circuit breaker and the trade prices its reference is taken from.
+#[derive(Debug, Clone)]
+pub(crate) struct BreakerState {
+    pub(crate) breaker: CircuitBreaker,
+    /// The trades within the window, oldest first, after the last trade before it.
+    prices: VecDeque<(Timestamp, Price)>,
+    /// The reference price and the price of the trade that tripped the breaker, until the
+    /// exchange halts the market.
+    tripped: Option<(Price, Price)>,
+}
+
+impl BreakerState {
+    pub(crate) fn new(breaker: CircuitBreaker) -> Self {
+        Self {
+            breaker,
+            prices: VecDeque::new(),
+            tripped: None,
+        }
+    }
+
+    /// Record the price of a trade executed at `time`.
+    pub(crate) fn observe(&mut self, time: Timestamp, price: Price) {
+        let start = time.get().saturating_sub(self.breaker.window);
+        while self.prices.len() > 1 && self.prices[1].0.get() <= start {
+            self.prices.pop_front();
+        }
+        if let Some(&(_, reference)) = self.prices.front()
+            && self.tripped.is_none()
+            && self.breaker.is_breached(reference, price)
+        {
+            self.tripped = Some((reference, price));
+        }
+        self.prices.push_back((time, price));
+    }
+
+    /// Take the reference and trade price of a trip, if the breaker tripped, and start a
+    /// new reference from the next trade.
+    pub(crate) fn take_trip(&mut self) -> Option<(Price, Price)> {
+        let trip = self.tripped.take()?;
+        self.prices.clear();
+        Some(trip)
+    }
+
+    /// Forget the reference, so that the next trade sets a new one.
+    pub(crate) fn reset(&mut self) {
+        self.prices.clear();
+        self.tripped = None;
+    }
+}
+
+impl Exchange {
+    /// Set or remove the circuit breaker of a market
+    ///
+    /// A new breaker starts without a reference price, which the market's next trade sets.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market
+    /// * `breaker` - How far and how fast the market's price may move, or `None` to remove
+    ///   its breaker
+    pub fn set_circuit_breaker(
+        &mut self,
+        pair: Pair,
+        breaker: Option<CircuitBreaker>,
+    ) -> Result<()> {
+        let market = self
+            .markets
+            .get_mut(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        market.set_circuit_breaker(breaker);
+        Ok(())
+    }
+
+    /// Halt a market whose circuit breaker tripped by opening its opening auction, and
+    /// raise a `CircuitBreakerTripped` event.
+    pub(crate) fn check_circuit_breaker(&mut self, pair: Pair) {
+        let Some((reference_price, price)) = self
+            .markets
+            .get_mut(&pair)
+            .and_then(|market| market.take_breaker_trip())
+        else {
+            return;
+        };
+        if self.open_opening_auction(pair).is_ok() {
+            self.events.push(ExchangeEvent::CircuitBreakerTripped {
+                pair,
+                reference_price,
+                price,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        auction::AuctionKind,
+        market::Market,
+        order::{AccountId, Order, OrderId, Quantity, Side},
+        reject::RejectReason,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_reference_is_the_price_at_the_start_of_the_window() {
+        let breaker = CircuitBreaker {
+            max_move_bps: 1_000,
+            window: 10,
+        };
+        assert!(!breaker.is_breached(Price::new(100), Price::new(110)));
+        assert!(breaker.is_breached(Price::new(100), Price::new(89)));
+
+        // A slow drift never moves 10% within the window
+        let mut state = BreakerState::new(breaker);
+        for (time, price) in [(1, 100), (5, 108), (20, 116), (31, 125)] {
+            state.observe(Timestamp::new(time), Price::new(price));
+        }
+        assert_eq!(state.take_trip(), None);
+        // 116 was the last price when the window of this trade began
+        state.observe(Timestamp::new(35), Price::new(128));
+        assert_eq!(state.take_trip(), Some((Price::new(116), Price::new(128))));
+        // The next trade sets a new reference
+        state.observe(Timestamp::new(36), Price::new(140));
+        assert_eq!(state.take_trip(), None);
+    }
+
+    #[test]
+    fn test_breaker_halts_market_until_reopened() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 10);
+        exchange.add_balance(account("bob"), pair.numeraire, 10_000);
+        exchange.add_balance(account("carol"), pair.base, 1);
+        exchange
+            .set_circuit_breaker(
+                pair,
+                Some(CircuitBreaker {
+                    max_move_bps: 1_000,
+                    window: 100,
+                }),
+            )
+            .unwrap();
+        let limit = |id: u64, price: u64, quantity: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+        for order in [
+            limit(1, 100, 1, Side::Ask, "alice"),
+            limit(2, 105, 1, Side::Ask, "alice"),
+            limit(3, 120, 1, Side::Ask, "alice"),
+            limit(4, 100, 1, Side::Bid, "bob"),
+            // Bob's stop buys once the price reaches 105
+            Order {
+                stop_price: Some(Price::new(105)),
+                ..limit(5, 130, 1, Side::Bid, "bob")
+            },
+        ] {
+            exchange.post_order(order, pair).unwrap();
+        }
+
+        // A group's first leg could halt the market before its second is posted
+        let error = exchange
+            .post_order_group(vec![
+                (limit(10, 120, 1, Side::Bid, "bob"), pair),
+                (limit(11, 120, 1, Side::Bid, "bob"), pair),
+            ])
+            .unwrap_err();
+        assert!(error.to_string().contains("circuit breaker"));
+
+        // The sweep completes, but its trade at 120 trips the breaker
+        let report = exchange
+            .post_order(limit(6, 120, 2, Side::Bid, "bob"), pair)
+            .unwrap();
+        let prices: Vec<u64> = report.fills.iter().map(|t| t.price.get()).collect();
+        assert_eq!(prices, vec![105, 120]);
+        assert!(report.triggered.is_empty());
+        assert_eq!(exchange.auction_kind(pair), Some(AuctionKind::Opening));
+        assert!(
+            exchange
+                .drain_events()
+                .contains(&ExchangeEvent::CircuitBreakerTripped {
+                    pair,
+                    reference_price: Price::new(100),
+                    price: Price::new(120),
+                })
+        );
+        // The triggered stop waits for the market to reopen
+        assert_eq!(exchange.markets[&pair].pending_stops().len(), 1);
+
+        // Continuous orders are rejected; limit orders rest without matching
+        let market_order = Order::market(
+            OrderId::new(7),
+            Quantity::new(1),
+            Side::Ask,
+            account("carol"),
+            Timestamp::new(7),
+        );
+        let error = exchange.post_order(market_order, pair).unwrap_err();
+        assert_eq!(RejectReason::of(&error), Some(RejectReason::PreOpen));
+        exchange
+            .post_order(limit(8, 110, 1, Side::Ask, "carol"), pair)
+            .unwrap();
+        exchange
+            .post_order(limit(9, 112, 1, Side::Ask, "alice"), pair)
+            .unwrap();
+
+        // Reopening uncrosses nothing, then the stop fires against the reopened book and
+        // does not trip the breaker again
+        let trades = exchange
+            .uncross_opening_auction(pair, Timestamp::new(10))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Price::new(110));
+        assert_eq!(exchange.auction_kind(pair), None);
+        assert!(exchange.markets[&pair].pending_stops().is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+//! Sources of timestamps for orders placed with the exchange.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::order::Timestamp;
+
+/// Something that tells the time, in nanoseconds.
+pub trait Clock: Send {
+    /// The current time. Called once per order stamped.
+    fn now(&mut self) -> Timestamp;
+}
+
+/// Nanoseconds since the Unix epoch, as reported by the system.
+///
+/// The system time can jump backwards, e.g. when it is synchronised; use `MonotonicClock`
+/// when order timestamps must never decrease.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WallClock;
+
+impl Clock for WallClock {
+    fn now(&mut self) -> Timestamp {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Timestamp::new(u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX))
+    }
+}
+
+/// Nanoseconds since the clock was created. Never goes backwards.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&mut self) -> Timestamp {
+        Timestamp::new(u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX))
+    }
+}
+
+/// A deterministic clock for tests and simulations: it starts at a given time and moves
+/// forward by a fixed step each time it is read, or when advanced explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestClock {
+    now: u64,
+    step: u64,
+}
+
+impl TestClock {
+    pub fn new(start: u64, step: u64) -> Self {
+        Self { now: start, step }
+    }
+
+    /// Moves the clock forward without reading it.
+    pub fn advance(&mut self, by: u64) {
+        self.now = self.now.saturating_add(by);
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&mut self) -> Timestamp {
+        let now = Timestamp::new(self.now);
+        self.advance(self.step);
+        now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clocks() {
+        let mut clock = TestClock::new(10, 5);
+        assert_eq!(clock.now(), Timestamp::new(10));
+        assert_eq!(clock.now(), Timestamp::new(15));
+        clock.advance(100);
+        assert_eq!(clock.now(), Timestamp::new(120));
+
+        let mut clock = MonotonicClock::new();
+        let first = clock.now();
+        assert!(clock.now() >= first);
+        assert!(WallClock.now() > Timestamp::new(0));
+    }
+}
@@ -0,0 +1,816 @@
+//! Canonical binary encoding shared by snapshots, witnesses and commitments.
+//!
+//! Integers are fixed-width big-endian, strings are prefixed with their `u32` byte length
+//! and enums are a one-byte tag followed by their fields. Every value has exactly one
+//! encoding.
+//!
+//! Orders end with a list of optional fields, each a one-byte tag and a length-prefixed
+//! value. Fields are written in tag order and only when they differ from their default, so
+//! new order attributes can be added without changing the encoding of existing orders.
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    command::Command,
+    market::{FeeSchedule, FlatFee, MarketConfig, MinQtyShortfall, OddLots, Pair},
+    match_policy::Allocation,
+    matching::{SelfTradePrevention, Trade, TradeId},
+    order::{
+        AccountId, ClientOrderId, Order, OrderId, OrderTag, OrderType, Peg, PegReference, Price,
+        Quantity, Side, StopTrigger, TimeInForce, Timestamp,
+    },
+    orderbook::BookBackend,
+    witness::CommandOutcome,
+};
+
+/// Tag of the order type field: one byte, `1` for market orders.
+const ORDER_TYPE: u8 = 1;
+/// Tag of the time in force field: one byte, `0` for good-till-cancelled and `1` for
+/// immediate-or-cancel.
+const TIME_IN_FORCE: u8 = 2;
+/// Tag of the expiry field: the `u64` expiry timestamp.
+const EXPIRES_AT: u8 = 3;
+/// Tag of the stop price field: the `u64` stop price.
+const STOP_PRICE: u8 = 4;
+/// Tag of the peg field: the reference (`0` best bid, `1` best ask, `2` mid), the `i64`
+/// offset and, if capped, the `u64` cap.
+const PEG: u8 = 5;
+/// Tag of the minimum quantity field: the `u64` minimum fill quantity.
+const MIN_QTY: u8 = 6;
+/// Tag of the all-or-none flag, present with value `1` when set.
+const ALL_OR_NONE: u8 = 7;
+/// Tag of the reduce-only flag, present with value `1` when set.
+const REDUCE_ONLY: u8 = 8;
+/// Tag of the protection price field: the `u64` protection price.
+const PROTECTION_PRICE: u8 = 9;
+/// Tag of the client order ID field: the UTF-8 bytes of the ID.
+const CLIENT_ORDER_ID: u8 = 10;
+/// Tag of the order tag field: the UTF-8 bytes of the tag.
+const TAG: u8 = 11;
+/// Tag of the stop trigger field: one byte, `1` for the index price.
+const STOP_TRIGGER: u8 = 12;
+/// Tag of the post-only flag: one byte, `0` or `1`.
+const POST_ONLY: u8 = 13;
+/// Tag of the self-trade prevention field: one byte, `0` cancel newest, `1` cancel oldest,
+/// `2` decrement both.
+const SELF_TRADE_PREVENTION: u8 = 14;
+
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn str(&mut self, value: &str) {
+        self.buf
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    pub fn side(&mut self, side: Side) {
+        self.u8(match side {
+            Side::Bid => 0,
+            Side::Ask => 1,
+        });
+    }
+
+    pub fn pair(&mut self, pair: Pair) {
+        self.str(pair.base.symbol);
+        self.str(pair.numeraire.symbol);
+    }
+
+    pub fn order(&mut self, order: &Order) {
+        self.u64(order.id.get());
+        self.u64(order.price.get());
+        self.u64(order.quantity.get());
+        self.side(order.side);
+        self.str(order.account_id.as_str());
+        self.u64(order.timestamp.get());
+
+        let mut fields: Vec<(u8, Vec<u8>)> = Vec::new();
+        if order.order_type == OrderType::Market {
+            fields.push((ORDER_TYPE, vec![1]));
+        }
+        if let Some(time_in_force) = order.time_in_force {
+            let value = match time_in_force {
+                TimeInForce::Gtc => 0,
+                TimeInForce::Ioc => 1,
+            };
+            fields.push((TIME_IN_FORCE, vec![value]));
+        }
+        if let Some(expires_at) = order.expires_at {
+            fields.push((EXPIRES_AT, expires_at.get().to_be_bytes().to_vec()));
+        }
+        if let Some(stop_price) = order.stop_price {
+            fields.push((STOP_PRICE, stop_price.get().to_be_bytes().to_vec()));
+        }
+        if let Some(peg) = order.peg {
+            let mut value = vec![match peg.reference {
+                PegReference::BestBid => 0,
+                PegReference::BestAsk => 1,
+                PegReference::Mid => 2,
+            }];
+            value.extend(peg.offset.to_be_bytes());
+            if let Some(cap) = peg.cap {
+                value.extend(cap.get().to_be_bytes());
+            }
+            fields.push((PEG, value));
+        }
+        if let Some(min_qty) = order.min_qty {
+            fields.push((MIN_QTY, min_qty.get().to_be_bytes().to_vec()));
+        }
+        if order.all_or_none {
+            fields.push((ALL_OR_NONE, vec![1]));
+        }
+        if order.reduce_only {
+            fields.push((REDUCE_ONLY, vec![1]));
+        }
+        if let Some(protection_price) = order.protection_price {
+            fields.push((
+                PROTECTION_PRICE,
+                protection_price.get().to_be_bytes().to_vec(),
+            ));
+        }
+        if let Some(client_order_id) = &order.client_order_id {
+            fields.push((
+                CLIENT_ORDER_ID,
+                client_order_id.as_str().as_bytes().to_vec(),
+            ));
+        }
+        if let Some(tag) = &order.tag {
+            fields.push((TAG, tag.as_str().as_bytes().to_vec()));
+        }
+        if order.stop_trigger == StopTrigger::Index {
+            fields.push((STOP_TRIGGER, vec![1]));
+        }
+        if let Some(post_only) = order.post_only {
+            fields.push((POST_ONLY, vec![post_only as u8]));
+        }
+        if let Some(stp) = order.self_trade_prevention {
+            let value = match stp {
+                SelfTradePrevention::CancelNewest => 0,
+                SelfTradePrevention::CancelOldest => 1,
+                SelfTradePrevention::DecrementBoth => 2,
+            };
+            fields.push((SELF_TRADE_PREVENTION, vec![value]));
+        }
+        self.u8(fields.len() as u8);
+        for (tag, value) in fields {
+            self.u8(tag);
+            self.bytes(&value);
+        }
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.buf
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn trade(&mut self, trade: &Trade) {
+        self.u64(trade.ask_order_id.get());
+        self.u64(trade.bid_order_id.get());
+        self.str(trade.ask_account_id.as_str());
+        self.str(trade.bid_account_id.as_str());
+        self.u64(trade.price.get());
+        self.u64(trade.quantity.get());
+        match trade.aggressor {
+            None => self.u8(0),
+            Some(side) => {
+                self.u8(1);
+                self.side(side);
+            }
+        }
+        for client_order_id in [&trade.ask_client_order_id, &trade.bid_client_order_id] {
+            match client_order_id {
+                None => self.u8(0),
+                Some(id) => {
+                    self.u8(1);
+                    self.str(id.as_str());
+                }
+            }
+        }
+        for tag in [&trade.ask_tag, &trade.bid_tag] {
+            match tag {
+                None => self.u8(0),
+                Some(tag) => {
+                    self.u8(1);
+                    self.str(tag.as_str());
+                }
+            }
+        }
+        self.u64(trade.id.get());
+        self.u64(trade.sequence);
+    }
+
+    pub fn outcome(&mut self, outcome: &CommandOutcome) {
+        match outcome {
+            CommandOutcome::Rejected => self.u8(0),
+            CommandOutcome::Executed(trades) => {
+                self.u8(1);
+                self.len(trades.len());
+                for trade in trades {
+                    self.trade(trade);
+                }
+            }
+        }
+    }
+
+    pub fn market_config(&mut self, config: &MarketConfig) {
+        match config.book_backend {
+            BookBackend::BTree => self.u8(0),
+            BookBackend::Ladder {
+                min_price,
+                tick_size,
+                num_ticks,
+            } => {
+                self.u8(1);
+                self.u64(min_price.get());
+                self.u64(tick_size);
+                self.u64(num_ticks as u64);
+            }
+        }
+        self.u64(config.fees.maker_fee_bps);
+        self.u64(config.fees.taker_fee_bps);
+        self.bool(config.anonymize_public_trades);
+        self.u8(match config.min_qty_shortfall {
+            MinQtyShortfall::Reject => 0,
+            MinQtyShortfall::Rest => 1,
+        });
+        match config.fees.flat_fee {
+            None => self.u8(0),
+            Some(flat_fee) => {
+                self.u8(1);
+                self.str(flat_fee.asset.symbol);
+                self.u64(flat_fee.amount);
+            }
+        }
+        match config.odd_lots {
+            OddLots::Mixed => self.u8(0),
+            OddLots::Segregated { round_lot } => {
+                self.u8(1);
+                self.u64(round_lot);
+            }
+        }
+        match config.allocation {
+            Allocation::Fifo => self.u8(0),
+            Allocation::ProRata { min_allocation } => {
+                self.u8(1);
+                self.u64(min_allocation);
+            }
+            Allocation::SizePriority => self.u8(2),
+            Allocation::TopOrder { min_allocation } => {
+                self.u8(3);
+                self.u64(min_allocation);
+            }
+        }
+    }
+
+    pub fn command(&mut self, command: &Command) {
+        match command {
+            Command::Deposit {
+                account_id,
+                asset,
+                amount,
+            } => {
+                self.u8(0);
+                self.str(account_id.as_str());
+                self.str(asset.symbol);
+                self.u64(*amount);
+            }
+            Command::Withdraw {
+                account_id,
+                asset,
+                amount,
+            } => {
+                self.u8(1);
+                self.str(account_id.as_str());
+                self.str(asset.symbol);
+                self.u64(*amount);
+            }
+            Command::PostOrder { pair, order } => {
+                self.u8(2);
+                self.pair(*pair);
+                self.order(order);
+            }
+            Command::CancelOrder {
+                pair,
+                order_id,
+                side,
+                price,
+            } => {
+                self.u8(3);
+                self.pair(*pair);
+                self.u64(order_id.get());
+                self.side(*side);
+                self.u64(price.get());
+            }
+            Command::ExpireOrders { now } => {
+                self.u8(4);
+                self.u64(now.get());
+            }
+            Command::UpdateIndexPrice { pair, price } => {
+                self.u8(5);
+                self.pair(*pair);
+                self.u64(price.get());
+            }
+            Command::AmendOrder {
+                pair,
+                order_id,
+                side,
+                price,
+                new_price,
+                new_quantity,
+                timestamp,
+            } => {
+                self.u8(6);
+                self.pair(*pair);
+                self.u64(order_id.get());
+                self.side(*side);
+                self.u64(price.get());
+                self.u64(new_price.get());
+                self.u64(new_quantity.get());
+                self.u64(timestamp.get());
+            }
+            Command::ReduceOrder {
+                pair,
+                order_id,
+                side,
+                price,
+                quantity,
+            } => {
+                self.u8(7);
+                self.pair(*pair);
+                self.u64(order_id.get());
+                self.side(*side);
+                self.u64(price.get());
+                self.u64(quantity.get());
+            }
+            Command::CancelReplace {
+                pair,
+                order_id,
+                side,
+                price,
+                replacement,
+            } => {
+                self.u8(8);
+                self.pair(*pair);
+                self.u64(order_id.get());
+                self.side(*side);
+                self.u64(price.get());
+                self.order(replacement);
+            }
+            Command::PostOrderGroup { legs } => {
+                self.u8(9);
+                self.len(legs.len());
+                for (order, pair) in legs {
+                    self.pair(*pair);
+                    self.order(order);
+                }
+            }
+            Command::PlaceOrder { pair, order } => {
+                self.u8(10);
+                self.pair(*pair);
+                self.order(order);
+            }
+            Command::CreateBasketUnits {
+                account_id,
+                token,
+                units,
+            } => {
+                self.u8(11);
+                self.str(account_id.as_str());
+                self.str(token.symbol);
+                self.u64(*units);
+            }
+            Command::RedeemBasketUnits {
+                account_id,
+                token,
+                units,
+            } => {
+                self.u8(12);
+                self.str(account_id.as_str());
+                self.str(token.symbol);
+                self.u64(*units);
+            }
+            Command::Cross {
+                pair,
+                seller,
+                buyer,
+                price,
+                quantity,
+                timestamp,
+            } => {
+                self.u8(13);
+                self.pair(*pair);
+                self.str(seller.as_str());
+                self.str(buyer.as_str());
+                self.u64(price.get());
+                self.u64(quantity.get());
+                self.u64(timestamp.get());
+            }
+            Command::CloseAccount {
+                account_id,
+                dust_threshold,
+            } => {
+                self.u8(14);
+                self.str(account_id.as_str());
+                self.u64(*dust_threshold);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Fails if any input was left undecoded.
+    pub fn finish(self) -> Result<()> {
+        if !self.data.is_empty() {
+            return Err(anyhow::anyhow!("Trailing bytes after encoded value"));
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(anyhow::anyhow!("Unexpected end of input"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(anyhow::anyhow!("Invalid bool {}", tag)),
+        }
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = u32::from_be_bytes(self.take(4)?.try_into()?) as usize;
+        self.take(len)
+    }
+
+    pub fn str(&mut self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.bytes()?)?)
+    }
+
+    pub fn len(&mut self) -> Result<usize> {
+        let len = self.u64()?;
+        // Every element takes at least one byte, which bounds allocations on corrupt input
+        if len > self.data.len() as u64 {
+            return Err(anyhow::anyhow!("Invalid length {}", len));
+        }
+        Ok(len as usize)
+    }
+
+    pub fn side(&mut self) -> Result<Side> {
+        match self.u8()? {
+            0 => Ok(Side::Bid),
+            1 => Ok(Side::Ask),
+            tag => Err(anyhow::anyhow!("Invalid side {}", tag)),
+        }
+    }
+
+    pub fn asset(&mut self) -> Result<Asset> {
+        Ok(Asset::intern(self.str()?))
+    }
+
+    pub fn account_id(&mut self) -> Result<AccountId> {
+        Ok(AccountId::new(self.str()?.to_string()))
+    }
+
+    pub fn pair(&mut self) -> Result<Pair> {
+        let base = self.asset()?;
+        let numeraire = self.asset()?;
+        Ok(Pair { numeraire, base })
+    }
+
+    pub fn order(&mut self) -> Result<Order> {
+        let mut order = Order::new(
+            OrderId::new(self.u64()?),
+            Price::new(self.u64()?),
+            Quantity::new(self.u64()?),
+            self.side()?,
+            self.account_id()?,
+            Timestamp::new(self.u64()?),
+        );
+
+        let mut last_tag = 0;
+        for _ in 0..self.u8()? {
+            let tag = self.u8()?;
+            if tag <= last_tag {
+                return Err(anyhow::anyhow!("Order fields out of order"));
+            }
+            last_tag = tag;
+            let value = self.bytes()?;
+            match (tag, value) {
+                (ORDER_TYPE, [1]) => order.order_type = OrderType::Market,
+                (TIME_IN_FORCE, [0]) => order.time_in_force = Some(TimeInForce::Gtc),
+                (TIME_IN_FORCE, [1]) => order.time_in_force = Some(TimeInForce::Ioc),
+                (EXPIRES_AT, value) if value.len() == 8 => {
+                    order.expires_at = Some(Timestamp::new(u64::from_be_bytes(
+                        value.try_into().unwrap(),
+                    )));
+                }
+                (STOP_PRICE, value) if value.len() == 8 => {
+                    order.stop_price =
+                        Some(Price::new(u64::from_be_bytes(value.try_into().unwrap())));
+                }
+                (PEG, [reference, rest @ ..]) if rest.len() == 8 || rest.len() == 16 => {
+                    let reference = match reference {
+                        0 => PegReference::BestBid,
+                        1 => PegReference::BestAsk,
+                        2 => PegReference::Mid,
+                        _ => return Err(anyhow::anyhow!("Invalid peg reference {}", reference)),
+                    };
+                    let (offset, cap) = rest.split_at(8);
+                    order.peg = Some(Peg {
+                        reference,
+                        offset: i64::from_be_bytes(offset.try_into().unwrap()),
+                        cap: (!cap.is_empty())
+                            .then(|| Price::new(u64::from_be_bytes(cap.try_into().unwrap()))),
+                    });
+                }
+                (MIN_QTY, value) if value.len() == 8 => {
+                    order.min_qty =
+                        Some(Quantity::new(u64::from_be_bytes(value.try_into().unwrap())));
+                }
+                (ALL_OR_NONE, [1]) => order.all_or_none = true,
+                (REDUCE_ONLY, [1]) => order.reduce_only = true,
+                (PROTECTION_PRICE, value) if value.len() == 8 => {
+                    order.protection_price =
+                        Some(Price::new(u64::from_be_bytes(value.try_into().unwrap())));
+                }
+                (CLIENT_ORDER_ID, value) => {
+                    order.client_order_id =
+                        Some(ClientOrderId::new(std::str::from_utf8(value)?.to_string()));
+                }
+                (TAG, value) => {
+                    order.tag = Some(OrderTag::new(std::str::from_utf8(value)?.to_string()));
+                }
+                (STOP_TRIGGER, [1]) => order.stop_trigger = StopTrigger::Index,
+                (POST_ONLY, [value @ (0 | 1)]) => order.post_only = Some(*value == 1),
+                (SELF_TRADE_PREVENTION, [value]) => {
+                    order.self_trade_prevention = Some(match value {
+                        0 => SelfTradePrevention::CancelNewest,
+                        1 => SelfTradePrevention::CancelOldest,
+                        2 => SelfTradePrevention::DecrementBoth,
+                        _ => {
+                            return Err(anyhow::anyhow!("Invalid self-trade prevention {}", value));
+                        }
+                    });
+                }
+                _ => return Err(anyhow::anyhow!("Invalid order field {}", tag)),
+            }
+        }
+        Ok(order)
+    }
+
+    pub fn trade(&mut self) -> Result<Trade> {
+        Ok(Trade {
+            ask_order_id: OrderId::new(self.u64()?),
+            bid_order_id: OrderId::new(self.u64()?),
+            ask_account_id: self.account_id()?,
+            bid_account_id: self.account_id()?,
+            price: Price::new(self.u64()?),
+            quantity: Quantity::new(self.u64()?),
+            aggressor: match self.u8()? {
+                0 => None,
+                1 => Some(self.side()?),
+                tag => return Err(anyhow::anyhow!("Invalid aggressor {}", tag)),
+            },
+            ask_client_order_id: self.client_order_id()?,
+            bid_client_order_id: self.client_order_id()?,
+            ask_tag: self.tag()?,
+            bid_tag: self.tag()?,
+            id: TradeId::new(self.u64()?),
+            sequence: self.u64()?,
+        })
+    }
+
+    fn client_order_id(&mut self) -> Result<Option<ClientOrderId>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(ClientOrderId::new(self.str()?.to_string()))),
+            tag => Err(anyhow::anyhow!("Invalid client order ID tag {}", tag)),
+        }
+    }
+
+    fn tag(&mut self) -> Result<Option<OrderTag>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(OrderTag::new(self.str()?.to_string()))),
+            tag => Err(anyhow::anyhow!("Invalid order tag flag {}", tag)),
+        }
+    }
+
+    pub fn outcome(&mut self) -> Result<CommandOutcome> {
+        Ok(match self.u8()? {
+            0 => CommandOutcome::Rejected,
+            1 => {
+                let mut trades = Vec::new();
+                for _ in 0..self.len()? {
+                    trades.push(self.trade()?);
+                }
+                CommandOutcome::Executed(trades)
+            }
+            tag => return Err(anyhow::anyhow!("Invalid outcome {}", tag)),
+        })
+    }
+
+    pub fn market_config(&mut self) -> Result<MarketConfig> {
+        let book_backend = match self.u8()? {
+            0 => BookBackend::BTree,
+            1 => BookBackend::Ladder {
+                min_price: Price::new(self.u64()?),
+                tick_size: self.u64()?,
+                num_ticks: self.u64()? as usize,
+            },
+            tag => return Err(anyhow::anyhow!("Invalid book backend {}", tag)),
+        };
+        let (maker_fee_bps, taker_fee_bps) = (self.u64()?, self.u64()?);
+        let anonymize_public_trades = self.bool()?;
+        let min_qty_shortfall = match self.u8()? {
+            0 => MinQtyShortfall::Reject,
+            1 => MinQtyShortfall::Rest,
+            tag => return Err(anyhow::anyhow!("Invalid min quantity shortfall {}", tag)),
+        };
+        let flat_fee = match self.u8()? {
+            0 => None,
+            1 => Some(FlatFee {
+                asset: self.asset()?,
+                amount: self.u64()?,
+            }),
+            tag => return Err(anyhow::anyhow!("Invalid flat fee {}", tag)),
+        };
+        let odd_lots = match self.u8()? {
+            0 => OddLots::Mixed,
+            1 => OddLots::Segregated {
+                round_lot: self.u64()?,
+            },
+            tag => return Err(anyhow::anyhow!("Invalid odd lot handling {}", tag)),
+        };
+        let allocation = match self.u8()? {
+            0 => Allocation::Fifo,
+            1 => Allocation::ProRata {
+                min_allocation: self.u64()?,
+            },
+            2 => Allocation::SizePriority,
+            3 => Allocation::TopOrder {
+                min_allocation: self.u64()?,
+            },
+            tag => return Err(anyhow::anyhow!("Invalid allocation {}", tag)),
+        };
+        Ok(MarketConfig {
+            book_backend,
+            fees: FeeSchedule {
+                maker_fee_bps,
+                taker_fee_bps,
+                flat_fee,
+            },
+            anonymize_public_trades,
+            min_qty_shortfall,
+            odd_lots,
+            allocation,
+        })
+    }
+
+    pub fn command(&mut self) -> Result<Command> {
+        Ok(match self.u8()? {
+            0 => Command::Deposit {
+                account_id: self.account_id()?,
+                asset: self.asset()?,
+                amount: self.u64()?,
+            },
+            1 => Command::Withdraw {
+                account_id: self.account_id()?,
+                asset: self.asset()?,
+                amount: self.u64()?,
+            },
+            2 => Command::PostOrder {
+                pair: self.pair()?,
+                order: self.order()?,
+            },
+            3 => Command::CancelOrder {
+                pair: self.pair()?,
+                order_id: OrderId::new(self.u64()?),
+                side: self.side()?,
+                price: Price::new(self.u64()?),
+            },
+            4 => Command::ExpireOrders {
+                now: Timestamp::new(self.u64()?),
+            },
+            5 => Command::UpdateIndexPrice {
+                pair: self.pair()?,
+                price: Price::new(self.u64()?),
+            },
+            6 => Command::AmendOrder {
+                pair: self.pair()?,
+                order_id: OrderId::new(self.u64()?),
+                side: self.side()?,
+                price: Price::new(self.u64()?),
+                new_price: Price::new(self.u64()?),
+                new_quantity: Quantity::new(self.u64()?),
+                timestamp: Timestamp::new(self.u64()?),
+            },
+            7 => Command::ReduceOrder {
+                pair: self.pair()?,
+                order_id: OrderId::new(self.u64()?),
+                side: self.side()?,
+                price: Price::new(self.u64()?),
+                quantity: Quantity::new(self.u64()?),
+            },
+            8 => Command::CancelReplace {
+                pair: self.pair()?,
+                order_id: OrderId::new(self.u64()?),
+                side: self.side()?,
+                price: Price::new(self.u64()?),
+                replacement: self.order()?,
+            },
+            9 => {
+                let mut legs = Vec::new();
+                for _ in 0..self.len()? {
+                    let pair = self.pair()?;
+                    legs.push((self.order()?, pair));
+                }
+                Command::PostOrderGroup { legs }
+            }
+            10 => Command::PlaceOrder {
+                pair: self.pair()?,
+                order: self.order()?,
+            },
+            11 => Command::CreateBasketUnits {
+                account_id: self.account_id()?,
+                token: self.asset()?,
+                units: self.u64()?,
+            },
+            12 => Command::RedeemBasketUnits {
+                account_id: self.account_id()?,
+                token: self.asset()?,
+                units: self.u64()?,
+            },
+            13 => Command::Cross {
+                pair: self.pair()?,
+                seller: self.account_id()?,
+                buyer: self.account_id()?,
+                price: Price::new(self.u64()?),
+                quantity: Quantity::new(self.u64()?),
+                timestamp: Timestamp::new(self.u64()?),
+            },
+            14 => Command::CloseAccount {
+                account_id: self.account_id()?,
+                dust_threshold: self.u64()?,
+            },
+            tag => return Err(anyhow::anyhow!("Invalid command {}", tag)),
+        })
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    exchange::Exchange,
+    execution::ExecutionReport,
+    market::Pair,
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+};
+
+/// A state-changing request to the exchange.
+///
+/// Executing the same sequence of commands against the same starting state always produces
+/// the same trades and the same final state, which makes commands the unit of replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Deposit {
+        account_id: AccountId,
+        asset: Asset,
+        amount: u64,
+    },
+    Withdraw {
+        account_id: AccountId,
+        asset: Asset,
+        amount: u64,
+    },
+    PostOrder {
+        pair: Pair,
+        order: Order,
+    },
+    CancelOrder {
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+    },
+    /// Change the price or quantity of a resting order; see `Exchange::amend_order`.
+    AmendOrder {
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        new_price: Price,
+        new_quantity: Quantity,
+        timestamp: Timestamp,
+    },
+    /// Sweep the orders that expired by `now`.
+    ExpireOrders {
+        now: Timestamp,
+    },
+    /// Supply a market's index price, posting the index stops it triggers.
+    UpdateIndexPrice {
+        pair: Pair,
+        price: Price,
+    },
+    /// Shrink a resting order in place; see `Exchange::reduce_order`.
+    ReduceOrder {
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    },
+    /// Cancel a resting order and post its replacement; see `Exchange::cancel_replace`.
+    CancelReplace {
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        replacement: Order,
+    },
+    /// Post orders across markets atomically; see `Exchange::post_order_group`. The trades
+    /// of every leg are returned in leg order.
+    PostOrderGroup {
+        legs: Vec<(Order, Pair)>,
+    },
+    /// Post an order under an ID assigned by the exchange; see `Exchange::place_order`.
+    PlaceOrder {
+        pair: Pair,
+        order: Order,
+    },
+    CreateBasketUnits {
+        account_id: AccountId,
+        token: Asset,
+        units: u64,
+    },
+    RedeemBasketUnits {
+        account_id: AccountId,
+        token: Asset,
+        units: u64,
+    },
+    /// Cross base between affiliated accounts; see `Exchange::cross`.
+    Cross {
+        pair: Pair,
+        seller: AccountId,
+        buyer: AccountId,
+        price: Price,
+        quantity: Quantity,
+        timestamp: Timestamp,
+    },
+    CloseAccount {
+        account_id: AccountId,
+        dust_threshold: u64,
+    },
+}
+
+impl Exchange {
+    /// Execute a command, returning the trades it executed
+    ///
+    /// The command and its outcome are written to the command log, if enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute
+    pub fn execute(&mut self, command: Command) -> Result<Vec<Trade>> {
+        let logged = self.command_log.as_ref().map(|_| command.clone());
+        let result = self.apply(command);
+        if let (Some(log), Some(command)) = (&mut self.command_log, logged) {
+            log.record(&command, &result);
+        }
+        result
+    }
+
+    fn apply(&mut self, command: Command) -> Result<Vec<Trade>> {
+        match command {
+            Command::Deposit {
+                account_id,
+                asset,
+                amount,
+            } => self.deposit(account_id, asset, amount).map(|_| Vec::new()),
+            Command::Withdraw {
+                account_id,
+                asset,
+                amount,
+            } => self.withdraw(account_id, asset, amount).map(|_| Vec::new()),
+            Command::PostOrder { pair, order } => self
+                .post_order(order, pair)
+                .map(ExecutionReport::into_trades),
+            Command::CancelOrder {
+                pair,
+                order_id,
+                side,
+                price,
+            } => self
+                .cancel_order(order_id, price, side, pair)
+                .map(|_| Vec::new()),
+            Command::AmendOrder {
+                pair,
+                order_id,
+                side,
+                price,
+                new_price,
+                new_quantity,
+                timestamp,
+            } => self.amend_order(
+                order_id,
+                price,
+                side,
+                pair,
+                new_price,
+                new_quantity,
+                timestamp,
+            ),
+            Command::ExpireOrders { now } => {
+                self.expire_orders(now);
+                Ok(Vec::new())
+            }
+            Command::UpdateIndexPrice { pair, price } => self.update_index_price(pair, price),
+            Command::ReduceOrder {
+                pair,
+                order_id,
+                side,
+                price,
+                quantity,
+            } => self
+                .reduce_order(order_id, price, side, pair, quantity)
+                .map(|_| Vec::new()),
+            Command::CancelReplace {
+                pair,
+                order_id,
+                side,
+                price,
+                replacement,
+            } => self
+                .cancel_replace(order_id, price, side, pair, replacement)
+                .map(|(_, trades)| trades),
+            Command::PostOrderGroup { legs } => self
+                .post_order_group(legs)
+                .map(|(_, trades)| trades.into_iter().flatten().collect()),
+            Command::PlaceOrder { pair, order } => self
+                .place_order(order, pair)
+                .map(ExecutionReport::into_trades),
+            Command::CreateBasketUnits {
+                account_id,
+                token,
+                units,
+            } => self
+                .create_basket_units(account_id, token, units)
+                .map(|_| Vec::new()),
+            Command::RedeemBasketUnits {
+                account_id,
+                token,
+                units,
+            } => self
+                .redeem_basket_units(account_id, token, units)
+                .map(|_| Vec::new()),
+            Command::Cross {
+                pair,
+                seller,
+                buyer,
+                price,
+                quantity,
+                timestamp,
+            } => self
+                .cross(pair, seller, buyer, price, quantity, timestamp)
+                .map(|_| Vec::new()),
+            Command::CloseAccount {
+                account_id,
+                dust_threshold,
+            } => self
+                .close_account(account_id, dust_threshold)
+                .map(|_| Vec::new()),
+        }
+    }
+}
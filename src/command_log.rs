@@ -0,0 +1,403 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use crate::{
+    command::Command,
+    market::Pair,
+    matching::{SelfTradePrevention, Trade},
+    order::{Order, OrderType, PegReference, Side, StopTrigger, TimeInForce},
+};
+
+/// A JSON-lines log of every command executed by the exchange, for humans and log pipelines.
+///
+/// Each line records the command, whether it was accepted, the rejection reason or the
+/// trades it executed. The log is independent of the binary formats and is never read back.
+/// Sequence numbers count every executed command, so commands executed while the log is
+/// disabled show up as gaps. Write errors never fail a command; whether the last write failed
+/// is reported by `write_failed`.
+pub struct CommandLog {
+    writer: Box<dyn Write + Send>,
+    enabled: bool,
+    next_seq: u64,
+    write_failed: bool,
+}
+
+impl CommandLog {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            enabled: true,
+            next_seq: 0,
+            write_failed: false,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Sequence number of the next command logged, which is also the number of commands
+    /// executed so far.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Returns true if the last line could not be written.
+    pub fn write_failed(&self) -> bool {
+        self.write_failed
+    }
+
+    /// Start or stop writing lines, e.g. while investigating an incident.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Log a command and its outcome.
+    pub fn record(&mut self, command: &Command, result: &Result<Vec<Trade>>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if !self.enabled {
+            return;
+        }
+        let mut line = command_json(command);
+        line["seq"] = json!(seq);
+        match result {
+            Ok(trades) => {
+                line["outcome"] = json!("accepted");
+                line["trades"] = trades.iter().map(trade_json).collect();
+            }
+            Err(error) => {
+                line["outcome"] = json!("rejected");
+                line["reason"] = json!(error.to_string());
+            }
+        }
+        self.write_failed = writeln!(self.writer, "{}", line).is_err();
+    }
+}
+
+fn command_json(command: &Command) -> Value {
+    match command {
+        Command::Deposit {
+            account_id,
+            asset,
+            amount,
+        } => json!({
+            "command": "deposit",
+            "account_id": account_id.as_str(),
+            "asset": asset.symbol,
+            "amount": amount,
+        }),
+        Command::Withdraw {
+            account_id,
+            asset,
+            amount,
+        } => json!({
+            "command": "withdraw",
+            "account_id": account_id.as_str(),
+            "asset": asset.symbol,
+            "amount": amount,
+        }),
+        Command::PostOrder { pair, order } => with_order(
+            json!({
+                "command": "post_order",
+                "market": market(pair),
+            }),
+            order,
+        ),
+        Command::CancelOrder {
+            pair,
+            order_id,
+            side: order_side,
+            price,
+        } => json!({
+            "command": "cancel_order",
+            "market": market(pair),
+            "order_id": order_id.get(),
+            "side": side(*order_side),
+            "price": price.get(),
+        }),
+        Command::ExpireOrders { now } => json!({
+            "command": "expire_orders",
+            "now": now.get(),
+        }),
+        Command::AmendOrder {
+            pair,
+            order_id,
+            side: order_side,
+            price,
+            new_price,
+            new_quantity,
+            timestamp,
+        } => json!({
+            "command": "amend_order",
+            "market": market(pair),
+            "order_id": order_id.get(),
+            "side": side(*order_side),
+            "price": price.get(),
+            "new_price": new_price.get(),
+            "new_quantity": new_quantity.get(),
+            "timestamp": timestamp.get(),
+        }),
+        Command::UpdateIndexPrice { pair, price } => json!({
+            "command": "update_index_price",
+            "market": market(pair),
+            "price": price.get(),
+        }),
+        Command::ReduceOrder {
+            pair,
+            order_id,
+            side: order_side,
+            price,
+            quantity,
+        } => json!({
+            "command": "reduce_order",
+            "market": market(pair),
+            "order_id": order_id.get(),
+            "side": side(*order_side),
+            "price": price.get(),
+            "quantity": quantity.get(),
+        }),
+        Command::CancelReplace {
+            pair,
+            order_id,
+            side: order_side,
+            price,
+            replacement,
+        } => json!({
+            "command": "cancel_replace",
+            "market": market(pair),
+            "order_id": order_id.get(),
+            "side": side(*order_side),
+            "price": price.get(),
+            "replacement": order_json(replacement),
+        }),
+        Command::PostOrderGroup { legs } => json!({
+            "command": "post_order_group",
+            "legs": legs
+                .iter()
+                .map(|(order, pair)| with_order(json!({ "market": market(pair) }), order))
+                .collect::<Vec<_>>(),
+        }),
+        Command::PlaceOrder { pair, order } => with_order(
+            json!({
+                "command": "place_order",
+                "market": market(pair),
+            }),
+            order,
+        ),
+        Command::CreateBasketUnits {
+            account_id,
+            token,
+            units,
+        } => json!({
+            "command": "create_basket_units",
+            "account_id": account_id.as_str(),
+            "token": token.symbol,
+            "units": units,
+        }),
+        Command::RedeemBasketUnits {
+            account_id,
+            token,
+            units,
+        } => json!({
+            "command": "redeem_basket_units",
+            "account_id": account_id.as_str(),
+            "token": token.symbol,
+            "units": units,
+        }),
+        Command::Cross {
+            pair,
+            seller,
+            buyer,
+            price,
+            quantity,
+            timestamp,
+        } => json!({
+            "command": "cross",
+            "market": market(pair),
+            "seller": seller.as_str(),
+            "buyer": buyer.as_str(),
+            "price": price.get(),
+            "quantity": quantity.get(),
+            "timestamp": timestamp.get(),
+        }),
+        Command::CloseAccount {
+            account_id,
+            dust_threshold,
+        } => json!({
+            "command": "close_account",
+            "account_id": account_id.as_str(),
+            "dust_threshold": dust_threshold,
+        }),
+    }
+}
+
+/// Adds the fields of an order to the JSON of the command carrying it.
+fn with_order(mut line: Value, order: &Order) -> Value {
+    if let (Value::Object(line), Value::Object(fields)) = (&mut line, order_json(order)) {
+        line.extend(fields);
+    }
+    line
+}
+
+fn order_json(order: &Order) -> Value {
+    json!({
+        "order_id": order.id.get(),
+        "account_id": order.account_id.as_str(),
+        "side": side(order.side),
+        "type": match order.order_type {
+            OrderType::Limit => "limit",
+            OrderType::Market => "market",
+        },
+        "time_in_force": order.time_in_force.map(|time_in_force| match time_in_force {
+            TimeInForce::Gtc => "gtc",
+            TimeInForce::Ioc => "ioc",
+        }),
+        "post_only": order.post_only,
+        "price": order.price.get(),
+        "quantity": order.quantity.get(),
+        "stop_price": order.stop_price.map(|price| price.get()),
+        "stop_trigger": order.stop_price.map(|_| match order.stop_trigger {
+            StopTrigger::LastTrade => "last_trade",
+            StopTrigger::Index => "index",
+        }),
+        "min_qty": order.min_qty.map(|quantity| quantity.get()),
+        "all_or_none": order.all_or_none,
+        "reduce_only": order.reduce_only,
+        "protection_price": order.protection_price.map(|price| price.get()),
+        "client_order_id": order.client_order_id.as_ref().map(|id| id.as_str()),
+        "tag": order.tag.as_ref().map(|tag| tag.as_str()),
+        "self_trade_prevention": order.self_trade_prevention.map(|stp| match stp {
+            SelfTradePrevention::CancelNewest => "cancel_newest",
+            SelfTradePrevention::CancelOldest => "cancel_oldest",
+            SelfTradePrevention::DecrementBoth => "decrement_both",
+        }),
+        "peg": order.peg.map(|peg| json!({
+            "reference": match peg.reference {
+                PegReference::BestBid => "best_bid",
+                PegReference::BestAsk => "best_ask",
+                PegReference::Mid => "mid",
+            },
+            "offset": peg.offset,
+            "cap": peg.cap.map(|price| price.get()),
+        })),
+        "expires_at": order.expires_at.map(|time| time.get()),
+        "timestamp": order.timestamp.get(),
+    })
+}
+
+fn trade_json(trade: &Trade) -> Value {
+    json!({
+        "ask_order_id": trade.ask_order_id.get(),
+        "bid_order_id": trade.bid_order_id.get(),
+        "ask_client_order_id": trade.ask_client_order_id.as_ref().map(|id| id.as_str()),
+        "bid_client_order_id": trade.bid_client_order_id.as_ref().map(|id| id.as_str()),
+        "ask_tag": trade.ask_tag.as_ref().map(|tag| tag.as_str()),
+        "bid_tag": trade.bid_tag.as_ref().map(|tag| tag.as_str()),
+        "price": trade.price.get(),
+        "quantity": trade.quantity.get(),
+    })
+}
+
+fn market(pair: &Pair) -> String {
+    format!("{}/{}", pair.base.symbol, pair.numeraire.symbol)
+}
+
+fn side(side: Side) -> &'static str {
+    match side {
+        Side::Bid => "bid",
+        Side::Ask => "ask",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        asset::Asset,
+        exchange::Exchange,
+        market::Market,
+        order::{AccountId, Order, OrderId, Price, Quantity, Timestamp},
+    };
+
+    use super::*;
+
+    /// A writer whose output stays readable after it is moved into the log.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_command_log_records_outcomes() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let buffer = Buffer::default();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.command_log = Some(CommandLog::new(buffer.clone()));
+
+        let post = Command::PostOrder {
+            pair,
+            order: Order::new(
+                OrderId::new(1),
+                Price::new(100),
+                Quantity::new(5),
+                Side::Bid,
+                alice.clone(),
+                Timestamp::new(1),
+            ),
+        };
+        let deposit = Command::Deposit {
+            account_id: alice,
+            asset: pair.numeraire,
+            amount: 500,
+        };
+        assert!(exchange.execute(post.clone()).is_err());
+        exchange.command_log.as_mut().unwrap().set_enabled(false);
+        exchange.execute(deposit).unwrap();
+        exchange.command_log.as_mut().unwrap().set_enabled(true);
+        exchange.execute(post).unwrap();
+        exchange
+            .execute(Command::ReduceOrder {
+                pair,
+                order_id: OrderId::new(1),
+                side: Side::Bid,
+                price: Price::new(100),
+                quantity: Quantity::new(2),
+            })
+            .unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["seq"], 0);
+        assert_eq!(lines[0]["command"], "post_order");
+        assert_eq!(lines[0]["market"], "BTC/USD");
+        assert_eq!(lines[0]["outcome"], "rejected");
+        assert_eq!(lines[0]["reason"], "Account not found");
+        assert_eq!(lines[1]["seq"], 2);
+        assert_eq!(lines[1]["outcome"], "accepted");
+        assert_eq!(lines[1]["trades"], json!([]));
+        assert_eq!(lines[2]["command"], "reduce_order");
+        assert_eq!(lines[2]["quantity"], 2);
+        assert_eq!(lines[2]["outcome"], "accepted");
+    }
+}
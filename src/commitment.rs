@@ -0,0 +1,226 @@
+use crate::{asset::Asset, codec::Encoder, exchange::Exchange, order::AccountId};
+
+/// A 32-byte digest.
+pub type Digest = [u8; 32];
+
+/// The hash function used for state roots and proof-of-reserves commitments.
+///
+/// Implementations are feature-gated: `sha256` (the default), `keccak` and `poseidon`.
+/// Embedders can also provide their own.
+pub trait StateHasher {
+    /// Hashes arbitrary bytes.
+    fn hash(&self, data: &[u8]) -> Digest;
+
+    /// Hashes two child nodes of a Merkle tree.
+    fn hash_pair(&self, left: &Digest, right: &Digest) -> Digest {
+        let mut data = [0u8; 64];
+        data[..32].copy_from_slice(left);
+        data[32..].copy_from_slice(right);
+        self.hash(&data)
+    }
+}
+
+#[cfg(feature = "sha256")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sha256Hasher;
+
+#[cfg(feature = "sha256")]
+impl StateHasher for Sha256Hasher {
+    fn hash(&self, data: &[u8]) -> Digest {
+        use sha2::Digest as _;
+        sha2::Sha256::digest(data).into()
+    }
+}
+
+/// Keccak-256, as used by Ethereum.
+#[cfg(feature = "keccak")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KeccakHasher;
+
+#[cfg(feature = "keccak")]
+impl StateHasher for KeccakHasher {
+    fn hash(&self, data: &[u8]) -> Digest {
+        use sha3::Digest as _;
+        sha3::Keccak256::digest(data).into()
+    }
+}
+
+/// Circom-compatible Poseidon over the BN254 scalar field.
+///
+/// Merkle nodes are hashed as two field elements. Arbitrary bytes are absorbed in 31-byte
+/// chunks, each folded into the running digest with a two-input hash.
+#[cfg(feature = "poseidon")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PoseidonHasher;
+
+#[cfg(feature = "poseidon")]
+impl PoseidonHasher {
+    fn hash_two(left: &[u8], right: &[u8]) -> Digest {
+        use light_poseidon::{Poseidon, PoseidonBytesHasher};
+        // Both inputs are below the field modulus: chunks are 31 bytes and digests are
+        // field elements.
+        Poseidon::<ark_bn254::Fr>::new_circom(2)
+            .and_then(|mut poseidon| poseidon.hash_bytes_be(&[left, right]))
+            .expect("Poseidon inputs are valid field elements")
+    }
+}
+
+#[cfg(feature = "poseidon")]
+impl StateHasher for PoseidonHasher {
+    fn hash(&self, data: &[u8]) -> Digest {
+        // Domain-separate by length so that trailing zero bytes change the digest
+        let mut digest = Self::hash_two(&[0], &(data.len() as u64).to_be_bytes());
+        for chunk in data.chunks(31) {
+            digest = Self::hash_two(&digest, chunk);
+        }
+        digest
+    }
+
+    fn hash_pair(&self, left: &Digest, right: &Digest) -> Digest {
+        Self::hash_two(left, right)
+    }
+}
+
+/// Computes the root of a binary Merkle tree over `leaves`.
+///
+/// Odd nodes are paired with themselves. The root of an empty tree is the hash of no bytes.
+pub fn merkle_root<H: StateHasher + ?Sized>(hasher: &H, leaves: &[Digest]) -> Digest {
+    if leaves.is_empty() {
+        return hasher.hash(&[]);
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| hasher.hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level[0]
+}
+
+/// A commitment to the total liabilities of the exchange in one asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReservesCommitment {
+    pub asset: Asset,
+    /// Merkle root over one leaf per account committing to its hashed ID and liability.
+    pub root: Digest,
+    /// Sum of all account liabilities, to be compared against the exchange's reserves.
+    pub total_liabilities: u128,
+}
+
+impl Exchange {
+    /// Computes a commitment to every balance and resting order of the exchange.
+    ///
+    /// Leaves are canonically encoded and sorted, so the root only depends on the state and
+    /// not on hash map iteration order.
+    pub fn state_root<H: StateHasher + ?Sized>(&self, hasher: &H) -> Digest {
+        let mut encoded: Vec<Vec<u8>> = Vec::new();
+        for (account_id, asset, amount) in self.account_manager.balances() {
+            let mut leaf = Encoder::new();
+            leaf.u8(0);
+            leaf.str(account_id.as_str());
+            leaf.str(asset.symbol);
+            leaf.u64(amount);
+            encoded.push(leaf.finish());
+        }
+        for (pair, market) in &self.markets {
+            for order in market.resting_orders() {
+                let mut leaf = Encoder::new();
+                leaf.u8(1);
+                leaf.pair(*pair);
+                leaf.order(order);
+                encoded.push(leaf.finish());
+            }
+        }
+        encoded.sort();
+        let leaves: Vec<Digest> = encoded.iter().map(|leaf| hasher.hash(leaf)).collect();
+        merkle_root(hasher, &leaves)
+    }
+
+    /// Computes a proof-of-reserves commitment to what the exchange owes its accounts in an
+    /// asset: available balances plus funds locked by resting orders.
+    ///
+    /// Account IDs are hashed into their leaves so the tree can be published without
+    /// revealing them.
+    pub fn reserves_commitment<H: StateHasher + ?Sized>(
+        &self,
+        asset: Asset,
+        hasher: &H,
+    ) -> ReservesCommitment {
+        let mut accounts: Vec<&AccountId> = self
+            .account_manager
+            .balances()
+            .filter(|(_, a, _)| *a == asset)
+            .map(|(account_id, _, _)| account_id)
+            .collect();
+        accounts.sort();
+
+        let mut total_liabilities = 0u128;
+        let mut leaves = Vec::with_capacity(accounts.len());
+        for account_id in accounts {
+            let liability = self.get_balance(account_id.clone(), asset).unwrap_or(0)
+                + self.locked_balance(account_id, asset);
+            total_liabilities += liability as u128;
+            let mut leaf = hasher.hash(account_id.as_str().as_bytes()).to_vec();
+            leaf.extend_from_slice(&liability.to_be_bytes());
+            leaves.push(hasher.hash(&leaf));
+        }
+        ReservesCommitment {
+            asset,
+            root: merkle_root(hasher, &leaves),
+            total_liabilities,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sha256"))]
+mod tests {
+    use crate::{
+        market::{Market, Pair},
+        order::{Order, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_state_root_is_canonical() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let build = |accounts: &[&str]| {
+            let mut exchange = Exchange::new();
+            exchange.add_market(Market::new(pair));
+            for name in accounts {
+                exchange.add_balance(AccountId::new(name.to_string()), pair.numeraire, 1_000);
+            }
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(1),
+                        Price::new(100),
+                        Quantity::new(2),
+                        Side::Bid,
+                        AccountId::new("alice".to_string()),
+                        Timestamp::new(1),
+                    ),
+                    pair,
+                )
+                .unwrap();
+            exchange
+        };
+
+        // Insertion order does not matter
+        let a = build(&["alice", "bob", "carol"]);
+        let b = build(&["carol", "bob", "alice"]);
+        assert_eq!(a.state_root(&Sha256Hasher), b.state_root(&Sha256Hasher));
+
+        let mut c = build(&["alice", "bob", "carol"]);
+        c.add_balance(AccountId::new("bob".to_string()), pair.numeraire, 1);
+        assert_ne!(a.state_root(&Sha256Hasher), c.state_root(&Sha256Hasher));
+
+        // Locked funds still count as liabilities
+        let reserves = a.reserves_commitment(pair.numeraire, &Sha256Hasher);
+        assert_eq!(reserves.total_liabilities, 3_000);
+    }
+}
@@ -0,0 +1,184 @@
+//! Edge-case corpora of prices and quantities, for boundary and overflow tests.
+//!
+//! The corpora cover zero, one, the powers of two around the square root of `u64::MAX`,
+//! and the largest values, and pair every price with the largest quantity whose notional
+//! still fits in a `u64` and the smallest one whose notional does not. Tests that add
+//! arithmetic on prices or quantities should feed it the corpora rather than pick their own
+//! large values.
+//!
+//! The exchange rejects orders whose hold does not fit in a `u64`, and every amount an
+//! accepted order settles is bounded by its hold. Balances are only bounded as long as the
+//! total of each asset held on the exchange fits in a `u64`, so tests fund accounts within
+//! that total.
+
+use crate::order::{Price, Quantity};
+
+/// Values around every boundary of `u64` arithmetic.
+pub(crate) fn values() -> Vec<u64> {
+    vec![
+        0,
+        1,
+        2,
+        10_000,
+        u64::from(u32::MAX),
+        1 << 32,
+        u64::MAX / 10_000,
+        u64::MAX / 2,
+        u64::MAX - 1,
+        u64::MAX,
+    ]
+}
+
+pub(crate) fn prices() -> Vec<Price> {
+    values().into_iter().map(Price::new).collect()
+}
+
+pub(crate) fn quantities() -> Vec<Quantity> {
+    values().into_iter().map(Quantity::new).collect()
+}
+
+/// Every price of the corpus with the largest quantity whose notional fits in a `u64` and
+/// the smallest one whose notional does not.
+pub(crate) fn notional_boundaries() -> Vec<(Price, Quantity)> {
+    let mut cases = Vec::new();
+    for price in prices().into_iter().filter(|price| price.get() > 0) {
+        let largest = u64::MAX / price.get();
+        cases.push((price, Quantity::new(largest)));
+        if let Some(overflowing) = largest.checked_add(1) {
+            cases.push((price, Quantity::new(overflowing)));
+        }
+    }
+    cases
+}
+
+/// Every combination of a corpus price and a corpus quantity, then the notional boundaries.
+pub(crate) fn price_quantity_pairs() -> Vec<(Price, Quantity)> {
+    let mut cases: Vec<(Price, Quantity)> = prices()
+        .into_iter()
+        .flat_map(|price| {
+            quantities()
+                .into_iter()
+                .map(move |quantity| (price, quantity))
+        })
+        .collect();
+    cases.extend(notional_boundaries());
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        exchange::Exchange,
+        market::{FeeSchedule, Market, MarketConfig, Pair},
+        order::{AccountId, Order, OrderId, OrderType, Side, TimeInForce, Timestamp},
+        orderbook::BookBackend,
+        reject::RejectReason,
+    };
+
+    use super::*;
+
+    fn pair() -> Pair {
+        Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        }
+    }
+
+    /// Markets with and without fees, on both book backends.
+    fn configs() -> Vec<MarketConfig> {
+        let fees = FeeSchedule {
+            maker_fee_bps: 10,
+            taker_fee_bps: 30,
+            flat_fee: None,
+        };
+        let ladder = BookBackend::Ladder {
+            min_price: Price::new(u64::MAX - 100),
+            tick_size: 1,
+            num_ticks: 101,
+        };
+        [BookBackend::BTree, ladder]
+            .into_iter()
+            .flat_map(|book_backend| {
+                [FeeSchedule::default(), fees].map(|fees| MarketConfig {
+                    book_backend,
+                    fees,
+                    ..MarketConfig::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Total of every balance and hold of an asset.
+    fn supply(exchange: &Exchange, accounts: &[AccountId], asset: Asset) -> u128 {
+        accounts
+            .iter()
+            .map(|account| {
+                exchange.get_balance(account.clone(), asset).unwrap_or(0) as u128
+                    + exchange.locked_balance(account, asset) as u128
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_corpus_orders_are_matched_or_rejected_without_overflow() {
+        let pair = pair();
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let fees = crate::account::SystemAccount::Fees.id();
+        let accounts = [alice.clone(), bob.clone(), fees];
+        for config in configs() {
+            for (price, quantity) in price_quantity_pairs() {
+                for bid_price in prices() {
+                    let mut exchange = Exchange::new();
+                    exchange.add_market(Market::with_config(pair, config));
+                    exchange
+                        .deposit(alice.clone(), pair.base, u64::MAX)
+                        .unwrap();
+                    exchange
+                        .deposit(bob.clone(), pair.numeraire, u64::MAX)
+                        .unwrap();
+                    assert!(exchange.deposit(bob.clone(), pair.numeraire, 1).is_err());
+                    let order = |id: u64, price: Price, side: Side, account: &AccountId| {
+                        Order::new(
+                            OrderId::new(id),
+                            price,
+                            quantity,
+                            side,
+                            account.clone(),
+                            Timestamp::new(id),
+                        )
+                    };
+                    // The bid takes the resting ask at its price or rests against it, and
+                    // the last two orders take whatever is left on either side
+                    let orders = [
+                        order(1, price, Side::Ask, &alice),
+                        order(2, bid_price, Side::Bid, &bob),
+                        Order {
+                            order_type: OrderType::Market,
+                            ..order(3, price, Side::Bid, &bob)
+                        },
+                        Order {
+                            time_in_force: Some(TimeInForce::Ioc),
+                            ..order(4, price, Side::Ask, &alice)
+                        },
+                    ];
+                    let case = format!("{price:?} x {quantity:?} against {bid_price:?}");
+                    for order in orders {
+                        if let Err(error) = exchange.post_order(order, pair) {
+                            assert!(RejectReason::of(&error).is_some(), "{case}: {error}");
+                        }
+                    }
+                    exchange.orderbook(pair).unwrap();
+                    for asset in [pair.base, pair.numeraire] {
+                        assert_eq!(
+                            supply(&exchange, &accounts, asset),
+                            u64::MAX as u128,
+                            "{case}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Internal crosses between accounts of the same beneficial owner.
+//!
+//! A cross moves base against numeraire between two affiliated accounts at a reference
+//! price, without touching the book and without fees. Crosses are printed on the market's
+//! tape apart from its trades, so they never set the last trade price or trigger stops.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{
+    account::SystemAccount,
+    exchange::Exchange,
+    market::Pair,
+    order::{AccountId, Price, Quantity, Timestamp},
+};
+
+/// A transfer of base from `seller` to `buyer` against numeraire, as printed on the tape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cross {
+    pub seller: AccountId,
+    pub buyer: AccountId,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub timestamp: Timestamp,
+}
+
+/// The beneficial owner of each account that has one other than itself.
+#[derive(Debug, Default)]
+pub(crate) struct Affiliations {
+    owners: HashMap<AccountId, AccountId>,
+}
+
+impl Exchange {
+    /// Set or clear the beneficial owner of an account
+    ///
+    /// Accounts with the same beneficial owner are affiliated and may cross with each
+    /// other. An account without one is its own beneficial owner.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `owner` - The beneficial owner, or `None` to make the account its own
+    pub fn set_beneficial_owner(&mut self, account_id: AccountId, owner: Option<AccountId>) {
+        match owner {
+            Some(owner) if owner != account_id => {
+                self.affiliations.owners.insert(account_id, owner);
+            }
+            _ => {
+                self.affiliations.owners.remove(&account_id);
+            }
+        }
+    }
+
+    /// Returns the beneficial owner of an account.
+    pub fn beneficial_owner<'a>(&'a self, account_id: &'a AccountId) -> &'a AccountId {
+        self.affiliations
+            .owners
+            .get(account_id)
+            .unwrap_or(account_id)
+    }
+
+    /// Returns true if the accounts have the same beneficial owner.
+    pub fn are_affiliated(&self, a: &AccountId, b: &AccountId) -> bool {
+        self.beneficial_owner(a) == self.beneficial_owner(b)
+    }
+
+    /// Cross base from one account to an affiliated one at a reference price
+    ///
+    /// The seller's available base and the buyer's available numeraire are exchanged
+    /// directly, free of fees. The price must lie within the market's best bid and ask, so
+    /// a cross can never be priced through the book. The cross updates positions and is
+    /// printed on the market's tape of crosses.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market to cross in
+    /// * `seller` - The account giving up base
+    /// * `buyer` - The account receiving base, the seller itself or an affiliate
+    /// * `price` - The reference price
+    /// * `quantity` - The quantity of base to cross
+    /// * `timestamp` - The time of the cross
+    pub fn cross(
+        &mut self,
+        pair: Pair,
+        seller: AccountId,
+        buyer: AccountId,
+        price: Price,
+        quantity: Quantity,
+        timestamp: Timestamp,
+    ) -> Result<Cross> {
+        let market = self
+            .markets
+            .get(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        for account_id in [&seller, &buyer] {
+            if self.account_manager.is_closed(account_id) {
+                return Err(anyhow::anyhow!("Account closed"));
+            }
+            if SystemAccount::from_id(account_id).is_some() {
+                return Err(anyhow::anyhow!("System accounts cannot trade"));
+            }
+        }
+        if !self.are_affiliated(&seller, &buyer) {
+            return Err(anyhow::anyhow!("Accounts do not share a beneficial owner"));
+        }
+        if quantity.get() == 0 {
+            return Err(anyhow::anyhow!("Cross quantity must be positive"));
+        }
+        if !market.supports_price(price) {
+            return Err(anyhow::anyhow!("Price not supported by market"));
+        }
+        let book = market.matching_engine.orderbook();
+        if book.get_best_bid().is_some_and(|bid| price.get() < bid)
+            || book.get_best_ask().is_some_and(|ask| price.get() > ask)
+        {
+            return Err(anyhow::anyhow!(
+                "Cross price must be within the best bid and ask"
+            ));
+        }
+        let notional = quantity
+            .get()
+            .checked_mul(price.get())
+            .ok_or(anyhow::anyhow!("Cross notional overflows"))?;
+        if self.get_balance(seller.clone(), pair.base).unwrap_or(0) < quantity.get()
+            || self.get_balance(buyer.clone(), pair.numeraire).unwrap_or(0) < notional
+        {
+            return Err(anyhow::anyhow!("Insufficient balance"));
+        }
+
+        self.remove_balance(seller.clone(), pair.base, quantity.get())?;
+        self.remove_balance(buyer.clone(), pair.numeraire, notional)?;
+        self.add_balance(buyer.clone(), pair.base, quantity.get());
+        self.add_balance(seller.clone(), pair.numeraire, notional);
+        *self.positions.entry((buyer.clone(), pair)).or_default() += quantity.get() as i64;
+        *self.positions.entry((seller.clone(), pair)).or_default() -= quantity.get() as i64;
+
+        let cross = Cross {
+            seller,
+            buyer,
+            price,
+            quantity,
+            timestamp,
+        };
+        self.markets
+            .get_mut(&pair)
+            .unwrap()
+            .record_cross(cross.clone());
+        Ok(cross)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::Market,
+        order::{Order, OrderId, Side},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_cross_between_affiliates() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let carol = AccountId::new("carol".to_string());
+        let fund = AccountId::new("fund".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.base, 5);
+        exchange.add_balance(bob.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(carol.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(carol.clone(), pair.base, 5);
+        for (id, price, side) in [(1, 95, Side::Bid), (2, 105, Side::Ask)] {
+            let order = Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                carol.clone(),
+                Timestamp::new(id),
+            );
+            exchange.post_order(order, pair).unwrap();
+        }
+        exchange.set_beneficial_owner(alice.clone(), Some(fund.clone()));
+        exchange.set_beneficial_owner(bob.clone(), Some(fund.clone()));
+        let cross = |exchange: &mut Exchange, buyer: &AccountId, price: u64, quantity: u64| {
+            exchange.cross(
+                pair,
+                alice.clone(),
+                buyer.clone(),
+                Price::new(price),
+                Quantity::new(quantity),
+                Timestamp::new(10),
+            )
+        };
+
+        assert!(cross(&mut exchange, &carol, 100, 3).is_err());
+        // Priced through the book
+        assert!(cross(&mut exchange, &bob, 110, 3).is_err());
+        assert!(cross(&mut exchange, &bob, 100, 6).is_err());
+        cross(&mut exchange, &bob, 100, 3).unwrap();
+
+        // No fees, no trades
+        assert_eq!(
+            exchange.get_balance(alice.clone(), pair.numeraire).unwrap(),
+            300
+        );
+        assert_eq!(
+            exchange.get_balance(bob.clone(), pair.numeraire).unwrap(),
+            700
+        );
+        assert_eq!(exchange.get_balance(bob.clone(), pair.base).unwrap(), 3);
+        assert_eq!(exchange.position(&alice, pair), -3);
+        let market = &exchange.markets[&pair];
+        assert!(market.trades().is_empty());
+        assert_eq!(market.last_trade_price(), None);
+        assert_eq!(market.crosses().len(), 1);
+        assert_eq!(market.crosses()[0].buyer, bob);
+
+        // Affiliations can be withdrawn
+        exchange.set_beneficial_owner(bob.clone(), None);
+        assert!(!exchange.are_affiliated(&alice, &bob));
+        assert!(cross(&mut exchange, &bob, 100, 1).is_err());
+    }
+}
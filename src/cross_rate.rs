@@ -0,0 +1,179 @@
+//! Conversion rates between assets, implied by the books of the exchange's markets.
+//!
+//! Rates come from book midpoints rather than last trade prices, so they are part of the
+//! state captured by snapshots and replay to the same values.
+
+use crate::{asset::Asset, exchange::Exchange, market::Pair};
+
+/// The value of one unit of `from` in units of `to`, as an exact fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrossRate {
+    pub from: Asset,
+    pub to: Asset,
+    numerator: u128,
+    denominator: u128,
+}
+
+impl CrossRate {
+    /// The rate between an asset and itself.
+    pub fn identity(asset: Asset) -> Self {
+        Self {
+            from: asset,
+            to: asset,
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+
+    /// The rate from the base to the numeraire of a market whose book has both sides.
+    fn midpoint(exchange: &Exchange, pair: Pair) -> Option<Self> {
+        let book = exchange.markets.get(&pair)?.matching_engine.orderbook();
+        let (bid, ask) = (book.get_best_bid()?, book.get_best_ask()?);
+        Some(Self {
+            from: pair.base,
+            to: pair.numeraire,
+            numerator: bid as u128 + ask as u128,
+            denominator: 2,
+        })
+        .filter(|rate| rate.numerator > 0)
+    }
+
+    fn inverse(self) -> Self {
+        Self {
+            from: self.to,
+            to: self.from,
+            numerator: self.denominator,
+            denominator: self.numerator,
+        }
+    }
+
+    /// Chains this rate with one from `self.to` onward, or `None` on overflow.
+    fn then(self, next: Self) -> Option<Self> {
+        let numerator = self.numerator.checked_mul(next.numerator)?;
+        let denominator = self.denominator.checked_mul(next.denominator)?;
+        let gcd = gcd(numerator, denominator);
+        Some(Self {
+            from: self.from,
+            to: next.to,
+            numerator: numerator / gcd,
+            denominator: denominator / gcd,
+        })
+    }
+
+    /// Converts an amount of `from` into `to`, rounding up. Returns `None` if the result does
+    /// not fit in a `u64`.
+    pub fn convert_up(&self, amount: u64) -> Option<u64> {
+        let scaled = (amount as u128).checked_mul(self.numerator)?;
+        u64::try_from(scaled.div_ceil(self.denominator)).ok()
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Exchange {
+    /// The rate converting `from` into `to`
+    ///
+    /// Uses the midpoint of a market between the two assets if there is one, otherwise the
+    /// midpoints of two markets through a single intermediate asset. Candidates are tried in
+    /// symbol order so the result does not depend on market insertion order. Returns `None`
+    /// if no market with a two-sided book connects the assets.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The asset to convert from
+    /// * `to` - The asset to convert to
+    pub fn cross_rate(&self, from: Asset, to: Asset) -> Option<CrossRate> {
+        if from == to {
+            return Some(CrossRate::identity(from));
+        }
+        if let Some(rate) = self.direct_rate(from, to) {
+            return Some(rate);
+        }
+        let mut via: Vec<Asset> = self
+            .markets
+            .keys()
+            .flat_map(|pair| [pair.base, pair.numeraire])
+            .filter(|asset| *asset != from && *asset != to)
+            .collect();
+        via.sort_by_key(|asset| asset.symbol);
+        via.dedup();
+        via.into_iter().find_map(|via| {
+            self.direct_rate(from, via)?
+                .then(self.direct_rate(via, to)?)
+        })
+    }
+
+    fn direct_rate(&self, from: Asset, to: Asset) -> Option<CrossRate> {
+        let direct = Pair {
+            numeraire: to,
+            base: from,
+        };
+        let inverse = Pair {
+            numeraire: from,
+            base: to,
+        };
+        CrossRate::midpoint(self, direct)
+            .or_else(|| CrossRate::midpoint(self, inverse).map(CrossRate::inverse))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        market::Market,
+        order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_cross_rates() {
+        let (usd, btc, eth, sol) = (
+            Asset::new("USD"),
+            Asset::new("BTC"),
+            Asset::new("ETH"),
+            Asset::new("SOL"),
+        );
+        let mut exchange = Exchange::new();
+        for (base, numeraire, bid, ask) in [(btc, usd, 99, 102), (eth, btc, 4, 4)] {
+            let pair = Pair { numeraire, base };
+            exchange.add_market(Market::new(pair));
+            let engine = &mut exchange.markets.get_mut(&pair).unwrap().matching_engine;
+            for (id, side, price) in [(1, Side::Bid, bid), (2, Side::Ask, ask)] {
+                engine.restore_order(Order::new(
+                    OrderId::new(id),
+                    Price::new(price),
+                    Quantity::new(1),
+                    side,
+                    AccountId::new("maker".to_string()),
+                    Timestamp::new(id),
+                ));
+            }
+        }
+
+        // 1 BTC is worth 100.5 USD, rounded up
+        let btc_usd = exchange.cross_rate(btc, usd).unwrap();
+        assert_eq!(btc_usd.convert_up(2), Some(201));
+        assert_eq!(btc_usd.convert_up(1), Some(101));
+        // Inverse: 201 USD is 2 BTC
+        assert_eq!(
+            exchange.cross_rate(usd, btc).unwrap().convert_up(201),
+            Some(2)
+        );
+        // Through BTC: 1 ETH is 4 BTC, so 402 USD
+        assert_eq!(
+            exchange.cross_rate(eth, usd).unwrap().convert_up(1),
+            Some(402)
+        );
+        assert_eq!(
+            exchange.cross_rate(usd, usd).unwrap().convert_up(7),
+            Some(7)
+        );
+        assert!(exchange.cross_rate(sol, usd).is_none());
+    }
+}
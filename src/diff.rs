@@ -0,0 +1,416 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{
+    market::{MarketConfig, Pair},
+    order::{AccountId, Order, OrderId, Side},
+    snapshot::{MarketSnapshot, Snapshot},
+};
+
+/// A single difference between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Change {
+    /// A balance changed. Missing balances are reported as zero.
+    Balance {
+        account_id: AccountId,
+        asset: &'static str,
+        before: u64,
+        after: u64,
+    },
+    /// A position changed. Missing positions are reported as zero.
+    Position {
+        account_id: AccountId,
+        pair: Pair,
+        before: i64,
+        after: i64,
+    },
+    MarketAdded {
+        pair: Pair,
+    },
+    MarketRemoved {
+        pair: Pair,
+    },
+    MarketConfig {
+        pair: Pair,
+        before: MarketConfig,
+        after: MarketConfig,
+    },
+    OrderAdded {
+        pair: Pair,
+        order: Order,
+    },
+    OrderRemoved {
+        pair: Pair,
+        order: Order,
+    },
+    /// A resting order with the same ID and side differs, e.g. after a partial fill.
+    OrderChanged {
+        pair: Pair,
+        before: Order,
+        after: Order,
+    },
+    /// The sequence number of a market's next trade changed.
+    TradeSequence {
+        pair: Pair,
+        before: u64,
+        after: u64,
+    },
+    /// The ID of the exchange's next trade changed.
+    TradeId {
+        before: u64,
+        after: u64,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let market = |pair: &Pair| format!("{}/{}", pair.base.symbol, pair.numeraire.symbol);
+        match self {
+            Change::Balance {
+                account_id,
+                asset,
+                before,
+                after,
+            } => write!(
+                f,
+                "balance {} {}: {} -> {}",
+                account_id.as_str(),
+                asset,
+                before,
+                after
+            ),
+            Change::Position {
+                account_id,
+                pair,
+                before,
+                after,
+            } => write!(
+                f,
+                "position {} {}: {} -> {}",
+                account_id.as_str(),
+                market(pair),
+                before,
+                after
+            ),
+            Change::MarketAdded { pair } => write!(f, "market {} added", market(pair)),
+            Change::MarketRemoved { pair } => write!(f, "market {} removed", market(pair)),
+            Change::MarketConfig {
+                pair,
+                before,
+                after,
+            } => write!(
+                f,
+                "market {} config: {:?} -> {:?}",
+                market(pair),
+                before,
+                after
+            ),
+            Change::OrderAdded { pair, order } => {
+                write!(f, "order {} added: {:?}", market(pair), order)
+            }
+            Change::OrderRemoved { pair, order } => {
+                write!(f, "order {} removed: {:?}", market(pair), order)
+            }
+            Change::OrderChanged {
+                pair,
+                before,
+                after,
+            } => write!(
+                f,
+                "order {} changed: {:?} -> {:?}",
+                market(pair),
+                before,
+                after
+            ),
+            Change::TradeSequence {
+                pair,
+                before,
+                after,
+            } => write!(
+                f,
+                "market {} trade sequence: {} -> {}",
+                market(pair),
+                before,
+                after
+            ),
+            Change::TradeId { before, after } => {
+                write!(f, "next trade ID: {} -> {}", before, after)
+            }
+        }
+    }
+}
+
+/// The structured differences between two snapshots.
+///
+/// Changes are reported in a deterministic order: balances by account and asset, then
+/// positions by account and market, then markets by base and numeraire symbol, each
+/// followed by its order changes by side and ID, then the next trade ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SnapshotDiff {
+    /// Returns true if the snapshots are identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Computes the changes that turn `self` into `other`.
+    pub fn diff(&self, other: &Snapshot) -> SnapshotDiff {
+        let mut changes = Vec::new();
+
+        let balances = |snapshot: &Snapshot| -> BTreeMap<(AccountId, &'static str), u64> {
+            snapshot
+                .balances
+                .iter()
+                .map(|(account_id, asset, amount)| ((account_id.clone(), asset.symbol), *amount))
+                .collect()
+        };
+        let (before, after) = (balances(self), balances(other));
+        let keys: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+        for key in keys {
+            let (b, a) = (
+                before.get(key).copied().unwrap_or(0),
+                after.get(key).copied().unwrap_or(0),
+            );
+            if b != a {
+                changes.push(Change::Balance {
+                    account_id: key.0.clone(),
+                    asset: key.1,
+                    before: b,
+                    after: a,
+                });
+            }
+        }
+
+        let positions = |snapshot: &Snapshot| -> BTreeMap<_, (Pair, i64)> {
+            snapshot
+                .positions
+                .iter()
+                .map(|(account_id, pair, position)| {
+                    let key = (account_id.clone(), pair.base.symbol, pair.numeraire.symbol);
+                    (key, (*pair, *position))
+                })
+                .collect()
+        };
+        let (before, after) = (positions(self), positions(other));
+        let keys: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+        for key in keys {
+            let (b, a) = (before.get(key), after.get(key));
+            let pair = b.or(a).map(|(pair, _)| *pair).unwrap();
+            let (b, a) = (b.map_or(0, |(_, p)| *p), a.map_or(0, |(_, p)| *p));
+            if b != a {
+                changes.push(Change::Position {
+                    account_id: key.0.clone(),
+                    pair,
+                    before: b,
+                    after: a,
+                });
+            }
+        }
+
+        let (before, after) = (markets(self), markets(other));
+        let keys: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+        for key in keys {
+            match (before.get(key).copied(), after.get(key).copied()) {
+                (Some(b), Some(a)) => {
+                    if b.config != a.config {
+                        changes.push(Change::MarketConfig {
+                            pair: a.pair,
+                            before: b.config,
+                            after: a.config,
+                        });
+                    }
+                    if b.next_trade_sequence != a.next_trade_sequence {
+                        changes.push(Change::TradeSequence {
+                            pair: a.pair,
+                            before: b.next_trade_sequence,
+                            after: a.next_trade_sequence,
+                        });
+                    }
+                    diff_orders(a.pair, Some(b), Some(a), &mut changes);
+                }
+                (Some(b), None) => {
+                    changes.push(Change::MarketRemoved { pair: b.pair });
+                    diff_orders(b.pair, Some(b), None, &mut changes);
+                }
+                (None, Some(a)) => {
+                    changes.push(Change::MarketAdded { pair: a.pair });
+                    diff_orders(a.pair, None, Some(a), &mut changes);
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+
+        if self.next_trade_id != other.next_trade_id {
+            changes.push(Change::TradeId {
+                before: self.next_trade_id,
+                after: other.next_trade_id,
+            });
+        }
+
+        SnapshotDiff { changes }
+    }
+}
+
+fn markets(snapshot: &Snapshot) -> BTreeMap<(&'static str, &'static str), &MarketSnapshot> {
+    snapshot
+        .markets
+        .iter()
+        .map(|m| ((m.pair.base.symbol, m.pair.numeraire.symbol), m))
+        .collect()
+}
+
+fn diff_orders(
+    pair: Pair,
+    before: Option<&MarketSnapshot>,
+    after: Option<&MarketSnapshot>,
+    changes: &mut Vec<Change>,
+) {
+    let orders = |market: Option<&MarketSnapshot>| -> BTreeMap<(u8, OrderId), Order> {
+        market
+            .into_iter()
+            .flat_map(|m| m.bids.iter().chain(&m.asks))
+            .map(|order| {
+                let side = match order.side {
+                    Side::Bid => 0,
+                    Side::Ask => 1,
+                };
+                ((side, order.id), order.clone())
+            })
+            .collect()
+    };
+    let (before, after) = (orders(before), orders(after));
+    let keys: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+    for key in keys {
+        match (before.get(key), after.get(key)) {
+            (Some(b), Some(a)) if b != a => changes.push(Change::OrderChanged {
+                pair,
+                before: b.clone(),
+                after: a.clone(),
+            }),
+            (Some(b), None) => changes.push(Change::OrderRemoved {
+                pair,
+                order: b.clone(),
+            }),
+            (None, Some(a)) => changes.push(Change::OrderAdded {
+                pair,
+                order: a.clone(),
+            }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        exchange::Exchange,
+        market::Market,
+        order::{Price, Quantity, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_diff_reports_balance_and_order_changes() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let order = |id: u64, side: Side, qty: u64, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(qty),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(bob.clone(), pair.base, 10);
+        exchange
+            .post_order(order(1, Side::Ask, 10, &bob), pair)
+            .unwrap();
+        let before = exchange.snapshot();
+        assert!(before.diff(&before).is_empty());
+
+        exchange
+            .post_order(order(2, Side::Bid, 4, &alice), pair)
+            .unwrap();
+        let after = exchange.snapshot();
+
+        let diff = before.diff(&after);
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::Balance {
+                    account_id: alice.clone(),
+                    asset: "BTC",
+                    before: 0,
+                    after: 4,
+                },
+                Change::Balance {
+                    account_id: alice.clone(),
+                    asset: "USD",
+                    before: 1_000,
+                    after: 600,
+                },
+                Change::Balance {
+                    account_id: bob.clone(),
+                    asset: "USD",
+                    before: 0,
+                    after: 400,
+                },
+                Change::Position {
+                    account_id: alice.clone(),
+                    pair,
+                    before: 0,
+                    after: 4,
+                },
+                Change::Position {
+                    account_id: bob.clone(),
+                    pair,
+                    before: 0,
+                    after: -4,
+                },
+                Change::TradeSequence {
+                    pair,
+                    before: 1,
+                    after: 2,
+                },
+                Change::OrderChanged {
+                    pair,
+                    before: order(1, Side::Ask, 10, &bob),
+                    after: order(1, Side::Ask, 6, &bob),
+                },
+                Change::TradeId {
+                    before: 1,
+                    after: 2,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{
+    account::BalanceThreshold,
+    asset::Asset,
+    market::Pair,
+    order::{AccountId, OrderId, Price, Quantity, Side},
+    reject::RejectReason,
+};
+
+/// A notable change of exchange state, queued for embedders to consume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExchangeEvent {
+    AccountClosed {
+        account_id: AccountId,
+        /// Dust balances moved to the dust account on closure.
+        swept: Vec<(Asset, u64)>,
+        /// Number of ledger entries moved to the archive.
+        archived_entries: usize,
+    },
+    OrderExpired {
+        pair: Pair,
+        order_id: OrderId,
+        account_id: AccountId,
+        /// Unfilled quantity removed from the book.
+        quantity: Quantity,
+    },
+    /// A triggered stop order could not be posted, usually for lack of funds.
+    StopRejected {
+        pair: Pair,
+        order_id: OrderId,
+        account_id: AccountId,
+        reason: RejectReason,
+    },
+    /// A pegged order was cancelled because its hold at the new peg price was not covered.
+    PegCancelled {
+        pair: Pair,
+        order_id: OrderId,
+        account_id: AccountId,
+    },
+    /// Self-trade prevention took quantity out of an order of the account, resting or
+    /// incoming.
+    SelfTradePrevented {
+        pair: Pair,
+        order_id: OrderId,
+        account_id: AccountId,
+        side: Side,
+        /// Quantity cancelled.
+        quantity: Quantity,
+    },
+    /// An account's available balance started breaching one of its registered thresholds.
+    BalanceAlert {
+        account_id: AccountId,
+        asset: Asset,
+        threshold: BalanceThreshold,
+        balance: u64,
+    },
+    /// A market's circuit breaker tripped, and the market was halted in pre-open.
+    CircuitBreakerTripped {
+        pair: Pair,
+        reference_price: Price,
+        /// Price of the trade that tripped the breaker.
+        price: Price,
+    },
+    /// An implied order of a resting spread order filled, but its hedge in the other leg
+    /// did not fill in full, leaving the account with part of one leg unhedged.
+    ImpliedHedgeShortfall {
+        /// The market of the hedge.
+        pair: Pair,
+        account_id: AccountId,
+        /// Quantity the hedge did not fill.
+        quantity: Quantity,
+    },
+}
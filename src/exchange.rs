@@ -1,29 +1,266 @@
 use crate::{
+    account::{BalanceThreshold, SystemAccount},
     account_manager::AccountManager,
     asset::Asset,
-    market::{Market, Pair},
-    order::{AccountId, Order, OrderId, Price, Side},
+    auction::Auction,
+    basket::Basket,
+    clock::Clock,
+    command_log::CommandLog,
+    cross::Affiliations,
+    event::ExchangeEvent,
+    execution::ExecutionReport,
+    lending::ShortSales,
+    market::{BookView, FeeSchedule, FlatFee, Market, MinQtyShortfall, Pair},
+    matching::{Liquidity, Trade, TradeIds},
+    order::{
+        AccountId, ClientOrderId, GroupId, Order, OrderId, OrderType, Price, Quantity, Side,
+        Timestamp,
+    },
+    phase_timing::PhaseTimings,
+    price_band::BandReference,
+    reject::RejectReason,
+    retention::Retention,
+    self_trade::SelfTradePolicies,
+    settlement::SettlementHook,
+    spread::{ImpliedQuote, Spread, SpreadBooks, SpreadOrder},
+    surveillance::Surveillance,
 };
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::ControlFlow;
+use std::time::Instant;
+
+/// Number of recent client order IDs remembered per account to detect duplicates.
+pub const CLIENT_ORDER_ID_WINDOW: usize = 1_000;
+
+/// Which sequence `place_order` draws order IDs from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OrderIdScope {
+    /// One sequence shared by every market, so IDs are unique across the exchange.
+    #[default]
+    Exchange,
+    /// A sequence per market, so each market numbers its orders 1, 2, 3... on its own and
+    /// IDs repeat across markets.
+    Market,
+}
 
 pub struct Exchange {
     pub markets: HashMap<Pair, Market>,
     pub account_manager: AccountManager,
+    /// Legs of every order group that may still be resting.
+    order_groups: HashMap<GroupId, Vec<GroupLeg>>,
+    /// The group each grouped order belongs to.
+    grouped_orders: HashMap<(Pair, OrderId), GroupId>,
+    next_group_id: u64,
+    /// Listed baskets, keyed by their token.
+    baskets: HashMap<Asset, Basket>,
+    /// Order-to-trade monitoring, if enabled.
+    pub surveillance: Option<Surveillance>,
+    /// Structured log of executed commands, if enabled.
+    pub command_log: Option<CommandLog>,
+    /// Time spent in each phase of processing orders, if enabled.
+    pub phase_timings: Option<PhaseTimings>,
+    /// Events not yet drained by the embedder.
+    pub(crate) events: Vec<ExchangeEvent>,
+    /// Net base quantity each account has bought in each market, negative if it sold more.
+    pub(crate) positions: HashMap<(AccountId, Pair), i64>,
+    /// Registered balance thresholds, and whether each is currently breached.
+    balance_thresholds: Vec<(AccountId, Asset, BalanceThreshold, bool)>,
+    /// The latest client order IDs accepted from each account, oldest first.
+    client_order_ids: HashMap<AccountId, VecDeque<ClientOrderId>>,
+    /// Stamps orders placed with `place_order`, if set.
+    pub clock: Option<Box<dyn Clock>>,
+    /// The sequence `place_order` assigns IDs from.
+    pub order_id_scope: OrderIdScope,
+    /// One past the highest order ID accepted in any market.
+    next_order_id: u64,
+    /// One past the highest order ID accepted in each market.
+    next_market_order_ids: HashMap<Pair, u64>,
+    /// The sequence every market added with `add_market` draws trade IDs from.
+    pub(crate) trade_ids: TradeIds,
+    /// Markets open to short sales, and the borrows backing them.
+    pub(crate) short_sales: ShortSales,
+    /// Open auctions, by market.
+    pub(crate) auctions: HashMap<Pair, Auction>,
+    /// Beneficial owners of accounts, for internal crosses.
+    pub(crate) affiliations: Affiliations,
+    /// Bounds on the history kept in memory.
+    pub(crate) retention: Retention,
+    /// Self-trade prevention of accounts.
+    pub(crate) self_trade: SelfTradePolicies,
+    /// Receives every settled batch of trades, if set.
+    pub(crate) settlement_hook: Option<Box<dyn SettlementHook>>,
+    /// Resting spread orders and the implied orders they generate into the legs' books.
+    pub(crate) spreads: SpreadBooks,
+    /// Set while triggered stops are being posted, so the stops their trades trigger are
+    /// left to the loop posting them rather than posted recursively.
+    posting_stops: bool,
+    /// Set while a leg of an order group is posted, whose account the group already checked
+    /// for throttling, so that earlier legs cannot throttle it.
+    posting_group_leg: bool,
+}
+
+/// A leg of an order group, with enough information to cancel it.
+#[derive(Debug, Clone, Copy)]
+struct GroupLeg {
+    pair: Pair,
+    order_id: OrderId,
+    side: Side,
+    price: Price,
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Exchange {
     pub fn new() -> Self {
+        let mut account_manager = AccountManager::new();
+        for system in SystemAccount::ALL {
+            account_manager.open_account(system.id());
+        }
         Exchange {
             markets: HashMap::new(),
-            account_manager: AccountManager::new(),
+            account_manager,
+            order_groups: HashMap::new(),
+            grouped_orders: HashMap::new(),
+            next_group_id: 0,
+            baskets: HashMap::new(),
+            surveillance: None,
+            command_log: None,
+            phase_timings: None,
+            events: Vec::new(),
+            positions: HashMap::new(),
+            balance_thresholds: Vec::new(),
+            client_order_ids: HashMap::new(),
+            clock: None,
+            order_id_scope: OrderIdScope::default(),
+            next_order_id: 1,
+            next_market_order_ids: HashMap::new(),
+            trade_ids: TradeIds::default(),
+            short_sales: ShortSales::default(),
+            auctions: HashMap::new(),
+            affiliations: Affiliations::default(),
+            retention: Retention::default(),
+            self_trade: SelfTradePolicies::default(),
+            settlement_hook: None,
+            spreads: SpreadBooks::default(),
+            posting_stops: false,
+            posting_group_leg: false,
         }
     }
 
-    pub fn add_market(&mut self, market: Market) {
+    /// List a market
+    ///
+    /// The market's trades get IDs from the exchange's sequence from now on, so trade IDs are
+    /// unique across markets.
+    ///
+    /// # Arguments
+    ///
+    /// * `market` - The market to list
+    pub fn add_market(&mut self, mut market: Market) {
+        market.set_trade_ids(self.trade_ids.clone());
         self.markets.insert(market.pair, market);
     }
 
+    /// Open a default market for a pair that has none, drawing its trade IDs from the
+    /// exchange's like those of every other market.
+    fn ensure_market(&mut self, pair: Pair) {
+        if !self.markets.contains_key(&pair) {
+            self.add_market(Market::new(pair));
+        }
+    }
+
+    /// List a basket and open a market for its token against `numeraire`
+    ///
+    /// # Arguments
+    ///
+    /// * `basket` - The basket to list
+    /// * `numeraire` - The asset the basket token is quoted in
+    pub fn add_basket(&mut self, basket: Basket, numeraire: Asset) {
+        let pair = Pair {
+            numeraire,
+            base: basket.token,
+        };
+        self.ensure_market(pair);
+        self.baskets.insert(basket.token, basket);
+    }
+
+    /// Create units of a basket token from an account's constituent balances
+    ///
+    /// Either every constituent is debited and the token credited, or nothing changes, e.g.
+    /// when the amounts or the new token balance would not fit in a `u64`.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The account creating the basket units
+    /// * `token` - The basket token to create
+    /// * `units` - The number of units to create
+    pub fn create_basket_units(
+        &mut self,
+        account_id: AccountId,
+        token: Asset,
+        units: u64,
+    ) -> Result<()> {
+        let basket = self
+            .baskets
+            .get(&token)
+            .ok_or(anyhow::anyhow!("Basket not found"))?
+            .clone();
+        let constituents = basket.constituents_for(units)?;
+        for (asset, amount) in &constituents {
+            if self.get_balance(account_id.clone(), *asset)? < *amount {
+                return Err(anyhow::anyhow!("Insufficient balance"));
+            }
+        }
+        let balance = self.get_balance(account_id.clone(), token).unwrap_or(0);
+        if balance.checked_add(units).is_none() {
+            return Err(anyhow::anyhow!("Balance too large"));
+        }
+        for (asset, amount) in constituents {
+            self.remove_balance(account_id.clone(), asset, amount)?;
+        }
+        self.add_balance(account_id, token, units);
+        Ok(())
+    }
+
+    /// Redeem units of a basket token for its constituents
+    ///
+    /// Nothing changes if a constituent amount or the new balance of a constituent would not
+    /// fit in a `u64`.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The account redeeming the basket units
+    /// * `token` - The basket token to redeem
+    /// * `units` - The number of units to redeem
+    pub fn redeem_basket_units(
+        &mut self,
+        account_id: AccountId,
+        token: Asset,
+        units: u64,
+    ) -> Result<()> {
+        let basket = self
+            .baskets
+            .get(&token)
+            .ok_or(anyhow::anyhow!("Basket not found"))?
+            .clone();
+        let constituents = basket.constituents_for(units)?;
+        for (asset, amount) in &constituents {
+            let balance = self.get_balance(account_id.clone(), *asset).unwrap_or(0);
+            if balance.checked_add(*amount).is_none() {
+                return Err(anyhow::anyhow!("Balance too large"));
+            }
+        }
+        self.remove_balance(account_id.clone(), token, units)?;
+        for (asset, amount) in constituents {
+            self.add_balance(account_id.clone(), asset, amount);
+        }
+        Ok(())
+    }
+
     /// Add a balance to an account
     ///
     /// # Arguments
@@ -32,7 +269,108 @@ impl Exchange {
     /// * `asset` - The asset to add the balance to
     /// * `amount` - The amount of the balance to add
     pub fn add_balance(&mut self, account_id: AccountId, asset: Asset, amount: u64) {
-        self.account_manager.add_balance(account_id, asset, amount);
+        self.account_manager
+            .add_balance(account_id.clone(), asset, amount);
+        self.check_balance_thresholds(&account_id, asset);
+    }
+
+    /// Deposit external funds into an account
+    ///
+    /// Unlike `add_balance`, fails for closed accounts and when the balance would exceed
+    /// `u64::MAX`.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account to deposit into
+    /// * `asset` - The asset to deposit
+    /// * `amount` - The amount to deposit
+    pub fn deposit(&mut self, account_id: AccountId, asset: Asset, amount: u64) -> Result<()> {
+        if self.account_manager.is_closed(&account_id) {
+            return Err(anyhow::anyhow!("Account closed"));
+        }
+        let balance = self.get_balance(account_id.clone(), asset).unwrap_or(0);
+        if balance.checked_add(amount).is_none() {
+            return Err(anyhow::anyhow!("Balance too large"));
+        }
+        self.add_balance(account_id, asset, amount);
+        Ok(())
+    }
+
+    /// Close an account
+    ///
+    /// The account must have no resting orders, and every balance must be at most
+    /// `dust_threshold`. Remaining dust is swept to the dust account, the account's ledger
+    /// entries are archived, and its ID can never be used again.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account to close
+    /// * `dust_threshold` - The largest balance that may be swept instead of withdrawn
+    pub fn close_account(&mut self, account_id: AccountId, dust_threshold: u64) -> Result<()> {
+        if SystemAccount::from_id(&account_id).is_some() {
+            return Err(anyhow::anyhow!("System accounts cannot be closed"));
+        }
+        if self.account_manager.is_closed(&account_id) {
+            return Err(anyhow::anyhow!("Account already closed"));
+        }
+        let has_orders = self.markets.values().any(|market| {
+            market
+                .resting_orders()
+                .chain(market.pending_stops())
+                .any(|order| order.account_id == account_id)
+        });
+        if has_orders {
+            return Err(anyhow::anyhow!("Account has open orders"));
+        }
+        let mut swept: Vec<(Asset, u64)> = self
+            .account_manager
+            .balances()
+            .filter(|(id, _, amount)| **id == account_id && *amount > 0)
+            .map(|(_, asset, amount)| (asset, amount))
+            .collect();
+        if swept.iter().any(|(_, amount)| *amount > dust_threshold) {
+            return Err(anyhow::anyhow!(
+                "Account has balances above the dust threshold"
+            ));
+        }
+        swept.sort_by_key(|(asset, _)| asset.symbol);
+
+        for (asset, amount) in &swept {
+            self.remove_balance(account_id.clone(), *asset, *amount)?;
+            self.add_balance(SystemAccount::Dust.id(), *asset, *amount);
+        }
+        let archived_entries = self.account_manager.close_account(&account_id)?;
+        self.events.push(ExchangeEvent::AccountClosed {
+            account_id,
+            swept,
+            archived_entries,
+        });
+        Ok(())
+    }
+
+    /// Move funds between system accounts, e.g. to sweep collected fees to the treasury
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The system account to debit
+    /// * `to` - The system account to credit
+    /// * `asset` - The asset to move
+    /// * `amount` - The amount to move
+    pub fn system_transfer(
+        &mut self,
+        from: SystemAccount,
+        to: SystemAccount,
+        asset: Asset,
+        amount: u64,
+    ) -> Result<()> {
+        self.remove_balance(from.id(), asset, amount)?;
+        self.add_balance(to.id(), asset, amount);
+        Ok(())
+    }
+
+    /// Remove and return the events raised since the last call
+    pub fn drain_events(&mut self) -> Vec<ExchangeEvent> {
+        std::mem::take(&mut self.events)
     }
 
     /// Remove a balance from an account
@@ -49,7 +387,62 @@ impl Exchange {
         amount: u64,
     ) -> Result<()> {
         self.account_manager
-            .remove_balance(account_id, asset, amount)
+            .remove_balance(account_id.clone(), asset, amount)?;
+        self.check_balance_thresholds(&account_id, asset);
+        Ok(())
+    }
+
+    /// Register a threshold on the available balance of an account
+    ///
+    /// A `BalanceAlert` event is raised whenever a balance movement takes the balance from
+    /// outside the threshold to breaching it, including right away if it already does.
+    /// Holds of resting orders count as unavailable, so posting an order can raise an alert.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account to watch
+    /// * `asset` - The asset to watch
+    /// * `threshold` - The balance level to alert on
+    pub fn register_balance_threshold(
+        &mut self,
+        account_id: AccountId,
+        asset: Asset,
+        threshold: BalanceThreshold,
+    ) {
+        self.balance_thresholds
+            .push((account_id.clone(), asset, threshold, false));
+        self.check_balance_thresholds(&account_id, asset);
+    }
+
+    /// Remove every balance threshold registered for an account
+    pub fn clear_balance_thresholds(&mut self, account_id: &AccountId) {
+        self.balance_thresholds
+            .retain(|(id, _, _, _)| id != account_id);
+    }
+
+    fn check_balance_thresholds(&mut self, account_id: &AccountId, asset: Asset) {
+        if self.balance_thresholds.is_empty() {
+            return;
+        }
+        let balance = self
+            .account_manager
+            .get_balance(account_id.clone(), asset)
+            .unwrap_or(0);
+        for (id, a, threshold, breached) in &mut self.balance_thresholds {
+            if id != account_id || *a != asset {
+                continue;
+            }
+            let breaching = threshold.is_breached(balance);
+            if breaching && !*breached {
+                self.events.push(ExchangeEvent::BalanceAlert {
+                    account_id: account_id.clone(),
+                    asset,
+                    threshold: *threshold,
+                    balance,
+                });
+            }
+            *breached = breaching;
+        }
     }
 
     /// Get the balance of an account
@@ -62,76 +455,3687 @@ impl Exchange {
         self.account_manager.get_balance(account_id, asset)
     }
 
-    /// Post an order
+    /// Get the balance of an account locked by its resting orders
+    ///
+    /// Bids lock numeraire and asks lock base. Locked funds are already deducted from the
+    /// account's balance, so they are never withdrawable.
     ///
     /// # Arguments
     ///
-    /// * `order` - The order to post
-    /// * `pair` - The pair of the order
-    pub fn post_order(&mut self, order: Order, pair: Pair) -> Result<()> {
-        if order.side == Side::Bid {
-            self.remove_balance(
-                order.account_id.clone(),
-                pair.numeraire,
-                order.quantity.get() * order.price.get(),
-            )?;
-        } else {
-            self.remove_balance(order.account_id.clone(), pair.base, order.quantity.get())?;
+    /// * `account_id` - The ID of the account
+    /// * `asset` - The asset to get the locked balance of
+    pub fn locked_balance(&self, account_id: &AccountId, asset: Asset) -> u64 {
+        let mut locked = 0;
+        for (pair, market) in &self.markets {
+            for order in market
+                .resting_orders()
+                .filter(|order| order.account_id == *account_id)
+            {
+                let (held, amount) = Self::hold_for(order, *pair, market.config.fees);
+                if held == asset {
+                    locked += amount;
+                }
+            }
         }
+        locked
+    }
 
-        let trades = self
-            .markets
-            .entry(pair)
-            .or_insert(Market::new(pair))
-            .process_order(order);
+    /// Get a read-only view of a market's book, or `None` if the market does not exist
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market of the book
+    pub fn orderbook(&self, pair: Pair) -> Option<BookView> {
+        self.markets.get(&pair).map(Market::book_view)
+    }
 
-        for trade in trades {
-            // Ask side receives numeraire
-            self.add_balance(
-                trade.ask_account_id,
-                pair.numeraire,
-                trade.quantity.get() * trade.price.get(),
-            );
+    /// Get the position of an account in a market
+    ///
+    /// The position is the net base quantity the account has bought through the market's
+    /// trades: positive when long, negative when short, regardless of its deposits.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `pair` - The market of the position
+    pub fn position(&self, account_id: &AccountId, pair: Pair) -> i64 {
+        self.positions
+            .get(&(account_id.clone(), pair))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get the balance of an account that can be withdrawn
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `asset` - The asset to get the withdrawable balance of
+    pub fn withdrawable_balance(&self, account_id: AccountId, asset: Asset) -> Result<u64> {
+        // Holds for open orders are taken out of the balance when the order is posted
+        self.get_balance(account_id, asset)
+    }
+
+    /// Withdraw funds from an account
+    ///
+    /// Fails without changing the balance if the amount exceeds the withdrawable balance, so
+    /// funds backing resting orders can never be withdrawn.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account to withdraw from
+    /// * `asset` - The asset to withdraw
+    /// * `amount` - The amount to withdraw
+    pub fn withdraw(&mut self, account_id: AccountId, asset: Asset, amount: u64) -> Result<()> {
+        if SystemAccount::from_id(&account_id).is_some() {
+            return Err(anyhow::anyhow!("System accounts cannot withdraw"));
+        }
+        if self.withdrawable_balance(account_id.clone(), asset)? < amount {
+            return Err(anyhow::anyhow!("Amount exceeds withdrawable balance"));
+        }
+        self.remove_balance(account_id, asset, amount)
+    }
 
-            // Bid side receives base
-            self.add_balance(trade.bid_account_id, pair.base, trade.quantity.get());
+    /// Post an order, returning the trades it executed
+    ///
+    /// The balance movements of all trades are netted per account and asset and applied once
+    /// the order has finished matching.
+    ///
+    /// Stop orders are queued in the market until the last trade price reaches their stop
+    /// price, and are only funded then. A triggered stop-market order sweeps the book, while
+    /// a triggered stop-limit order matches and rests at its limit price. The returned trades
+    /// include those of any stop orders triggered by the order.
+    ///
+    /// Orders with a minimum quantity only match if the book can fill at least that much
+    /// immediately. Otherwise they are rejected, or rest untouched if the market is configured
+    /// to and they would not trade at all.
+    ///
+    /// All-or-none orders that can trade must fill entirely right away, and otherwise rest
+    /// whole. Resting, they are skipped by aggressors too small to fill them, which can leave
+    /// the book locked or crossed behind them.
+    ///
+    /// In markets with a flat fee, an order that takes liquidity pays it once, in numeraire at
+    /// the cross rate when the order is posted. Bids reserve it with their hold; asks pay it
+    /// from their proceeds. Orders of accounts whose `OrderDefaults` name a fee asset pay it
+    /// in that asset instead, reserved on either side. Orders are rejected while the fee
+    /// cannot be priced.
+    ///
+    /// Reduce-only orders are capped to the account's position in the market, and rejected if
+    /// they would not reduce it. Stops are capped when they trigger. Resting orders are not
+    /// capped again when the position later changes.
+    ///
+    /// Orders with a client order ID are rejected if the account used the same ID in one of
+    /// its last `CLIENT_ORDER_ID_WINDOW` accepted orders. Trades echo the client order IDs of
+    /// both orders.
+    ///
+    /// Pegged orders must be good-till-cancelled limit orders. They are posted at their peg
+    /// price, or at their cap while the book has no reference price, and re-priced whenever
+    /// the book moves; see `reprice_pegs`.
+    ///
+    /// Market bids hold enough numeraire to pay for the sweep at the book's current prices,
+    /// or at their protection price if that is lower. Whatever a market or
+    /// immediate-or-cancel order does not fill is discarded and its hold refunded.
+    ///
+    /// In markets open to short selling, an ask for more base than the account has available
+    /// borrows the difference from the lending pool, and is rejected if the pool cannot lend
+    /// it; see `set_short_selling`.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order to post
+    /// * `pair` - The pair of the order
+    pub fn post_order(&mut self, order: Order, pair: Pair) -> Result<ExecutionReport> {
+        let client_order_id = order.client_order_id.clone();
+        if let Some(id) = &client_order_id
+            && self.is_recent_client_order_id(&order.account_id, id)
+        {
+            return Err(RejectReason::DuplicateClientOrderId.into());
         }
-        Ok(())
+        let account_id = order.account_id.clone();
+        let order_id = order.id;
+        let report = self.submit_order(order, pair)?;
+        self.record_order_id(order_id, pair);
+        if let Some(id) = client_order_id {
+            let recent = self.client_order_ids.entry(account_id).or_default();
+            if recent.len() == CLIENT_ORDER_ID_WINDOW {
+                recent.pop_front();
+            }
+            recent.push_back(id);
+        }
+        Ok(report)
     }
 
-    /// Cancel an order
+    /// Post an order under an ID assigned by the exchange, ignoring the order's own ID
+    ///
+    /// If the exchange has a clock, the order is also stamped with its current time.
+    /// IDs are drawn from the sequence selected by `order_id_scope`. A sequence continues
+    /// after the highest ID accepted so far, including IDs chosen by callers of
+    /// `post_order`, so assigned IDs never collide with earlier orders. Rejected orders do
+    /// not use up an ID, so a sequence only used through this method has no gaps, unless
+    /// resting spread orders draw the IDs of their implied and hedge orders from it too.
     ///
     /// # Arguments
     ///
-    /// * `order_id` - The ID of the order to cancel
-    /// * `price` - The price of the order
-    /// * `side` - The side of the order
-    pub fn cancel_order(
+    /// * `order` - The order to post
+    /// * `pair` - The pair of the order
+    pub fn place_order(&mut self, mut order: Order, pair: Pair) -> Result<ExecutionReport> {
+        order.id = self.assigned_order_id(pair);
+        if let Some(clock) = &mut self.clock {
+            order.timestamp = clock.now();
+        }
+        self.post_order(order, pair)
+    }
+
+    /// The next ID of the sequence `order_id_scope` selects for the market.
+    pub(crate) fn assigned_order_id(&self, pair: Pair) -> OrderId {
+        OrderId::new(match self.order_id_scope {
+            OrderIdScope::Exchange => self.next_order_id,
+            OrderIdScope::Market => self.next_market_order_ids.get(&pair).copied().unwrap_or(1),
+        })
+    }
+
+    /// Move the ID sequences past an accepted order's ID.
+    pub(crate) fn record_order_id(&mut self, order_id: OrderId, pair: Pair) {
+        let next = order_id.get().saturating_add(1);
+        self.next_order_id = self.next_order_id.max(next);
+        let market_next = self.next_market_order_ids.entry(pair).or_insert(1);
+        *market_next = (*market_next).max(next);
+    }
+
+    fn is_recent_client_order_id(&self, account_id: &AccountId, id: &ClientOrderId) -> bool {
+        self.client_order_ids
+            .get(account_id)
+            .is_some_and(|recent| recent.contains(id))
+    }
+
+    /// Reject orders the account cannot place, whatever the market.
+    fn check_account_entry(&self, order: &Order) -> Result<(), RejectReason> {
+        if self.account_manager.is_closed(&order.account_id) {
+            return Err(RejectReason::AccountClosed);
+        }
+        if SystemAccount::from_id(&order.account_id).is_some() {
+            return Err(RejectReason::SystemAccount);
+        }
+        if order.is_expired(order.timestamp) {
+            return Err(RejectReason::Expired);
+        }
+        Ok(())
+    }
+
+    /// Reject orders the market cannot take in its current state.
+    fn check_book_entry(
+        market: &Market,
+        order: &Order,
+        pre_open: bool,
+    ) -> Result<(), RejectReason> {
+        if !market.is_valid_lot(order.quantity) {
+            return Err(RejectReason::BadLotSize);
+        }
+        // Before the open, orders can only wait on the book for the uncross
+        if pre_open
+            && (!order.rests()
+                || order.stop_price.is_some()
+                || order.peg.is_some()
+                || order.min_qty.is_some()
+                || order.all_or_none
+                || order.is_post_only()
+                || market.is_odd_lot(order.quantity))
+        {
+            return Err(RejectReason::PreOpen);
+        }
+        if order.is_post_only() && !order.rests() {
+            return Err(RejectReason::BadPostOnlyOrder);
+        }
+        Ok(())
+    }
+
+    /// Reject limit and stop prices the market does not support.
+    fn check_prices(market: &Market, order: &Order) -> Result<(), RejectReason> {
+        if order.order_type == OrderType::Limit && !market.supports_price(order.price) {
+            return Err(RejectReason::BadTickSize);
+        }
+        if let Some(stop_price) = order.stop_price
+            && !market.supports_price(stop_price)
+        {
+            return Err(RejectReason::BadStopTickSize);
+        }
+        Ok(())
+    }
+
+    /// Post an order without checking its client order ID, which triggered stops already
+    /// passed when they were queued.
+    pub(crate) fn submit_order(
         &mut self,
-        order_id: OrderId,
-        price: Price,
-        side: Side,
+        mut order: Order,
         pair: Pair,
-    ) -> Result<()> {
-        let order = self
-            .markets
-            .entry(pair)
-            .or_insert(Market::new(pair))
-            .cancel_order(order_id, side, price);
+    ) -> Result<ExecutionReport, RejectReason> {
+        let started = self.phase_timings.is_some().then(Instant::now);
+        let group_leg = std::mem::take(&mut self.posting_group_leg);
+        self.apply_order_defaults(&mut order);
+        self.check_account_entry(&order)?;
+        if order.reduce_only && order.stop_price.is_none() {
+            let position = self.position(&order.account_id, pair);
+            let reducible = match order.side {
+                Side::Bid => position.min(0).unsigned_abs(),
+                Side::Ask => position.max(0).unsigned_abs(),
+            };
+            if reducible == 0 {
+                return Err(RejectReason::WouldNotReduce);
+            }
+            order.quantity = order.quantity.min(Quantity::new(reducible));
+        }
+        if order
+            .min_qty
+            .is_some_and(|min_qty| min_qty > order.quantity)
+        {
+            return Err(RejectReason::MinQtyAboveQuantity);
+        }
+        if order.protection_price.is_some() && order.order_type != OrderType::Market {
+            return Err(RejectReason::ProtectionPriceNotMarket);
+        }
+        if let Some(surveillance) = &mut self.surveillance {
+            if !group_leg && surveillance.is_throttled(&order.account_id) {
+                return Err(RejectReason::Throttled);
+            }
+            surveillance.record_order(&order.account_id, order.timestamp.get());
+        }
 
-        if let Some(order) = order {
-            if side == Side::Bid {
-                self.add_balance(
-                    order.account_id,
-                    pair.numeraire,
-                    order.quantity.get() * order.price.get(),
-                );
-            } else {
-                self.add_balance(order.account_id, pair.base, order.quantity.get());
+        let pre_open = self.is_pre_open(pair);
+        self.ensure_market(pair);
+        let market = self.markets.get_mut(&pair).unwrap();
+        Self::check_book_entry(market, &order, pre_open)?;
+        if let Some(peg) = order.peg {
+            if !order.rests() || order.stop_price.is_some() {
+                return Err(RejectReason::BadPeggedOrder);
             }
-            Ok(())
+            if market.is_odd_lot(order.quantity) {
+                return Err(RejectReason::PeggedOddLot);
+            }
+            order.price = market
+                .matching_engine
+                .orderbook()
+                .peg_price(&order)
+                .or(peg.cap)
+                .ok_or(RejectReason::NoPegReference)?;
+        }
+        self.apply_price_bands(&mut order, pair)?;
+        let market = self.markets.get_mut(&pair).unwrap();
+        Self::check_prices(market, &order)?;
+        if order.stop_price.is_some() {
+            // Stops are funded when they trigger, so nothing is held while they wait
+            let (order_id, quantity, side, price) =
+                (order.id, order.quantity, order.side, order.price);
+            let report = market.process_order(order);
+            let mut triggered = self.post_triggered_stops(pair);
+            self.reprice_pegs(pair);
+            self.refresh_implied_orders(pair);
+            let market = &self.markets[&pair];
+            if market
+                .pending_stops()
+                .iter()
+                .any(|stop| stop.id == order_id)
+            {
+                return Ok(ExecutionReport {
+                    triggered,
+                    ..report
+                });
+            }
+            // The stop triggered on arrival, and its trades are its own fills
+            let fills = triggered
+                .extract_if(.., |trade| trade.order_id(side) == order_id)
+                .collect();
+            let remaining = market
+                .resting_order(order_id, side, price)
+                .map_or(Quantity::new(0), |order| order.quantity);
+            return Ok(ExecutionReport::new(
+                order_id, quantity, fills, triggered, remaining,
+            ));
+        }
+        if order.is_post_only()
+            && market
+                .engine_for(order.quantity)
+                .orderbook()
+                .matchable_quantity(&order)
+                .get()
+                > 0
+        {
+            return Err(RejectReason::PostOnlyWouldTrade);
+        }
+        if let Some(min_qty) = order.min_qty {
+            // Nothing is matched unless the book can fill the minimum right away
+            let matchable = market
+                .engine_for(order.quantity)
+                .orderbook()
+                .matchable_quantity(&order);
+            let rests_untouched = market.config.min_qty_shortfall == MinQtyShortfall::Rest
+                && matchable.get() == 0
+                && order.rests();
+            if matchable < min_qty && !rests_untouched {
+                return Err(RejectReason::MinQtyUnavailable);
+            }
+        }
+        if order.all_or_none {
+            let matchable = market
+                .engine_for(order.quantity)
+                .orderbook()
+                .matchable_quantity(&order);
+            if matchable.get() > 0 && matchable < order.quantity {
+                return Err(RejectReason::AllOrNoneUnfillable);
+            }
+        }
+        if order.order_type == OrderType::Market && order.side == Side::Bid {
+            // Hold enough to pay the worst price the sweep can reach
+            order.price = Self::market_bid_price(market, order.quantity);
+            if let Some(protection_price) = order.protection_price {
+                order.price = order.price.min(protection_price);
+            }
+        }
+
+        let fees = market.config.fees;
+        let fee_asset = self.fee_asset(&order.account_id, pair);
+        let flat_fee = self.flat_fee_price(fees, fee_asset)?;
+        let (asset, amount) =
+            Self::checked_hold_for(&order, pair, fees).ok_or(RejectReason::HoldOverflow)?;
+        // Bids reserve the flat fee in case they trade on arrival, as do asks that do not pay
+        // it in numeraire out of their proceeds
+        let flat_fee_reserve = match flat_fee {
+            Some((_, price))
+                if !pre_open && (order.side == Side::Bid || fee_asset != pair.numeraire) =>
+            {
+                price
+            }
+            _ => 0,
+        };
+        self.locate_short_sale(&order, pair)?;
+        if fee_asset == asset {
+            let amount = amount
+                .checked_add(flat_fee_reserve)
+                .ok_or(RejectReason::HoldOverflow)?;
+            self.take_for_order(&order.account_id, asset, amount)?;
+        } else {
+            self.take_for_order(&order.account_id, asset, amount)?;
+            if let Err(reason) = self.take_for_order(&order.account_id, fee_asset, flat_fee_reserve)
+            {
+                self.add_balance(order.account_id.clone(), asset, amount);
+                return Err(reason);
+            }
+        }
+        if pre_open {
+            let (order_id, quantity) = (order.id, order.quantity);
+            self.markets.get_mut(&pair).unwrap().restore_order(order);
+            return Ok(ExecutionReport::new(
+                order_id,
+                quantity,
+                Vec::new(),
+                Vec::new(),
+                quantity,
+            ));
+        }
+
+        let (order_id, quantity) = (order.id, order.quantity);
+        let (taker, taker_side) = (order.account_id.clone(), order.side);
+        let taker_limit = order.price;
+        let time = order.timestamp.get();
+        let mut unfilled = order.clone();
+        let stp = order
+            .self_trade_prevention
+            .or(self.self_trade_prevention(&order.account_id));
+        self.ensure_market(pair);
+        let market = self.markets.get_mut(&pair).unwrap();
+        let matching_started = started.map(|_| Instant::now());
+        let (trades, cancels) = market.process_order_with_stp(order, stp);
+        let settlement_started = started.map(|_| Instant::now());
+        let remaining = ExecutionReport::resting(&unfilled, &trades, &cancels);
+
+        let filled: u64 = trades.iter().map(|trade| trade.quantity.get()).sum();
+        let prevented: u64 = cancels
+            .iter()
+            .filter(|cancel| cancel.order.side == taker_side)
+            .map(|cancel| cancel.cancelled.get())
+            .sum();
+        // The unfilled remainder of a market or IOC order is discarded rather than rested,
+        // like quantity self-trade prevention takes out of any order
+        let released = if unfilled.rests() {
+            prevented
+        } else {
+            unfilled.quantity.get() - filled
+        };
+        if released > 0 {
+            unfilled.quantity = Quantity::new(released);
+            let (asset, amount) = Self::hold_for(&unfilled, pair, fees);
+            self.add_balance(unfilled.account_id, asset, amount);
+        }
+        self.release_self_trade_cancels(pair, fees, taker_side, cancels);
+
+        let mut batch = SettlementBatch::default();
+        for trade in &trades {
+            self.settle_trade(&mut batch, trade, pair, fees, taker_limit);
+        }
+        let proceeds = batch.credited(&taker, pair.numeraire);
+        let mark = self.account_manager.ledger().entries().len();
+        for (account_id, asset, amount) in batch.credits {
+            self.add_balance(account_id, asset, amount);
+        }
+        if let Some((flat_fee, price)) = flat_fee {
+            match taker_side {
+                _ if trades.is_empty() => {
+                    if flat_fee_reserve > 0 {
+                        self.add_balance(taker.clone(), fee_asset, flat_fee_reserve);
+                    }
+                }
+                Side::Ask if fee_asset == pair.numeraire => {
+                    // Asks pay from their proceeds, up to what they received
+                    let paid = price.min(proceeds);
+                    self.take_for_order(&taker, pair.numeraire, paid)?;
+                    self.collect_flat_fee(fee_asset, flat_fee, paid, paid == price);
+                }
+                _ => self.collect_flat_fee(fee_asset, flat_fee, price, true),
+            }
+        }
+        self.notify_settlement(pair, &trades, Timestamp::new(time), mark);
+        self.record_fills(pair, &trades, time);
+        if let (Some(timings), Some(started), Some(matching), Some(settlement)) = (
+            &mut self.phase_timings,
+            started,
+            matching_started,
+            settlement_started,
+        ) {
+            timings.record(started, matching, settlement, Instant::now());
+        }
+        let triggered = self.post_triggered_stops(pair);
+        self.reprice_pegs(pair);
+        self.refresh_implied_orders(pair);
+        Ok(ExecutionReport::new(
+            order_id, quantity, trades, triggered, remaining,
+        ))
+    }
+
+    /// Update the positions and surveillance of the accounts of settled trades.
+    fn record_fills(&mut self, pair: Pair, trades: &[Trade], time: u64) {
+        for trade in trades {
+            let quantity = trade.quantity.get() as i64;
+            *self
+                .positions
+                .entry((trade.bid_account_id.clone(), pair))
+                .or_default() += quantity;
+            *self
+                .positions
+                .entry((trade.ask_account_id.clone(), pair))
+                .or_default() -= quantity;
+        }
+        if let Some(surveillance) = &mut self.surveillance {
+            for trade in trades {
+                surveillance.record_fill(&trade.bid_account_id, time);
+                surveillance.record_fill(&trade.ask_account_id, time);
+            }
+        }
+        self.spreads.record_fills(pair, trades);
+    }
+
+    /// Take a hold or fee an order owes out of the account's available balance.
+    pub(crate) fn take_for_order(
+        &mut self,
+        account_id: &AccountId,
+        asset: Asset,
+        amount: u64,
+    ) -> Result<(), RejectReason> {
+        self.remove_balance(account_id.clone(), asset, amount)
+            .map_err(|_| match self.get_balance(account_id.clone(), asset) {
+                Ok(_) => RejectReason::InsufficientBalance,
+                Err(_) => RejectReason::UnknownAccount,
+            })
+    }
+
+    /// Settle trades executed outside continuous matching, such as an auction uncross
+    ///
+    /// Each trade comes with the limit price its bid's hold was taken at. The trades are
+    /// recorded in the market at `time`, and the stops they trigger are posted. Returns the
+    /// trades of those stops.
+    pub(crate) fn settle_uncross(
+        &mut self,
+        pair: Pair,
+        trades: &mut [(Trade, Price)],
+        time: Timestamp,
+    ) -> Vec<Trade> {
+        let Some(market) = self.markets.get_mut(&pair) else {
+            return Vec::new();
+        };
+        let fees = market.config.fees;
+        market.record_trades(trades.iter_mut().map(|(trade, _)| trade), time);
+        let executed: Vec<Trade> = trades.iter().map(|(trade, _)| trade.clone()).collect();
+        let mut batch = SettlementBatch::default();
+        for (trade, bid_limit) in trades {
+            self.settle_trade(&mut batch, trade, pair, fees, *bid_limit);
+        }
+        let mark = self.account_manager.ledger().entries().len();
+        for (account_id, asset, amount) in batch.credits {
+            self.add_balance(account_id, asset, amount);
+        }
+        self.notify_settlement(pair, &executed, time, mark);
+        self.record_fills(pair, &executed, time.get());
+        let stop_trades = self.post_triggered_stops(pair);
+        self.reprice_pegs(pair);
+        self.refresh_implied_orders(pair);
+        stop_trades
+    }
+
+    /// The flat fee of a market and its price in the asset it is paid in at the current cross
+    /// rate, rounded up, or `None` if the market has no flat fee.
+    fn flat_fee_price(
+        &self,
+        fees: FeeSchedule,
+        paid_in: Asset,
+    ) -> Result<Option<(FlatFee, u64)>, RejectReason> {
+        let Some(flat_fee) = fees.flat_fee else {
+            return Ok(None);
+        };
+        let price = self
+            .cross_rate(flat_fee.asset, paid_in)
+            .and_then(|rate| rate.convert_up(flat_fee.amount))
+            .ok_or(RejectReason::NoFlatFeeRate)?;
+        Ok(Some((flat_fee, price)))
+    }
+
+    /// Book a flat fee a taker paid
+    ///
+    /// When the fee was paid in full and the treasury holds enough of the fee's asset, the
+    /// treasury converts it: it keeps what the taker paid and the fees account receives the
+    /// fee in its own asset. Otherwise the fees account keeps what the taker paid.
+    fn collect_flat_fee(&mut self, paid_in: Asset, flat_fee: FlatFee, paid: u64, in_full: bool) {
+        let treasury = SystemAccount::Treasury.id();
+        let converts = in_full
+            && flat_fee.asset != paid_in
+            && self
+                .get_balance(treasury.clone(), flat_fee.asset)
+                .unwrap_or(0)
+                >= flat_fee.amount;
+        if converts {
+            self.add_balance(treasury, paid_in, paid);
+            self.system_transfer(
+                SystemAccount::Treasury,
+                SystemAccount::Fees,
+                flat_fee.asset,
+                flat_fee.amount,
+            )
+            .expect("treasury balance was checked");
         } else {
-            Err(anyhow::anyhow!("Order not found"))
+            self.add_balance(SystemAccount::Fees.id(), paid_in, paid);
         }
     }
+
+    /// Move the pegged orders of a market whose peg price changed to their new price
+    ///
+    /// Pegged orders never take liquidity when re-priced, so this executes no trades. A
+    /// re-priced order keeps its time priority and its hold follows its price; if the hold
+    /// cannot be covered the order is cancelled with a `PegCancelled` event. Each order is
+    /// re-priced once per call, in price-time order.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market to re-price
+    pub fn reprice_pegs(&mut self, pair: Pair) {
+        let Some(market) = self.markets.get(&pair) else {
+            return;
+        };
+        for stale in market.matching_engine.orderbook().stale_pegs() {
+            let market = self.markets.get_mut(&pair).unwrap();
+            // Re-pricing earlier orders can move this order's peg price again
+            let Some(price) = market.matching_engine.orderbook().peg_price(&stale) else {
+                continue;
+            };
+            if price == stale.price || !market.supports_price(price) {
+                continue;
+            }
+            let fees = market.config.fees;
+            let Some(mut order) = market.cancel_order(stale.id, stale.side, stale.price) else {
+                continue;
+            };
+            let (asset, amount) = Self::hold_for(&order, pair, fees);
+            self.add_balance(order.account_id.clone(), asset, amount);
+            order.price = price;
+            let covered =
+                Self::checked_hold_for(&order, pair, fees).is_some_and(|(asset, amount)| {
+                    self.remove_balance(order.account_id.clone(), asset, amount)
+                        .is_ok()
+                });
+            if !covered {
+                self.events.push(ExchangeEvent::PegCancelled {
+                    pair,
+                    order_id: order.id,
+                    account_id: order.account_id,
+                });
+                continue;
+            }
+            let market = self.markets.get_mut(&pair).unwrap();
+            market.restore_order(order);
+        }
+    }
+
+    /// Post the stop orders of a market triggered by its trades, returning their trades
+    ///
+    /// Trades of triggered stops can trigger further stops, which are posted in turn, round
+    /// by round: every stop a round triggers is posted, in arrival order, before those its
+    /// trades trigger. Stops that cannot be funded are dropped with a `StopRejected` event.
+    ///
+    /// A market whose circuit breaker tripped is halted first, and while a market is in
+    /// pre-open its triggered stops wait for it to open.
+    fn post_triggered_stops(&mut self, pair: Pair) -> Vec<Trade> {
+        self.check_circuit_breaker(pair);
+        // A stop posted below hands what it triggers to the loop instead
+        if self.posting_stops || self.is_pre_open(pair) {
+            return Vec::new();
+        }
+        self.posting_stops = true;
+        let mut trades = Vec::new();
+        'rounds: loop {
+            let stops = match self.markets.get_mut(&pair) {
+                Some(market) => market.take_triggered_stops(),
+                None => Vec::new(),
+            };
+            if stops.is_empty() {
+                break;
+            }
+            let mut stops = stops.into_iter();
+            while let Some(stop) = stops.next() {
+                if self.is_pre_open(pair) {
+                    let market = self.markets.get_mut(&pair).unwrap();
+                    market.requeue_stops(std::iter::once(stop).chain(stops).collect());
+                    break 'rounds;
+                }
+                let (order_id, account_id) = (stop.id, stop.account_id.clone());
+                match self.submit_order(stop, pair) {
+                    Ok(report) => trades.extend(report.into_trades()),
+                    Err(reason) => self.events.push(ExchangeEvent::StopRejected {
+                        pair,
+                        order_id,
+                        account_id,
+                        reason,
+                    }),
+                }
+            }
+        }
+        self.posting_stops = false;
+        trades
+    }
+
+    /// Post a group of orders across markets atomically, returning the group ID and the trades
+    /// executed by each leg
+    ///
+    /// Either every leg is funded and accepted, or the group is rejected without changing any
+    /// balance or book. Legs left resting are linked, so cancelling one cancels the group.
+    /// Every leg is checked as order entry would check it, including against its market's
+    /// price bands and pre-open, before any of them is posted, and every leg's hold is
+    /// reserved before the first is posted. Stops and implied orders an earlier leg's trades
+    /// set off cannot spend a later leg's hold. A market whose last-trade band or circuit
+    /// breaker an earlier leg could move cannot have more than one leg. Market orders, whose
+    /// hold depends on the book earlier legs trade against, and flat fees paid at a cross
+    /// rate, which moves with the books, cannot be grouped with other legs.
+    ///
+    /// # Arguments
+    ///
+    /// * `legs` - The orders of the group and the pairs they are posted to
+    pub fn post_order_group(
+        &mut self,
+        mut legs: Vec<(Order, Pair)>,
+    ) -> Result<(GroupId, Vec<Vec<Trade>>)> {
+        for (order, pair) in &mut legs {
+            self.apply_order_defaults(order);
+            self.apply_price_bands(order, *pair)?;
+        }
+        // Validate every leg and the total holds per account and asset before touching state
+        let mut holds: Vec<(AccountId, Asset, u64)> = Vec::new();
+        let mut reserves: Vec<Vec<(Asset, u64)>> = Vec::with_capacity(legs.len());
+        let mut client_order_ids: Vec<(&AccountId, &ClientOrderId)> = Vec::new();
+        for (order, pair) in &legs {
+            if let Some(id) = &order.client_order_id {
+                if self.is_recent_client_order_id(&order.account_id, id)
+                    || client_order_ids.contains(&(&order.account_id, id))
+                {
+                    return Err(anyhow::anyhow!("Duplicate client order ID"));
+                }
+                client_order_ids.push((&order.account_id, id));
+            }
+            if order.peg.is_some() {
+                return Err(anyhow::anyhow!("Pegged orders cannot be grouped"));
+            }
+            // Their hold depends on the book, which earlier legs change
+            if order.order_type == OrderType::Market {
+                return Err(anyhow::anyhow!("Market orders cannot be grouped"));
+            }
+            // Their rejection would only be known after earlier legs executed
+            if order.min_qty.is_some() {
+                return Err(anyhow::anyhow!("Minimum quantity orders cannot be grouped"));
+            }
+            if order.all_or_none {
+                return Err(anyhow::anyhow!("All-or-none orders cannot be grouped"));
+            }
+            if order.reduce_only {
+                return Err(anyhow::anyhow!("Reduce-only orders cannot be grouped"));
+            }
+            if order.is_post_only() {
+                return Err(anyhow::anyhow!("Post-only orders cannot be grouped"));
+            }
+            // An earlier leg's trades would move the band of a later one
+            if self
+                .price_bands(*pair)
+                .iter()
+                .any(|band| band.reference == BandReference::LastTrade)
+                && legs.iter().filter(|(_, other)| other == pair).count() > 1
+            {
+                return Err(anyhow::anyhow!(
+                    "Legs of a market with a last-trade price band cannot share a group"
+                ));
+            }
+            // An earlier leg's trades could halt the market before a later one is posted
+            if self
+                .markets
+                .get(pair)
+                .is_some_and(|market| market.circuit_breaker().is_some())
+                && legs.iter().filter(|(_, other)| other == pair).count() > 1
+            {
+                return Err(anyhow::anyhow!(
+                    "Legs of a market with a circuit breaker cannot share a group"
+                ));
+            }
+            // Every rejection order entry would make is made before any leg is posted
+            self.check_account_entry(order)?;
+            if self
+                .surveillance
+                .as_ref()
+                .is_some_and(|surveillance| surveillance.is_throttled(&order.account_id))
+            {
+                return Err(RejectReason::Throttled.into());
+            }
+            if order.protection_price.is_some() {
+                return Err(RejectReason::ProtectionPriceNotMarket.into());
+            }
+            let default_market;
+            let market = match self.markets.get(pair) {
+                Some(market) => market,
+                None => {
+                    default_market = Market::new(*pair);
+                    &default_market
+                }
+            };
+            Self::check_book_entry(market, order, self.is_pre_open(*pair))?;
+            Self::check_prices(market, order)?;
+            let fees = self
+                .markets
+                .get(pair)
+                .map(|market| market.config.fees)
+                .unwrap_or_default();
+            let hold =
+                Self::checked_hold_for(order, *pair, fees).ok_or(RejectReason::HoldOverflow)?;
+            let fee_asset = self.fee_asset(&order.account_id, *pair);
+            let flat_fee = match self.flat_fee_price(fees, fee_asset)? {
+                Some((_, price)) if order.side == Side::Bid || fee_asset != pair.numeraire => {
+                    Some((fee_asset, price))
+                }
+                _ => None,
+            };
+            // An earlier leg's trades would move the rate a later leg's fee is priced at
+            if flat_fee.is_some()
+                && legs.len() > 1
+                && fees
+                    .flat_fee
+                    .is_some_and(|flat_fee| flat_fee.asset != fee_asset)
+            {
+                return Err(anyhow::anyhow!(
+                    "Legs paying a flat fee at a cross rate cannot share a group"
+                ));
+            }
+            reserves.push(std::iter::once(hold).chain(flat_fee).collect());
+            for (asset, amount) in std::iter::once(hold).chain(flat_fee) {
+                match holds
+                    .iter_mut()
+                    .find(|(id, a, _)| *id == order.account_id && *a == asset)
+                {
+                    Some((_, _, total)) => {
+                        *total = total
+                            .checked_add(amount)
+                            .ok_or(RejectReason::HoldOverflow)?
+                    }
+                    None => holds.push((order.account_id.clone(), asset, amount)),
+                }
+            }
+        }
+        for (account_id, asset, amount) in &holds {
+            if self.get_balance(account_id.clone(), *asset)? < *amount {
+                return Err(anyhow::anyhow!("Insufficient balance"));
+            }
+        }
+        // Each leg's hold is released only as the leg is posted, which takes it again
+        for (account_id, asset, amount) in &holds {
+            self.account_manager
+                .remove_balance(account_id.clone(), *asset, *amount)?;
+        }
+
+        let group_id = GroupId::new(self.next_group_id);
+        self.next_group_id += 1;
+
+        let mut resting_legs = Vec::with_capacity(legs.len());
+        let mut trades = Vec::with_capacity(legs.len());
+        for ((order, pair), reserve) in legs.into_iter().zip(reserves) {
+            for (asset, amount) in reserve {
+                self.account_manager
+                    .add_balance(order.account_id.clone(), asset, amount);
+            }
+            let leg = GroupLeg {
+                pair,
+                order_id: order.id,
+                side: order.side,
+                price: order.price,
+            };
+            let quantity = order.quantity;
+            self.posting_group_leg = true;
+            let report = self.post_order(order, pair);
+            self.posting_group_leg = false;
+            let report = report?;
+            if report.filled() < quantity {
+                self.grouped_orders.insert((pair, leg.order_id), group_id);
+                resting_legs.push(leg);
+            }
+            trades.push(report.into_trades());
+        }
+        if !resting_legs.is_empty() {
+            self.order_groups.insert(group_id, resting_legs);
+        }
+        Ok((group_id, trades))
+    }
+
+    /// The number of order groups whose resting legs are linked.
+    pub(crate) fn linked_order_groups(&self) -> usize {
+        self.order_groups.len()
+    }
+
+    /// Get the implied quote for trading a spread on the given side
+    ///
+    /// # Arguments
+    ///
+    /// * `spread` - The spread to quote
+    /// * `side` - `Side::Bid` to buy the spread, `Side::Ask` to sell it
+    pub fn implied_spread_quote(&self, spread: Spread, side: Side) -> Option<ImpliedQuote> {
+        let buy_market = self.markets.get(&spread.buy_leg)?;
+        let sell_market = self.markets.get(&spread.sell_leg)?;
+        spread.implied_quote(buy_market, sell_market, side)
+    }
+
+    /// Post a spread order, returning the trades executed in each leg
+    ///
+    /// The order executes in full against the implied quote as an atomic order group, or is
+    /// rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `spread` - The spread to trade
+    /// * `order` - The spread order
+    pub fn post_spread_order(
+        &mut self,
+        spread: Spread,
+        order: SpreadOrder,
+    ) -> Result<Vec<Vec<Trade>>> {
+        let buy_market = self
+            .markets
+            .get(&spread.buy_leg)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        let sell_market = self
+            .markets
+            .get(&spread.sell_leg)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        let legs = spread.leg_orders(buy_market, sell_market, &order)?;
+        let (_, trades) = self.post_order_group(legs.into())?;
+        Ok(trades)
+    }
+
+    /// The price of the last ask level a market bid for `quantity` would reach, or zero if
+    /// the ask side of the book it matches in is empty.
+    fn market_bid_price(market: &Market, quantity: Quantity) -> Price {
+        let mut remaining = quantity.get();
+        let mut worst = Price::new(0);
+        let _ = market
+            .engine_for(quantity)
+            .orderbook()
+            .walk_side(Side::Ask, |level| {
+                if remaining == 0 {
+                    return ControlFlow::Break(());
+                }
+                worst = level.price;
+                remaining = remaining.saturating_sub(level.quantity().get());
+                ControlFlow::Continue(())
+            });
+        worst
+    }
+
+    /// Returns the asset and amount locked when an order is posted.
+    ///
+    /// Bids also lock the most they can pay in fees, per unit and rounded up, so that the
+    /// hold released by a fill always covers its cost. Only for orders whose hold was
+    /// checked with `checked_hold_for` at the same or a higher price and quantity.
+    pub(crate) fn hold_for(order: &Order, pair: Pair, fees: FeeSchedule) -> (Asset, u64) {
+        Self::checked_hold_for(order, pair, fees).expect("hold checked when the order was posted")
+    }
+
+    /// Returns the asset and amount locked when an order is posted, or `None` if the amount
+    /// does not fit in a `u64`.
+    ///
+    /// A hold that fits bounds every amount the order's fills settle, so orders are checked
+    /// once before they are accepted.
+    pub(crate) fn checked_hold_for(
+        order: &Order,
+        pair: Pair,
+        fees: FeeSchedule,
+    ) -> Option<(Asset, u64)> {
+        match order.side {
+            Side::Bid => {
+                let unit = order
+                    .price
+                    .get()
+                    .checked_add(fees.max_fee_per_unit(order.price))?;
+                Some((pair.numeraire, order.quantity.get().checked_mul(unit)?))
+            }
+            Side::Ask => Some((pair.base, order.quantity.get())),
+        }
+    }
+
+    /// Settle a trade between its bid and ask accounts into a batch of balance credits
+    ///
+    /// Each side pays the maker or taker fee depending on its liquidity role in the trade, in
+    /// numeraire and on the trade's notional. The ask's fee is deducted from its proceeds.
+    /// The bid pays from its hold, which was locked at `taker_limit` for a taker bid and at
+    /// the trade price for a maker bid; the part of the hold the fill did not use, such as
+    /// price improvement and the unused fee reserve, is refunded.
+    fn settle_trade(
+        &self,
+        batch: &mut SettlementBatch,
+        trade: &Trade,
+        pair: Pair,
+        fees: FeeSchedule,
+        taker_limit: Price,
+    ) {
+        let quantity = trade.quantity.get();
+        let notional = quantity * trade.price.get();
+        let ask_fee = fees.fee(trade.liquidity(Side::Ask), notional);
+        let bid_fee = fees.fee(trade.liquidity(Side::Bid), notional);
+
+        // Ask side receives numeraire
+        batch.credit(&trade.ask_account_id, pair.numeraire, notional - ask_fee);
+
+        // Bid side receives base, and the hold released by the fill less what it paid
+        batch.credit(&trade.bid_account_id, pair.base, quantity);
+        let bid_limit = match trade.liquidity(Side::Bid) {
+            Liquidity::Taker => taker_limit,
+            Liquidity::Maker => trade.price,
+        };
+        let released = quantity * (bid_limit.get() + fees.max_fee_per_unit(bid_limit));
+        batch.credit(
+            &trade.bid_account_id,
+            pair.numeraire,
+            released - notional - bid_fee,
+        );
+
+        let fee_account = SystemAccount::Fees.id();
+        batch.credit(&fee_account, pair.numeraire, ask_fee + bid_fee);
+    }
+
+    /// Cancel an order
+    ///
+    /// If the order belongs to an order group, the other legs of the group that are still
+    /// resting are cancelled too.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The ID of the order to cancel
+    /// * `price` - The price of the order, the current peg price for pegged orders
+    /// * `side` - The side of the order
+    pub fn cancel_order(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        side: Side,
+        pair: Pair,
+    ) -> Result<()> {
+        self.cancel_single_order(order_id, price, side, pair)?;
+
+        if let Some(group_id) = self.grouped_orders.remove(&(pair, order_id)) {
+            for leg in self.order_groups.remove(&group_id).unwrap_or_default() {
+                self.grouped_orders.remove(&(leg.pair, leg.order_id));
+                // Legs that already filled or were cancelled are no longer in the book
+                let _ = self.cancel_single_order(leg.order_id, leg.price, leg.side, leg.pair);
+            }
+        }
+        self.reprice_pegs(pair);
+        self.refresh_implied_orders(pair);
+        Ok(())
+    }
+
+    /// Remove every resting order that has expired by `now` and release its locked balance
+    ///
+    /// Expired orders keep matching until they are swept. An `OrderExpired` event is raised
+    /// for each removed order, in pair and order ID order. Expiry does not cancel the other
+    /// legs of an order group.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    pub fn expire_orders(&mut self, now: Timestamp) {
+        let mut expired: Vec<(Pair, OrderId, Side, Price)> = Vec::new();
+        for (pair, market) in &self.markets {
+            for order in market
+                .resting_orders()
+                .filter(|order| order.is_expired(now))
+            {
+                expired.push((*pair, order.id, order.side, order.price));
+            }
+        }
+        expired.sort_by_key(|(pair, order_id, _, _)| {
+            (pair.numeraire.symbol, pair.base.symbol, *order_id)
+        });
+
+        let mut pairs: Vec<Pair> = expired.iter().map(|(pair, ..)| *pair).collect();
+        pairs.dedup();
+        for (pair, order_id, side, price) in expired {
+            let Some(market) = self.markets.get_mut(&pair) else {
+                continue;
+            };
+            let fees = market.config.fees;
+            let Some(order) = market.cancel_order(order_id, side, price) else {
+                continue;
+            };
+            self.grouped_orders.remove(&(pair, order_id));
+            let (asset, amount) = Self::hold_for(&order, pair, fees);
+            self.add_balance(order.account_id.clone(), asset, amount);
+            self.events.push(ExchangeEvent::OrderExpired {
+                pair,
+                order_id,
+                account_id: order.account_id,
+                quantity: order.quantity,
+            });
+        }
+        for pair in pairs {
+            self.reprice_pegs(pair);
+            self.refresh_implied_orders(pair);
+        }
+    }
+
+    /// Supply a market's index price and post the stops it triggers, returning their trades
+    ///
+    /// Stops with an index trigger fire when the index price reaches their stop price, so they
+    /// work in markets that rarely trade; their trades can in turn fire last-trade stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market of the index
+    /// * `price` - The index price
+    pub fn update_index_price(&mut self, pair: Pair, price: Price) -> Result<Vec<Trade>> {
+        let market = self
+            .markets
+            .get_mut(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        market.update_index_price(price);
+        let trades = self.post_triggered_stops(pair);
+        if !trades.is_empty() {
+            self.reprice_pegs(pair);
+            self.refresh_implied_orders(pair);
+        }
+        Ok(trades)
+    }
+
+    /// Reduce the quantity of a resting order without losing its queue priority
+    ///
+    /// The hold on the removed quantity is released. Orders can only shrink this way; to
+    /// increase the quantity or change the price, cancel the order and post a new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The ID of the order to reduce
+    /// * `price` - The price of the order
+    /// * `side` - The side of the order
+    /// * `pair` - The pair of the order
+    /// * `quantity` - The new quantity of the order
+    pub fn reduce_order(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        side: Side,
+        pair: Pair,
+        quantity: Quantity,
+    ) -> Result<()> {
+        let market = self
+            .markets
+            .get_mut(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        let current = market
+            .resting_order(order_id, side, price)
+            .map(|order| order.quantity)
+            .ok_or(RejectReason::UnknownOrder)?;
+        if quantity.get() == 0 {
+            return Err(anyhow::anyhow!("Reduced quantity must be positive"));
+        }
+        if quantity >= current {
+            return Err(anyhow::anyhow!(
+                "Reduced quantity must be below the current quantity"
+            ));
+        }
+        // Orders of the main book stay whole round lots
+        let odd = market.is_odd_lot(quantity) || !market.is_valid_lot(quantity);
+        if !market.is_odd_lot(current) && odd {
+            return Err(anyhow::anyhow!(
+                "Reduced quantity must be a whole number of round lots"
+            ));
+        }
+        let fees = market.config.fees;
+        let order = market
+            .reduce_order(order_id, side, price, quantity)
+            .ok_or(RejectReason::UnknownOrder)?;
+        let (asset, held) = Self::hold_for(&order, pair, fees);
+        let remaining = Order {
+            quantity,
+            ..order.clone()
+        };
+        let (_, still_held) = Self::hold_for(&remaining, pair, fees);
+        self.add_balance(order.account_id, asset, held - still_held);
+        self.refresh_implied_orders(pair);
+        Ok(())
+    }
+
+    /// Amend the price and quantity of a resting order
+    ///
+    /// An amend that only reduces the quantity is applied in place, as by `reduce_order`, and
+    /// keeps the order's queue priority. Any other amend cancels the order and posts it again
+    /// with the new price and quantity at `timestamp`, behind the orders already resting at
+    /// the new price, and returns the trades it makes if the new price crosses the book. The
+    /// order's hold is adjusted either way. If the amended order is rejected, the original
+    /// is put back in the book untouched. Pending stop orders and grouped orders cannot be
+    /// amended. Since queue priority follows timestamps, a re-posting amend is rejected if
+    /// `timestamp` is earlier than the order's own, which would let it jump the queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The ID of the order to amend
+    /// * `price` - The current price of the order
+    /// * `side` - The side of the order
+    /// * `pair` - The pair of the order
+    /// * `new_price` - The price of the amended order
+    /// * `new_quantity` - The quantity of the amended order
+    /// * `timestamp` - The time of the amend, used if the order is posted again
+    #[allow(clippy::too_many_arguments)]
+    pub fn amend_order(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        side: Side,
+        pair: Pair,
+        new_price: Price,
+        new_quantity: Quantity,
+        timestamp: Timestamp,
+    ) -> Result<Vec<Trade>> {
+        self.check_replaceable(order_id, side, pair)?;
+        let market = &self.markets[&pair];
+        if new_price == price {
+            let current = market
+                .resting_order(order_id, side, price)
+                .map(|order| order.quantity)
+                .ok_or(RejectReason::UnknownOrder)?;
+            if new_quantity == current {
+                return Ok(Vec::new());
+            }
+            if new_quantity < current {
+                return self
+                    .reduce_order(order_id, price, side, pair, new_quantity)
+                    .map(|_| Vec::new());
+            }
+        }
+
+        let amend = |original: &Order| {
+            if timestamp < original.timestamp {
+                return Err(anyhow::anyhow!(
+                    "Amend timestamp cannot precede the amended order"
+                ));
+            }
+            Ok(Order {
+                price: new_price,
+                quantity: new_quantity,
+                timestamp,
+                ..original.clone()
+            })
+        };
+        let post = |exchange: &mut Self, order, pair| Ok(exchange.submit_order(order, pair)?);
+        self.replace_order(order_id, price, side, pair, amend, post)
+            .map(|(_, report)| report.into_trades())
+    }
+
+    /// Cancel a resting order and post its replacement as one operation
+    ///
+    /// Either both happen or neither does: if the original order is not resting any more,
+    /// the replacement is not posted, and if the replacement is rejected, the original is
+    /// put back in the book untouched. The replacement is posted like any new order, with
+    /// its own ID, and must belong to the same account. Pending stop orders and grouped
+    /// orders cannot be replaced.
+    ///
+    /// Returns the cancelled order, with the quantity it had left, and the replacement's
+    /// trades.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_id` - The ID of the order to cancel
+    /// * `price` - The price of the order to cancel
+    /// * `side` - The side of the order to cancel
+    /// * `pair` - The pair of both orders
+    /// * `replacement` - The order to post in its place
+    pub fn cancel_replace(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        side: Side,
+        pair: Pair,
+        replacement: Order,
+    ) -> Result<(Order, Vec<Trade>)> {
+        self.check_replaceable(order_id, side, pair)?;
+        let replace = |original: &Order| {
+            if original.account_id != replacement.account_id {
+                return Err(anyhow::anyhow!(
+                    "Replacement must belong to the same account"
+                ));
+            }
+            Ok(replacement)
+        };
+        let (cancelled, report) =
+            self.replace_order(order_id, price, side, pair, replace, Self::post_order)?;
+        if let Some(surveillance) = &mut self.surveillance {
+            surveillance.record_cancel(&cancelled.account_id);
+        }
+        Ok((cancelled, report.into_trades()))
+    }
+
+    /// Fails if an order cannot be amended or replaced.
+    fn check_replaceable(&self, order_id: OrderId, side: Side, pair: Pair) -> Result<()> {
+        if self.grouped_orders.contains_key(&(pair, order_id)) {
+            return Err(anyhow::anyhow!("Grouped orders cannot be replaced"));
+        }
+        let market = self
+            .markets
+            .get(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        if market
+            .pending_stops()
+            .iter()
+            .any(|order| order.id == order_id && order.side == side)
+        {
+            return Err(anyhow::anyhow!("Pending stop orders cannot be replaced"));
+        }
+        Ok(())
+    }
+
+    /// Pull a resting order from the book and post `replacement(original)` with `post`,
+    /// putting the original back untouched if either fails. Returns the original order and
+    /// the replacement's execution report.
+    fn replace_order(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        side: Side,
+        pair: Pair,
+        replacement: impl FnOnce(&Order) -> Result<Order>,
+        post: fn(&mut Self, Order, Pair) -> Result<ExecutionReport>,
+    ) -> Result<(Order, ExecutionReport)> {
+        let market = self
+            .markets
+            .get_mut(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        let fees = market.config.fees;
+        let original = market
+            .cancel_order(order_id, side, price)
+            .ok_or(RejectReason::UnknownOrder)?;
+        let (asset, held) = Self::hold_for(&original, pair, fees);
+        self.add_balance(original.account_id.clone(), asset, held);
+        match replacement(&original).and_then(|order| post(self, order, pair)) {
+            Ok(report) => Ok((original, report)),
+            Err(error) => {
+                // The hold was released just above, so taking it again cannot fail
+                let _ = self.remove_balance(original.account_id.clone(), asset, held);
+                if let Some(market) = self.markets.get_mut(&pair) {
+                    market.restore_order(original);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Cancel a single order and release its locked balance.
+    fn cancel_single_order(
+        &mut self,
+        order_id: OrderId,
+        price: Price,
+        side: Side,
+        pair: Pair,
+    ) -> Result<()> {
+        self.ensure_market(pair);
+        let market = self.markets.get_mut(&pair).unwrap();
+        let fees = market.config.fees;
+        let order = market.cancel_order(order_id, side, price);
+
+        if let Some(order) = order {
+            if let Some(surveillance) = &mut self.surveillance {
+                surveillance.record_cancel(&order.account_id);
+            }
+            // Pending stops hold nothing
+            if order.stop_price.is_none() {
+                let (asset, amount) = Self::hold_for(&order, pair, fees);
+                self.add_balance(order.account_id, asset, amount);
+            }
+            Ok(())
+        } else {
+            Err(RejectReason::UnknownOrder.into())
+        }
+    }
+}
+
+/// Balance credits produced by settling the trades of one order, netted per account and asset
+///
+/// Credits are kept in first-seen order so that they are applied deterministically.
+#[derive(Debug, Default)]
+struct SettlementBatch {
+    credits: Vec<(AccountId, Asset, u64)>,
+}
+
+impl SettlementBatch {
+    /// The amount of `asset` credited to an account so far.
+    fn credited(&self, account_id: &AccountId, asset: Asset) -> u64 {
+        self.credits
+            .iter()
+            .find(|(id, a, _)| id == account_id && *a == asset)
+            .map_or(0, |(_, _, amount)| *amount)
+    }
+
+    fn credit(&mut self, account_id: &AccountId, asset: Asset, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        match self
+            .credits
+            .iter_mut()
+            .find(|(id, a, _)| id == account_id && *a == asset)
+        {
+            Some((_, _, total)) => *total += amount,
+            None => self.credits.push((account_id.clone(), asset, amount)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        clock::TestClock,
+        ledger::Direction,
+        market::{BookLevel, MarketConfig, OddLots},
+        matching::TradeId,
+        order::{OrderTag, Peg, PegReference, Quantity, TimeInForce, Timestamp},
+        surveillance::SurveillanceConfig,
+    };
+
+    use super::*;
+
+    fn pair() -> Pair {
+        Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        }
+    }
+
+    fn account(name: &str) -> AccountId {
+        AccountId::new(name.to_string())
+    }
+
+    #[test]
+    fn test_settlement_charges_maker_and_taker_fees() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            pair,
+            MarketConfig {
+                fees: FeeSchedule {
+                    maker_fee_bps: 10,
+                    taker_fee_bps: 20,
+                    flat_fee: None,
+                },
+                ..Default::default()
+            },
+        ));
+        exchange.add_balance(account("maker"), pair.base, 10_000);
+        exchange.add_balance(account("maker"), pair.numeraire, 0);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_062_000);
+        exchange.add_balance(account("taker"), pair.base, 0);
+
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(100),
+                    Quantity::new(10_000),
+                    Side::Ask,
+                    account("maker"),
+                    Timestamp::new(1),
+                ),
+                pair,
+            )
+            .unwrap();
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(2),
+                    Price::new(105),
+                    Quantity::new(10_000),
+                    Side::Bid,
+                    account("taker"),
+                    Timestamp::new(2),
+                ),
+                pair,
+            )
+            .unwrap();
+
+        // Maker pays 10 bps and taker 20 bps of the 1_000_000 notional
+        assert_eq!(
+            exchange
+                .get_balance(account("maker"), pair.numeraire)
+                .unwrap(),
+            999_000
+        );
+        assert_eq!(
+            exchange.get_balance(account("taker"), pair.base).unwrap(),
+            10_000
+        );
+        // The taker locked 106 per unit, 105 plus its worst-case fee, but traded at 100 and
+        // paid 2_000 in fees, so the rest is refunded
+        assert_eq!(
+            exchange
+                .get_balance(account("taker"), pair.numeraire)
+                .unwrap(),
+            60_000
+        );
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Fees.id(), pair.numeraire)
+                .unwrap(),
+            3_000
+        );
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Fees.id(), pair.base)
+                .unwrap_or(0),
+            0
+        );
+    }
+
+    #[test]
+    fn test_fee_holds_release_unused_reserve() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            pair,
+            MarketConfig {
+                fees: FeeSchedule {
+                    maker_fee_bps: 10,
+                    taker_fee_bps: 30,
+                    flat_fee: None,
+                },
+                ..Default::default()
+            },
+        ));
+        exchange.add_balance(account("maker"), pair.base, 10);
+        exchange.add_balance(account("taker"), pair.numeraire, 60_000);
+        let order = |id: u64, side: Side, price: u64, quantity: u64, account_id: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Ask, 10_000, 2, "maker"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(2, Side::Ask, 10_500, 1, "maker"), pair)
+            .unwrap();
+
+        // Holds 5 * (11_000 + 33), then fills 3 at better prices and discards the rest
+        let ioc = Order {
+            time_in_force: Some(TimeInForce::Ioc),
+            ..order(3, Side::Bid, 11_000, 5, "taker")
+        };
+        assert_eq!(exchange.post_order(ioc, pair).unwrap().fills.len(), 2);
+        // Paid 20_000 + 60 and 10_500 + 31 in taker fees
+        assert_eq!(
+            exchange
+                .get_balance(account("taker"), pair.numeraire)
+                .unwrap(),
+            60_000 - 30_591
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("taker"), pair.numeraire),
+            0
+        );
+
+        // A resting bid holds its maker fee reserve too
+        exchange
+            .post_order(order(4, Side::Bid, 9_000, 1, "taker"), pair)
+            .unwrap();
+        assert_eq!(
+            exchange.locked_balance(&account("taker"), pair.numeraire),
+            9_027
+        );
+        exchange
+            .post_order(order(5, Side::Ask, 8_000, 1, "maker"), pair)
+            .unwrap();
+        // Paid 9_000 + 9 in maker fees; the ask paid 20 + 10 as maker and 27 as taker
+        assert_eq!(
+            exchange
+                .get_balance(account("taker"), pair.numeraire)
+                .unwrap(),
+            60_000 - 30_591 - 9_009
+        );
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Fees.id(), pair.numeraire)
+                .unwrap(),
+            91 + 30 + 27 + 9
+        );
+    }
+
+    #[test]
+    fn test_multi_fill_settlement_is_netted() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.base, 30);
+        exchange.add_balance(account("maker"), pair.numeraire, 0);
+        exchange.add_balance(account("taker"), pair.numeraire, 3_000);
+        exchange.add_balance(account("taker"), pair.base, 0);
+
+        for id in 1..=3 {
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(id),
+                        Price::new(100),
+                        Quantity::new(10),
+                        Side::Ask,
+                        account("maker"),
+                        Timestamp::new(id),
+                    ),
+                    pair,
+                )
+                .unwrap();
+        }
+        let taker = Order::new(
+            OrderId::new(4),
+            Price::new(100),
+            Quantity::new(30),
+            Side::Bid,
+            account("taker"),
+            Timestamp::new(4),
+        );
+
+        // Three fills against the same maker produce one credit per account and asset
+        let trades: Vec<Trade> = (1..=3)
+            .map(|id| Trade {
+                id: TradeId::default(),
+                sequence: 0,
+                ask_order_id: OrderId::new(id),
+                bid_order_id: taker.id,
+                ask_account_id: account("maker"),
+                bid_account_id: account("taker"),
+                price: Price::new(100),
+                quantity: Quantity::new(10),
+                aggressor: Some(Side::Bid),
+                ask_client_order_id: None,
+                bid_client_order_id: None,
+                ask_tag: None,
+                bid_tag: None,
+            })
+            .collect();
+        let mut batch = SettlementBatch::default();
+        for trade in &trades {
+            exchange.settle_trade(&mut batch, trade, pair, FeeSchedule::default(), taker.price);
+        }
+        assert_eq!(batch.credits.len(), 2);
+
+        let trades = exchange.post_order(taker, pair).unwrap().fills;
+        assert_eq!(trades.len(), 3);
+        assert_eq!(
+            exchange
+                .get_balance(account("maker"), pair.numeraire)
+                .unwrap(),
+            3_000
+        );
+        assert_eq!(
+            exchange.get_balance(account("taker"), pair.base).unwrap(),
+            30
+        );
+    }
+
+    #[test]
+    fn test_order_group_is_all_or_nothing() {
+        let btc = pair();
+        let eth = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("trader"), btc.numeraire, 1_000);
+        exchange.add_balance(account("trader"), eth.base, 5);
+
+        let legs = |eth_qty: u64| {
+            vec![
+                (
+                    Order::new(
+                        OrderId::new(1),
+                        Price::new(100),
+                        Quantity::new(10),
+                        Side::Bid,
+                        account("trader"),
+                        Timestamp::new(1),
+                    ),
+                    btc,
+                ),
+                (
+                    Order::new(
+                        OrderId::new(2),
+                        Price::new(50),
+                        Quantity::new(eth_qty),
+                        Side::Ask,
+                        account("trader"),
+                        Timestamp::new(1),
+                    ),
+                    eth,
+                ),
+            ]
+        };
+
+        // The ETH leg cannot be funded, so neither leg is accepted
+        assert!(exchange.post_order_group(legs(6)).is_err());
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), btc.numeraire)
+                .unwrap(),
+            1_000
+        );
+        assert_eq!(
+            exchange.get_balance(account("trader"), eth.base).unwrap(),
+            5
+        );
+        assert!(!exchange.markets.contains_key(&btc));
+
+        exchange.post_order_group(legs(5)).unwrap();
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), btc.numeraire)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            exchange.get_balance(account("trader"), eth.base).unwrap(),
+            0
+        );
+
+        // Cancelling one resting leg cancels the other
+        exchange
+            .cancel_order(OrderId::new(2), Price::new(50), Side::Ask, eth)
+            .unwrap();
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), btc.numeraire)
+                .unwrap(),
+            1_000
+        );
+        assert_eq!(
+            exchange.get_balance(account("trader"), eth.base).unwrap(),
+            5
+        );
+        assert!(
+            exchange
+                .cancel_order(OrderId::new(1), Price::new(100), Side::Bid, btc)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_order_group_rejects_legs_before_any_trades() {
+        let btc = pair();
+        let eth = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(btc));
+        exchange.add_market(Market::new(eth));
+        exchange.add_balance(account("seller"), btc.base, 1);
+        exchange.add_balance(account("trader"), btc.numeraire, 1_000);
+        let order = |id: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(1),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Ask, "seller"), btc)
+            .unwrap();
+        exchange.open_opening_auction(eth).unwrap();
+
+        // The ETH leg cannot be taken in pre-open, so the crossing BTC leg must not trade
+        let legs = vec![
+            (order(2, Side::Bid, "trader"), btc),
+            (
+                Order {
+                    time_in_force: Some(TimeInForce::Ioc),
+                    ..order(3, Side::Bid, "trader")
+                },
+                eth,
+            ),
+        ];
+        let error = exchange.post_order_group(legs).unwrap_err();
+        assert_eq!(RejectReason::of(&error), Some(RejectReason::PreOpen));
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), btc.base)
+                .unwrap_or(0),
+            0
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), btc.numeraire)
+                .unwrap(),
+            1_000
+        );
+        assert!(exchange.markets[&btc].trades().is_empty());
+
+        // Nor can a market order, whose hold the BTC leg's trades would change
+        let market_order = Order::market(
+            OrderId::new(4),
+            Quantity::new(1),
+            Side::Bid,
+            account("trader"),
+            Timestamp::new(4),
+        );
+        let legs = vec![(order(5, Side::Bid, "trader"), btc), (market_order, btc)];
+        assert!(exchange.post_order_group(legs).is_err());
+        assert!(exchange.markets[&btc].trades().is_empty());
+    }
+
+    #[test]
+    fn test_order_group_reserves_later_legs_from_stops_earlier_legs_trigger() {
+        let btc = pair();
+        let eth = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(btc));
+        exchange.add_market(Market::new(eth));
+        exchange.add_balance(account("seller"), btc.base, 1);
+        exchange.add_balance(account("trader"), btc.numeraire, 1_500);
+        let order = |id: u64, quantity: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(quantity),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, 1, Side::Ask, "seller"), btc)
+            .unwrap();
+        let stop = Order {
+            stop_price: Some(Price::new(100)),
+            ..order(2, 10, Side::Bid, "trader")
+        };
+        exchange.post_order(stop, btc).unwrap();
+
+        // The BTC leg's trade triggers the trader's stop, which must not spend the ETH leg's hold
+        let legs = vec![
+            (order(3, 1, Side::Bid, "trader"), btc),
+            (order(4, 10, Side::Bid, "trader"), eth),
+        ];
+        let (_, trades) = exchange.post_order_group(legs).unwrap();
+        assert_eq!(trades[0].len(), 1);
+        assert_eq!(exchange.markets[&eth].resting_orders().count(), 1);
+        assert_eq!(
+            exchange.drain_events(),
+            vec![ExchangeEvent::StopRejected {
+                pair: btc,
+                order_id: OrderId::new(2),
+                account_id: account("trader"),
+                reason: RejectReason::InsufficientBalance,
+            }]
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), btc.numeraire)
+                .unwrap(),
+            400
+        );
+    }
+
+    #[test]
+    fn test_order_group_is_throttled_as_a_whole() {
+        let mut exchange = Exchange::new();
+        exchange.surveillance = Some(Surveillance::new(SurveillanceConfig {
+            window: 100,
+            max_order_to_trade: 1,
+            max_cancel_to_fill: 100,
+            min_orders: 2,
+            throttle: true,
+        }));
+        exchange.add_balance(account("trader"), pair().numeraire, 1_000);
+        let bid = |id: u64| {
+            let order = Order::new(
+                OrderId::new(id),
+                Price::new(10),
+                Quantity::new(1),
+                Side::Bid,
+                account("trader"),
+                Timestamp::new(1),
+            );
+            (order, pair())
+        };
+
+        // The second leg breaches the ratio, which must not throttle the third
+        let (_, trades) = exchange
+            .post_order_group(vec![bid(1), bid(2), bid(3)])
+            .unwrap();
+        assert_eq!(trades.len(), 3);
+        let error = exchange.post_order_group(vec![bid(4), bid(5)]).unwrap_err();
+        assert_eq!(RejectReason::of(&error), Some(RejectReason::Throttled));
+        assert_eq!(exchange.markets[&pair()].resting_orders().count(), 3);
+    }
+
+    #[test]
+    fn test_spread_order_trades_both_legs() {
+        let usd = Asset::new("USD");
+        let front = Pair {
+            numeraire: usd,
+            base: Asset::new("BTC-MAR"),
+        };
+        let back = Pair {
+            numeraire: usd,
+            base: Asset::new("BTC-JUN"),
+        };
+        let spread = Spread {
+            buy_leg: back,
+            sell_leg: front,
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(front));
+        exchange.add_market(Market::new(back));
+        exchange.add_balance(account("mm"), usd, 10_000);
+        exchange.add_balance(account("mm"), back.base, 10);
+        exchange.add_balance(account("trader"), usd, 1_100);
+        exchange.add_balance(account("trader"), front.base, 10);
+
+        // Back month offered at 110, front month bid at 100
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(110),
+                    Quantity::new(10),
+                    Side::Ask,
+                    account("mm"),
+                    Timestamp::new(1),
+                ),
+                back,
+            )
+            .unwrap();
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(100),
+                    Quantity::new(10),
+                    Side::Bid,
+                    account("mm"),
+                    Timestamp::new(1),
+                ),
+                front,
+            )
+            .unwrap();
+
+        assert_eq!(
+            exchange.implied_spread_quote(spread, Side::Bid),
+            Some(ImpliedQuote {
+                price: 10,
+                quantity: Quantity::new(10),
+            })
+        );
+        assert_eq!(exchange.implied_spread_quote(spread, Side::Ask), None);
+
+        let order = |price: i64| SpreadOrder {
+            id: OrderId::new(2),
+            price,
+            quantity: Quantity::new(10),
+            side: Side::Bid,
+            account_id: account("trader"),
+            timestamp: Timestamp::new(2),
+        };
+        assert!(exchange.post_spread_order(spread, order(9)).is_err());
+
+        let trades = exchange.post_spread_order(spread, order(10)).unwrap();
+        assert_eq!(trades[0].len(), 1);
+        assert_eq!(trades[1].len(), 1);
+        assert_eq!(
+            exchange.get_balance(account("trader"), back.base).unwrap(),
+            10
+        );
+        assert_eq!(
+            exchange.get_balance(account("trader"), front.base).unwrap(),
+            0
+        );
+        assert_eq!(exchange.get_balance(account("trader"), usd).unwrap(), 1_000);
+        assert_eq!(exchange.implied_spread_quote(spread, Side::Bid), None);
+
+        // A spread between legs priced beyond an i64 has no quote
+        exchange.add_balance(account("whale"), usd, u64::MAX);
+        let whale_bid = Order::new(
+            OrderId::new(3),
+            Price::new(u64::MAX),
+            Quantity::new(1),
+            Side::Bid,
+            account("whale"),
+            Timestamp::new(3),
+        );
+        exchange.post_order(whale_bid, back).unwrap();
+        let front_ask = Order::new(
+            OrderId::new(4),
+            Price::new(1),
+            Quantity::new(1),
+            Side::Ask,
+            account("mm"),
+            Timestamp::new(4),
+        );
+        exchange.post_order(front_ask, front).unwrap();
+        assert_eq!(exchange.implied_spread_quote(spread, Side::Ask), None);
+    }
+
+    #[test]
+    fn test_spread_legs_are_immediate_or_cancel() {
+        let usd = Asset::new("USD");
+        let front = Pair {
+            numeraire: usd,
+            base: Asset::new("BTC-MAR"),
+        };
+        let back = Pair {
+            numeraire: usd,
+            base: Asset::new("BTC-JUN"),
+        };
+        let spread = Spread {
+            buy_leg: back,
+            sell_leg: front,
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(front));
+        exchange.add_market(Market::new(back));
+        exchange.add_balance(account("mm"), usd, 1_000);
+
+        // Two asks whose quantities add up beyond a u64
+        for (id, name) in [(1, "mm"), (2, "whale")] {
+            exchange.add_balance(account(name), back.base, u64::MAX);
+            let ask = Order::new(
+                OrderId::new(id),
+                Price::new(110),
+                Quantity::new(u64::MAX / 2 + 1),
+                Side::Ask,
+                account(name),
+                Timestamp::new(id),
+            );
+            exchange.post_order(ask, back).unwrap();
+        }
+        let bid = Order::new(
+            OrderId::new(3),
+            Price::new(100),
+            Quantity::new(5),
+            Side::Bid,
+            account("mm"),
+            Timestamp::new(3),
+        );
+        exchange.post_order(bid, front).unwrap();
+        assert_eq!(
+            exchange.implied_spread_quote(spread, Side::Bid),
+            Some(ImpliedQuote {
+                price: 10,
+                quantity: Quantity::new(5),
+            })
+        );
+        let quote = spread
+            .implied_quote(
+                &exchange.markets[&front],
+                &exchange.markets[&back],
+                Side::Ask,
+            )
+            .unwrap();
+        assert_eq!(quote.quantity, Quantity::new(5));
+
+        let order = SpreadOrder {
+            id: OrderId::new(4),
+            price: 10,
+            quantity: Quantity::new(5),
+            side: Side::Bid,
+            account_id: account("trader"),
+            timestamp: Timestamp::new(4),
+        };
+        let legs = spread
+            .leg_orders(&exchange.markets[&back], &exchange.markets[&front], &order)
+            .unwrap();
+        assert!(legs.iter().all(|(leg, _)| !leg.rests()));
+    }
+
+    #[test]
+    fn test_basket_creation_and_redemption() {
+        let usd = Asset::new("USD");
+        let btc = Asset::new("BTC");
+        let eth = Asset::new("ETH");
+        let index = Asset::new("IDX");
+        let mut exchange = Exchange::new();
+        exchange.add_basket(Basket::new(index, vec![(btc, 1), (eth, 10)]), usd);
+        exchange.add_balance(account("trader"), btc, 3);
+        exchange.add_balance(account("trader"), eth, 25);
+
+        // Three units would need 30 ETH
+        assert!(
+            exchange
+                .create_basket_units(account("trader"), index, 3)
+                .is_err()
+        );
+        assert_eq!(exchange.get_balance(account("trader"), btc).unwrap(), 3);
+
+        exchange
+            .create_basket_units(account("trader"), index, 2)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("trader"), index).unwrap(), 2);
+        assert_eq!(exchange.get_balance(account("trader"), btc).unwrap(), 1);
+        assert_eq!(exchange.get_balance(account("trader"), eth).unwrap(), 5);
+
+        exchange
+            .redeem_basket_units(account("trader"), index, 1)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("trader"), index).unwrap(), 1);
+        assert_eq!(exchange.get_balance(account("trader"), btc).unwrap(), 2);
+        assert_eq!(exchange.get_balance(account("trader"), eth).unwrap(), 15);
+
+        // Amounts that do not fit in a u64 are refused without changing any balance
+        assert!(
+            exchange
+                .create_basket_units(account("trader"), index, u64::MAX / 2 + 1)
+                .is_err()
+        );
+        exchange.add_balance(account("trader"), btc, u64::MAX - 2);
+        assert!(
+            exchange
+                .redeem_basket_units(account("trader"), index, 1)
+                .is_err()
+        );
+        assert_eq!(exchange.get_balance(account("trader"), index).unwrap(), 1);
+        assert_eq!(exchange.get_balance(account("trader"), eth).unwrap(), 15);
+
+        // The basket token trades like any other asset
+        assert!(exchange.markets.contains_key(&Pair {
+            numeraire: usd,
+            base: index,
+        }));
+    }
+
+    #[test]
+    fn test_resting_orders_are_not_withdrawable() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("trader"), pair.base, 10);
+        exchange.add_balance(account("trader"), pair.numeraire, 1_000);
+
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(100),
+                    Quantity::new(6),
+                    Side::Ask,
+                    account("trader"),
+                    Timestamp::new(1),
+                ),
+                pair,
+            )
+            .unwrap();
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(2),
+                    Price::new(90),
+                    Quantity::new(5),
+                    Side::Bid,
+                    account("trader"),
+                    Timestamp::new(2),
+                ),
+                pair,
+            )
+            .unwrap();
+
+        assert_eq!(exchange.locked_balance(&account("trader"), pair.base), 6);
+        assert_eq!(
+            exchange.locked_balance(&account("trader"), pair.numeraire),
+            450
+        );
+        assert_eq!(
+            exchange
+                .withdrawable_balance(account("trader"), pair.base)
+                .unwrap(),
+            4
+        );
+        assert!(exchange.withdraw(account("trader"), pair.base, 5).is_err());
+        exchange.withdraw(account("trader"), pair.base, 4).unwrap();
+        assert_eq!(
+            exchange.get_balance(account("trader"), pair.base).unwrap(),
+            0
+        );
+        assert_eq!(exchange.locked_balance(&account("trader"), pair.base), 6);
+    }
+
+    #[test]
+    fn test_market_bid_refunds_unfilled_hold() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.base, 5);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        for (id, price) in [(1, 100), (2, 110)] {
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(id),
+                        Price::new(price),
+                        Quantity::new(2),
+                        Side::Ask,
+                        account("maker"),
+                        Timestamp::new(id),
+                    ),
+                    pair,
+                )
+                .unwrap();
+        }
+
+        let trades = exchange
+            .post_order(
+                Order::market(
+                    OrderId::new(3),
+                    Quantity::new(6),
+                    Side::Bid,
+                    account("taker"),
+                    Timestamp::new(3),
+                ),
+                pair,
+            )
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 2);
+
+        // Paid 2 * 100 + 2 * 110; the hold for the 2 unfilled units was returned
+        assert_eq!(
+            exchange
+                .get_balance(account("taker"), pair.numeraire)
+                .unwrap(),
+            580
+        );
+        assert_eq!(
+            exchange.get_balance(account("taker"), pair.base).unwrap(),
+            4
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("taker"), pair.numeraire),
+            0
+        );
+    }
+
+    #[test]
+    fn test_close_account() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("trader"), pair.numeraire, 1_000);
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(100),
+                    Quantity::new(2),
+                    Side::Bid,
+                    account("trader"),
+                    Timestamp::new(1),
+                ),
+                pair,
+            )
+            .unwrap();
+
+        assert!(exchange.close_account(account("trader"), 5).is_err());
+        exchange
+            .cancel_order(OrderId::new(1), Price::new(100), Side::Bid, pair)
+            .unwrap();
+        assert!(exchange.close_account(account("trader"), 5).is_err());
+        exchange
+            .withdraw(account("trader"), pair.numeraire, 997)
+            .unwrap();
+        exchange.close_account(account("trader"), 5).unwrap();
+
+        assert_eq!(
+            exchange.drain_events(),
+            vec![ExchangeEvent::AccountClosed {
+                account_id: account("trader"),
+                swept: vec![(pair.numeraire, 3)],
+                archived_entries: 5,
+            }]
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("dust"), pair.numeraire)
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            exchange
+                .account_manager
+                .ledger()
+                .archived(&account("trader"))
+                .len(),
+            5
+        );
+
+        // The ID cannot be used again
+        assert!(
+            exchange
+                .deposit(account("trader"), pair.numeraire, 1)
+                .is_err()
+        );
+        assert!(
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(2),
+                        Price::new(100),
+                        Quantity::new(1),
+                        Side::Ask,
+                        account("trader"),
+                        Timestamp::new(2),
+                    ),
+                    pair,
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_ioc_remainder_is_refunded_not_rested() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.base, 2);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(95),
+                    Quantity::new(2),
+                    Side::Ask,
+                    account("maker"),
+                    Timestamp::new(1),
+                ),
+                pair,
+            )
+            .unwrap();
+
+        let ioc = Order {
+            time_in_force: Some(TimeInForce::Ioc),
+            ..Order::new(
+                OrderId::new(2),
+                Price::new(100),
+                Quantity::new(5),
+                Side::Bid,
+                account("taker"),
+                Timestamp::new(2),
+            )
+        };
+        let trades = exchange.post_order(ioc, pair).unwrap().fills;
+        assert_eq!(trades.len(), 1);
+
+        let book = exchange.markets[&pair].matching_engine.orderbook();
+        assert_eq!(book.get_best_bid(), None);
+        // Paid 2 * 95; nothing stays locked for the 3 unfilled units
+        assert_eq!(
+            exchange
+                .get_balance(account("taker"), pair.numeraire)
+                .unwrap(),
+            810
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("taker"), pair.numeraire),
+            0
+        );
+    }
+
+    #[test]
+    fn test_system_accounts() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        for system in SystemAccount::ALL {
+            assert_eq!(exchange.get_balance(system.id(), pair.base).unwrap(), 0);
+        }
+
+        exchange.add_balance(SystemAccount::Fees.id(), pair.base, 10);
+        let order = Order::new(
+            OrderId::new(1),
+            Price::new(100),
+            Quantity::new(1),
+            Side::Ask,
+            SystemAccount::Fees.id(),
+            Timestamp::new(1),
+        );
+        assert!(exchange.post_order(order, pair).is_err());
+        assert!(
+            exchange
+                .withdraw(SystemAccount::Fees.id(), pair.base, 1)
+                .is_err()
+        );
+        assert!(
+            exchange
+                .close_account(SystemAccount::Fees.id(), 10)
+                .is_err()
+        );
+
+        exchange
+            .system_transfer(SystemAccount::Fees, SystemAccount::Treasury, pair.base, 4)
+            .unwrap();
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Fees.id(), pair.base)
+                .unwrap(),
+            6
+        );
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Treasury.id(), pair.base)
+                .unwrap(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_expire_orders() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.numeraire, 1_000);
+        let order = |id: u64, expires_at: Option<u64>| Order {
+            expires_at: expires_at.map(Timestamp::new),
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(3),
+                Side::Bid,
+                account("maker"),
+                Timestamp::new(1),
+            )
+        };
+        exchange.post_order(order(1, Some(10)), pair).unwrap();
+        exchange.post_order(order(2, None), pair).unwrap();
+        assert!(exchange.post_order(order(3, Some(1)), pair).is_err());
+        assert_eq!(
+            exchange
+                .get_balance(account("maker"), pair.numeraire)
+                .unwrap(),
+            400
+        );
+
+        exchange.expire_orders(Timestamp::new(9));
+        assert!(exchange.drain_events().is_empty());
+
+        exchange.expire_orders(Timestamp::new(10));
+        assert_eq!(
+            exchange.drain_events(),
+            vec![ExchangeEvent::OrderExpired {
+                pair,
+                order_id: OrderId::new(1),
+                account_id: account("maker"),
+                quantity: Quantity::new(3),
+            }]
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("maker"), pair.numeraire)
+                .unwrap(),
+            700
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("maker"), pair.numeraire),
+            300
+        );
+    }
+
+    #[test]
+    fn test_stop_market_order_triggers_on_trade() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.base, 10);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        exchange.add_balance(account("stopper"), pair.numeraire, 1_000);
+        let ask = |id: u64, price: u64| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(2),
+                Side::Ask,
+                account("maker"),
+                Timestamp::new(id),
+            )
+        };
+        exchange.post_order(ask(1, 100), pair).unwrap();
+        exchange.post_order(ask(2, 110), pair).unwrap();
+
+        let stop = Order {
+            stop_price: Some(Price::new(100)),
+            ..Order::market(
+                OrderId::new(3),
+                Quantity::new(2),
+                Side::Bid,
+                account("stopper"),
+                Timestamp::new(3),
+            )
+        };
+        assert!(exchange.post_order(stop, pair).unwrap().fills.is_empty());
+        // Nothing is held while the stop waits
+        assert_eq!(
+            exchange
+                .get_balance(account("stopper"), pair.numeraire)
+                .unwrap(),
+            1_000
+        );
+
+        let bid = Order::new(
+            OrderId::new(4),
+            Price::new(100),
+            Quantity::new(2),
+            Side::Bid,
+            account("taker"),
+            Timestamp::new(4),
+        );
+        let trades = exchange.post_order(bid, pair).unwrap().into_trades();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[1].bid_order_id, OrderId::new(3));
+        assert_eq!(trades[1].price, Price::new(110));
+        assert_eq!(
+            exchange
+                .get_balance(account("stopper"), pair.numeraire)
+                .unwrap(),
+            780
+        );
+        assert_eq!(
+            exchange.get_balance(account("stopper"), pair.base).unwrap(),
+            2
+        );
+        assert!(exchange.markets[&pair].pending_stops().is_empty());
+    }
+
+    #[test]
+    fn test_stop_limit_order_rests_at_limit_when_triggered() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.base, 10);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        exchange.add_balance(account("stopper"), pair.numeraire, 1_000);
+        for (id, price) in [(1, 100), (2, 110)] {
+            let ask = Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(2),
+                Side::Ask,
+                account("maker"),
+                Timestamp::new(id),
+            );
+            exchange.post_order(ask, pair).unwrap();
+        }
+
+        let stop_limit = Order {
+            stop_price: Some(Price::new(100)),
+            ..Order::new(
+                OrderId::new(3),
+                Price::new(105),
+                Quantity::new(2),
+                Side::Bid,
+                account("stopper"),
+                Timestamp::new(3),
+            )
+        };
+        assert!(
+            exchange
+                .post_order(stop_limit, pair)
+                .unwrap()
+                .fills
+                .is_empty()
+        );
+
+        let bid = Order::new(
+            OrderId::new(4),
+            Price::new(100),
+            Quantity::new(2),
+            Side::Bid,
+            account("taker"),
+            Timestamp::new(4),
+        );
+        // The stop triggers but its limit does not reach the ask at 110
+        assert_eq!(exchange.post_order(bid, pair).unwrap().fills.len(), 1);
+        let market = &exchange.markets[&pair];
+        assert!(market.pending_stops().is_empty());
+        assert_eq!(market.matching_engine.orderbook().get_best_bid(), Some(105));
+        assert_eq!(
+            exchange.locked_balance(&account("stopper"), pair.numeraire),
+            210
+        );
+    }
+
+    #[test]
+    fn test_pegged_order_follows_best_bid() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(account("maker"), pair.numeraire, 1_000);
+        exchange.add_balance(account("maker"), pair.base, 10);
+        exchange.add_balance(account("pegger"), pair.numeraire, 1_000);
+        let order = |id: u64, side: Side, price: u64, account_id: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(2),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Bid, 100, "maker"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(2, Side::Ask, 110, "maker"), pair)
+            .unwrap();
+
+        let pegged = Order {
+            peg: Some(Peg {
+                reference: PegReference::BestBid,
+                offset: 1,
+                cap: Some(Price::new(105)),
+            }),
+            ..order(3, Side::Bid, 0, "pegger")
+        };
+        assert!(exchange.post_order(pegged, pair).unwrap().fills.is_empty());
+        let best_bid = |exchange: &Exchange| {
+            exchange.markets[&pair]
+                .matching_engine
+                .orderbook()
+                .get_best_bid()
+        };
+        assert_eq!(best_bid(&exchange), Some(101));
+        assert_eq!(
+            exchange.locked_balance(&account("pegger"), pair.numeraire),
+            202
+        );
+
+        // A better bid moves the peg up to its cap
+        exchange
+            .post_order(order(4, Side::Bid, 104, "maker"), pair)
+            .unwrap();
+        assert_eq!(best_bid(&exchange), Some(105));
+        assert_eq!(
+            exchange.locked_balance(&account("pegger"), pair.numeraire),
+            210
+        );
+
+        // Cancelling it moves the peg back down
+        exchange
+            .cancel_order(OrderId::new(4), Price::new(104), Side::Bid, pair)
+            .unwrap();
+        assert_eq!(best_bid(&exchange), Some(101));
+        assert_eq!(
+            exchange.locked_balance(&account("pegger"), pair.numeraire),
+            202
+        );
+        assert_eq!(
+            exchange
+                .get_balance(account("pegger"), pair.numeraire)
+                .unwrap(),
+            798
+        );
+
+        let unpriced = Order {
+            peg: Some(Peg {
+                reference: PegReference::BestAsk,
+                offset: 0,
+                cap: None,
+            }),
+            ..order(5, Side::Ask, 0, "maker")
+        };
+        exchange
+            .cancel_order(OrderId::new(2), Price::new(110), Side::Ask, pair)
+            .unwrap();
+        assert!(exchange.post_order(unpriced, pair).is_err());
+    }
+
+    #[test]
+    fn test_min_qty() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            pair,
+            MarketConfig {
+                min_qty_shortfall: MinQtyShortfall::Rest,
+                ..MarketConfig::default()
+            },
+        ));
+        exchange.add_balance(account("maker"), pair.base, 10);
+        exchange.add_balance(account("taker"), pair.numeraire, 10_000);
+        let order = |id: u64, side: Side, price: u64, quantity: u64, min_qty: u64| Order {
+            min_qty: Some(Quantity::new(min_qty)),
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(if side == Side::Bid { "taker" } else { "maker" }),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Ask, 100, 3, 0), pair)
+            .unwrap();
+
+        assert!(
+            exchange
+                .post_order(order(2, Side::Bid, 100, 2, 3), pair)
+                .is_err()
+        );
+        // Only 3 of the 4 required are available
+        assert!(
+            exchange
+                .post_order(order(3, Side::Bid, 100, 5, 4), pair)
+                .is_err()
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("taker"), pair.numeraire),
+            0
+        );
+
+        // Nothing crosses, so the order rests untouched
+        assert!(
+            exchange
+                .post_order(order(4, Side::Bid, 99, 5, 4), pair)
+                .unwrap()
+                .fills
+                .is_empty()
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("taker"), pair.numeraire),
+            495
+        );
+
+        let trades = exchange
+            .post_order(order(5, Side::Bid, 100, 5, 3), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, Quantity::new(3));
+    }
+
+    #[test]
+    fn test_flat_fee_converted_from_another_asset() {
+        let (usd, btc, eth) = (Asset::new("USD"), Asset::new("BTC"), Asset::new("ETH"));
+        let btc_usd = Pair {
+            numeraire: usd,
+            base: btc,
+        };
+        let eth_btc = Pair {
+            numeraire: btc,
+            base: eth,
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(btc_usd));
+        exchange.add_market(Market::with_config(
+            eth_btc,
+            MarketConfig {
+                fees: FeeSchedule {
+                    flat_fee: Some(FlatFee {
+                        asset: usd,
+                        amount: 150,
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ));
+        let order = |id: u64, side: Side, price: u64, account_id: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange.add_balance(account("quoter"), usd, 99);
+        exchange.add_balance(account("quoter"), btc, 1);
+        exchange.add_balance(account("maker"), eth, 2);
+        exchange.add_balance(account("taker"), btc, 12);
+        exchange.add_balance(account("bidder"), btc, 11);
+        exchange.add_balance(SystemAccount::Treasury.id(), usd, 150);
+
+        // No BTC/USD book yet, so the fee cannot be priced
+        exchange
+            .post_order(order(1, Side::Ask, 10, "maker"), eth_btc)
+            .unwrap_err();
+        exchange
+            .post_order(order(2, Side::Bid, 99, "quoter"), btc_usd)
+            .unwrap();
+        exchange
+            .post_order(order(3, Side::Ask, 101, "quoter"), btc_usd)
+            .unwrap();
+
+        // 1 BTC is worth 100 USD, so the fee is 1.5 BTC, rounded up to 2
+        exchange
+            .post_order(order(4, Side::Ask, 10, "maker"), eth_btc)
+            .unwrap();
+        exchange
+            .post_order(order(5, Side::Bid, 10, "taker"), eth_btc)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("taker"), btc).unwrap(), 0);
+        assert_eq!(exchange.get_balance(account("taker"), eth).unwrap(), 1);
+        // The treasury converts the fee, so the fees account is paid in USD
+        let treasury = SystemAccount::Treasury.id();
+        let fees = SystemAccount::Fees.id();
+        assert_eq!(exchange.get_balance(treasury.clone(), btc).unwrap(), 2);
+        assert_eq!(exchange.get_balance(treasury.clone(), usd).unwrap(), 0);
+        assert_eq!(exchange.get_balance(fees.clone(), usd).unwrap(), 150);
+        let ledger = exchange.account_manager.ledger();
+        assert!(ledger.entries_for(&treasury).any(|entry| entry.asset == btc
+            && entry.direction == Direction::Credit
+            && entry.amount == 2));
+        assert!(ledger.entries_for(&fees).any(|entry| entry.asset == usd
+            && entry.direction == Direction::Credit
+            && entry.amount == 150));
+
+        // A resting bid does not pay the fee, but the taker ask does, out of its proceeds
+        exchange
+            .post_order(order(6, Side::Bid, 9, "bidder"), eth_btc)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("bidder"), btc).unwrap(), 2);
+        exchange
+            .post_order(order(7, Side::Ask, 9, "maker"), eth_btc)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("maker"), btc).unwrap(), 17);
+        // The treasury is out of USD, so the fees account keeps the BTC
+        assert_eq!(exchange.get_balance(fees, btc).unwrap(), 2);
+        assert_eq!(exchange.get_balance(treasury, usd).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_reduce_only_orders_are_capped_to_position() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.numeraire, 1_000);
+        exchange.add_balance(account("bob"), pair.base, 10);
+        exchange.add_balance(account("bob"), pair.numeraire, 1_000);
+        let order = |id: u64, side: Side, quantity: u64, account_id: &str| Order {
+            reduce_only: id > 2,
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(quantity),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Ask, 3, "bob"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(2, Side::Bid, 3, "alice"), pair)
+            .unwrap();
+        assert_eq!(exchange.position(&account("alice"), pair), 3);
+        assert_eq!(exchange.position(&account("bob"), pair), -3);
+
+        // Alice is long, so she can only sell, and no more than she holds
+        assert!(
+            exchange
+                .post_order(order(3, Side::Bid, 1, "alice"), pair)
+                .is_err()
+        );
+        exchange
+            .post_order(order(4, Side::Ask, 5, "alice"), pair)
+            .unwrap();
+        assert_eq!(exchange.locked_balance(&account("alice"), pair.base), 3);
+
+        // Bob covers his short against it, capped to 3 as well
+        let trades = exchange
+            .post_order(order(5, Side::Bid, 5, "bob"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, Quantity::new(3));
+        assert_eq!(exchange.position(&account("alice"), pair), 0);
+        assert_eq!(exchange.position(&account("bob"), pair), 0);
+        assert!(
+            exchange
+                .post_order(order(6, Side::Ask, 1, "bob"), pair)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_balance_thresholds_raise_alerts_when_breached() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.numeraire, 1_000);
+        let below = BalanceThreshold::Below(500);
+        exchange.register_balance_threshold(account("alice"), pair.numeraire, below);
+        exchange.register_balance_threshold(
+            account("alice"),
+            pair.base,
+            BalanceThreshold::Above(0),
+        );
+        assert!(exchange.drain_events().is_empty());
+
+        // The hold of a resting bid makes the funds unavailable
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(1),
+                    Price::new(100),
+                    Quantity::new(6),
+                    Side::Bid,
+                    account("alice"),
+                    Timestamp::new(1),
+                ),
+                pair,
+            )
+            .unwrap();
+        let alert = |balance: u64| ExchangeEvent::BalanceAlert {
+            account_id: account("alice"),
+            asset: pair.numeraire,
+            threshold: below,
+            balance,
+        };
+        assert_eq!(exchange.drain_events(), vec![alert(400)]);
+
+        // Recovering re-arms the threshold
+        exchange
+            .cancel_order(OrderId::new(1), Price::new(100), Side::Bid, pair)
+            .unwrap();
+        exchange
+            .withdraw(account("alice"), pair.numeraire, 900)
+            .unwrap();
+        assert_eq!(exchange.drain_events(), vec![alert(100)]);
+
+        exchange.clear_balance_thresholds(&account("alice"));
+        exchange.add_balance(account("alice"), pair.base, 1);
+        assert!(exchange.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_protection_price_stops_market_sweep() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("maker"), pair.base, 9);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        for (id, price, quantity) in [(1, 100, 2), (2, 110, 2), (3, 200, 5)] {
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(id),
+                        Price::new(price),
+                        Quantity::new(quantity),
+                        Side::Ask,
+                        account("maker"),
+                        Timestamp::new(id),
+                    ),
+                    pair,
+                )
+                .unwrap();
+        }
+        let market_bid = |id: u64, protection_price: Option<u64>| Order {
+            protection_price: protection_price.map(Price::new),
+            ..Order::market(
+                OrderId::new(id),
+                Quantity::new(6),
+                Side::Bid,
+                account("taker"),
+                Timestamp::new(id),
+            )
+        };
+
+        // Unprotected, the sweep would reach 200 and hold more than the taker has
+        assert!(exchange.post_order(market_bid(4, None), pair).is_err());
+        let trades = exchange
+            .post_order(market_bid(5, Some(110)), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 2);
+        assert_eq!(
+            exchange
+                .get_balance(account("taker"), pair.numeraire)
+                .unwrap(),
+            1_000 - 200 - 220
+        );
+        assert_eq!(
+            exchange.markets[&pair]
+                .matching_engine
+                .orderbook()
+                .get_best_ask(),
+            Some(200)
+        );
+
+        let limit = Order {
+            protection_price: Some(Price::new(200)),
+            ..Order::new(
+                OrderId::new(6),
+                Price::new(200),
+                Quantity::new(1),
+                Side::Bid,
+                account("taker"),
+                Timestamp::new(6),
+            )
+        };
+        assert!(exchange.post_order(limit, pair).is_err());
+    }
+
+    #[test]
+    fn test_client_order_ids_are_echoed_and_deduplicated() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("maker"), pair.base, 2_000);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        let order = |id: u64, side: Side, account_id: &str, client_order_id: &str| Order {
+            client_order_id: Some(ClientOrderId::new(client_order_id.to_string())),
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(1),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Ask, "maker", "a"), pair)
+            .unwrap();
+        assert!(
+            exchange
+                .post_order(order(2, Side::Ask, "maker", "a"), pair)
+                .is_err()
+        );
+        // IDs are per account, and rejected orders do not use theirs up
+        assert!(
+            exchange
+                .post_order(order(3, Side::Bid, "nobody", "b"), pair)
+                .is_err()
+        );
+        let trades = exchange
+            .post_order(order(4, Side::Bid, "taker", "b"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(
+            trades[0].ask_client_order_id,
+            Some(ClientOrderId::new("a".to_string()))
+        );
+        assert_eq!(
+            trades[0].bid_client_order_id,
+            Some(ClientOrderId::new("b".to_string()))
+        );
+
+        // Old IDs drop out of the window and can be reused
+        for id in 0..CLIENT_ORDER_ID_WINDOW as u64 {
+            exchange
+                .post_order(order(10 + id, Side::Ask, "maker", &id.to_string()), pair)
+                .unwrap();
+        }
+        exchange
+            .post_order(order(5, Side::Ask, "maker", "a"), pair)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_orderbook_view_aggregates_levels() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        assert!(exchange.orderbook(pair).is_none());
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 100);
+        exchange.add_balance(account("bob"), pair.numeraire, 10_000);
+        let orders = [
+            (1, 101, 2, Side::Ask, "alice"),
+            (2, 101, 3, Side::Ask, "alice"),
+            (3, 103, 1, Side::Ask, "alice"),
+            (4, 99, 4, Side::Bid, "bob"),
+            (5, 98, 1, Side::Bid, "bob"),
+        ];
+        for (id, price, quantity, side, account_id) in orders {
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(id),
+                        Price::new(price),
+                        Quantity::new(quantity),
+                        side,
+                        account(account_id),
+                        Timestamp::new(id),
+                    ),
+                    pair,
+                )
+                .unwrap();
+        }
+
+        let view = exchange.orderbook(pair).unwrap();
+        let level = |price: u64, quantity: u64, orders: usize| BookLevel {
+            price: Price::new(price),
+            quantity: Quantity::new(quantity),
+            orders,
+        };
+        assert_eq!(view.asks, vec![level(101, 5, 2), level(103, 1, 1)]);
+        assert_eq!(view.bids, vec![level(99, 4, 1), level(98, 1, 1)]);
+    }
+
+    #[test]
+    fn test_place_order_assigns_ids() {
+        let pair = pair();
+        let other = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_market(Market::new(other));
+        exchange.add_balance(account("alice"), pair.numeraire, 1_000);
+        let bid = |price: u64| {
+            Order::new(
+                OrderId::new(0),
+                Price::new(price),
+                Quantity::new(1),
+                Side::Bid,
+                account("alice"),
+                Timestamp::new(1),
+            )
+        };
+        let ack = exchange.place_order(bid(100), pair).unwrap();
+        assert_eq!(ack.order_id, OrderId::new(1));
+        assert!(ack.fills.is_empty());
+        // Rejected orders do not use up an ID
+        assert!(exchange.place_order(bid(2_000), pair).is_err());
+        let ack = exchange.place_order(bid(100), other).unwrap();
+        assert_eq!(ack.order_id, OrderId::new(2));
+        // Sequences continue after IDs chosen by the caller
+        let mut order = bid(100);
+        order.id = OrderId::new(10);
+        exchange.post_order(order, pair).unwrap();
+        assert_eq!(
+            exchange.place_order(bid(100), other).unwrap().order_id,
+            OrderId::new(11)
+        );
+
+        exchange.order_id_scope = OrderIdScope::Market;
+        assert_eq!(
+            exchange.place_order(bid(100), pair).unwrap().order_id,
+            OrderId::new(11)
+        );
+        assert_eq!(
+            exchange.place_order(bid(100), other).unwrap().order_id,
+            OrderId::new(12)
+        );
+
+        // A restored exchange continues after the highest resting ID
+        let mut restored = Exchange::from_snapshot(&exchange.snapshot());
+        restored.order_id_scope = OrderIdScope::Market;
+        assert_eq!(
+            restored.place_order(bid(100), pair).unwrap().order_id,
+            OrderId::new(12)
+        );
+    }
+
+    #[test]
+    fn test_clock_stamps_placed_orders_and_trades() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 10);
+        exchange.add_balance(account("bob"), pair.numeraire, 1_000);
+        exchange.clock = Some(Box::new(TestClock::new(1_000, 10)));
+        let order = |price: u64, side: Side, account_id: &str| {
+            Order::new(
+                OrderId::new(0),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                account(account_id),
+                Timestamp::new(0),
+            )
+        };
+        // A stop waiting for a trade at 100 or more
+        let stop = Order {
+            stop_price: Some(Price::new(100)),
+            ..order(100, Side::Bid, "bob")
+        };
+        exchange.place_order(stop, pair).unwrap();
+        exchange
+            .place_order(order(100, Side::Ask, "alice"), pair)
+            .unwrap();
+        exchange
+            .place_order(order(100, Side::Ask, "alice"), pair)
+            .unwrap();
+        exchange
+            .place_order(order(100, Side::Bid, "bob"), pair)
+            .unwrap();
+
+        let market = &exchange.markets[&pair];
+        let times: Vec<u64> = market
+            .public_trades()
+            .map(|trade| trade.timestamp.get())
+            .collect();
+        // The stop trades at the time of the trade that triggered it
+        assert_eq!(times, vec![1_030, 1_030]);
+        assert_eq!(market.trades()[1].bid_order_id, OrderId::new(1));
+    }
+
+    #[test]
+    fn test_reduce_order_keeps_priority_and_releases_hold() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 10);
+        exchange.add_balance(account("bob"), pair.base, 10);
+        exchange.add_balance(account("carol"), pair.numeraire, 1_000);
+        let order = |id: u64, side: Side, quantity: u64, account_id: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(quantity),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, Side::Ask, 5, "alice"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(2, Side::Ask, 5, "bob"), pair)
+            .unwrap();
+        let reduce = |exchange: &mut Exchange, id: u64, side: Side, quantity: u64| {
+            exchange.reduce_order(
+                OrderId::new(id),
+                Price::new(100),
+                side,
+                pair,
+                Quantity::new(quantity),
+            )
+        };
+        assert!(reduce(&mut exchange, 1, Side::Ask, 5).is_err());
+        assert!(reduce(&mut exchange, 1, Side::Ask, 0).is_err());
+        assert!(reduce(&mut exchange, 9, Side::Ask, 1).is_err());
+        reduce(&mut exchange, 1, Side::Ask, 2).unwrap();
+        assert_eq!(
+            exchange.get_balance(account("alice"), pair.base).unwrap(),
+            8
+        );
+
+        // Alice keeps her place at the front of the queue
+        let trades = exchange
+            .post_order(order(4, Side::Bid, 3, "carol"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades[0].ask_order_id, OrderId::new(1));
+        assert_eq!(trades[0].quantity, Quantity::new(2));
+        assert_eq!(trades[1].ask_order_id, OrderId::new(2));
+    }
+
+    #[test]
+    fn test_amend_order() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.numeraire, 1_000);
+        exchange.add_balance(account("bob"), pair.numeraire, 1_000);
+        exchange.add_balance(account("carol"), pair.base, 10);
+        let bid = |id: u64, quantity: u64, account_id: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(50),
+                Quantity::new(quantity),
+                Side::Bid,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange.post_order(bid(1, 4, "alice"), pair).unwrap();
+        exchange.post_order(bid(2, 4, "bob"), pair).unwrap();
+        let amend = |exchange: &mut Exchange, price: u64, quantity: u64| {
+            exchange.amend_order(
+                OrderId::new(1),
+                Price::new(50),
+                Side::Bid,
+                pair,
+                Price::new(price),
+                Quantity::new(quantity),
+                Timestamp::new(10),
+            )
+        };
+
+        // Reducing keeps Alice ahead of Bob
+        amend(&mut exchange, 50, 2).unwrap();
+        assert_eq!(
+            exchange
+                .get_balance(account("alice"), pair.numeraire)
+                .unwrap(),
+            900
+        );
+        let book = exchange.orderbook(pair).unwrap();
+        assert_eq!(book.bids[0].quantity, Quantity::new(6));
+
+        // An amend Alice cannot afford leaves her order as it was
+        assert!(amend(&mut exchange, 50, 100).is_err());
+        assert_eq!(
+            exchange
+                .get_balance(account("alice"), pair.numeraire)
+                .unwrap(),
+            900
+        );
+        let sell = |exchange: &mut Exchange, id: u64| {
+            exchange.post_order(
+                Order::new(
+                    OrderId::new(id),
+                    Price::new(50),
+                    Quantity::new(1),
+                    Side::Ask,
+                    account("carol"),
+                    Timestamp::new(id),
+                ),
+                pair,
+            )
+        };
+        assert_eq!(
+            sell(&mut exchange, 3).unwrap().fills[0].bid_order_id,
+            OrderId::new(1)
+        );
+
+        // Increasing the quantity sends Alice behind Bob
+        amend(&mut exchange, 50, 3).unwrap();
+        assert_eq!(
+            exchange
+                .get_balance(account("alice"), pair.numeraire)
+                .unwrap(),
+            800
+        );
+        assert_eq!(
+            sell(&mut exchange, 4).unwrap().fills[0].bid_order_id,
+            OrderId::new(2)
+        );
+    }
+
+    #[test]
+    fn test_order_tags_are_echoed_in_trades() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("maker"), pair.base, 10);
+        exchange.add_balance(account("taker"), pair.numeraire, 1_000);
+        let tagged = |id: u64, side: Side, account_id: &str, tag: Option<&str>| Order {
+            tag: tag.map(|tag| OrderTag::new(tag.to_string())),
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(1),
+                side,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(tagged(1, Side::Ask, "maker", Some("market-making")), pair)
+            .unwrap();
+        let trades = exchange
+            .post_order(tagged(2, Side::Bid, "taker", None), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(
+            trades[0].ask_tag,
+            Some(OrderTag::new("market-making".to_string()))
+        );
+        assert_eq!(trades[0].bid_tag, None);
+    }
+
+    #[test]
+    fn test_cancel_replace() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 10);
+        exchange.add_balance(account("bob"), pair.base, 10);
+        let ask = |id: u64, price: u64, quantity: u64, account_id: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                Side::Ask,
+                account(account_id),
+                Timestamp::new(id),
+            )
+        };
+        exchange.post_order(ask(1, 100, 4, "alice"), pair).unwrap();
+        let replace = |exchange: &mut Exchange, replacement: Order| {
+            exchange.cancel_replace(
+                OrderId::new(1),
+                Price::new(100),
+                Side::Ask,
+                pair,
+                replacement,
+            )
+        };
+
+        // Rejected replacements leave the original resting
+        assert!(replace(&mut exchange, ask(2, 105, 20, "alice")).is_err());
+        assert!(replace(&mut exchange, ask(2, 105, 4, "bob")).is_err());
+        assert_eq!(
+            exchange.get_balance(account("alice"), pair.base).unwrap(),
+            6
+        );
+        assert_eq!(
+            exchange.orderbook(pair).unwrap().asks[0].price,
+            Price::new(100)
+        );
+
+        let (cancelled, trades) = replace(&mut exchange, ask(2, 105, 8, "alice")).unwrap();
+        assert_eq!(cancelled.id, OrderId::new(1));
+        assert!(trades.is_empty());
+        assert_eq!(
+            exchange.get_balance(account("alice"), pair.base).unwrap(),
+            2
+        );
+        let book = exchange.orderbook(pair).unwrap();
+        assert_eq!(book.asks.len(), 1);
+        assert_eq!(book.asks[0].price, Price::new(105));
+        assert_eq!(book.asks[0].quantity, Quantity::new(8));
+
+        // The original is gone, so nothing is posted
+        assert!(replace(&mut exchange, ask(3, 110, 1, "alice")).is_err());
+        assert_eq!(
+            exchange.get_balance(account("alice"), pair.base).unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_segregated_odd_lots() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            pair,
+            MarketConfig {
+                odd_lots: OddLots::Segregated { round_lot: 100 },
+                ..MarketConfig::default()
+            },
+        ));
+        exchange.add_balance(account("alice"), pair.base, 1_000);
+        exchange.add_balance(account("bob"), pair.numeraire, 100_000);
+        let order = |id: u64, price: u64, quantity: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+
+        // Mixed lots fit neither book
+        assert!(
+            exchange
+                .post_order(order(1, 100, 150, Side::Ask, "alice"), pair)
+                .is_err()
+        );
+        exchange
+            .post_order(order(2, 100, 100, Side::Ask, "alice"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(3, 99, 30, Side::Ask, "alice"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(4, 105, 200, Side::Ask, "alice"), pair)
+            .unwrap();
+
+        // A round lot skips the cheaper odd lot, and an odd lot only sees odd lots
+        let trades = exchange
+            .post_order(order(5, 101, 100, Side::Bid, "bob"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].ask_order_id, OrderId::new(2));
+        let trades = exchange
+            .post_order(order(6, 101, 20, Side::Bid, "bob"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].ask_order_id, OrderId::new(3));
+        assert_eq!(trades[0].price, Price::new(99));
+        assert_eq!(exchange.locked_balance(&account("alice"), pair.base), 210);
+
+        // Round lots can only be reduced to whole round lots
+        let reduce = |exchange: &mut Exchange, quantity: u64| {
+            exchange.reduce_order(
+                OrderId::new(4),
+                Price::new(105),
+                Side::Ask,
+                pair,
+                Quantity::new(quantity),
+            )
+        };
+        assert!(reduce(&mut exchange, 150).is_err());
+        assert!(reduce(&mut exchange, 50).is_err());
+        reduce(&mut exchange, 100).unwrap();
+
+        // Odd lots are restored to the odd-lot book
+        let mut restored = Exchange::from_snapshot(&exchange.snapshot());
+        assert_eq!(restored.snapshot(), exchange.snapshot());
+        let trades = restored
+            .post_order(order(7, 99, 10, Side::Bid, "bob"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].ask_order_id, OrderId::new(3));
+        assert!(
+            restored.markets[&pair]
+                .resting_orders()
+                .all(|o| o.id == OrderId::new(4))
+        );
+    }
+
+    #[test]
+    fn test_partially_filled_remainder_keeps_its_hold() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 10);
+        exchange.add_balance(account("bob"), pair.numeraire, 2_000);
+        let order = |id: u64, price: u64, quantity: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, 100, 3, Side::Ask, "alice"), pair)
+            .unwrap();
+
+        // Bob pays 300 for the fill and keeps 7 * 101 held for the resting remainder
+        let trades = exchange
+            .post_order(order(2, 101, 10, Side::Bid, "bob"), pair)
+            .unwrap()
+            .fills;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(
+            exchange
+                .get_balance(account("bob"), pair.numeraire)
+                .unwrap(),
+            993
+        );
+        assert_eq!(
+            exchange.locked_balance(&account("bob"), pair.numeraire),
+            707
+        );
+        let view = exchange.orderbook(pair).unwrap();
+        assert_eq!(view.bids[0].price, Price::new(101));
+        assert_eq!(view.bids[0].quantity, Quantity::new(7));
+
+        // The remainder trades as a maker, and cancelling it releases what is left
+        exchange
+            .post_order(order(3, 101, 4, Side::Ask, "alice"), pair)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("bob"), pair.base).unwrap(), 7);
+        assert_eq!(
+            exchange.locked_balance(&account("bob"), pair.numeraire),
+            303
+        );
+        exchange
+            .cancel_order(OrderId::new(2), Price::new(101), Side::Bid, pair)
+            .unwrap();
+        assert_eq!(
+            exchange
+                .get_balance(account("bob"), pair.numeraire)
+                .unwrap(),
+            1_296
+        );
+        assert_eq!(exchange.locked_balance(&account("bob"), pair.numeraire), 0);
+        assert_eq!(
+            exchange
+                .get_balance(account("alice"), pair.numeraire)
+                .unwrap(),
+            704
+        );
+    }
+
+    #[test]
+    fn test_amend_cannot_jump_the_queue() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.numeraire, 1_000);
+        exchange.add_balance(account("bob"), pair.numeraire, 1_000);
+        exchange.add_balance(account("carol"), pair.base, 10);
+        let bid = |id: u64, price: u64, account_id: &str, timestamp: u64| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(2),
+                Side::Bid,
+                account(account_id),
+                Timestamp::new(timestamp),
+            )
+        };
+        exchange.post_order(bid(1, 49, "alice", 5), pair).unwrap();
+        exchange.post_order(bid(2, 50, "bob", 6), pair).unwrap();
+        let amend = |exchange: &mut Exchange, timestamp: u64| {
+            exchange.amend_order(
+                OrderId::new(1),
+                Price::new(49),
+                Side::Bid,
+                pair,
+                Price::new(50),
+                Quantity::new(2),
+                Timestamp::new(timestamp),
+            )
+        };
+
+        // Re-posted before it first arrived, Alice's bid would rank ahead of Bob's
+        assert!(amend(&mut exchange, 4).is_err());
+        amend(&mut exchange, 7).unwrap();
+        let trades = exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(3),
+                    Price::new(50),
+                    Quantity::new(4),
+                    Side::Ask,
+                    account("carol"),
+                    Timestamp::new(8),
+                ),
+                pair,
+            )
+            .unwrap()
+            .fills;
+        let filled: Vec<OrderId> = trades.iter().map(|trade| trade.bid_order_id).collect();
+        assert_eq!(filled, vec![OrderId::new(2), OrderId::new(1)]);
+    }
+
+    #[test]
+    fn test_trade_ids_and_sequence_numbers() {
+        let eth = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair()));
+        exchange.add_market(Market::new(eth));
+        // SOL gets its market from its first order
+        let sol = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("SOL"),
+        };
+        exchange.add_balance(account("buyer"), pair().numeraire, 10_000);
+        for pair in [pair(), eth, sol] {
+            exchange.add_balance(account("seller"), pair.base, 10);
+        }
+        let mut next_id = 1;
+        let mut trade = |exchange: &mut Exchange, pair: Pair| {
+            for (side, name) in [(Side::Ask, "seller"), (Side::Bid, "buyer")] {
+                let order = Order::new(
+                    OrderId::new(next_id),
+                    Price::new(100),
+                    Quantity::new(1),
+                    side,
+                    account(name),
+                    Timestamp::new(next_id),
+                );
+                next_id += 1;
+                let trades = exchange.post_order(order, pair).unwrap().fills;
+                if side == Side::Bid {
+                    return (trades[0].id.get(), trades[0].sequence);
+                }
+            }
+            unreachable!()
+        };
+
+        assert_eq!(trade(&mut exchange, pair()), (1, 1));
+        assert_eq!(trade(&mut exchange, eth), (2, 1));
+        assert_eq!(trade(&mut exchange, pair()), (3, 2));
+        let feed: Vec<(TradeId, u64)> = exchange.markets[&pair()]
+            .public_trades()
+            .map(|trade| (trade.id, trade.sequence))
+            .collect();
+        assert_eq!(feed, vec![(TradeId::new(1), 1), (TradeId::new(3), 2)]);
+        assert_eq!(trade(&mut exchange, sol), (4, 1));
+
+        // A restored exchange continues both sequences
+        let mut restored = Exchange::from_snapshot(&exchange.snapshot());
+        assert_eq!(trade(&mut restored, eth), (5, 2));
+    }
 }
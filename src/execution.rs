@@ -0,0 +1,178 @@
+//! What became of an order once it was processed.
+
+use crate::{
+    matching::{SelfTradeCancel, Trade},
+    order::{Order, OrderId, Price, Quantity},
+};
+
+/// The state of an order after it was processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    /// Nothing filled and the order is working: resting on the book or waiting for its stop.
+    New,
+    /// Part of the order filled. What is left rests on the book, or was discarded if the
+    /// order could not rest.
+    PartiallyFilled,
+    Filled,
+    /// Nothing filled and nothing is left working, e.g. an immediate-or-cancel order that
+    /// found nothing to match. Orders the exchange refuses outright are errors instead.
+    Rejected,
+}
+
+/// The outcome of processing one order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionReport {
+    /// The ID the order was processed under.
+    pub order_id: OrderId,
+    pub status: OrderStatus,
+    /// The order's own trades, in execution order.
+    pub fills: Vec<Trade>,
+    /// Trades of stop orders the order triggered, after its own fills.
+    pub triggered: Vec<Trade>,
+    /// The quantity still working on the book or as a pending stop.
+    pub remaining: Quantity,
+    /// The quantity-weighted average price of the fills, rounded down, or `None` if
+    /// nothing filled.
+    pub average_price: Option<Price>,
+}
+
+impl ExecutionReport {
+    /// Reports on an order of `quantity` from its fills and the quantity it left working.
+    pub(crate) fn new(
+        order_id: OrderId,
+        quantity: Quantity,
+        fills: Vec<Trade>,
+        triggered: Vec<Trade>,
+        remaining: Quantity,
+    ) -> Self {
+        let filled: u64 = fills.iter().map(|trade| trade.quantity.get()).sum();
+        let notional: u128 = fills
+            .iter()
+            .map(|trade| trade.price.get() as u128 * trade.quantity.get() as u128)
+            .sum();
+        let status = if filled == 0 && remaining.get() == 0 {
+            OrderStatus::Rejected
+        } else if filled == 0 {
+            OrderStatus::New
+        } else if filled < quantity.get() {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Filled
+        };
+        Self {
+            order_id,
+            status,
+            fills,
+            triggered,
+            remaining,
+            average_price: (filled > 0).then(|| Price::new((notional / filled as u128) as u64)),
+        }
+    }
+
+    /// The quantity an order processed without a stop leaves on the book: none unless it
+    /// can rest, and otherwise what neither filled nor self-trade prevention took out.
+    pub(crate) fn resting(order: &Order, fills: &[Trade], cancels: &[SelfTradeCancel]) -> Quantity {
+        if !order.rests() {
+            return Quantity::new(0);
+        }
+        let taken: u64 = fills
+            .iter()
+            .map(|trade| trade.quantity.get())
+            .chain(
+                cancels
+                    .iter()
+                    .filter(|cancel| cancel.order.side == order.side)
+                    .map(|cancel| cancel.cancelled.get()),
+            )
+            .sum();
+        Quantity::new(order.quantity.get() - taken)
+    }
+
+    /// The quantity filled.
+    pub fn filled(&self) -> Quantity {
+        Quantity::new(self.fills.iter().map(|trade| trade.quantity.get()).sum())
+    }
+
+    /// Every trade the order executed, its own fills first and then those of the stops it
+    /// triggered.
+    pub fn into_trades(self) -> Vec<Trade> {
+        let mut trades = self.fills;
+        trades.extend(self.triggered);
+        trades
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        exchange::Exchange,
+        market::{Market, Pair},
+        order::{AccountId, Side, TimeInForce, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_execution_report_statuses() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.base, 10);
+        exchange.add_balance(bob.clone(), pair.numeraire, 10_000);
+        let order = |id: u64, price: u64, qty: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(qty),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        let report = exchange
+            .post_order(order(1, 100, 2, Side::Ask, &alice), pair)
+            .unwrap();
+        assert_eq!(report.status, OrderStatus::New);
+        assert_eq!(
+            (report.remaining, report.average_price),
+            (Quantity::new(2), None)
+        );
+        exchange
+            .post_order(order(2, 103, 2, Side::Ask, &alice), pair)
+            .unwrap();
+
+        let report = exchange
+            .post_order(order(3, 105, 5, Side::Bid, &bob), pair)
+            .unwrap();
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert_eq!(report.order_id, OrderId::new(3));
+        assert_eq!(
+            (report.filled(), report.remaining),
+            (Quantity::new(4), Quantity::new(1))
+        );
+        // 2 at 100 and 2 at 103
+        assert_eq!(report.average_price, Some(Price::new(101)));
+
+        let report = exchange
+            .post_order(order(4, 105, 1, Side::Ask, &alice), pair)
+            .unwrap();
+        assert_eq!(report.status, OrderStatus::Filled);
+        assert_eq!(report.remaining, Quantity::new(0));
+
+        let ioc = order(5, 90, 1, Side::Ask, &alice);
+        let ioc = Order {
+            time_in_force: Some(TimeInForce::Ioc),
+            ..ioc
+        };
+        let report = exchange.post_order(ioc, pair).unwrap();
+        assert_eq!(report.status, OrderStatus::Rejected);
+        assert!(report.into_trades().is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+//! A level-based market data feed and a conformance kit for its consumers.
+//!
+//! The feed publishes a market's public book aggregated by price level, as in `BookView`.
+//! It starts with a snapshot, continues with deltas that each replace or remove one level,
+//! and repeats the snapshot of the book every `snapshot_interval` messages so consumers can
+//! resynchronise. Every message carries the checksum of the book once it is applied.
+//!
+//! A consumer conforms if applying the deltas of a recorded feed to its snapshot reproduces
+//! every later snapshot and every checksum exactly. `certify` replays a recorded feed into
+//! any `FeedConsumer` and reports the first message it disagrees with. Feeds are recorded as
+//! JSON lines so they can be shared with consumers written in other languages.
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+
+use crate::{
+    market::{BookLevel, BookView, Market},
+    order::{Price, Quantity, Side},
+};
+
+/// The new state of one price level. A level with no quantity left is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelDelta {
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub orders: usize,
+}
+
+/// A message of the feed, numbered from zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedMessage {
+    /// The whole book, which replaces whatever the consumer holds.
+    Snapshot {
+        seq: u64,
+        book: BookView,
+        checksum: u64,
+    },
+    /// Level changes to apply in order.
+    Delta {
+        seq: u64,
+        deltas: Vec<LevelDelta>,
+        checksum: u64,
+    },
+}
+
+impl FeedMessage {
+    pub fn seq(&self) -> u64 {
+        match self {
+            FeedMessage::Snapshot { seq, .. } | FeedMessage::Delta { seq, .. } => *seq,
+        }
+    }
+
+    /// The checksum of the book after the message is applied.
+    pub fn checksum(&self) -> u64 {
+        match self {
+            FeedMessage::Snapshot { checksum, .. } | FeedMessage::Delta { checksum, .. } => {
+                *checksum
+            }
+        }
+    }
+
+    /// Encode the message as one line of JSON.
+    pub fn to_json(&self) -> String {
+        let line = match self {
+            FeedMessage::Snapshot {
+                seq,
+                book,
+                checksum,
+            } => {
+                let levels = |levels: &[BookLevel]| -> Vec<Value> {
+                    levels
+                        .iter()
+                        .map(|level| json!([level.price.get(), level.quantity.get(), level.orders]))
+                        .collect()
+                };
+                json!({
+                    "type": "snapshot",
+                    "seq": seq,
+                    "bids": levels(&book.bids),
+                    "asks": levels(&book.asks),
+                    "checksum": checksum,
+                })
+            }
+            FeedMessage::Delta {
+                seq,
+                deltas,
+                checksum,
+            } => json!({
+                "type": "delta",
+                "seq": seq,
+                "deltas": deltas
+                    .iter()
+                    .map(|delta| json!([
+                        match delta.side {
+                            Side::Bid => "bid",
+                            Side::Ask => "ask",
+                        },
+                        delta.price.get(),
+                        delta.quantity.get(),
+                        delta.orders,
+                    ]))
+                    .collect::<Vec<Value>>(),
+                "checksum": checksum,
+            }),
+        };
+        line.to_string()
+    }
+
+    /// Decode a message from a line written by `to_json`.
+    pub fn from_json(line: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(line)?;
+        let u64_at = |value: &Value, what: &str| {
+            value
+                .as_u64()
+                .with_context(|| format!("Invalid {} in feed message", what))
+        };
+        let seq = u64_at(&value["seq"], "seq")?;
+        let checksum = u64_at(&value["checksum"], "checksum")?;
+        let fields = |value: &Value, len: usize| -> Result<Vec<Value>> {
+            match value.as_array() {
+                Some(fields) if fields.len() == len => Ok(fields.clone()),
+                _ => Err(anyhow::anyhow!("Invalid level in feed message")),
+            }
+        };
+        match value["type"].as_str() {
+            Some("snapshot") => {
+                let levels = |levels: &Value| -> Result<Vec<BookLevel>> {
+                    let levels = levels
+                        .as_array()
+                        .context("Invalid levels in feed message")?;
+                    levels
+                        .iter()
+                        .map(|level| {
+                            let fields = fields(level, 3)?;
+                            Ok(BookLevel {
+                                price: Price::new(u64_at(&fields[0], "price")?),
+                                quantity: Quantity::new(u64_at(&fields[1], "quantity")?),
+                                orders: u64_at(&fields[2], "orders")? as usize,
+                            })
+                        })
+                        .collect()
+                };
+                Ok(FeedMessage::Snapshot {
+                    seq,
+                    book: BookView {
+                        bids: levels(&value["bids"])?,
+                        asks: levels(&value["asks"])?,
+                    },
+                    checksum,
+                })
+            }
+            Some("delta") => {
+                let deltas = value["deltas"]
+                    .as_array()
+                    .context("Invalid deltas in feed message")?
+                    .iter()
+                    .map(|delta| {
+                        let fields = fields(delta, 4)?;
+                        let side = match fields[0].as_str() {
+                            Some("bid") => Side::Bid,
+                            Some("ask") => Side::Ask,
+                            _ => return Err(anyhow::anyhow!("Invalid side in feed message")),
+                        };
+                        Ok(LevelDelta {
+                            side,
+                            price: Price::new(u64_at(&fields[1], "price")?),
+                            quantity: Quantity::new(u64_at(&fields[2], "quantity")?),
+                            orders: u64_at(&fields[3], "orders")? as usize,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(FeedMessage::Delta {
+                    seq,
+                    deltas,
+                    checksum,
+                })
+            }
+            _ => Err(anyhow::anyhow!("Unknown feed message type")),
+        }
+    }
+}
+
+/// The checksum of a book: 64-bit FNV-1a over the bids then the asks, best first, each
+/// level as its price, quantity and order count in little-endian `u64`s.
+pub fn checksum(book: &BookView) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for level in book.bids.iter().chain(&book.asks) {
+        for field in [level.price.get(), level.quantity.get(), level.orders as u64] {
+            for byte in field.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+    hash
+}
+
+impl BookView {
+    /// The deltas that turn this book into `next`: bids then asks, each side in price order.
+    pub fn deltas_to(&self, next: &BookView) -> Vec<LevelDelta> {
+        let mut deltas = Vec::new();
+        for (side, before, after) in [
+            (Side::Bid, &self.bids, &next.bids),
+            (Side::Ask, &self.asks, &next.asks),
+        ] {
+            let mut prices: Vec<Price> = before
+                .iter()
+                .chain(after.iter())
+                .map(|level| level.price)
+                .collect();
+            prices.sort();
+            prices.dedup();
+            for price in prices {
+                let find = |levels: &[BookLevel]| {
+                    levels.iter().find(|level| level.price == price).copied()
+                };
+                let new = find(after);
+                if find(before) == new {
+                    continue;
+                }
+                deltas.push(match new {
+                    Some(level) => LevelDelta {
+                        side,
+                        price,
+                        quantity: level.quantity,
+                        orders: level.orders,
+                    },
+                    None => LevelDelta {
+                        side,
+                        price,
+                        quantity: Quantity::new(0),
+                        orders: 0,
+                    },
+                });
+            }
+        }
+        deltas
+    }
+
+    /// Apply a delta, keeping each side best price first.
+    pub fn apply(&mut self, delta: &LevelDelta) {
+        let levels = match delta.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        levels.retain(|level| level.price != delta.price);
+        if delta.quantity.get() == 0 {
+            return;
+        }
+        let index = levels.partition_point(|level| match delta.side {
+            Side::Bid => level.price > delta.price,
+            Side::Ask => level.price < delta.price,
+        });
+        levels.insert(
+            index,
+            BookLevel {
+                price: delta.price,
+                quantity: delta.quantity,
+                orders: delta.orders,
+            },
+        );
+    }
+}
+
+/// Records the feed of a market from its book.
+#[derive(Debug)]
+pub struct FeedRecorder {
+    snapshot_interval: u64,
+    book: Option<BookView>,
+    messages: Vec<FeedMessage>,
+}
+
+impl FeedRecorder {
+    /// Start a feed that repeats the snapshot every `snapshot_interval` messages, at least
+    /// one.
+    pub fn new(snapshot_interval: u64) -> Self {
+        Self {
+            snapshot_interval: snapshot_interval.max(1),
+            book: None,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Publish the changes to the market's book since the last call, returning the new
+    /// messages: nothing if the book did not change, otherwise a delta followed by a
+    /// snapshot of the same book when one is due. The first call publishes a snapshot.
+    pub fn record(&mut self, market: &Market) -> &[FeedMessage] {
+        let book = market.book_view();
+        let start = self.messages.len();
+        let checksum = checksum(&book);
+        match &self.book {
+            Some(previous) => {
+                let deltas = previous.deltas_to(&book);
+                if deltas.is_empty() {
+                    return &[];
+                }
+                self.messages.push(FeedMessage::Delta {
+                    seq: start as u64,
+                    deltas,
+                    checksum,
+                });
+            }
+            None => self.messages.push(FeedMessage::Snapshot {
+                seq: 0,
+                book: book.clone(),
+                checksum,
+            }),
+        }
+        let seq = self.messages.len() as u64;
+        if start > 0 && seq.is_multiple_of(self.snapshot_interval) {
+            self.messages.push(FeedMessage::Snapshot {
+                seq,
+                book: book.clone(),
+                checksum,
+            });
+        }
+        self.book = Some(book);
+        &self.messages[start..]
+    }
+
+    /// The messages recorded so far.
+    pub fn messages(&self) -> &[FeedMessage] {
+        &self.messages
+    }
+}
+
+/// A consumer of the feed, which maintains its own copy of the book.
+pub trait FeedConsumer {
+    /// Replace the book with a snapshot.
+    fn snapshot(&mut self, book: &BookView);
+
+    /// Apply a level change.
+    fn delta(&mut self, delta: &LevelDelta);
+
+    /// The consumer's current book.
+    fn book(&self) -> BookView;
+}
+
+/// The reference consumer, which applies deltas with `BookView::apply`.
+#[derive(Debug, Default)]
+pub struct ReferenceConsumer {
+    book: BookView,
+}
+
+impl FeedConsumer for ReferenceConsumer {
+    fn snapshot(&mut self, book: &BookView) {
+        self.book = book.clone();
+    }
+
+    fn delta(&mut self, delta: &LevelDelta) {
+        self.book.apply(delta);
+    }
+
+    fn book(&self) -> BookView {
+        self.book.clone()
+    }
+}
+
+/// The first message a consumer disagrees with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nonconformance {
+    pub seq: u64,
+    pub reason: String,
+}
+
+/// Replay a recorded feed into a consumer and check it reproduces the feed exactly
+///
+/// Before each repeated snapshot, the consumer's book must equal the snapshot; after each
+/// message, its checksum must equal the message's. The feed itself must start with a
+/// snapshot and number its messages without gaps.
+///
+/// # Arguments
+///
+/// * `feed` - The recorded messages, in order
+/// * `consumer` - The implementation to certify
+pub fn certify(
+    feed: &[FeedMessage],
+    consumer: &mut dyn FeedConsumer,
+) -> std::result::Result<(), Nonconformance> {
+    let fail = |seq: u64, reason: &str| {
+        Err(Nonconformance {
+            seq,
+            reason: reason.to_string(),
+        })
+    };
+    for (index, message) in feed.iter().enumerate() {
+        let seq = message.seq();
+        if seq != index as u64 {
+            return fail(seq, "Feed message out of sequence");
+        }
+        match message {
+            FeedMessage::Snapshot { book, .. } => {
+                if index > 0 && consumer.book() != *book {
+                    return fail(seq, "Book differs from the snapshot");
+                }
+                consumer.snapshot(book);
+            }
+            FeedMessage::Delta { .. } if index == 0 => {
+                return fail(seq, "Feed does not start with a snapshot");
+            }
+            FeedMessage::Delta { deltas, .. } => {
+                for delta in deltas {
+                    consumer.delta(delta);
+                }
+            }
+        }
+        if checksum(&consumer.book()) != message.checksum() {
+            return fail(seq, "Checksum differs");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::Pair,
+        order::{AccountId, Order, OrderId, Timestamp},
+    };
+
+    use super::*;
+
+    /// Applies deltas but never removes a level.
+    #[derive(Default)]
+    struct Sticky(ReferenceConsumer);
+
+    impl FeedConsumer for Sticky {
+        fn snapshot(&mut self, book: &BookView) {
+            self.0.snapshot(book);
+        }
+
+        fn delta(&mut self, delta: &LevelDelta) {
+            if delta.quantity.get() > 0 {
+                self.0.delta(delta);
+            }
+        }
+
+        fn book(&self) -> BookView {
+            self.0.book()
+        }
+    }
+
+    #[test]
+    fn test_recorded_feed_certifies_consumers() {
+        let mut market = Market::new(Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        });
+        let mut recorder = FeedRecorder::new(3);
+        let order = |id: u64, price: u64, qty: u64, side: Side| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(qty),
+                side,
+                AccountId::new(format!("account{}", id)),
+                Timestamp::new(id),
+            )
+        };
+        recorder.record(&market);
+        for (id, price, qty, side) in [
+            (1, 100, 2, Side::Ask),
+            (2, 101, 1, Side::Ask),
+            (3, 98, 4, Side::Bid),
+            (4, 100, 2, Side::Bid),
+            (5, 99, 1, Side::Bid),
+        ] {
+            market.process_order(order(id, price, qty, side));
+            assert!(!recorder.record(&market).is_empty());
+            assert!(recorder.record(&market).is_empty());
+        }
+
+        let feed: Vec<FeedMessage> = recorder
+            .messages()
+            .iter()
+            .map(|message| FeedMessage::from_json(&message.to_json()).unwrap())
+            .collect();
+        assert_eq!(feed, recorder.messages());
+        assert!(matches!(feed[3], FeedMessage::Snapshot { .. }));
+        assert!(matches!(feed[6], FeedMessage::Snapshot { .. }));
+        // The bid at 100 takes out the ask level without resting
+        let FeedMessage::Delta { deltas, .. } = &feed[5] else {
+            panic!("expected a delta");
+        };
+        assert_eq!(
+            deltas,
+            &vec![LevelDelta {
+                side: Side::Ask,
+                price: Price::new(100),
+                quantity: Quantity::new(0),
+                orders: 0,
+            }]
+        );
+
+        assert_eq!(certify(&feed, &mut ReferenceConsumer::default()), Ok(()));
+        // The filled ask lingers in the consumer's book
+        assert_eq!(
+            certify(&feed, &mut Sticky::default()),
+            Err(Nonconformance {
+                seq: 5,
+                reason: "Checksum differs".to_string(),
+            })
+        );
+        assert_eq!(
+            certify(&feed[1..], &mut ReferenceConsumer::default())
+                .unwrap_err()
+                .seq,
+            1
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! C interface for embedding the exchange in non-Rust systems.
+//!
+//! The exchange is an opaque handle created by `ex_exchange_new` and released by
+//! `ex_exchange_free`. Every fallible function returns an `ExStatus`; when it is
+//! `EX_REJECTED`, `ex_last_error` describes why. Trades and events are delivered through
+//! callbacks registered on the handle, synchronously and before the call that caused them
+//! returns. The declarations are in `include/exchanges.h`.
+
+use std::{
+    ffi::{CStr, CString, c_char, c_void},
+    ptr,
+};
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    command::Command,
+    event::ExchangeEvent,
+    exchange::Exchange,
+    execution::ExecutionReport,
+    market::{Market, Pair},
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+};
+
+/// Result of an FFI call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExStatus {
+    Ok = 0,
+    /// A required pointer was null.
+    NullPointer = 1,
+    /// A string was not valid UTF-8 or an enum value was out of range.
+    InvalidArgument = 2,
+    /// The exchange refused the request; see `ex_last_error`.
+    Rejected = 3,
+}
+
+/// A trade, as delivered to the trade callback.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExTrade {
+    pub ask_order_id: u64,
+    pub bid_order_id: u64,
+    pub price: u64,
+    pub quantity: u64,
+    pub trade_id: u64,
+    pub sequence: u64,
+}
+
+/// Kind of an event delivered to the event callback.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExEventKind {
+    AccountClosed = 0,
+    OrderExpired = 1,
+    StopRejected = 2,
+    PegCancelled = 3,
+    BalanceAlert = 4,
+    SelfTradePrevented = 5,
+    CircuitBreakerTripped = 6,
+    ImpliedHedgeShortfall = 7,
+}
+
+/// An event, as delivered to the event callback. Strings are only valid during the callback.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExEvent {
+    pub kind: ExEventKind,
+    /// The account the event is about, empty for market events.
+    pub account_id: *const c_char,
+    /// The order the event is about, zero for account and market events.
+    pub order_id: u64,
+}
+
+pub type ExTradeCallback = extern "C" fn(user_data: *mut c_void, trade: *const ExTrade);
+pub type ExEventCallback = extern "C" fn(user_data: *mut c_void, event: *const ExEvent);
+
+/// Opaque handle owning an exchange and its registered callbacks.
+pub struct ExExchange {
+    exchange: Exchange,
+    trade_callback: Option<(ExTradeCallback, *mut c_void)>,
+    event_callback: Option<(ExEventCallback, *mut c_void)>,
+    last_error: CString,
+}
+
+impl ExExchange {
+    /// Records the outcome of a request, then delivers its trades and any queued events.
+    fn finish(&mut self, result: Result<Vec<Trade>>) -> ExStatus {
+        let trades = match result {
+            Ok(trades) => trades,
+            Err(error) => {
+                self.last_error = CString::new(error.to_string()).unwrap_or_default();
+                return ExStatus::Rejected;
+            }
+        };
+        if let Some((callback, user_data)) = self.trade_callback {
+            for trade in &trades {
+                let trade = ExTrade {
+                    ask_order_id: trade.ask_order_id.get(),
+                    bid_order_id: trade.bid_order_id.get(),
+                    price: trade.price.get(),
+                    quantity: trade.quantity.get(),
+                    trade_id: trade.id.get(),
+                    sequence: trade.sequence,
+                };
+                callback(user_data, &trade);
+            }
+        }
+        if let Some((callback, user_data)) = self.event_callback {
+            for event in self.exchange.drain_events() {
+                let (kind, account_id, order_id) = match event {
+                    ExchangeEvent::AccountClosed { account_id, .. } => {
+                        (ExEventKind::AccountClosed, account_id, OrderId::new(0))
+                    }
+                    ExchangeEvent::OrderExpired {
+                        account_id,
+                        order_id,
+                        ..
+                    } => (ExEventKind::OrderExpired, account_id, order_id),
+                    ExchangeEvent::StopRejected {
+                        account_id,
+                        order_id,
+                        ..
+                    } => (ExEventKind::StopRejected, account_id, order_id),
+                    ExchangeEvent::PegCancelled {
+                        account_id,
+                        order_id,
+                        ..
+                    } => (ExEventKind::PegCancelled, account_id, order_id),
+                    ExchangeEvent::BalanceAlert { account_id, .. } => {
+                        (ExEventKind::BalanceAlert, account_id, OrderId::new(0))
+                    }
+                    ExchangeEvent::SelfTradePrevented {
+                        account_id,
+                        order_id,
+                        ..
+                    } => (ExEventKind::SelfTradePrevented, account_id, order_id),
+                    ExchangeEvent::CircuitBreakerTripped { .. } => (
+                        ExEventKind::CircuitBreakerTripped,
+                        AccountId::default(),
+                        OrderId::new(0),
+                    ),
+                    ExchangeEvent::ImpliedHedgeShortfall { account_id, .. } => (
+                        ExEventKind::ImpliedHedgeShortfall,
+                        account_id,
+                        OrderId::new(0),
+                    ),
+                };
+                let account_id = CString::new(account_id.as_str()).unwrap_or_default();
+                let event = ExEvent {
+                    kind,
+                    account_id: account_id.as_ptr(),
+                    order_id: order_id.get(),
+                };
+                callback(user_data, &event);
+            }
+        }
+        ExStatus::Ok
+    }
+}
+
+/// Reads a NUL-terminated UTF-8 string.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, ExStatus> {
+    if s.is_null() {
+        return Err(ExStatus::NullPointer);
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|_| ExStatus::InvalidArgument)
+}
+
+/// # Safety
+///
+/// Both arguments must be null or point to NUL-terminated strings.
+unsafe fn pair_arg(numeraire: *const c_char, base: *const c_char) -> Result<Pair, ExStatus> {
+    Ok(Pair {
+        numeraire: Asset::intern(unsafe { str_arg(numeraire) }?),
+        base: Asset::intern(unsafe { str_arg(base) }?),
+    })
+}
+
+fn side_arg(side: i32) -> Result<Side, ExStatus> {
+    match side {
+        0 => Ok(Side::Bid),
+        1 => Ok(Side::Ask),
+        _ => Err(ExStatus::InvalidArgument),
+    }
+}
+
+macro_rules! try_arg {
+    ($e:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(status) => return status,
+        }
+    };
+}
+
+/// Creates an empty exchange.
+#[unsafe(no_mangle)]
+pub extern "C" fn ex_exchange_new() -> *mut ExExchange {
+    Box::into_raw(Box::new(ExExchange {
+        exchange: Exchange::new(),
+        trade_callback: None,
+        event_callback: None,
+        last_error: CString::default(),
+    }))
+}
+
+/// Releases an exchange.
+///
+/// # Safety
+///
+/// `handle` must be null or a handle returned by `ex_exchange_new` that was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_exchange_free(handle: *mut ExExchange) {
+    if !handle.is_null() {
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+/// The message of the last rejected request, valid until the next call on the handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_last_error(handle: *const ExExchange) -> *const c_char {
+    match unsafe { handle.as_ref() } {
+        Some(handle) => handle.last_error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Registers the callback receiving every trade, replacing any previous one. A null
+/// callback unregisters it.
+///
+/// # Safety
+///
+/// `handle` must be a live handle. `user_data` is passed back to the callback untouched.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_set_trade_callback(
+    handle: *mut ExExchange,
+    callback: Option<ExTradeCallback>,
+    user_data: *mut c_void,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    handle.trade_callback = callback.map(|callback| (callback, user_data));
+    ExStatus::Ok
+}
+
+/// Registers the callback receiving every exchange event, replacing any previous one. A
+/// null callback unregisters it; events are then left queued.
+///
+/// # Safety
+///
+/// `handle` must be a live handle. `user_data` is passed back to the callback untouched.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_set_event_callback(
+    handle: *mut ExExchange,
+    callback: Option<ExEventCallback>,
+    user_data: *mut c_void,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    handle.event_callback = callback.map(|callback| (callback, user_data));
+    ExStatus::Ok
+}
+
+/// Opens a market.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and the strings NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_add_market(
+    handle: *mut ExExchange,
+    numeraire: *const c_char,
+    base: *const c_char,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let pair = try_arg!(unsafe { pair_arg(numeraire, base) });
+    handle.exchange.add_market(Market::new(pair));
+    ExStatus::Ok
+}
+
+/// Deposits funds into an account.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and the strings NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_deposit(
+    handle: *mut ExExchange,
+    account_id: *const c_char,
+    asset: *const c_char,
+    amount: u64,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let account_id = AccountId::new(try_arg!(unsafe { str_arg(account_id) }).to_string());
+    let asset = Asset::intern(try_arg!(unsafe { str_arg(asset) }));
+    let result = handle.exchange.deposit(account_id, asset, amount);
+    handle.finish(result.map(|_| Vec::new()))
+}
+
+/// Writes the balance of an account to `out`, zero for unknown accounts.
+///
+/// # Safety
+///
+/// `handle` must be a live handle, the strings NUL-terminated and `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_balance(
+    handle: *const ExExchange,
+    account_id: *const c_char,
+    asset: *const c_char,
+    out: *mut u64,
+) -> ExStatus {
+    let (Some(handle), Some(out)) = (unsafe { handle.as_ref() }, unsafe { out.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let account_id = AccountId::new(try_arg!(unsafe { str_arg(account_id) }).to_string());
+    let asset = Asset::intern(try_arg!(unsafe { str_arg(asset) }));
+    *out = handle.exchange.get_balance(account_id, asset).unwrap_or(0);
+    ExStatus::Ok
+}
+
+/// Posts a limit order. `side` is 0 for bids and 1 for asks.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and the strings NUL-terminated.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn ex_post_limit_order(
+    handle: *mut ExExchange,
+    numeraire: *const c_char,
+    base: *const c_char,
+    order_id: u64,
+    side: i32,
+    price: u64,
+    quantity: u64,
+    account_id: *const c_char,
+    timestamp: u64,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let pair = try_arg!(unsafe { pair_arg(numeraire, base) });
+    let order = Order::new(
+        OrderId::new(order_id),
+        Price::new(price),
+        Quantity::new(quantity),
+        try_arg!(side_arg(side)),
+        AccountId::new(try_arg!(unsafe { str_arg(account_id) }).to_string()),
+        Timestamp::new(timestamp),
+    );
+    let result = handle.exchange.post_order(order, pair);
+    handle.finish(result.map(ExecutionReport::into_trades))
+}
+
+/// Cancels a resting order. `side` is 0 for bids and 1 for asks.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and the strings NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_cancel_order(
+    handle: *mut ExExchange,
+    numeraire: *const c_char,
+    base: *const c_char,
+    order_id: u64,
+    side: i32,
+    price: u64,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let pair = try_arg!(unsafe { pair_arg(numeraire, base) });
+    let side = try_arg!(side_arg(side));
+    let result =
+        handle
+            .exchange
+            .cancel_order(OrderId::new(order_id), Price::new(price), side, pair);
+    handle.finish(result.map(|_| Vec::new()))
+}
+
+/// Shrinks a resting order to `quantity` without losing its queue priority. `side` is 0 for
+/// bids and 1 for asks.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and the strings NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_reduce_order(
+    handle: *mut ExExchange,
+    numeraire: *const c_char,
+    base: *const c_char,
+    order_id: u64,
+    side: i32,
+    price: u64,
+    quantity: u64,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let pair = try_arg!(unsafe { pair_arg(numeraire, base) });
+    let side = try_arg!(side_arg(side));
+    let result = handle.exchange.execute(Command::ReduceOrder {
+        pair,
+        order_id: OrderId::new(order_id),
+        side,
+        price: Price::new(price),
+        quantity: Quantity::new(quantity),
+    });
+    handle.finish(result)
+}
+
+/// Closes an account, sweeping balances up to `dust_threshold` to the dust account.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and the string NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ex_close_account(
+    handle: *mut ExExchange,
+    account_id: *const c_char,
+    dust_threshold: u64,
+) -> ExStatus {
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return ExStatus::NullPointer;
+    };
+    let account_id = AccountId::new(try_arg!(unsafe { str_arg(account_id) }).to_string());
+    let result = handle.exchange.execute(Command::CloseAccount {
+        account_id,
+        dust_threshold,
+    });
+    handle.finish(result)
+}
@@ -0,0 +1,510 @@
+//! Deposits and withdrawals through an external chain.
+//!
+//! `Funding` tracks every transfer from the moment the chain reports it until it settles,
+//! crediting deposits only once they are final. Chains are reached through a
+//! `FundingAdapter`; `MockChain` simulates one, with confirmation delays, reorgs and failed
+//! withdrawals, so the state machine can be tested end to end.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{account::SystemAccount, asset::Asset, exchange::Exchange, order::AccountId};
+
+/// Identifies a transfer. Deposits are numbered by the chain and withdrawals by `Funding`,
+/// so a deposit and a withdrawal may share an ID.
+pub type TransferId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferState {
+    /// Seen on chain but not final yet. Pending withdrawals are already debited.
+    Pending,
+    /// Deposits are credited, withdrawals sent for good.
+    Settled,
+    /// The deposit dropped out of the chain in a reorg before finality and was never
+    /// credited.
+    Reversed,
+    /// The withdrawal failed on chain and was refunded, or the exchange refused the deposit.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    pub id: TransferId,
+    pub kind: TransferKind,
+    pub account_id: AccountId,
+    pub asset: Asset,
+    /// The amount credited to or debited from the account.
+    pub amount: u64,
+    /// The part of a withdrawal's amount kept as its fee rather than sent on chain.
+    pub fee: u64,
+    pub state: TransferState,
+}
+
+impl Transfer {
+    /// The amount that moves on chain.
+    pub fn net_amount(&self) -> u64 {
+        self.amount - self.fee
+    }
+}
+
+/// The fee charged on withdrawals of an asset: a flat amount covering the network fee, plus
+/// basis points of the amount withdrawn, rounded up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WithdrawalFee {
+    pub flat: u64,
+    pub bps: u64,
+}
+
+impl WithdrawalFee {
+    /// Returns the fee charged on a withdrawal of `amount`.
+    pub fn fee(&self, amount: u64) -> u64 {
+        let variable = (amount as u128 * self.bps as u128).div_ceil(10_000) as u64;
+        self.flat.saturating_add(variable)
+    }
+}
+
+/// What a withdrawal would cost, before it is requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WithdrawalPreview {
+    /// The amount debited from the account.
+    pub amount: u64,
+    pub fee: u64,
+    /// The amount that arrives on chain.
+    pub received: u64,
+}
+
+/// What a chain reports about a transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// A deposit was included in the chain, not final yet.
+    DepositSeen {
+        id: TransferId,
+        account_id: AccountId,
+        asset: Asset,
+        amount: u64,
+    },
+    DepositFinal {
+        id: TransferId,
+    },
+    /// A deposit dropped out of the chain in a reorg.
+    DepositReorged {
+        id: TransferId,
+    },
+    WithdrawalConfirmed {
+        id: TransferId,
+    },
+    WithdrawalFailed {
+        id: TransferId,
+    },
+}
+
+/// The connection to a chain.
+pub trait FundingAdapter {
+    /// Send a withdrawal to the chain. Its outcome is reported by a later `poll`.
+    fn submit_withdrawal(&mut self, transfer: &Transfer, now: u64);
+
+    /// The events that happened on chain up to `now` and were not reported yet, in order.
+    fn poll(&mut self, now: u64) -> Vec<ChainEvent>;
+}
+
+/// Moves funds between a chain and the exchange.
+///
+/// Deposits are credited when they become final, so a reorg only ever reverses a pending
+/// deposit. Withdrawals are debited when requested and refunded in full if they fail; their
+/// fee is credited to the fees account once they settle. Events that
+/// do not apply to a transfer's current state, such as a reorg after finality or a repeated
+/// confirmation, are ignored.
+pub struct Funding<A: FundingAdapter> {
+    adapter: A,
+    deposits: HashMap<TransferId, Transfer>,
+    withdrawals: HashMap<TransferId, Transfer>,
+    next_withdrawal_id: TransferId,
+    withdrawal_fees: HashMap<Asset, WithdrawalFee>,
+}
+
+impl<A: FundingAdapter> Funding<A> {
+    pub fn new(adapter: A) -> Self {
+        Self {
+            adapter,
+            deposits: HashMap::new(),
+            withdrawals: HashMap::new(),
+            next_withdrawal_id: 0,
+            withdrawal_fees: HashMap::new(),
+        }
+    }
+
+    pub fn adapter_mut(&mut self) -> &mut A {
+        &mut self.adapter
+    }
+
+    pub fn deposit(&self, id: TransferId) -> Option<&Transfer> {
+        self.deposits.get(&id)
+    }
+
+    pub fn withdrawal(&self, id: TransferId) -> Option<&Transfer> {
+        self.withdrawals.get(&id)
+    }
+
+    /// Set the fee charged on withdrawals of an asset, free by default.
+    pub fn set_withdrawal_fee(&mut self, asset: Asset, fee: WithdrawalFee) {
+        self.withdrawal_fees.insert(asset, fee);
+    }
+
+    pub fn withdrawal_fee(&self, asset: Asset) -> WithdrawalFee {
+        self.withdrawal_fees
+            .get(&asset)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the fee and the amount received of a withdrawal, or an error if the amount
+    /// does not cover the fee.
+    ///
+    /// # Arguments
+    ///
+    /// * `asset` - The asset to withdraw
+    /// * `amount` - The amount to debit from the account, fee included
+    pub fn preview_withdrawal(&self, asset: Asset, amount: u64) -> Result<WithdrawalPreview> {
+        let fee = self.withdrawal_fee(asset).fee(amount);
+        if fee >= amount {
+            return Err(anyhow::anyhow!("Withdrawal does not cover its fee"));
+        }
+        Ok(WithdrawalPreview {
+            amount,
+            fee,
+            received: amount - fee,
+        })
+    }
+
+    /// Debit a withdrawal from an account and send it to the chain
+    ///
+    /// The withdrawal fee is taken out of `amount`, so the chain receives the rest; see
+    /// `preview_withdrawal`.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange holding the account
+    /// * `account_id` - The ID of the account to withdraw from
+    /// * `asset` - The asset to withdraw
+    /// * `amount` - The amount to withdraw
+    /// * `now` - The current time
+    pub fn request_withdrawal(
+        &mut self,
+        exchange: &mut Exchange,
+        account_id: AccountId,
+        asset: Asset,
+        amount: u64,
+        now: u64,
+    ) -> Result<TransferId> {
+        let preview = self.preview_withdrawal(asset, amount)?;
+        exchange.withdraw(account_id.clone(), asset, amount)?;
+        let id = self.next_withdrawal_id;
+        self.next_withdrawal_id += 1;
+        let transfer = Transfer {
+            id,
+            kind: TransferKind::Withdrawal,
+            account_id,
+            asset,
+            amount,
+            fee: preview.fee,
+            state: TransferState::Pending,
+        };
+        self.adapter.submit_withdrawal(&transfer, now);
+        self.withdrawals.insert(id, transfer);
+        Ok(id)
+    }
+
+    /// Apply everything the chain reported up to `now` to the exchange
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange holding the accounts
+    /// * `now` - The current time
+    pub fn process(&mut self, exchange: &mut Exchange, now: u64) {
+        for event in self.adapter.poll(now) {
+            self.apply(exchange, event);
+        }
+    }
+
+    fn apply(&mut self, exchange: &mut Exchange, event: ChainEvent) {
+        match event {
+            ChainEvent::DepositSeen {
+                id,
+                account_id,
+                asset,
+                amount,
+            } => {
+                self.deposits.entry(id).or_insert(Transfer {
+                    id,
+                    kind: TransferKind::Deposit,
+                    account_id,
+                    asset,
+                    amount,
+                    fee: 0,
+                    state: TransferState::Pending,
+                });
+            }
+            ChainEvent::DepositFinal { id } => {
+                if let Some(deposit) = pending(&mut self.deposits, id) {
+                    deposit.state = match exchange.deposit(
+                        deposit.account_id.clone(),
+                        deposit.asset,
+                        deposit.amount,
+                    ) {
+                        Ok(()) => TransferState::Settled,
+                        Err(_) => TransferState::Failed,
+                    };
+                }
+            }
+            ChainEvent::DepositReorged { id } => {
+                if let Some(deposit) = pending(&mut self.deposits, id) {
+                    deposit.state = TransferState::Reversed;
+                }
+            }
+            ChainEvent::WithdrawalConfirmed { id } => {
+                if let Some(withdrawal) = pending(&mut self.withdrawals, id) {
+                    withdrawal.state = TransferState::Settled;
+                    if withdrawal.fee > 0 {
+                        exchange.add_balance(
+                            SystemAccount::Fees.id(),
+                            withdrawal.asset,
+                            withdrawal.fee,
+                        );
+                    }
+                }
+            }
+            ChainEvent::WithdrawalFailed { id } => {
+                if let Some(withdrawal) = pending(&mut self.withdrawals, id) {
+                    withdrawal.state = TransferState::Failed;
+                    exchange.add_balance(
+                        withdrawal.account_id.clone(),
+                        withdrawal.asset,
+                        withdrawal.amount,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn pending(transfers: &mut HashMap<TransferId, Transfer>, id: TransferId) -> Option<&mut Transfer> {
+    transfers
+        .get_mut(&id)
+        .filter(|transfer| transfer.state == TransferState::Pending)
+}
+
+/// A simulated chain
+///
+/// Deposits and withdrawals become final `confirmation_delay` after they are included.
+/// Reorgs and withdrawal failures are scripted by the test driving the chain.
+#[derive(Debug, Default)]
+pub struct MockChain {
+    confirmation_delay: u64,
+    /// Events not reported yet, by the time they happen. Ties keep their scheduling order.
+    scheduled: Vec<(u64, ChainEvent)>,
+    next_deposit_id: TransferId,
+    /// Number of upcoming withdrawals to fail.
+    failing_withdrawals: usize,
+}
+
+impl MockChain {
+    pub fn new(confirmation_delay: u64) -> Self {
+        Self {
+            confirmation_delay,
+            ..Default::default()
+        }
+    }
+
+    /// Include a deposit in the chain at time `at`, final `confirmation_delay` later unless
+    /// it is reorged first.
+    pub fn deposit(
+        &mut self,
+        account_id: AccountId,
+        asset: Asset,
+        amount: u64,
+        at: u64,
+    ) -> TransferId {
+        let id = self.next_deposit_id;
+        self.next_deposit_id += 1;
+        self.schedule(
+            at,
+            ChainEvent::DepositSeen {
+                id,
+                account_id,
+                asset,
+                amount,
+            },
+        );
+        self.schedule(
+            at + self.confirmation_delay,
+            ChainEvent::DepositFinal { id },
+        );
+        id
+    }
+
+    /// Drop a deposit out of the chain at time `at`. Reorgs at or after its finality come
+    /// too late to reverse it.
+    pub fn reorg(&mut self, id: TransferId, at: u64) {
+        self.schedule(at, ChainEvent::DepositReorged { id });
+    }
+
+    /// Fail the next `count` withdrawals submitted.
+    pub fn fail_withdrawals(&mut self, count: usize) {
+        self.failing_withdrawals += count;
+    }
+
+    fn schedule(&mut self, at: u64, event: ChainEvent) {
+        let index = self.scheduled.partition_point(|(time, _)| *time <= at);
+        self.scheduled.insert(index, (at, event));
+    }
+}
+
+impl FundingAdapter for MockChain {
+    fn submit_withdrawal(&mut self, transfer: &Transfer, now: u64) {
+        let id = transfer.id;
+        let event = if self.failing_withdrawals > 0 {
+            self.failing_withdrawals -= 1;
+            ChainEvent::WithdrawalFailed { id }
+        } else {
+            ChainEvent::WithdrawalConfirmed { id }
+        };
+        self.schedule(now + self.confirmation_delay, event);
+    }
+
+    fn poll(&mut self, now: u64) -> Vec<ChainEvent> {
+        let due = self.scheduled.partition_point(|(time, _)| *time <= now);
+        self.scheduled
+            .drain(..due)
+            .map(|(_, event)| event)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_chain_drives_transfers_to_settlement() {
+        let usd = Asset::new("USD");
+        let alice = AccountId::new("alice".to_string());
+        let mut exchange = Exchange::new();
+        let mut funding = Funding::new(MockChain::new(10));
+        let chain = funding.adapter_mut();
+        let final_deposit = chain.deposit(alice.clone(), usd, 500, 0);
+        let reorged = chain.deposit(alice.clone(), usd, 300, 2);
+        chain.reorg(reorged, 5);
+        // Too late: the deposit is final by then
+        chain.reorg(final_deposit, 12);
+
+        // Seen but not final, so nothing is credited yet
+        funding.process(&mut exchange, 5);
+        assert_eq!(
+            funding.deposit(final_deposit).unwrap().state,
+            TransferState::Pending
+        );
+        assert_eq!(
+            funding.deposit(reorged).unwrap().state,
+            TransferState::Reversed
+        );
+        assert!(exchange.get_balance(alice.clone(), usd).is_err());
+
+        funding.process(&mut exchange, 12);
+        assert_eq!(
+            funding.deposit(final_deposit).unwrap().state,
+            TransferState::Settled
+        );
+        assert_eq!(
+            funding.deposit(reorged).unwrap().state,
+            TransferState::Reversed
+        );
+        assert_eq!(exchange.get_balance(alice.clone(), usd).unwrap(), 500);
+
+        // The first withdrawal fails and is refunded, the second goes through
+        funding.adapter_mut().fail_withdrawals(1);
+        let failed = funding
+            .request_withdrawal(&mut exchange, alice.clone(), usd, 200, 20)
+            .unwrap();
+        let sent = funding
+            .request_withdrawal(&mut exchange, alice.clone(), usd, 100, 20)
+            .unwrap();
+        assert!(
+            funding
+                .request_withdrawal(&mut exchange, alice.clone(), usd, 300, 20)
+                .is_err()
+        );
+        assert_eq!(exchange.get_balance(alice.clone(), usd).unwrap(), 200);
+
+        funding.process(&mut exchange, 30);
+        assert_eq!(
+            funding.withdrawal(failed).unwrap().state,
+            TransferState::Failed
+        );
+        assert_eq!(
+            funding.withdrawal(sent).unwrap().state,
+            TransferState::Settled
+        );
+        assert_eq!(exchange.get_balance(alice, usd).unwrap(), 400);
+    }
+
+    #[test]
+    fn test_withdrawal_fees() {
+        let btc = Asset::new("BTC");
+        let alice = AccountId::new("alice".to_string());
+        let fees = SystemAccount::Fees.id();
+        let mut exchange = Exchange::new();
+        exchange.add_balance(alice.clone(), btc, 10_000);
+        let mut funding = Funding::new(MockChain::new(10));
+        funding.set_withdrawal_fee(btc, WithdrawalFee { flat: 5, bps: 10 });
+
+        // 5 flat plus 10 bps of 1,001 rounded up
+        assert_eq!(
+            funding.preview_withdrawal(btc, 1_001).unwrap(),
+            WithdrawalPreview {
+                amount: 1_001,
+                fee: 7,
+                received: 994,
+            }
+        );
+        assert!(funding.preview_withdrawal(btc, 5).is_err());
+        assert!(
+            funding
+                .request_withdrawal(&mut exchange, alice.clone(), btc, 5, 0)
+                .is_err()
+        );
+        // Other assets are free
+        assert_eq!(
+            funding
+                .preview_withdrawal(Asset::new("USD"), 100)
+                .unwrap()
+                .fee,
+            0
+        );
+
+        funding.adapter_mut().fail_withdrawals(1);
+        let failed = funding
+            .request_withdrawal(&mut exchange, alice.clone(), btc, 1_001, 0)
+            .unwrap();
+        let sent = funding
+            .request_withdrawal(&mut exchange, alice.clone(), btc, 1_001, 0)
+            .unwrap();
+        assert_eq!(funding.withdrawal(sent).unwrap().net_amount(), 994);
+        assert_eq!(exchange.get_balance(alice.clone(), btc).unwrap(), 7_998);
+
+        // The failed withdrawal is refunded with its fee; the fee of the other is collected
+        funding.process(&mut exchange, 10);
+        assert_eq!(
+            funding.withdrawal(failed).unwrap().state,
+            TransferState::Failed
+        );
+        assert_eq!(exchange.get_balance(alice, btc).unwrap(), 8_999);
+        assert_eq!(exchange.get_balance(fees, btc).unwrap(), 7);
+    }
+}
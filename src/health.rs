@@ -0,0 +1,208 @@
+//! Health and readiness of an exchange, for orchestration probes.
+//!
+//! `Exchange::health` gathers the queues an embedder has to keep draining and the state of
+//! the storage commands are recorded to. A server layer maps `Health::is_live` and
+//! `Health::is_ready` to its liveness and readiness endpoints.
+
+use crate::{exchange::Exchange, journal::Journal, market::Pair};
+
+/// The queues of one market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketHealth {
+    pub pair: Pair,
+    /// Orders resting on the market's books.
+    pub resting_orders: usize,
+    /// Stop orders waiting for their trigger.
+    pub pending_stops: usize,
+    /// Sequence number of the market's latest trade, zero before its first.
+    pub last_trade_sequence: u64,
+    /// Whether a closing auction is collecting orders.
+    pub auction_open: bool,
+}
+
+/// Where executed commands are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageStatus {
+    /// Commands are neither logged nor journaled.
+    None,
+    /// Commands are recorded.
+    Ok,
+    /// The command log was disabled, so commands go unrecorded.
+    Paused,
+    /// The command log could not write its last line.
+    Failing,
+}
+
+/// A point-in-time report on the health of an exchange.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    /// Every market, by numeraire then base symbol.
+    pub markets: Vec<MarketHealth>,
+    /// Events not yet drained by the embedder.
+    pub pending_events: usize,
+    /// Sequence number of the last command processed, or `None` if no command was
+    /// processed or commands are not counted.
+    pub last_sequence: Option<u64>,
+    /// Commands journaled since the journal's latest checkpoint, or `None` without a
+    /// journal.
+    pub journal_lag: Option<u64>,
+    pub storage: StorageStatus,
+}
+
+impl Health {
+    /// Returns true unless recording commands is failing.
+    pub fn is_live(&self) -> bool {
+        self.storage != StorageStatus::Failing
+    }
+
+    /// Returns true if the exchange is live and keeps up: no more than `max_pending_events`
+    /// events wait to be drained and, with a journal, recovery would replay no more than
+    /// `max_journal_lag` commands.
+    pub fn is_ready(&self, max_pending_events: usize, max_journal_lag: u64) -> bool {
+        self.is_live()
+            && self.pending_events <= max_pending_events
+            && self.journal_lag.is_none_or(|lag| lag <= max_journal_lag)
+    }
+}
+
+impl Exchange {
+    /// Report the health of the exchange
+    ///
+    /// Commands are counted by the journal when one is given, and otherwise by the command
+    /// log.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal` - The journal the exchange's commands go through, if any
+    pub fn health(&self, journal: Option<&Journal>) -> Health {
+        let mut markets: Vec<MarketHealth> = self
+            .markets
+            .values()
+            .map(|market| MarketHealth {
+                pair: market.pair,
+                resting_orders: market.resting_orders().count(),
+                pending_stops: market.pending_stops().len(),
+                last_trade_sequence: market.next_trade_sequence().saturating_sub(1),
+                auction_open: self.auctions.contains_key(&market.pair),
+            })
+            .collect();
+        markets.sort_by_key(|market| (market.pair.numeraire.symbol, market.pair.base.symbol));
+
+        let log = self.command_log.as_ref();
+        let storage = match log {
+            Some(log) if log.write_failed() => StorageStatus::Failing,
+            Some(log) if !log.is_enabled() => StorageStatus::Paused,
+            None if journal.is_none() => StorageStatus::None,
+            _ => StorageStatus::Ok,
+        };
+        let processed = journal
+            .map(Journal::len)
+            .or_else(|| log.map(|log| log.next_seq()));
+        Health {
+            markets,
+            pending_events: self.events.len(),
+            last_sequence: processed.and_then(|processed| processed.checked_sub(1)),
+            journal_lag: journal.map(Journal::checkpoint_lag),
+            storage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use crate::{
+        asset::Asset,
+        command::Command,
+        command_log::CommandLog,
+        market::Market,
+        order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    /// A writer whose disk is full.
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_health_reports_queues_and_storage() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        let health = exchange.health(None);
+        assert_eq!(
+            (health.last_sequence, health.storage),
+            (None, StorageStatus::None)
+        );
+
+        let mut journal = Journal::new(&exchange, 2);
+        journal
+            .execute(
+                &mut exchange,
+                Command::Deposit {
+                    account_id: alice.clone(),
+                    asset: pair.numeraire,
+                    amount: 1_000,
+                },
+            )
+            .unwrap();
+        for id in 1..=2 {
+            let order = Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(1),
+                Side::Bid,
+                alice.clone(),
+                Timestamp::new(id),
+            );
+            journal
+                .execute(&mut exchange, Command::PostOrder { pair, order })
+                .unwrap();
+        }
+
+        let health = exchange.health(Some(&journal));
+        assert_eq!(
+            health.markets,
+            vec![MarketHealth {
+                pair,
+                resting_orders: 2,
+                pending_stops: 0,
+                last_trade_sequence: 0,
+                auction_open: false,
+            }]
+        );
+        assert_eq!(health.last_sequence, Some(2));
+        // Checkpointed after the second command
+        assert_eq!(health.journal_lag, Some(1));
+        assert!(health.is_ready(0, 1));
+        assert!(!health.is_ready(0, 0));
+
+        exchange.command_log = Some(CommandLog::new(Full));
+        exchange
+            .execute(Command::ExpireOrders {
+                now: Timestamp::new(3),
+            })
+            .unwrap();
+        let health = exchange.health(None);
+        assert_eq!(
+            (health.last_sequence, health.storage),
+            (Some(0), StorageStatus::Failing)
+        );
+        assert!(!health.is_live());
+    }
+}
@@ -0,0 +1,254 @@
+//! Paged queries over a market's trade history, for charting and other history clients.
+//!
+//! A query selects a time range and a page size, and each page carries the cursor the next
+//! one continues from. Queries are served from the market's tape, so trades evicted by the
+//! retention policy are no longer returned; trade cursors are sequence numbers and stay
+//! valid across evictions.
+
+use crate::{
+    market::{Market, PublicTrade},
+    order::{Price, Quantity, Timestamp},
+};
+
+/// The slice of history a query asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryQuery {
+    /// Earliest time included, or `None` to start at the oldest retained trade.
+    pub from: Option<Timestamp>,
+    /// First time excluded, or `None` to run up to the latest trade.
+    pub to: Option<Timestamp>,
+    /// Where to continue from, as returned with the previous page.
+    pub cursor: Option<u64>,
+    /// Most items returned.
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    /// Query all retained history, `limit` items at a time.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            from: None,
+            to: None,
+            cursor: None,
+            limit,
+        }
+    }
+}
+
+/// One page of a history query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The cursor of the next page, or `None` if this page is the last of the range.
+    pub next_cursor: Option<u64>,
+}
+
+/// Prices and volume of the trades in one interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    /// Start of the interval, a multiple of the interval length.
+    pub open_time: Timestamp,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: Quantity,
+    pub trades: usize,
+}
+
+impl Market {
+    /// A page of the public trade feed, oldest first
+    ///
+    /// The cursor is the sequence number of the first trade of the page.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The time range, cursor and page size
+    pub fn trade_history(&self, query: &HistoryQuery) -> Page<PublicTrade> {
+        let (start, end) = self.trade_range(query);
+        let stop = end.min(start.saturating_add(query.limit));
+        Page {
+            items: (start..stop)
+                .map(|index| self.public_trade(index))
+                .collect(),
+            next_cursor: (stop < end).then(|| self.trades()[stop].sequence),
+        }
+    }
+
+    /// A page of candles of the trade feed, oldest first
+    ///
+    /// Intervals without trades have no candle. The cursor is the open time of the first
+    /// candle of the page; a range starting inside an interval only counts the trades of
+    /// the interval it includes.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Length of each candle, at least one
+    /// * `query` - The time range, cursor and page size
+    pub fn candles(&self, interval: u64, query: &HistoryQuery) -> Page<Candle> {
+        let interval = interval.max(1);
+        let from = query.from.max(query.cursor.map(Timestamp::new));
+        let (start, end) = self.trade_range(&HistoryQuery {
+            from,
+            cursor: None,
+            ..*query
+        });
+        let times = self.trade_times();
+        let mut candles: Vec<Candle> = Vec::new();
+        for (trade, time) in self.trades()[start..end].iter().zip(&times[start..end]) {
+            let open_time = Timestamp::new(time.get() / interval * interval);
+            match candles.last_mut() {
+                Some(candle) if candle.open_time == open_time => {
+                    candle.high = candle.high.max(trade.price);
+                    candle.low = candle.low.min(trade.price);
+                    candle.close = trade.price;
+                    candle.volume = candle.volume + trade.quantity;
+                    candle.trades += 1;
+                }
+                _ => {
+                    if candles.len() == query.limit {
+                        return Page {
+                            items: candles,
+                            next_cursor: Some(open_time.get()),
+                        };
+                    }
+                    candles.push(Candle {
+                        open_time,
+                        open: trade.price,
+                        high: trade.price,
+                        low: trade.price,
+                        close: trade.price,
+                        volume: trade.quantity,
+                        trades: 1,
+                    });
+                }
+            }
+        }
+        Page {
+            items: candles,
+            next_cursor: None,
+        }
+    }
+
+    /// The indices of the tape a query covers, ignoring its limit.
+    fn trade_range(&self, query: &HistoryQuery) -> (usize, usize) {
+        let times = self.trade_times();
+        let mut start = query
+            .from
+            .map_or(0, |from| times.partition_point(|time| *time < from));
+        if let Some(cursor) = query.cursor {
+            let after = self
+                .trades()
+                .partition_point(|trade| trade.sequence < cursor);
+            start = start.max(after);
+        }
+        let end = query
+            .to
+            .map_or(times.len(), |to| times.partition_point(|time| *time < to));
+        (start, end.max(start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        exchange::Exchange,
+        market::Pair,
+        order::{AccountId, Order, OrderId, Side},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_history_pages_through_a_time_range() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.base, 100);
+        exchange.add_balance(bob.clone(), pair.numeraire, 100_000);
+        let mut id = 0;
+        for (time, price, qty) in [
+            (10, 100, 1),
+            (15, 104, 2),
+            (25, 98, 1),
+            (31, 101, 3),
+            (32, 99, 1),
+        ] {
+            for (side, account) in [(Side::Ask, &alice), (Side::Bid, &bob)] {
+                id += 1;
+                let order = Order::new(
+                    OrderId::new(id),
+                    Price::new(price),
+                    Quantity::new(qty),
+                    side,
+                    account.clone(),
+                    Timestamp::new(time),
+                );
+                exchange.post_order(order, pair).unwrap();
+            }
+        }
+        let market = &exchange.markets[&pair];
+
+        // Trades from 15 up to, but excluding, 32, two at a time
+        let mut query = HistoryQuery {
+            from: Some(Timestamp::new(15)),
+            to: Some(Timestamp::new(32)),
+            ..HistoryQuery::new(2)
+        };
+        let page = market.trade_history(&query);
+        let prices: Vec<u64> = page.items.iter().map(|trade| trade.price.get()).collect();
+        assert_eq!(prices, vec![104, 98]);
+        assert_eq!(page.next_cursor, Some(page.items[1].sequence + 1));
+        query.cursor = page.next_cursor;
+        let page = market.trade_history(&query);
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].timestamp, Timestamp::new(31));
+        assert_eq!(page.next_cursor, None);
+
+        let query = HistoryQuery::new(2);
+        let page = market.candles(10, &query);
+        assert_eq!(
+            page.items,
+            vec![
+                Candle {
+                    open_time: Timestamp::new(10),
+                    open: Price::new(100),
+                    high: Price::new(104),
+                    low: Price::new(100),
+                    close: Price::new(104),
+                    volume: Quantity::new(3),
+                    trades: 2,
+                },
+                Candle {
+                    open_time: Timestamp::new(20),
+                    open: Price::new(98),
+                    high: Price::new(98),
+                    low: Price::new(98),
+                    close: Price::new(98),
+                    volume: Quantity::new(1),
+                    trades: 1,
+                },
+            ]
+        );
+        assert_eq!(page.next_cursor, Some(30));
+        let page = market.candles(
+            10,
+            &HistoryQuery {
+                cursor: page.next_cursor,
+                ..query
+            },
+        );
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(
+            (page.items[0].close, page.items[0].volume),
+            (Price::new(99), Quantity::new(4))
+        );
+        assert_eq!(page.next_cursor, None);
+    }
+}
@@ -0,0 +1,423 @@
+//! Normalizing market data imported from external venues.
+//!
+//! Venues name the same market differently (`BTC-USD`, `XBTUSD`, `btcusdt`) and quote prices
+//! and sizes as decimals at their own precision, while this crate names markets by `Pair`
+//! and trades integer prices and quantities. Each source of data gets a `SourceConfig`: a
+//! `SymbolMapper` that turns its symbols into pairs, and the `Scale` of its decimals in the
+//! crate's units. `Importer` keeps the configs by source name, so a loader feeding the
+//! exchange from several sources gets the same pairs and ticks from all of them.
+//!
+//! Decimals are parsed exactly rather than through floating point. A value finer than its
+//! scale, off its tick, or too large for a `u64` is an error rather than rounded, so an
+//! import never trades at prices the venue did not print.
+
+use std::{collections::HashMap, io::BufRead};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    asset::Asset,
+    market::Pair,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+};
+
+/// Turns the symbols of a source into pairs.
+pub trait SymbolMapper: Send {
+    /// The pair a symbol of the source stands for, if it is known.
+    fn pair(&self, symbol: &str) -> Option<Pair>;
+}
+
+/// A `SymbolMapper` for the usual venue conventions.
+///
+/// Symbols are read case-insensitively with the base first, either split by a separator
+/// (`BTC-USD`, `btc/usd`, `BTC_USD`, `BTC:USD`) or ending in one of `numeraires` (`BTCUSDT`).
+/// Asset names are then translated through `aliases`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolRules {
+    /// Symbols mapped explicitly, bypassing the rules.
+    pub symbols: HashMap<String, Pair>,
+    /// Venue names of assets that differ from the crate's, e.g. `XBT` for `BTC`, upper case.
+    pub aliases: HashMap<String, String>,
+    /// Numeraires that may end a symbol without a separator, upper case. The longest one
+    /// that leaves a base wins.
+    pub numeraires: Vec<String>,
+}
+
+impl SymbolMapper for SymbolRules {
+    fn pair(&self, symbol: &str) -> Option<Pair> {
+        if let Some(pair) = self.symbols.get(symbol) {
+            return Some(*pair);
+        }
+        let symbol = symbol.trim().to_uppercase();
+        let (base, numeraire) = match symbol.split_once(['/', '-', '_', ':']) {
+            Some(split) => split,
+            None => {
+                let numeraire = self
+                    .numeraires
+                    .iter()
+                    .filter(|numeraire| {
+                        symbol.len() > numeraire.len() && symbol.ends_with(numeraire.as_str())
+                    })
+                    .max_by_key(|numeraire| numeraire.len())?;
+                symbol.split_at(symbol.len() - numeraire.len())
+            }
+        };
+        if base.is_empty() || numeraire.is_empty() {
+            return None;
+        }
+        let asset =
+            |name: &str| Asset::intern(self.aliases.get(name).map_or(name, |alias| alias.as_str()));
+        Some(Pair {
+            numeraire: asset(numeraire),
+            base: asset(base),
+        })
+    }
+}
+
+/// How the decimals of a source map to the crate's integer units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    /// Decimal places of a price unit: with 2, a venue price of `1.25` is price 125.
+    pub price_decimals: u32,
+    /// Decimal places of a quantity unit.
+    pub quantity_decimals: u32,
+    /// The tick prices must fall on, in price units.
+    pub tick_size: u64,
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self {
+            price_decimals: 0,
+            quantity_decimals: 0,
+            tick_size: 1,
+        }
+    }
+}
+
+impl Scale {
+    /// Convert a venue price to a price on the tick
+    ///
+    /// # Arguments
+    ///
+    /// * `price` - The decimal price, e.g. `"101.25"`
+    pub fn price(&self, price: &str) -> Result<Price> {
+        let units = parse_decimal(price, self.price_decimals)?;
+        if units % self.tick_size.max(1) != 0 {
+            return Err(anyhow::anyhow!(
+                "Price {} is not on a tick of {}",
+                price,
+                self.tick_size
+            ));
+        }
+        Ok(Price::new(units))
+    }
+
+    /// Convert a venue quantity to a quantity
+    ///
+    /// # Arguments
+    ///
+    /// * `quantity` - The decimal quantity, e.g. `"0.5"`
+    pub fn quantity(&self, quantity: &str) -> Result<Quantity> {
+        Ok(Quantity::new(parse_decimal(
+            quantity,
+            self.quantity_decimals,
+        )?))
+    }
+}
+
+/// How to read the data of one source.
+pub struct SourceConfig {
+    pub symbols: Box<dyn SymbolMapper>,
+    /// The scale of every market of the source without its own.
+    pub scale: Scale,
+    /// Scales of markets quoted at another precision than the rest of the source.
+    pub market_scales: HashMap<Pair, Scale>,
+}
+
+impl SourceConfig {
+    pub fn new(symbols: impl SymbolMapper + 'static, scale: Scale) -> Self {
+        Self {
+            symbols: Box::new(symbols),
+            scale,
+            market_scales: HashMap::new(),
+        }
+    }
+
+    /// The scale of a market of the source.
+    pub fn scale(&self, pair: Pair) -> Scale {
+        self.market_scales.get(&pair).copied().unwrap_or(self.scale)
+    }
+}
+
+/// An order as recorded by a venue, one per line of a JSON-lines file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ExternalOrder {
+    pub id: u64,
+    pub account: String,
+    pub symbol: String,
+    /// `buy` or `bid`, `sell` or `ask`, in any case.
+    pub side: String,
+    /// The limit price as a decimal string, so that no precision is lost in parsing.
+    pub price: String,
+    pub quantity: String,
+    pub timestamp: u64,
+}
+
+/// The configs of every source, by name.
+#[derive(Default)]
+pub struct Importer {
+    sources: HashMap<String, SourceConfig>,
+}
+
+impl Importer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a source, replacing any config it had.
+    pub fn add_source(&mut self, name: impl Into<String>, config: SourceConfig) {
+        self.sources.insert(name.into(), config);
+    }
+
+    fn source(&self, name: &str) -> Result<&SourceConfig> {
+        self.sources
+            .get(name)
+            .ok_or(anyhow::anyhow!("Unknown source {}", name))
+    }
+
+    /// The pair a symbol of a source stands for
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the source
+    /// * `symbol` - The symbol as the source writes it
+    pub fn pair(&self, source: &str, symbol: &str) -> Result<Pair> {
+        self.source(source)?
+            .symbols
+            .pair(symbol)
+            .ok_or(anyhow::anyhow!("Unknown symbol {} of {}", symbol, source))
+    }
+
+    /// Convert an order of a source to an order of the crate and the pair it is posted to
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the source
+    /// * `order` - The order as the source recorded it
+    pub fn order(&self, source: &str, order: &ExternalOrder) -> Result<(Pair, Order)> {
+        let pair = self.pair(source, &order.symbol)?;
+        let scale = self.source(source)?.scale(pair);
+        let side = match order.side.to_lowercase().as_str() {
+            "buy" | "bid" => Side::Bid,
+            "sell" | "ask" => Side::Ask,
+            _ => return Err(anyhow::anyhow!("Invalid side {}", order.side)),
+        };
+        let normalized = Order::new(
+            OrderId::new(order.id),
+            scale.price(&order.price)?,
+            scale.quantity(&order.quantity)?,
+            side,
+            AccountId::new(order.account.clone()),
+            Timestamp::new(order.timestamp),
+        );
+        Ok((pair, normalized))
+    }
+
+    /// Read the orders of a source from JSON lines, skipping blank lines
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the source
+    /// * `reader` - One `ExternalOrder` per line
+    pub fn load_orders(&self, source: &str, reader: impl BufRead) -> Result<Vec<(Pair, Order)>> {
+        let mut orders = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let order: ExternalOrder = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid order on line {}", index + 1))?;
+            orders.push(
+                self.order(source, &order)
+                    .with_context(|| format!("Invalid order on line {}", index + 1))?,
+            );
+        }
+        Ok(orders)
+    }
+}
+
+/// Parse a non-negative decimal into units of `10^-decimals`, refusing any precision beyond
+/// them other than trailing zeros.
+fn parse_decimal(text: &str, decimals: u32) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("Invalid decimal {}", text);
+    let (whole, fraction) = text.trim().split_once('.').unwrap_or((text.trim(), ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let significant = fraction.trim_end_matches('0');
+    if significant.len() > decimals as usize {
+        return Err(anyhow::anyhow!(
+            "{} is finer than {} decimals",
+            text,
+            decimals
+        ));
+    }
+    let too_large = || anyhow::anyhow!("{} is too large", text);
+    let mut units: u64 = 0;
+    for digit in whole
+        .bytes()
+        .chain(significant.bytes())
+        .chain(std::iter::repeat_n(
+            b'0',
+            decimals as usize - significant.len(),
+        ))
+    {
+        units = units
+            .checked_mul(10)
+            .and_then(|units| units.checked_add((digit - b'0') as u64))
+            .ok_or_else(too_large)?;
+    }
+    Ok(units)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{exchange::Exchange, market::Market};
+
+    use super::*;
+
+    #[test]
+    fn test_sources_normalize_to_the_same_pairs_and_ticks() {
+        let btc = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let doge = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("DOGE"),
+        };
+        // One venue quotes cents, the other tenths of a cent with a cent tick and names
+        // bitcoin XBT, but doge in finer units
+        let mut importer = Importer::new();
+        importer.add_source(
+            "dashed",
+            SourceConfig::new(
+                SymbolRules::default(),
+                Scale {
+                    price_decimals: 2,
+                    ..Scale::default()
+                },
+            ),
+        );
+        let mut rules = SymbolRules {
+            aliases: HashMap::from([("XBT".to_string(), "BTC".to_string())]),
+            numeraires: vec!["USD".to_string(), "USDT".to_string()],
+            ..SymbolRules::default()
+        };
+        rules.symbols.insert("BTC-PERP".to_string(), btc);
+        let mut joined = SourceConfig::new(
+            rules,
+            Scale {
+                price_decimals: 3,
+                quantity_decimals: 0,
+                tick_size: 10,
+            },
+        );
+        joined.market_scales.insert(
+            doge,
+            Scale {
+                price_decimals: 4,
+                ..Scale::default()
+            },
+        );
+        importer.add_source("joined", joined);
+
+        for (source, symbol) in [
+            ("dashed", "BTC-USD"),
+            ("dashed", "btc/usd"),
+            ("joined", "XBTUSD"),
+            ("joined", "BTC-PERP"),
+        ] {
+            assert_eq!(importer.pair(source, symbol).unwrap(), btc, "{symbol}");
+        }
+        let usdt = importer.pair("joined", "XBTUSDT").unwrap();
+        assert_eq!(usdt.numeraire, Asset::new("USDT"));
+        assert!(importer.pair("dashed", "BTCUSD").is_err());
+        assert!(importer.pair("joined", "USD").is_err());
+        assert!(importer.pair("other", "BTC-USD").is_err());
+
+        let order = |symbol: &str, price: &str, quantity: &str| ExternalOrder {
+            id: 1,
+            account: "alice".to_string(),
+            symbol: symbol.to_string(),
+            side: "BUY".to_string(),
+            price: price.to_string(),
+            quantity: quantity.to_string(),
+            timestamp: 1,
+        };
+        let (pair, dashed) = importer
+            .order("dashed", &order("BTC-USD", "101.25", "3"))
+            .unwrap();
+        let (_, joined) = importer
+            .order("joined", &order("XBTUSD", "101.250", "3.0"))
+            .unwrap();
+        assert_eq!(pair, btc);
+        assert_eq!(dashed.price, Price::new(10_125));
+        assert_eq!(joined.price, Price::new(101_250));
+        assert_eq!(dashed.side, Side::Bid);
+        let (_, doge_order) = importer
+            .order("joined", &order("DOGEUSD", "0.0712", "10"))
+            .unwrap();
+        assert_eq!(doge_order.price, Price::new(712));
+
+        // Values the scale cannot represent exactly are refused, not rounded
+        for (source, price, quantity) in [
+            ("dashed", "101.255", "1"),
+            ("dashed", "101.25", "0.5"),
+            ("joined", "101.255", "1"),
+            ("dashed", "1e3", "1"),
+            ("dashed", "-1", "1"),
+            ("dashed", ".", "1"),
+            ("dashed", "184467440737095516.16", "1"),
+        ] {
+            assert!(
+                importer
+                    .order(source, &order("BTC-USD", price, quantity))
+                    .is_err(),
+                "{price} x {quantity}"
+            );
+        }
+        assert_eq!(parse_decimal("184467440737095516.15", 2).unwrap(), u64::MAX);
+        assert_eq!(parse_decimal(".5", 1).unwrap(), 5);
+
+        // Orders of a file load into the exchange
+        let lines = [
+            r#"{"id": 1, "account": "alice", "symbol": "BTC-USD", "side": "sell", "price": "100.5", "quantity": "2", "timestamp": 1}"#,
+            "",
+            r#"{"id": 2, "account": "bob", "symbol": "btc-usd", "side": "buy", "price": "101", "quantity": "1", "timestamp": 2}"#,
+        ]
+        .join("\n");
+        let orders = importer.load_orders("dashed", lines.as_bytes()).unwrap();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(btc));
+        exchange.add_balance(AccountId::new("alice".to_string()), btc.base, 2);
+        exchange.add_balance(AccountId::new("bob".to_string()), btc.numeraire, 10_100);
+        let fills: Vec<usize> = orders
+            .into_iter()
+            .map(|(pair, order)| exchange.post_order(order, pair).unwrap().fills.len())
+            .collect();
+        assert_eq!(fills, vec![0, 1]);
+        let error = importer
+            .load_orders("dashed", r#"{"id": 3}"#.as_bytes())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid order on line 1");
+    }
+}
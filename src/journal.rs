@@ -0,0 +1,854 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::{
+    codec::{Decoder, Encoder},
+    command::Command,
+    commitment::{Digest, StateHasher},
+    exchange::Exchange,
+    market::Pair,
+    matching::Trade,
+    migration::{self, Format},
+    order::{Order, OrderId, Price, Quantity},
+    snapshot::Snapshot,
+    witness::CommandOutcome,
+};
+
+/// A step in the life of an order, as reconstructed from the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    Accepted {
+        seq: u64,
+    },
+    /// The exchange refused the order.
+    Rejected {
+        seq: u64,
+    },
+    /// The order traded, leaving `remaining` unfilled.
+    Filled {
+        seq: u64,
+        price: Price,
+        quantity: Quantity,
+        remaining: Quantity,
+    },
+    /// The order's price or quantity was changed to these.
+    Amended {
+        seq: u64,
+        price: Price,
+        quantity: Quantity,
+    },
+    Cancelled {
+        seq: u64,
+    },
+    Expired {
+        seq: u64,
+    },
+}
+
+/// The first point at which re-executing a journal disagrees with what was recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Sequence number of the command with a different outcome, or of the state that differs.
+    pub seq: u64,
+    pub reason: String,
+}
+
+/// Sequenced record of the commands executed against an exchange, with periodic snapshots
+/// for fast offline replay.
+///
+/// The command at index `seq` has sequence number `seq`, and the state "at `seq`" is the
+/// state after the first `seq` commands. Rejected commands are journaled too, so sequence
+/// numbers match the order commands were submitted in. Replay only covers the state
+/// captured by `Snapshot`.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    commands: Vec<Command>,
+    /// The outcome of each command, by sequence number.
+    outcomes: Vec<CommandOutcome>,
+    /// Snapshots taken every `checkpoint_interval` commands, starting at sequence zero.
+    checkpoints: Vec<(u64, Snapshot)>,
+    checkpoint_interval: u64,
+}
+
+impl Journal {
+    /// Start journaling an exchange from its current state.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange to journal
+    /// * `checkpoint_interval` - Number of commands between snapshots, at least one
+    pub fn new(exchange: &Exchange, checkpoint_interval: u64) -> Self {
+        Self {
+            commands: Vec::new(),
+            outcomes: Vec::new(),
+            checkpoints: vec![(0, exchange.snapshot())],
+            checkpoint_interval: checkpoint_interval.max(1),
+        }
+    }
+
+    /// Execute a command against the journaled exchange and append it to the journal.
+    pub fn execute(&mut self, exchange: &mut Exchange, command: Command) -> Result<Vec<Trade>> {
+        self.commands.push(command.clone());
+        let result = exchange.execute(command);
+        self.outcomes.push(match &result {
+            Ok(trades) => CommandOutcome::Executed(trades.clone()),
+            Err(_) => CommandOutcome::Rejected,
+        });
+        let seq = self.len();
+        if seq.is_multiple_of(self.checkpoint_interval) {
+            self.checkpoints.push((seq, exchange.snapshot()));
+        }
+        result
+    }
+
+    /// Number of journaled commands, which is also the sequence number of the current state.
+    pub fn len(&self) -> u64 {
+        self.commands.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Number of commands journaled since the latest checkpoint, which recovery replays on
+    /// top of it.
+    pub fn checkpoint_lag(&self) -> u64 {
+        self.len() - self.checkpoints.last().map_or(0, |(seq, _)| *seq)
+    }
+
+    /// The journaled commands, in sequence order.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Re-executes the whole journal from its first snapshot, checking the outcome of every
+    /// command, every journaled snapshot and every given state root
+    ///
+    /// Returns the first divergence in sequence order, or `None` if the replay agrees with
+    /// everything recorded. State roots are checked before the command at the same sequence
+    /// number, since the state at `seq` precedes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `hasher` - The hash function the state roots were computed with
+    /// * `state_roots` - `(seq, root)` pairs taken from the live exchange, in any order
+    pub fn audit<H: StateHasher + ?Sized>(
+        &self,
+        hasher: &H,
+        state_roots: &[(u64, Digest)],
+    ) -> Result<Option<Divergence>> {
+        let mut roots = BTreeMap::new();
+        for (seq, root) in state_roots {
+            if *seq > self.len() {
+                return Err(anyhow::anyhow!(
+                    "State root at {} is beyond the journal of {} commands",
+                    seq,
+                    self.len()
+                ));
+            }
+            if roots
+                .insert(*seq, *root)
+                .is_some_and(|other| other != *root)
+            {
+                return Err(anyhow::anyhow!("Conflicting state roots at {}", seq));
+            }
+        }
+
+        let mut exchange = Exchange::from_snapshot(&self.checkpoints[0].1);
+        let mut checkpoints = self.checkpoints.iter().peekable();
+        for seq in 0..=self.len() {
+            if roots
+                .get(&seq)
+                .is_some_and(|root| *root != exchange.state_root(hasher))
+            {
+                return Ok(Some(Divergence {
+                    seq,
+                    reason: "State root differs from the live exchange".to_string(),
+                }));
+            }
+            if let Some((_, snapshot)) = checkpoints.next_if(|(checkpoint, _)| *checkpoint == seq)
+                && exchange.snapshot() != *snapshot
+            {
+                return Ok(Some(Divergence {
+                    seq,
+                    reason: "State differs from the journaled snapshot".to_string(),
+                }));
+            }
+            let Some(command) = self.commands.get(seq as usize) else {
+                break;
+            };
+            let actual = match exchange.execute(command.clone()) {
+                Ok(trades) => CommandOutcome::Executed(trades),
+                Err(_) => CommandOutcome::Rejected,
+            };
+            let expected = &self.outcomes[seq as usize];
+            if actual != *expected {
+                return Ok(Some(Divergence {
+                    seq,
+                    reason: format!(
+                        "Command produced {:?}, journal expects {:?}",
+                        actual, expected
+                    ),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Encodes the journal in the current version of the canonical binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        migration::write_header(&mut encoder, Format::Journal);
+        encoder.u64(self.checkpoint_interval);
+        encoder.len(self.commands.len());
+        for (command, outcome) in self.commands.iter().zip(&self.outcomes) {
+            encoder.command(command);
+            encoder.outcome(outcome);
+        }
+        encoder.len(self.checkpoints.len());
+        for (seq, snapshot) in &self.checkpoints {
+            encoder.u64(*seq);
+            snapshot.encode(&mut encoder);
+        }
+        encoder.finish()
+    }
+
+    /// Decodes a journal from any version of the canonical binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let body = migration::load(Format::Journal, bytes)?;
+        let mut decoder = Decoder::new(&body);
+        let checkpoint_interval = decoder.u64()?;
+        let mut commands = Vec::new();
+        let mut outcomes = Vec::new();
+        for _ in 0..decoder.len()? {
+            commands.push(decoder.command()?);
+            outcomes.push(decoder.outcome()?);
+        }
+        let mut checkpoints: Vec<(u64, Snapshot)> = Vec::new();
+        for _ in 0..decoder.len()? {
+            let seq = decoder.u64()?;
+            if seq > commands.len() as u64
+                || checkpoints.last().is_some_and(|(last, _)| *last >= seq)
+            {
+                return Err(anyhow::anyhow!("Invalid checkpoint {}", seq));
+            }
+            checkpoints.push((seq, Snapshot::decode(&mut decoder)?));
+        }
+        decoder.finish()?;
+        if checkpoints.first().is_none_or(|(seq, _)| *seq != 0) {
+            return Err(anyhow::anyhow!("Journal has no checkpoint at zero"));
+        }
+        Ok(Self {
+            commands,
+            outcomes,
+            checkpoints,
+            checkpoint_interval: checkpoint_interval.max(1),
+        })
+    }
+
+    /// The life of an order, from its submission to its last fill, cancellation or expiry
+    ///
+    /// Fills are read from the trades of every order posted to the same market, so fills
+    /// of a resting order are reported at the sequence number of the order that hit it.
+    /// The trades of an order group are read whole when any leg is in the market, so with
+    /// per-market order IDs a fill of another market's order with the same ID shows up too.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market the order was posted to
+    /// * `order_id` - The ID of the order
+    pub fn timeline(&self, pair: Pair, order_id: OrderId) -> Vec<LifecycleEvent> {
+        let mut events = Vec::new();
+        // The order while it may still rest in the book
+        let mut open = None;
+        for (seq, (command, outcome)) in self.commands.iter().zip(&self.outcomes).enumerate() {
+            let seq = seq as u64;
+            let executed = match outcome {
+                CommandOutcome::Executed(trades) => Some(trades),
+                CommandOutcome::Rejected => None,
+            };
+            match command {
+                Command::PostOrder { pair: p, order } if *p == pair && order.id == order_id => {
+                    let Some(trades) = executed else {
+                        events.push(LifecycleEvent::Rejected { seq });
+                        continue;
+                    };
+                    events.push(LifecycleEvent::Accepted { seq });
+                    open = Some(order.clone());
+                    fills(&mut events, &mut open, seq, trades);
+                }
+                Command::PostOrder { pair: p, .. } | Command::PlaceOrder { pair: p, .. }
+                    if *p == pair =>
+                {
+                    if let Some(trades) = executed {
+                        fills(&mut events, &mut open, seq, trades);
+                    }
+                }
+                Command::AmendOrder {
+                    pair: p,
+                    order_id: id,
+                    new_price,
+                    new_quantity,
+                    ..
+                } if *p == pair => {
+                    let Some(trades) = executed else {
+                        continue;
+                    };
+                    if *id == order_id
+                        && let Some(order) = &mut open
+                    {
+                        order.price = *new_price;
+                        order.quantity = *new_quantity;
+                        events.push(LifecycleEvent::Amended {
+                            seq,
+                            price: *new_price,
+                            quantity: *new_quantity,
+                        });
+                    }
+                    fills(&mut events, &mut open, seq, trades);
+                }
+                Command::PostOrderGroup { legs } if legs.iter().any(|(_, p)| *p == pair) => {
+                    let leg = legs
+                        .iter()
+                        .find(|(order, p)| *p == pair && order.id == order_id);
+                    let Some(trades) = executed else {
+                        if leg.is_some() {
+                            events.push(LifecycleEvent::Rejected { seq });
+                        }
+                        continue;
+                    };
+                    if let Some((order, _)) = leg {
+                        events.push(LifecycleEvent::Accepted { seq });
+                        open = Some(order.clone());
+                    }
+                    fills(&mut events, &mut open, seq, trades);
+                }
+                Command::ReduceOrder {
+                    pair: p,
+                    order_id: id,
+                    quantity,
+                    ..
+                } if *p == pair && *id == order_id && executed.is_some() => {
+                    if let Some(order) = &mut open {
+                        order.quantity = *quantity;
+                        events.push(LifecycleEvent::Amended {
+                            seq,
+                            price: order.price,
+                            quantity: *quantity,
+                        });
+                    }
+                }
+                Command::CancelReplace {
+                    pair: p,
+                    order_id: id,
+                    replacement,
+                    ..
+                } if *p == pair => {
+                    let Some(trades) = executed else {
+                        if replacement.id == order_id {
+                            events.push(LifecycleEvent::Rejected { seq });
+                        }
+                        continue;
+                    };
+                    if *id == order_id && open.is_some() {
+                        open = None;
+                        events.push(LifecycleEvent::Cancelled { seq });
+                    }
+                    if replacement.id == order_id {
+                        events.push(LifecycleEvent::Accepted { seq });
+                        open = Some(replacement.clone());
+                    }
+                    fills(&mut events, &mut open, seq, trades);
+                }
+                Command::CancelOrder {
+                    pair: p,
+                    order_id: id,
+                    ..
+                } if *p == pair && *id == order_id && executed.is_some() && open.is_some() => {
+                    open = None;
+                    events.push(LifecycleEvent::Cancelled { seq });
+                }
+                Command::ExpireOrders { now }
+                    if open.as_ref().is_some_and(|order| {
+                        order.stop_price.is_none() && order.is_expired(*now)
+                    }) =>
+                {
+                    open = None;
+                    events.push(LifecycleEvent::Expired { seq });
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+/// Records the fills of the open order among `trades`, closing it once fully filled.
+fn fills(events: &mut Vec<LifecycleEvent>, open: &mut Option<Order>, seq: u64, trades: &[Trade]) {
+    let Some(order) = open else {
+        return;
+    };
+    for trade in trades {
+        if trade.order_id(order.side) != order.id {
+            continue;
+        }
+        // A stop order trading has been triggered
+        order.stop_price = None;
+        order.quantity = order.quantity - trade.quantity;
+        events.push(LifecycleEvent::Filled {
+            seq,
+            price: trade.price,
+            quantity: trade.quantity,
+            remaining: order.quantity,
+        });
+    }
+    // Filled orders and the discarded remainders of orders that never rest are done. Stops
+    // stay open until they trade.
+    if order.quantity.get() == 0 || (order.stop_price.is_none() && !order.rests()) {
+        *open = None;
+    }
+}
+
+impl Exchange {
+    /// Reconstruct the exchange as it was after the first `seq` journaled commands
+    ///
+    /// Replays from the latest snapshot at or before `seq`, and fails if a replayed command
+    /// does not have the outcome the journal recorded for it. The returned exchange is
+    /// detached from the live one and can be inspected or driven further freely.
+    ///
+    /// # Arguments
+    ///
+    /// * `journal` - The journal of the exchange
+    /// * `seq` - The sequence number to reconstruct
+    pub fn state_at(journal: &Journal, seq: u64) -> Result<Exchange> {
+        if seq > journal.len() {
+            return Err(anyhow::anyhow!(
+                "Sequence number {} is beyond the journal of {} commands",
+                seq,
+                journal.len()
+            ));
+        }
+        let (start, snapshot) = journal
+            .checkpoints
+            .iter()
+            .rev()
+            .find(|(checkpoint, _)| *checkpoint <= seq)
+            .expect("journals always have a checkpoint at zero");
+        let mut exchange = Exchange::from_snapshot(snapshot);
+        let range = *start as usize..seq as usize;
+        for (index, (command, outcome)) in journal.commands[range.clone()]
+            .iter()
+            .zip(&journal.outcomes[range])
+            .enumerate()
+        {
+            // Rejections left the state unchanged, and are not replayed: some depend on
+            // state no checkpoint captures, such as the recent client order IDs, and would
+            // be accepted on top of a checkpoint
+            if *outcome == CommandOutcome::Rejected {
+                continue;
+            }
+            let replayed = match exchange.execute(command.clone()) {
+                Ok(trades) => CommandOutcome::Executed(trades),
+                Err(_) => CommandOutcome::Rejected,
+            };
+            if replayed != *outcome {
+                return Err(anyhow::anyhow!(
+                    "Replaying command {} produced {:?}, journal expects {:?}",
+                    *start as usize + index,
+                    replayed,
+                    outcome
+                ));
+            }
+        }
+        Ok(exchange)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::{Market, Pair},
+        order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_state_at_replays_from_checkpoints() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let post = |id: u64, side: Side, account: &AccountId| Command::PostOrder {
+            pair,
+            order: Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(2),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            ),
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        let mut journal = Journal::new(&exchange, 2);
+        let mut states = vec![exchange.snapshot()];
+        for command in [
+            Command::Deposit {
+                account_id: alice.clone(),
+                asset: pair.numeraire,
+                amount: 1_000,
+            },
+            Command::Deposit {
+                account_id: bob.clone(),
+                asset: pair.base,
+                amount: 5,
+            },
+            post(1, Side::Bid, &alice),
+            // Rejected: bob has no numeraire
+            post(2, Side::Bid, &bob),
+            post(3, Side::Ask, &bob),
+        ] {
+            let _ = journal.execute(&mut exchange, command);
+            states.push(exchange.snapshot());
+        }
+
+        for (seq, state) in states.iter().enumerate() {
+            let replayed = Exchange::state_at(&journal, seq as u64).unwrap();
+            assert_eq!(replayed.snapshot(), *state, "state at {}", seq);
+        }
+        assert!(Exchange::state_at(&journal, 6).is_err());
+    }
+
+    #[test]
+    fn test_state_at_keeps_positions_across_checkpoints() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let order = |id: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(2),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+        let deposit = |account: &AccountId, asset: Asset| Command::Deposit {
+            account_id: account.clone(),
+            asset,
+            amount: 1_000,
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        let mut journal = Journal::new(&exchange, 6);
+        for command in [
+            deposit(&alice, pair.numeraire),
+            deposit(&bob, pair.base),
+            Command::PostOrder {
+                pair,
+                order: order(1, Side::Ask, &bob),
+            },
+            // Opens a long position for alice
+            Command::PostOrder {
+                pair,
+                order: order(2, Side::Bid, &alice),
+            },
+            deposit(&alice, pair.numeraire),
+            deposit(&bob, pair.base),
+            // After the checkpoint at 6, and only accepted because alice is long
+            Command::PostOrder {
+                pair,
+                order: Order {
+                    reduce_only: true,
+                    ..order(3, Side::Ask, &alice)
+                },
+            },
+        ] {
+            journal.execute(&mut exchange, command).unwrap();
+        }
+
+        let replayed = Exchange::state_at(&journal, 7).unwrap();
+        assert_eq!(replayed.snapshot(), exchange.snapshot());
+        assert_eq!(replayed.position(&alice, pair), 2);
+
+        // The journal claims the trade opening the position never happened
+        let mut tampered = journal.clone();
+        tampered.outcomes[3] = CommandOutcome::Executed(Vec::new());
+        assert!(Exchange::state_at(&tampered, 4).is_err());
+        // Replay from the checkpoint does not reach the tampered command
+        assert!(Exchange::state_at(&tampered, 7).is_ok());
+    }
+
+    #[test]
+    fn test_timeline() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let maker = AccountId::new("maker".to_string());
+        let taker = AccountId::new("taker".to_string());
+        let order = |id: u64, side: Side, quantity: u64, account: &AccountId| Order {
+            expires_at: Some(Timestamp::new(50)),
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(quantity),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_balance(maker.clone(), pair.base, 10);
+        exchange.add_balance(taker.clone(), pair.numeraire, 1_000);
+        let mut journal = Journal::new(&exchange, 10);
+        for command in [
+            Command::PostOrder {
+                pair,
+                order: order(1, Side::Ask, 5, &maker),
+            },
+            Command::PostOrder {
+                pair,
+                order: order(2, Side::Bid, 2, &taker),
+            },
+            Command::ExpireOrders {
+                now: Timestamp::new(10),
+            },
+            Command::PostOrder {
+                pair,
+                order: order(3, Side::Bid, 1, &taker),
+            },
+            Command::ExpireOrders {
+                now: Timestamp::new(50),
+            },
+            // Too late: the order already expired
+            Command::CancelOrder {
+                pair,
+                order_id: OrderId::new(1),
+                side: Side::Ask,
+                price: Price::new(100),
+            },
+        ] {
+            let _ = journal.execute(&mut exchange, command);
+        }
+
+        let fill = |seq: u64, quantity: u64, remaining: u64| LifecycleEvent::Filled {
+            seq,
+            price: Price::new(100),
+            quantity: Quantity::new(quantity),
+            remaining: Quantity::new(remaining),
+        };
+        assert_eq!(
+            journal.timeline(pair, OrderId::new(1)),
+            vec![
+                LifecycleEvent::Accepted { seq: 0 },
+                fill(1, 2, 3),
+                fill(3, 1, 2),
+                LifecycleEvent::Expired { seq: 4 },
+            ]
+        );
+        assert_eq!(
+            journal.timeline(pair, OrderId::new(2)),
+            vec![LifecycleEvent::Accepted { seq: 1 }, fill(1, 2, 0)]
+        );
+        assert!(journal.timeline(pair, OrderId::new(9)).is_empty());
+    }
+
+    #[test]
+    fn test_journal_records_every_command() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let order = |id: u64, side: Side, quantity: u64, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(quantity),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        let mut journal = Journal::new(&exchange, 3);
+        for command in [
+            Command::Deposit {
+                account_id: alice.clone(),
+                asset: pair.numeraire,
+                amount: 1_000,
+            },
+            Command::Deposit {
+                account_id: bob.clone(),
+                asset: pair.base,
+                amount: 10,
+            },
+            Command::PostOrder {
+                pair,
+                order: order(1, Side::Bid, 5, &alice),
+            },
+            Command::ReduceOrder {
+                pair,
+                order_id: OrderId::new(1),
+                side: Side::Bid,
+                price: Price::new(100),
+                quantity: Quantity::new(3),
+            },
+            Command::CancelReplace {
+                pair,
+                order_id: OrderId::new(1),
+                side: Side::Bid,
+                price: Price::new(100),
+                replacement: order(2, Side::Bid, 4, &alice),
+            },
+            // Placed as order 3
+            Command::PlaceOrder {
+                pair,
+                order: order(0, Side::Ask, 1, &bob),
+            },
+            Command::PostOrderGroup {
+                legs: vec![(order(4, Side::Ask, 1, &bob), pair)],
+            },
+            // Rejected: there is no such basket, nor are the accounts affiliated
+            Command::CreateBasketUnits {
+                account_id: bob.clone(),
+                token: Asset::new("ETF"),
+                units: 1,
+            },
+            Command::Cross {
+                pair,
+                seller: bob.clone(),
+                buyer: alice.clone(),
+                price: Price::new(100),
+                quantity: Quantity::new(1),
+                timestamp: Timestamp::new(9),
+            },
+            Command::CloseAccount {
+                account_id: bob.clone(),
+                dust_threshold: 1_000,
+            },
+        ] {
+            let _ = journal.execute(&mut exchange, command);
+        }
+
+        let decoded = Journal::from_bytes(&journal.to_bytes()).unwrap();
+        assert_eq!(decoded.commands(), journal.commands());
+        let replayed = Exchange::state_at(&decoded, decoded.len()).unwrap();
+        assert_eq!(replayed.snapshot(), exchange.snapshot());
+
+        let fill = |seq: u64, remaining: u64| LifecycleEvent::Filled {
+            seq,
+            price: Price::new(100),
+            quantity: Quantity::new(1),
+            remaining: Quantity::new(remaining),
+        };
+        assert_eq!(
+            journal.timeline(pair, OrderId::new(1)),
+            vec![
+                LifecycleEvent::Accepted { seq: 2 },
+                LifecycleEvent::Amended {
+                    seq: 3,
+                    price: Price::new(100),
+                    quantity: Quantity::new(3),
+                },
+                LifecycleEvent::Cancelled { seq: 4 },
+            ]
+        );
+        assert_eq!(
+            journal.timeline(pair, OrderId::new(2)),
+            vec![LifecycleEvent::Accepted { seq: 4 }, fill(5, 3), fill(6, 2)]
+        );
+    }
+
+    #[test]
+    fn test_audit_finds_first_divergence() {
+        use crate::commitment::Sha256Hasher;
+
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let post = |id: u64, side: Side, account: &AccountId| Command::PostOrder {
+            pair,
+            order: Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(2),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            ),
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        let mut journal = Journal::new(&exchange, 2);
+        let mut roots = vec![(0, exchange.state_root(&Sha256Hasher))];
+        for command in [
+            Command::Deposit {
+                account_id: alice.clone(),
+                asset: pair.numeraire,
+                amount: 1_000,
+            },
+            Command::Deposit {
+                account_id: bob.clone(),
+                asset: pair.base,
+                amount: 5,
+            },
+            post(1, Side::Bid, &alice),
+            post(2, Side::Bid, &bob),
+            post(3, Side::Ask, &bob),
+        ] {
+            let _ = journal.execute(&mut exchange, command);
+            roots.push((journal.len(), exchange.state_root(&Sha256Hasher)));
+        }
+
+        let decoded = Journal::from_bytes(&journal.to_bytes()).unwrap();
+        assert_eq!(decoded.commands(), journal.commands());
+        assert_eq!(decoded.audit(&Sha256Hasher, &roots).unwrap(), None);
+
+        // The live exchange drifted after the fourth command
+        let mut drifted = roots.clone();
+        drifted[4].1[0] ^= 1;
+        assert_eq!(
+            decoded
+                .audit(&Sha256Hasher, &drifted)
+                .unwrap()
+                .map(|d| d.seq),
+            Some(4)
+        );
+
+        // The journal claims the rejected order executed
+        let mut tampered = decoded.clone();
+        tampered.outcomes[3] = CommandOutcome::Executed(Vec::new());
+        assert_eq!(
+            tampered
+                .audit(&Sha256Hasher, &drifted)
+                .unwrap()
+                .map(|d| d.seq),
+            Some(3)
+        );
+
+        assert!(decoded.audit(&Sha256Hasher, &[(6, roots[0].1)]).is_err());
+        assert!(Journal::from_bytes(&journal.to_bytes()[..20]).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::order::{Order, Price};
+use crate::orderbook::insert_by_priority;
+
+/// One side of an orderbook stored as a vector of price levels indexed by tick.
+///
+/// Level `i` holds the orders resting at `min_price + i * tick_size`. Occupied
+/// levels are tracked in a bitset so best-price scans skip empty levels a word
+/// (64 ticks) at a time.
+#[derive(Debug, Clone)]
+pub struct PriceLadder {
+    min_price: u64,
+    tick_size: u64,
+    levels: Vec<Vec<Order>>,
+    occupied: Vec<u64>,
+}
+
+impl PriceLadder {
+    /// Creates an empty ladder covering `num_ticks` prices starting at `min_price`.
+    pub fn new(min_price: Price, tick_size: u64, num_ticks: usize) -> Self {
+        assert!(tick_size > 0, "tick size must be non-zero");
+        Self {
+            min_price: min_price.get(),
+            tick_size,
+            levels: vec![Vec::new(); num_ticks],
+            occupied: vec![0; num_ticks.div_ceil(64)],
+        }
+    }
+
+    /// Returns the level index of a price, or `None` if the price is off-tick or out of range.
+    pub fn index_of(&self, price: Price) -> Option<usize> {
+        let offset = price.get().checked_sub(self.min_price)?;
+        if offset % self.tick_size != 0 {
+            return None;
+        }
+        let index = usize::try_from(offset / self.tick_size).ok()?;
+        (index < self.levels.len()).then_some(index)
+    }
+
+    /// Returns the price of the level at `index`.
+    pub fn price_at(&self, index: usize) -> Price {
+        Price::new(self.min_price + index as u64 * self.tick_size)
+    }
+
+    /// Returns true if the price can be stored in this ladder.
+    pub fn contains_price(&self, price: Price) -> bool {
+        self.index_of(price).is_some()
+    }
+
+    /// Inserts an order into the level at its price, in `(timestamp, id)` priority.
+    ///
+    /// Panics if the price is not representable in the ladder.
+    pub fn insert(&mut self, order: Order) {
+        let index = self
+            .index_of(order.price)
+            .expect("order price outside of ladder range");
+        insert_by_priority(&mut self.levels[index], order);
+        self.set_occupied(index, true);
+    }
+
+    /// Returns the orders resting at a price, if the level is occupied.
+    pub fn level(&self, price: Price) -> Option<&Vec<Order>> {
+        let index = self.index_of(price)?;
+        self.is_occupied(index).then(|| &self.levels[index])
+    }
+
+    /// Returns a mutable reference to the orders resting at a price, if the level is occupied.
+    ///
+    /// Callers that empty the level must call `release_if_empty` afterwards.
+    pub fn level_mut(&mut self, price: Price) -> Option<&mut Vec<Order>> {
+        let index = self.index_of(price)?;
+        if self.is_occupied(index) {
+            Some(&mut self.levels[index])
+        } else {
+            None
+        }
+    }
+
+    /// Clears the occupancy bit of a level once its last order has been removed.
+    pub fn release_if_empty(&mut self, price: Price) {
+        if let Some(index) = self.index_of(price)
+            && self.levels[index].is_empty()
+        {
+            self.set_occupied(index, false);
+        }
+    }
+
+    /// Iterates mutably over all occupied levels, lowest price first.
+    pub fn levels_mut(&mut self) -> impl Iterator<Item = &mut Vec<Order>> {
+        self.levels.iter_mut().filter(|orders| !orders.is_empty())
+    }
+
+    /// Returns the lowest occupied price.
+    pub fn lowest(&self) -> Option<Price> {
+        self.next_occupied_from(0).map(|i| self.price_at(i))
+    }
+
+    /// Returns the highest occupied price.
+    pub fn highest(&self) -> Option<Price> {
+        self.prev_occupied_from(self.levels.len().checked_sub(1)?)
+            .map(|i| self.price_at(i))
+    }
+
+    /// Iterates over occupied levels from the lowest price upwards.
+    pub fn iter_ascending(&self) -> LadderIter<'_> {
+        LadderIter {
+            ladder: self,
+            next: self.next_occupied_from(0),
+            ascending: true,
+        }
+    }
+
+    /// Iterates over occupied levels from the highest price downwards.
+    pub fn iter_descending(&self) -> LadderIter<'_> {
+        LadderIter {
+            ladder: self,
+            next: self
+                .levels
+                .len()
+                .checked_sub(1)
+                .and_then(|last| self.prev_occupied_from(last)),
+            ascending: false,
+        }
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        self.occupied[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set_occupied(&mut self, index: usize, occupied: bool) {
+        let bit = 1 << (index % 64);
+        if occupied {
+            self.occupied[index / 64] |= bit;
+        } else {
+            self.occupied[index / 64] &= !bit;
+        }
+    }
+
+    /// Finds the first occupied level at or above `index`.
+    fn next_occupied_from(&self, index: usize) -> Option<usize> {
+        let mut word_index = index / 64;
+        let mut word = *self.occupied.get(word_index)? & (u64::MAX << (index % 64));
+        loop {
+            if word != 0 {
+                return Some(word_index * 64 + word.trailing_zeros() as usize);
+            }
+            word_index += 1;
+            word = *self.occupied.get(word_index)?;
+        }
+    }
+
+    /// Finds the last occupied level at or below `index`.
+    fn prev_occupied_from(&self, index: usize) -> Option<usize> {
+        let mut word_index = index / 64;
+        let mut word = *self.occupied.get(word_index)? & (u64::MAX >> (63 - index % 64));
+        loop {
+            if word != 0 {
+                return Some(word_index * 64 + 63 - word.leading_zeros() as usize);
+            }
+            word_index = word_index.checked_sub(1)?;
+            word = self.occupied[word_index];
+        }
+    }
+}
+
+/// Iterator over the occupied levels of a `PriceLadder`.
+pub struct LadderIter<'a> {
+    ladder: &'a PriceLadder,
+    next: Option<usize>,
+    ascending: bool,
+}
+
+impl<'a> Iterator for LadderIter<'a> {
+    type Item = (Price, &'a Vec<Order>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next?;
+        self.next = if self.ascending {
+            self.ladder.next_occupied_from(index + 1)
+        } else {
+            index
+                .checked_sub(1)
+                .and_then(|prev| self.ladder.prev_occupied_from(prev))
+        };
+        Some((self.ladder.price_at(index), &self.ladder.levels[index]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::order::{AccountId, OrderId, Quantity, Side, Timestamp};
+
+    use super::*;
+
+    fn order(id: u64, price: u64) -> Order {
+        Order::new(
+            OrderId::new(id),
+            Price::new(price),
+            Quantity::new(1),
+            Side::Ask,
+            AccountId::new("trader".to_string()),
+            Timestamp::new(id),
+        )
+    }
+
+    #[test]
+    fn test_ladder_scans_across_words() {
+        let mut ladder = PriceLadder::new(Price::new(1_000), 5, 200);
+
+        assert_eq!(ladder.index_of(Price::new(995)), None);
+        assert_eq!(ladder.index_of(Price::new(1_003)), None);
+        assert_eq!(ladder.index_of(Price::new(2_000)), None);
+        assert_eq!(ladder.index_of(Price::new(1_995)), Some(199));
+
+        ladder.insert(order(1, 1_005));
+        ladder.insert(order(2, 1_400));
+        ladder.insert(order(3, 1_995));
+
+        assert_eq!(ladder.lowest(), Some(Price::new(1_005)));
+        assert_eq!(ladder.highest(), Some(Price::new(1_995)));
+
+        let ascending: Vec<u64> = ladder.iter_ascending().map(|(p, _)| p.get()).collect();
+        assert_eq!(ascending, vec![1_005, 1_400, 1_995]);
+        let descending: Vec<u64> = ladder.iter_descending().map(|(p, _)| p.get()).collect();
+        assert_eq!(descending, vec![1_995, 1_400, 1_005]);
+
+        ladder.level_mut(Price::new(1_995)).unwrap().clear();
+        ladder.release_if_empty(Price::new(1_995));
+        assert_eq!(ladder.highest(), Some(Price::new(1_400)));
+        assert!(ladder.level(Price::new(1_995)).is_none());
+    }
+}
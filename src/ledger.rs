@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::{asset::Asset, order::AccountId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Credit,
+    Debit,
+}
+
+/// A single balance movement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// Position of the entry in the ledger, starting at zero.
+    pub seq: u64,
+    pub account_id: AccountId,
+    pub asset: Asset,
+    pub direction: Direction,
+    pub amount: u64,
+}
+
+/// Append-only record of every balance movement.
+///
+/// Entries of closed accounts are moved to the archive, where they stay queryable.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    entries: Vec<LedgerEntry>,
+    next_seq: u64,
+    archived: HashMap<AccountId, Vec<LedgerEntry>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a movement to the ledger.
+    pub fn record(
+        &mut self,
+        account_id: &AccountId,
+        asset: Asset,
+        direction: Direction,
+        amount: u64,
+    ) {
+        self.entries.push(LedgerEntry {
+            seq: self.next_seq,
+            account_id: account_id.clone(),
+            asset,
+            direction,
+            amount,
+        });
+        self.next_seq += 1;
+    }
+
+    /// Every live entry, oldest first.
+    pub fn entries(&self) -> &[LedgerEntry] {
+        &self.entries
+    }
+
+    /// The live entries of an account, oldest first.
+    pub fn entries_for<'a>(
+        &'a self,
+        account_id: &'a AccountId,
+    ) -> impl Iterator<Item = &'a LedgerEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.account_id == *account_id)
+    }
+
+    /// The net balance of every account and asset with live entries, recomputed from the
+    /// entries alone. Debits in excess of credits show up as negative balances.
+    pub fn net_balances(&self) -> HashMap<(AccountId, Asset), i128> {
+        let mut balances = HashMap::new();
+        for entry in &self.entries {
+            let balance = balances
+                .entry((entry.account_id.clone(), entry.asset))
+                .or_insert(0);
+            match entry.direction {
+                Direction::Credit => *balance += entry.amount as i128,
+                Direction::Debit => *balance -= entry.amount as i128,
+            }
+        }
+        balances
+    }
+
+    /// Move every live entry of an account to the archive, returning how many were moved.
+    pub fn archive(&mut self, account_id: &AccountId) -> usize {
+        let (archived, live): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| entry.account_id == *account_id);
+        self.entries = live;
+        let moved = archived.len();
+        self.archived
+            .entry(account_id.clone())
+            .or_default()
+            .extend(archived);
+        moved
+    }
+
+    /// The archived entries of a closed account, oldest first.
+    pub fn archived(&self, account_id: &AccountId) -> &[LedgerEntry] {
+        self.archived
+            .get(account_id)
+            .map_or(&[], |entries| entries.as_slice())
+    }
+}
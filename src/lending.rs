@@ -0,0 +1,234 @@
+//! Short selling against the exchange's lending pool.
+//!
+//! The pool is the `SystemAccount::Lending` account: the base it holds can be lent to
+//! accounts selling more than they own in markets open to short sales.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    account::SystemAccount,
+    exchange::Exchange,
+    market::Pair,
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Quantity, Side, Timestamp},
+    reject::RejectReason,
+};
+
+/// Markets open to short sales and the base each account has borrowed in them.
+#[derive(Debug, Default)]
+pub(crate) struct ShortSales {
+    enabled: HashSet<Pair>,
+    borrows: HashMap<(AccountId, Pair), u64>,
+}
+
+impl Exchange {
+    /// Open or close a market to short sales
+    ///
+    /// Closing a market only stops new borrows; existing borrows stay until they are repaid
+    /// or recalled.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market
+    /// * `enabled` - Whether asks may borrow their shortfall from the lending pool
+    pub fn set_short_selling(&mut self, pair: Pair, enabled: bool) {
+        if enabled {
+            self.short_sales.enabled.insert(pair);
+        } else {
+            self.short_sales.enabled.remove(&pair);
+        }
+    }
+
+    /// Returns true if asks in the market may borrow from the lending pool.
+    pub fn allows_short_selling(&self, pair: Pair) -> bool {
+        self.short_sales.enabled.contains(&pair)
+    }
+
+    /// Get the base an account owes the lending pool for its short sales in a market
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `pair` - The market the base was borrowed in
+    pub fn borrowed(&self, account_id: &AccountId, pair: Pair) -> u64 {
+        self.short_sales
+            .borrows
+            .get(&(account_id.clone(), pair))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Return borrowed base to the lending pool from the account's available balance
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the borrowing account
+    /// * `pair` - The market the base was borrowed in
+    /// * `amount` - The amount to return, at most the amount borrowed
+    pub fn repay_borrow(&mut self, account_id: AccountId, pair: Pair, amount: u64) -> Result<()> {
+        if amount > self.borrowed(&account_id, pair) {
+            return Err(anyhow::anyhow!("Amount exceeds borrowed balance"));
+        }
+        self.remove_balance(account_id.clone(), pair.base, amount)?;
+        self.add_balance(SystemAccount::Lending.id(), pair.base, amount);
+        let key = (account_id, pair);
+        let borrowed = self.short_sales.borrows.get_mut(&key).unwrap();
+        *borrowed -= amount;
+        if *borrowed == 0 {
+            self.short_sales.borrows.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Recall an account's borrow in a market, buying in what the account cannot return
+    ///
+    /// The part of the borrow the account's available base does not cover is bought back
+    /// with a market bid placed for the account at `timestamp`. Everything the account then
+    /// holds, up to the borrow, is returned to the pool. If the book is too thin to buy in
+    /// the whole shortfall, the rest stays borrowed. Nothing changes if the buy-in is
+    /// rejected, e.g. for lack of numeraire.
+    ///
+    /// Returns the trades of the buy-in.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the borrowing account
+    /// * `pair` - The market the base was borrowed in
+    /// * `timestamp` - The time of the recall
+    pub fn recall_borrow(
+        &mut self,
+        account_id: AccountId,
+        pair: Pair,
+        timestamp: Timestamp,
+    ) -> Result<Vec<Trade>> {
+        let borrowed = self.borrowed(&account_id, pair);
+        if borrowed == 0 {
+            return Err(anyhow::anyhow!("Nothing borrowed"));
+        }
+        let available = self.get_balance(account_id.clone(), pair.base).unwrap_or(0);
+        let mut trades = Vec::new();
+        if available < borrowed {
+            let buy_in = Order::market(
+                OrderId::default(),
+                Quantity::new(borrowed - available),
+                Side::Bid,
+                account_id.clone(),
+                timestamp,
+            );
+            trades = self.place_order(buy_in, pair)?.into_trades();
+        }
+        let available = self.get_balance(account_id.clone(), pair.base).unwrap_or(0);
+        self.repay_borrow(account_id, pair, available.min(borrowed))?;
+        Ok(trades)
+    }
+
+    /// Borrow the base an ask lacks from the lending pool, if its market allows short sales.
+    pub(crate) fn locate_short_sale(
+        &mut self,
+        order: &Order,
+        pair: Pair,
+    ) -> Result<(), RejectReason> {
+        if order.side != Side::Ask || !self.allows_short_selling(pair) {
+            return Ok(());
+        }
+        let available = self
+            .get_balance(order.account_id.clone(), pair.base)
+            .unwrap_or(0);
+        let shortfall = order.quantity.get().saturating_sub(available);
+        if shortfall == 0 {
+            return Ok(());
+        }
+        self.remove_balance(SystemAccount::Lending.id(), pair.base, shortfall)
+            .map_err(|_| RejectReason::ShortSaleNotLocated)?;
+        self.add_balance(order.account_id.clone(), pair.base, shortfall);
+        *self
+            .short_sales
+            .borrows
+            .entry((order.account_id.clone(), pair))
+            .or_insert(0) += shortfall;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::Market,
+        order::{Price, Quantity},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_short_sale_borrows_and_buy_in() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(SystemAccount::Lending.id(), pair.base, 10);
+        exchange.add_balance(bob.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(bob.clone(), pair.base, 5);
+        let ask = |id: u64, price: u64, quantity: u64, account_id: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(quantity),
+                Side::Ask,
+                account_id.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        // Short sales need the market to allow them and the pool to have the base
+        assert!(exchange.post_order(ask(1, 100, 5, &alice), pair).is_err());
+        exchange.set_short_selling(pair, true);
+        assert!(exchange.post_order(ask(2, 100, 11, &alice), pair).is_err());
+        exchange.post_order(ask(3, 100, 5, &alice), pair).unwrap();
+        assert_eq!(exchange.borrowed(&alice, pair), 5);
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Lending.id(), pair.base)
+                .unwrap(),
+            5
+        );
+
+        let bid = Order::new(
+            OrderId::new(4),
+            Price::new(100),
+            Quantity::new(5),
+            Side::Bid,
+            bob.clone(),
+            Timestamp::new(4),
+        );
+        exchange.post_order(bid, pair).unwrap();
+        assert_eq!(exchange.position(&alice, pair), -5);
+        assert!(exchange.repay_borrow(alice.clone(), pair, 1).is_err());
+
+        // The recall buys the borrow back from the book and returns it to the pool
+        exchange.post_order(ask(5, 90, 5, &bob), pair).unwrap();
+        let trades = exchange
+            .recall_borrow(alice.clone(), pair, Timestamp::new(6))
+            .unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, Price::new(90));
+        assert_eq!(exchange.borrowed(&alice, pair), 0);
+        assert_eq!(exchange.position(&alice, pair), 0);
+        assert_eq!(
+            exchange.get_balance(alice.clone(), pair.numeraire).unwrap(),
+            50
+        );
+        assert_eq!(
+            exchange
+                .get_balance(SystemAccount::Lending.id(), pair.base)
+                .unwrap(),
+            10
+        );
+    }
+}
@@ -1,8 +1,63 @@
 pub mod account;
 pub mod account_manager;
+pub mod account_overview;
+pub mod analytics;
 pub mod asset;
+pub mod auction;
+pub mod basket;
+pub mod book_shape;
+pub mod capabilities;
+pub mod circuit_breaker;
+pub mod clock;
+pub(crate) mod codec;
+pub mod command;
+pub mod command_log;
+pub mod commitment;
+#[cfg(test)]
+mod corpus;
+pub mod cross;
+pub mod cross_rate;
+pub mod diff;
+pub mod event;
 pub mod exchange;
+pub mod execution;
+pub mod feed;
+pub mod ffi;
+pub mod funding;
+pub mod health;
+pub mod history;
+pub mod import;
+pub mod journal;
+pub mod ladder;
+pub mod ledger;
+pub mod lending;
+pub mod listing;
 pub mod market;
+pub mod match_policy;
 pub mod matching;
+pub mod migration;
+pub mod misbehaving_client;
 pub mod order;
+pub mod order_defaults;
 pub mod orderbook;
+pub mod paper;
+pub mod phase_timing;
+pub mod price_band;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reject;
+pub mod retention;
+pub mod scenario;
+pub mod self_trade;
+pub mod settlement;
+pub mod simulation;
+pub mod snapshot;
+pub mod speed_bump;
+pub mod spread;
+pub mod surveillance;
+pub mod tag_report;
+pub mod treasury;
+pub mod trigger;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod witness;
@@ -0,0 +1,150 @@
+//! Markets listing the same base against several numeraires.
+//!
+//! A base such as BTC can be listed against any number of numeraires (USD, EUR, USDT, ...).
+//! Every market takes its holds out of the same per-asset balance kept by the
+//! `AccountManager`, so base offered in one market is never available to another. Statistics
+//! are consolidated across those markets in base units, which all of them share.
+
+use crate::{
+    asset::Asset,
+    exchange::Exchange,
+    market::Pair,
+    order::{AccountId, Price, Side},
+};
+
+/// Activity in every market of one base, in base units.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BaseStatistics {
+    /// The markets of the base, by numeraire symbol.
+    pub markets: Vec<Pair>,
+    /// The last trade price of each market that has traded, in its own numeraire.
+    pub last_prices: Vec<(Asset, Price)>,
+    /// Number of retained trades across the markets.
+    pub trades: usize,
+    /// Base traded across the markets' retained trades.
+    pub volume: u64,
+    /// Base resting on the bid side of every book of the markets.
+    pub bid_depth: u64,
+    /// Base resting on the ask side of every book of the markets.
+    pub ask_depth: u64,
+}
+
+impl Exchange {
+    /// Returns the markets listing `base`, sorted by numeraire symbol.
+    pub fn markets_for_base(&self, base: Asset) -> Vec<Pair> {
+        let mut pairs: Vec<Pair> = self
+            .markets
+            .keys()
+            .filter(|pair| pair.base == base)
+            .copied()
+            .collect();
+        pairs.sort_by_key(|pair| pair.numeraire.symbol);
+        pairs
+    }
+
+    /// Returns the net position of an account in `base` across all of its markets.
+    pub fn base_position(&self, account_id: &AccountId, base: Asset) -> i64 {
+        self.markets_for_base(base)
+            .into_iter()
+            .map(|pair| self.position(account_id, pair))
+            .sum()
+    }
+
+    /// Consolidated statistics of every market listing `base`
+    ///
+    /// Prices are quoted in different numeraires and are reported per market; quantities
+    /// are all in base and are summed.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The base asset
+    pub fn base_statistics(&self, base: Asset) -> BaseStatistics {
+        let mut stats = BaseStatistics {
+            markets: self.markets_for_base(base),
+            ..BaseStatistics::default()
+        };
+        for pair in &stats.markets {
+            let market = &self.markets[pair];
+            if let Some(price) = market.last_trade_price() {
+                stats.last_prices.push((pair.numeraire, price));
+            }
+            stats.trades += market.trades().len();
+            stats.volume += market
+                .trades()
+                .iter()
+                .map(|trade| trade.quantity.get())
+                .sum::<u64>();
+            for order in market.resting_orders() {
+                match order.side {
+                    Side::Bid => stats.bid_depth += order.quantity.get(),
+                    Side::Ask => stats.ask_depth += order.quantity.get(),
+                }
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        market::Market,
+        order::{Order, OrderId, Quantity, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_base_listed_against_several_numeraires() {
+        let btc = Asset::new("BTC");
+        let pair = |numeraire: &'static str| Pair {
+            numeraire: Asset::new(numeraire),
+            base: btc,
+        };
+        let (usd, eur, usdt) = (pair("USD"), pair("EUR"), pair("USDT"));
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        for pair in [usdt, usd, eur] {
+            exchange.add_market(Market::new(pair));
+        }
+        exchange.add_balance(alice.clone(), btc, 5);
+        exchange.add_balance(bob.clone(), usd.numeraire, 1_000);
+        let order = |id: u64, price: u64, qty: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(qty),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        // The same base cannot be offered twice
+        exchange
+            .post_order(order(1, 100, 4, Side::Ask, &alice), usd)
+            .unwrap();
+        assert!(
+            exchange
+                .post_order(order(2, 90, 2, Side::Ask, &alice), eur)
+                .is_err()
+        );
+        exchange
+            .post_order(order(3, 90, 1, Side::Ask, &alice), eur)
+            .unwrap();
+        assert_eq!(exchange.locked_balance(&alice, btc), 5);
+
+        exchange
+            .post_order(order(4, 100, 3, Side::Bid, &bob), usd)
+            .unwrap();
+        assert_eq!(exchange.base_position(&alice, btc), -3);
+        assert_eq!(exchange.base_position(&bob, btc), 3);
+
+        let stats = exchange.base_statistics(btc);
+        assert_eq!(stats.markets, vec![eur, usd, usdt]);
+        assert_eq!(stats.last_prices, vec![(usd.numeraire, Price::new(100))]);
+        assert_eq!((stats.trades, stats.volume), (1, 3));
+        assert_eq!((stats.bid_depth, stats.ask_depth), (0, 2));
+    }
+}
@@ -1,7 +1,18 @@
+use std::sync::Arc;
+
 use crate::{
     asset::Asset,
-    matching::{MatchingEngine, Trade},
-    order::{Order, OrderId, Price, Side},
+    circuit_breaker::{BreakerState, CircuitBreaker},
+    cross::Cross,
+    execution::ExecutionReport,
+    match_policy::{Allocation, MatchPolicy},
+    matching::{
+        Liquidity, MatchingEngine, SelfTradeCancel, SelfTradePrevention, Trade, TradeId, TradeIds,
+    },
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    orderbook::{BookBackend, OrderBook},
+    price_band::PriceBand,
+    trigger::StopTriggers,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -9,25 +20,637 @@ pub struct Pair {
     pub numeraire: Asset,
     pub base: Asset,
 }
+
+/// A fee of a fixed amount, possibly in an asset other than the market's numeraire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatFee {
+    pub asset: Asset,
+    pub amount: u64,
+}
+
+/// Maker and taker fees of a market, in basis points of the trade notional.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub maker_fee_bps: u64,
+    pub taker_fee_bps: u64,
+    /// Charged once per order that takes liquidity, in numeraire or the account's fee asset,
+    /// converted at the cross rate when the order is posted.
+    pub flat_fee: Option<FlatFee>,
+}
+
+impl FeeSchedule {
+    /// Returns the fee charged on `amount` for the given liquidity role, rounded down.
+    pub fn fee(&self, liquidity: Liquidity, amount: u64) -> u64 {
+        let bps = match liquidity {
+            Liquidity::Maker => self.maker_fee_bps,
+            Liquidity::Taker => self.taker_fee_bps,
+        };
+        (amount as u128 * bps as u128 / 10_000) as u64
+    }
+
+    /// Returns the most either role can be charged per unit traded at `price`, rounded up.
+    pub fn max_fee_per_unit(&self, price: Price) -> u64 {
+        let bps = self.maker_fee_bps.max(self.taker_fee_bps);
+        (price.get() as u128 * bps as u128).div_ceil(10_000) as u64
+    }
+}
+
+/// What happens to an order whose minimum quantity cannot be matched immediately.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MinQtyShortfall {
+    /// The order is rejected.
+    #[default]
+    Reject,
+    /// An order that would not trade at all rests untouched like any passive order. Orders
+    /// that would trade less than their minimum are still rejected, since resting them would
+    /// cross the book.
+    Rest,
+}
+
+/// Treatment of orders smaller than a market's round lot.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OddLots {
+    /// Every order matches in the same book, whatever its size.
+    #[default]
+    Mixed,
+    /// Orders below `round_lot` rest in, and only match against, a separate odd-lot book.
+    /// Orders of the main book must be whole round lots, so their fills and remainders are
+    /// whole round lots too.
+    Segregated { round_lot: u64 },
+}
+
+/// Per-market configuration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MarketConfig {
+    /// Storage backend of the market's orderbook.
+    pub book_backend: BookBackend,
+    /// Fees charged on trades in this market.
+    pub fees: FeeSchedule,
+    /// Redact account identifiers from public trade prints.
+    pub anonymize_public_trades: bool,
+    /// Handling of orders whose minimum quantity is not available.
+    pub min_qty_shortfall: MinQtyShortfall,
+    /// Handling of orders below the round lot.
+    pub odd_lots: OddLots,
+    /// How an aggressor's quantity is shared among the orders at a price level.
+    pub allocation: Allocation,
+}
+
+/// A trade as printed on the public feed of a market.
+///
+/// Account identifiers are `None` when the market anonymizes its public prints. Trades are
+/// stamped with the time of the order that executed them; a triggered stop order executes at
+/// the time of the trade that triggered it, so the feed's timestamps never decrease.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicTrade {
+    pub id: TradeId,
+    pub sequence: u64,
+    pub ask_order_id: OrderId,
+    pub bid_order_id: OrderId,
+    pub ask_account_id: Option<AccountId>,
+    pub bid_account_id: Option<AccountId>,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub aggressor: Option<Side>,
+    /// When the trade executed.
+    pub timestamp: Timestamp,
+}
+
+/// One price level of a book as shown publicly: the total resting quantity and the number
+/// of orders, without their accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price: Price,
+    pub quantity: Quantity,
+    pub orders: usize,
+}
+
+/// A read-only copy of a market's book, aggregated by price level, best price first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookView {
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+}
+
 pub struct Market {
     pub pair: Pair,
+    pub config: MarketConfig,
     pub matching_engine: MatchingEngine,
+    /// The book of odd lots, empty unless odd lots are segregated.
+    pub odd_lot_engine: MatchingEngine,
+    /// Every trade executed in the market, with account identifiers.
+    trades: Vec<Trade>,
+    /// When each trade of `trades` executed.
+    trade_times: Vec<Timestamp>,
+    /// Price of the most recent trade.
+    last_trade_price: Option<Price>,
+    /// The latest index price supplied from outside.
+    index_price: Option<Price>,
+    /// Sequence number of the next trade recorded.
+    next_trade_sequence: u64,
+    /// Where trade IDs are drawn from, shared with the other markets of the exchange.
+    trade_ids: TradeIds,
+    /// Stop orders waiting for their trigger, fed every trade price.
+    stops: StopTriggers,
+    /// Internal crosses printed in the market, oldest first.
+    crosses: Vec<Cross>,
+    /// The market's circuit breaker, fed every trade price.
+    breaker: Option<BreakerState>,
+    /// Bands limit prices must fall within, applied in order.
+    price_bands: Vec<PriceBand>,
+    /// Whether fills are allocated by a policy other than `config.allocation`.
+    custom_policy: bool,
 }
 
 impl Market {
     pub fn new(pair: Pair) -> Self {
-        Market {
+        Self::with_config(pair, MarketConfig::default())
+    }
+
+    pub fn with_config(pair: Pair, config: MarketConfig) -> Self {
+        let mut market = Self::with_policy(pair, config, config.allocation.policy());
+        market.custom_policy = false;
+        market
+    }
+
+    /// Creates a market that allocates fills with a custom policy instead of
+    /// `config.allocation`
+    ///
+    /// Snapshots only record `config.allocation`, so a market restored from one uses that
+    /// until `set_policy` installs the custom policy again.
+    pub fn with_policy(pair: Pair, config: MarketConfig, policy: Arc<dyn MatchPolicy>) -> Self {
+        let mut market = Market {
             pair,
-            matching_engine: MatchingEngine::new(),
+            config,
+            matching_engine: MatchingEngine::with_backend(config.book_backend),
+            odd_lot_engine: MatchingEngine::with_backend(config.book_backend),
+            trades: Vec::new(),
+            trade_times: Vec::new(),
+            last_trade_price: None,
+            index_price: None,
+            next_trade_sequence: 1,
+            trade_ids: TradeIds::default(),
+            stops: StopTriggers::default(),
+            crosses: Vec::new(),
+            breaker: None,
+            price_bands: Vec::new(),
+            custom_policy: true,
+        };
+        market.set_policy(policy);
+        market
+    }
+
+    /// Sets how both books of the market share an aggressor's quantity within a price level.
+    pub fn set_policy(&mut self, policy: Arc<dyn MatchPolicy>) {
+        // Fills in a segregated main book are whole round lots
+        let round_lot = match self.config.odd_lots {
+            OddLots::Mixed => 1,
+            OddLots::Segregated { round_lot } => round_lot,
+        };
+        self.matching_engine.set_policy(policy.clone(), round_lot);
+        self.odd_lot_engine.set_policy(policy, 1);
+        self.custom_policy = true;
+    }
+
+    /// Returns true if fills are allocated by a policy installed with `with_policy` or
+    /// `set_policy` rather than by `config.allocation`.
+    pub fn has_custom_policy(&self) -> bool {
+        self.custom_policy
+    }
+
+    /// Returns true if an order at this price can be accepted by the market. Zero is never
+    /// accepted, since a bid at zero would hold nothing whatever its quantity.
+    pub fn supports_price(&self, price: Price) -> bool {
+        price.get() > 0 && self.config.book_backend.supports_price(price)
+    }
+
+    /// Returns true if an order of this quantity belongs in the odd-lot book.
+    pub fn is_odd_lot(&self, quantity: Quantity) -> bool {
+        match self.config.odd_lots {
+            OddLots::Segregated { round_lot } => quantity.get() < round_lot,
+            OddLots::Mixed => false,
+        }
+    }
+
+    /// Returns true if an order of this quantity can rest in the market: an odd lot or a
+    /// whole number of round lots when odd lots are segregated, any quantity otherwise.
+    pub fn is_valid_lot(&self, quantity: Quantity) -> bool {
+        match self.config.odd_lots {
+            OddLots::Segregated { round_lot } if round_lot > 0 => {
+                quantity.get() < round_lot || quantity.get().is_multiple_of(round_lot)
+            }
+            _ => true,
         }
     }
 
-    /// Processes an order, returning the trades.
-    pub fn process_order(&mut self, order: Order) -> Vec<Trade> {
-        self.matching_engine.process_order(order)
+    /// The engine an order of this quantity matches in.
+    pub fn engine_for(&self, quantity: Quantity) -> &MatchingEngine {
+        if self.is_odd_lot(quantity) {
+            &self.odd_lot_engine
+        } else {
+            &self.matching_engine
+        }
+    }
+
+    fn engine_for_mut(&mut self, quantity: Quantity) -> &mut MatchingEngine {
+        if self.is_odd_lot(quantity) {
+            &mut self.odd_lot_engine
+        } else {
+            &mut self.matching_engine
+        }
+    }
+
+    /// The main book, then the odd-lot book.
+    pub(crate) fn books(&self) -> impl Iterator<Item = &OrderBook> {
+        [&self.matching_engine, &self.odd_lot_engine]
+            .into_iter()
+            .map(|engine| engine.orderbook())
+    }
+
+    /// Every resting order of both books: bids, then asks, of the main book first.
+    pub fn resting_orders(&self) -> impl Iterator<Item = &Order> {
+        self.books().flat_map(|book| {
+            book.get_bids()
+                .flat_map(|(_, orders)| orders.iter())
+                .chain(book.get_asks().flat_map(|(_, orders)| orders.iter()))
+        })
+    }
+
+    /// Finds a resting order in either book.
+    pub fn resting_order(&self, order_id: OrderId, side: Side, price: Price) -> Option<&Order> {
+        self.books().find_map(|book| {
+            book.levels(side)
+                .find(|(level, _)| *level == price)
+                .and_then(|(_, orders)| orders.iter().find(|order| order.id == order_id))
+        })
+    }
+
+    /// Places an order in its book without matching it, e.g. when restoring a snapshot.
+    pub(crate) fn restore_order(&mut self, order: Order) {
+        self.engine_for_mut(order.quantity).restore_order(order);
     }
 
+    /// Reduces the quantity of a resting order in either book, keeping its priority.
+    pub(crate) fn reduce_order(
+        &mut self,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Option<Order> {
+        self.matching_engine
+            .reduce_order(order_id, side, price, quantity)
+            .or_else(|| {
+                self.odd_lot_engine
+                    .reduce_order(order_id, side, price, quantity)
+            })
+    }
+
+    /// Processes an order, reporting its fills and what is left of it working.
+    ///
+    /// Stop orders are queued without matching; they are released by `take_triggered_stops`
+    /// once the last trade price reaches their stop price. Odd lots are matched in the
+    /// odd-lot book if the market segregates them.
+    pub fn process_order(&mut self, order: Order) -> ExecutionReport {
+        let (fills, cancels) = self.process_order_with_stp(order.clone(), None);
+        let remaining = match order.stop_price {
+            Some(_) => order.quantity,
+            None => ExecutionReport::resting(&order, &fills, &cancels),
+        };
+        ExecutionReport::new(order.id, order.quantity, fills, Vec::new(), remaining)
+    }
+
+    /// Processes an order like `process_order`, with self-trade prevention.
+    pub fn process_order_with_stp(
+        &mut self,
+        order: Order,
+        stp: Option<SelfTradePrevention>,
+    ) -> (Vec<Trade>, Vec<SelfTradeCancel>) {
+        if order.stop_price.is_some() {
+            self.stops.queue(order);
+            return (Vec::new(), Vec::new());
+        }
+        let time = order.timestamp;
+        let (mut trades, cancels) = self
+            .engine_for_mut(order.quantity)
+            .process_order_with_stp(order, stp);
+        self.record_trades(&mut trades, time);
+        (trades, cancels)
+    }
+
+    /// Returns the trades an order would execute if it were processed now, without changing
+    /// the market. Stop orders would only be queued, so they execute nothing.
+    pub fn simulate_order(&self, order: Order) -> Vec<Trade> {
+        if order.stop_price.is_some() {
+            return Vec::new();
+        }
+        self.engine_for(order.quantity).simulate_order(order)
+    }
+
+    /// Assigns trades executed at `time` their IDs and sequence numbers, and appends them to
+    /// the market's history at that time, or at the time of the last trade if that is later,
+    /// so the history stays in time order.
+    pub(crate) fn record_trades<'a>(
+        &mut self,
+        trades: impl IntoIterator<Item = &'a mut Trade>,
+        time: Timestamp,
+    ) {
+        let time = match self.trade_times.last() {
+            Some(last) => time.max(*last),
+            None => time,
+        };
+        for trade in trades {
+            trade.id = self.trade_ids.next();
+            trade.sequence = self.next_trade_sequence;
+            self.next_trade_sequence += 1;
+            self.last_trade_price = Some(trade.price);
+            self.stops.observe(trade.price);
+            if let Some(breaker) = &mut self.breaker {
+                breaker.observe(time, trade.price);
+            }
+            self.trades.push(trade.clone());
+            self.trade_times.push(time);
+        }
+    }
+
+    /// The sequence number the next trade recorded will get.
+    pub fn next_trade_sequence(&self) -> u64 {
+        self.next_trade_sequence
+    }
+
+    /// Continues the market's trade sequence from `next`, e.g. when restoring a snapshot.
+    pub(crate) fn set_next_trade_sequence(&mut self, next: u64) {
+        self.next_trade_sequence = next;
+    }
+
+    /// Draws the market's trade IDs from `trade_ids`.
+    pub(crate) fn set_trade_ids(&mut self, trade_ids: TradeIds) {
+        self.trade_ids = trade_ids;
+    }
+
+    /// When each trade of `trades` executed.
+    pub(crate) fn trade_times(&self) -> &[Timestamp] {
+        &self.trade_times
+    }
+
+    /// Removes the `count` oldest trades from the market's history, returning them with
+    /// their execution times. The last trade price is kept.
+    pub(crate) fn evict_trades(&mut self, count: usize) -> Vec<(Timestamp, Trade)> {
+        self.trade_times
+            .drain(..count)
+            .zip(self.trades.drain(..count))
+            .collect()
+    }
+
+    /// Prints an internal cross on the market's tape.
+    pub(crate) fn record_cross(&mut self, cross: Cross) {
+        self.crosses.push(cross);
+    }
+
+    /// Internal crosses printed in the market, oldest first. They are not trades.
+    pub fn crosses(&self) -> &[Cross] {
+        &self.crosses
+    }
+
+    /// Cancels a resting order or a pending stop order.
     pub fn cancel_order(&mut self, order_id: OrderId, side: Side, price: Price) -> Option<Order> {
-        self.matching_engine.cancel_order(order_id, side, price)
+        if let Some(stop) = self.stops.cancel(order_id, side) {
+            return Some(stop);
+        }
+        self.matching_engine
+            .cancel_order(order_id, side, price)
+            .or_else(|| self.odd_lot_engine.cancel_order(order_id, side, price))
+    }
+
+    /// Price of the most recent trade in the market.
+    pub fn last_trade_price(&self) -> Option<Price> {
+        self.last_trade_price
+    }
+
+    /// Stop orders waiting for their trigger, in arrival order.
+    pub fn pending_stops(&self) -> &[Order] {
+        self.stops.pending()
+    }
+
+    /// Puts triggered stops that could not be posted yet back ahead of the pending stops.
+    pub(crate) fn requeue_stops(&mut self, stops: Vec<Order>) {
+        self.stops.requeue(stops);
+    }
+
+    /// The market's circuit breaker, if it has one.
+    pub fn circuit_breaker(&self) -> Option<CircuitBreaker> {
+        self.breaker.as_ref().map(|state| state.breaker)
+    }
+
+    /// Sets or removes the market's circuit breaker. A new breaker has no reference price.
+    pub(crate) fn set_circuit_breaker(&mut self, breaker: Option<CircuitBreaker>) {
+        self.breaker = breaker.map(BreakerState::new);
+    }
+
+    /// Takes the reference price and the trade price of a trip of the circuit breaker.
+    pub(crate) fn take_breaker_trip(&mut self) -> Option<(Price, Price)> {
+        self.breaker.as_mut()?.take_trip()
+    }
+
+    /// Forgets the circuit breaker's reference price, so the next trade sets it.
+    pub(crate) fn reset_circuit_breaker(&mut self) {
+        if let Some(breaker) = &mut self.breaker {
+            breaker.reset();
+        }
+    }
+
+    /// Bands limit prices must fall within, applied in order.
+    pub fn price_bands(&self) -> &[PriceBand] {
+        &self.price_bands
+    }
+
+    /// Replaces the market's price bands.
+    pub(crate) fn set_price_bands(&mut self, bands: Vec<PriceBand>) {
+        self.price_bands = bands;
+    }
+
+    /// Removes and returns the pending stops triggered by any trade or index price since
+    /// stops were last taken, or by the current prices, in arrival order, with their stop
+    /// price cleared so they match when processed.
+    pub fn take_triggered_stops(&mut self) -> Vec<Order> {
+        self.stops.release(self.last_trade_price, self.index_price)
+    }
+
+    /// Sets the market's index price, which fires pending stops with an index trigger
+    ///
+    /// The stops it reaches are released by the next `take_triggered_stops`.
+    pub fn update_index_price(&mut self, price: Price) {
+        self.index_price = Some(price);
+        self.stops.observe_index(price);
+    }
+
+    /// The latest index price supplied to the market, if any.
+    pub fn index_price(&self) -> Option<Price> {
+        self.index_price
+    }
+
+    /// Every trade executed in the market, oldest first. For internal use only: account
+    /// identifiers are always included.
+    pub fn trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    /// The public trade feed of the market, oldest first, redacted according to the market's
+    /// configuration.
+    pub fn public_trades(&self) -> impl Iterator<Item = PublicTrade> + '_ {
+        (0..self.trades.len()).map(|index| self.public_trade(index))
+    }
+
+    /// The trade at `index` of the tape as printed on the public feed.
+    pub(crate) fn public_trade(&self, index: usize) -> PublicTrade {
+        let anonymize = self.config.anonymize_public_trades;
+        let trade = &self.trades[index];
+        PublicTrade {
+            id: trade.id,
+            sequence: trade.sequence,
+            ask_order_id: trade.ask_order_id,
+            bid_order_id: trade.bid_order_id,
+            ask_account_id: (!anonymize).then(|| trade.ask_account_id.clone()),
+            bid_account_id: (!anonymize).then(|| trade.bid_account_id.clone()),
+            price: trade.price,
+            quantity: trade.quantity,
+            aggressor: trade.aggressor,
+            timestamp: self.trade_times[index],
+        }
+    }
+
+    /// The public view of the book, aggregated by price level. Pending stops are not in the
+    /// book until they trigger, so they are not shown, and neither is the odd-lot book.
+    pub fn book_view(&self) -> BookView {
+        let book = self.matching_engine.orderbook();
+        let levels = |side: Side| {
+            book.levels(side)
+                .map(|(price, orders)| BookLevel {
+                    price,
+                    quantity: Quantity::new(orders.iter().map(|o| o.quantity.get()).sum()),
+                    orders: orders.len(),
+                })
+                .collect()
+        };
+        BookView {
+            bids: levels(Side::Bid),
+            asks: levels(Side::Ask),
+        }
+    }
+
+    /// Every trade executed in the market with the time it executed, oldest first. For
+    /// internal use only: account identifiers are always included.
+    pub fn timed_trades(&self) -> impl Iterator<Item = (Timestamp, &Trade)> + '_ {
+        self.trade_times.iter().copied().zip(&self.trades)
+    }
+
+    /// The private trade feed of an account: every trade it took part in, unredacted.
+    pub fn private_trades<'a>(
+        &'a self,
+        account_id: &'a AccountId,
+    ) -> impl Iterator<Item = &'a Trade> + 'a {
+        self.trades.iter().filter(move |trade| {
+            trade.bid_account_id == *account_id || trade.ask_account_id == *account_id
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u64, price: u64, side: Side, account: &str) -> Order {
+        Order::new(
+            OrderId::new(id),
+            Price::new(price),
+            Quantity::new(1),
+            side,
+            AccountId::new(account.to_string()),
+            Timestamp::new(id),
+        )
+    }
+
+    #[test]
+    fn test_anonymized_public_trades() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let mut market = Market::with_config(
+            pair,
+            MarketConfig {
+                anonymize_public_trades: true,
+                ..MarketConfig::default()
+            },
+        );
+        market.process_order(order(1, 100, Side::Ask, "alice"));
+        market.process_order(order(2, 100, Side::Bid, "bob"));
+
+        let public: Vec<PublicTrade> = market.public_trades().collect();
+        assert_eq!(public.len(), 1);
+        assert_eq!(public[0].ask_account_id, None);
+        assert_eq!(public[0].bid_account_id, None);
+        assert_eq!(public[0].price, Price::new(100));
+
+        // Internally and on the private feed the accounts are retained
+        let bob = AccountId::new("bob".to_string());
+        assert_eq!(market.trades()[0].bid_account_id, bob);
+        assert_eq!(market.private_trades(&bob).count(), 1);
+        assert_eq!(
+            market
+                .private_trades(&AccountId::new("carol".to_string()))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_stops_trigger_on_last_trade_price() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let mut market = Market::new(pair);
+        let stop = |id: u64, stop_price: u64, side: Side| Order {
+            stop_price: Some(Price::new(stop_price)),
+            ..Order::market(
+                OrderId::new(id),
+                Quantity::new(1),
+                side,
+                AccountId::new("carol".to_string()),
+                Timestamp::new(id),
+            )
+        };
+        assert!(
+            market
+                .process_order(stop(1, 105, Side::Bid))
+                .fills
+                .is_empty()
+        );
+        assert!(
+            market
+                .process_order(stop(2, 95, Side::Ask))
+                .fills
+                .is_empty()
+        );
+        assert!(market.take_triggered_stops().is_empty());
+
+        market.process_order(order(3, 105, Side::Ask, "alice"));
+        market.process_order(order(4, 105, Side::Bid, "bob"));
+        assert_eq!(market.last_trade_price(), Some(Price::new(105)));
+
+        let triggered = market.take_triggered_stops();
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].id, OrderId::new(1));
+        assert_eq!(triggered[0].stop_price, None);
+        assert_eq!(market.pending_stops().len(), 1);
+
+        // Pending stops can be cancelled
+        assert!(
+            market
+                .cancel_order(OrderId::new(2), Side::Ask, Price::new(0))
+                .is_some()
+        );
+        assert!(market.pending_stops().is_empty());
     }
 }
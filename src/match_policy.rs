@@ -0,0 +1,315 @@
+//! Allocation of an aggressor's quantity among the orders resting at a price level.
+//!
+//! `MatchingEngine` walks price levels best first and asks its `MatchPolicy` how to share
+//! the quantity at each level. The built-in policies are selected per market with
+//! `Allocation`; custom policies can be installed with `Market::with_policy`.
+
+use std::sync::Arc;
+
+use crate::order::Order;
+
+/// Decides how an aggressor's quantity is shared among the orders resting at a price level
+///
+/// Policies only choose fills; the engine applies them and keeps the book consistent. Each
+/// fill is capped at the order's open quantity and at what is left of the aggressor, and
+/// rounded down to whole lots. Repeated orders and partial fills of all-or-none orders are
+/// dropped.
+pub trait MatchPolicy: Send + Sync {
+    /// Returns the orders of the level to fill, by index, with their fill quantities, in
+    /// the order their trades are printed
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The orders resting at the price, in time priority
+    /// * `quantity` - What is left of the aggressor, more or less than the level holds
+    /// * `lot` - Fills should be whole multiples of this
+    fn allocate(&self, level: &[Order], quantity: u64, lot: u64) -> Vec<(usize, u64)>;
+
+    /// Like `allocate`, but appends the fills to `fills`, which is empty when called
+    ///
+    /// The engine calls this with a buffer it keeps across orders. The default forwards to
+    /// `allocate`; policies that can write their fills directly override it so that matching
+    /// does not allocate.
+    fn allocate_into(
+        &self,
+        level: &[Order],
+        quantity: u64,
+        lot: u64,
+        fills: &mut Vec<(usize, u64)>,
+    ) {
+        fills.extend(self.allocate(level, quantity, lot));
+    }
+}
+
+/// The built-in policies, as recorded in market configs and snapshots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    /// See `Fifo`.
+    #[default]
+    Fifo,
+    /// See `ProRata`.
+    ProRata { min_allocation: u64 },
+    /// See `SizePriority`.
+    SizePriority,
+    /// See `TopOrder`.
+    TopOrder { min_allocation: u64 },
+}
+
+impl Allocation {
+    /// The policy implementing this allocation.
+    pub fn policy(self) -> Arc<dyn MatchPolicy> {
+        match self {
+            Allocation::Fifo => Arc::new(Fifo),
+            Allocation::ProRata { min_allocation } => Arc::new(ProRata { min_allocation }),
+            Allocation::SizePriority => Arc::new(SizePriority),
+            Allocation::TopOrder { min_allocation } => Arc::new(TopOrder {
+                pro_rata: ProRata { min_allocation },
+            }),
+        }
+    }
+}
+
+/// The whole quantity goes to the orders in time priority.
+///
+/// All-or-none orders the aggressor cannot fill completely are skipped and keep their
+/// place for later aggressors.
+#[derive(Debug, Clone, Copy)]
+pub struct Fifo;
+
+impl MatchPolicy for Fifo {
+    fn allocate(&self, level: &[Order], quantity: u64, _lot: u64) -> Vec<(usize, u64)> {
+        in_order(level, 0..level.len(), quantity)
+    }
+
+    fn allocate_into(
+        &self,
+        level: &[Order],
+        quantity: u64,
+        _lot: u64,
+        fills: &mut Vec<(usize, u64)>,
+    ) {
+        in_order_into(level, 0..level.len(), quantity, fills);
+    }
+}
+
+/// Each order first gets a share of the quantity proportional to its size, rounded down to
+/// whole lots. Shares below `min_allocation` are dropped. Whatever the proportional pass
+/// leaves is then filled in time priority.
+///
+/// All-or-none orders cannot take a partial share, so they only fill in the priority pass.
+#[derive(Debug, Clone, Copy)]
+pub struct ProRata {
+    pub min_allocation: u64,
+}
+
+impl MatchPolicy for ProRata {
+    fn allocate(&self, level: &[Order], quantity: u64, lot: u64) -> Vec<(usize, u64)> {
+        let shared = |order: &Order| !order.all_or_none;
+        let level_qty: u64 = level
+            .iter()
+            .filter(|order| shared(order))
+            .map(|order| order.quantity.get())
+            .sum();
+        let target = quantity.min(level_qty);
+        let mut remaining = quantity;
+        let mut fills: Vec<u64> = level
+            .iter()
+            .map(|order| {
+                if !shared(order) {
+                    return 0;
+                }
+                let share = (target as u128 * order.quantity.get() as u128 / level_qty as u128)
+                    as u64
+                    / lot
+                    * lot;
+                if share == 0 || share < self.min_allocation {
+                    return 0;
+                }
+                remaining -= share;
+                share
+            })
+            .collect();
+
+        // Hand out what the proportional pass left in time priority
+        for (order, fill) in level.iter().zip(&mut fills) {
+            if remaining == 0 {
+                break;
+            }
+            let open = order.quantity.get() - *fill;
+            if open == 0 || !order.can_fill_against(remaining) {
+                continue;
+            }
+            *fill += remaining.min(open);
+            remaining -= remaining.min(open);
+        }
+
+        fills
+            .into_iter()
+            .enumerate()
+            .filter(|(_, fill)| *fill > 0)
+            .collect()
+    }
+}
+
+/// Larger orders fill first, in time priority among orders of the same size.
+///
+/// All-or-none orders are skipped as under `Fifo`.
+#[derive(Debug, Clone, Copy)]
+pub struct SizePriority;
+
+impl MatchPolicy for SizePriority {
+    fn allocate(&self, level: &[Order], quantity: u64, _lot: u64) -> Vec<(usize, u64)> {
+        let mut by_size: Vec<usize> = (0..level.len()).collect();
+        by_size.sort_by_key(|&i| std::cmp::Reverse(level[i].quantity));
+        in_order(level, by_size, quantity)
+    }
+}
+
+/// The order at the head of the level fills first, up to its whole size, and the rest is
+/// shared by `pro_rata`.
+///
+/// The head of the queue stands in for the order that set the price level, which is the
+/// one top-order allocation rewards. An all-or-none head gets no priority.
+#[derive(Debug, Clone, Copy)]
+pub struct TopOrder {
+    pub pro_rata: ProRata,
+}
+
+impl MatchPolicy for TopOrder {
+    fn allocate(&self, level: &[Order], quantity: u64, lot: u64) -> Vec<(usize, u64)> {
+        let Some(top) = level.first().filter(|order| !order.all_or_none) else {
+            return self.pro_rata.allocate(level, quantity, lot);
+        };
+        let top_fill = quantity.min(top.quantity.get());
+        let mut fills = vec![(0, top_fill)];
+        fills.extend(
+            self.pro_rata
+                .allocate(&level[1..], quantity - top_fill, lot)
+                .into_iter()
+                .map(|(i, fill)| (i + 1, fill)),
+        );
+        fills
+    }
+}
+
+/// Fills the orders of `level` at `indices` one after the other.
+fn in_order(
+    level: &[Order],
+    indices: impl IntoIterator<Item = usize>,
+    quantity: u64,
+) -> Vec<(usize, u64)> {
+    let mut fills = Vec::new();
+    in_order_into(level, indices, quantity, &mut fills);
+    fills
+}
+
+/// Like `in_order`, appending the fills to `fills`.
+fn in_order_into(
+    level: &[Order],
+    indices: impl IntoIterator<Item = usize>,
+    quantity: u64,
+    fills: &mut Vec<(usize, u64)>,
+) {
+    let mut remaining = quantity;
+    for i in indices {
+        if remaining == 0 {
+            break;
+        }
+        let order = &level[i];
+        // Skipped all-or-none orders keep their place for later aggressors
+        if order.can_fill_against(remaining) {
+            let fill = remaining.min(order.quantity.get());
+            fills.push((i, fill));
+            remaining -= fill;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::{Market, MarketConfig, Pair},
+        matching::{MatchingEngine, Trade},
+        order::{AccountId, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    fn order(id: u64, qty: u64, side: Side) -> Order {
+        Order::new(
+            OrderId::new(id),
+            Price::new(100),
+            Quantity::new(qty),
+            side,
+            AccountId::new(format!("account{}", id)),
+            Timestamp::new(id),
+        )
+    }
+
+    fn fills(trades: &[Trade]) -> Vec<(u64, u64)> {
+        trades
+            .iter()
+            .map(|t| (t.ask_order_id.get(), t.quantity.get()))
+            .collect()
+    }
+
+    #[test]
+    fn test_built_in_policies() {
+        let asks = [
+            order(1, 10, Side::Ask),
+            order(2, 30, Side::Ask),
+            order(3, 20, Side::Ask),
+        ];
+        let run = |allocation: Allocation, quantity: u64| {
+            let mut engine = MatchingEngine::new().with_allocation(allocation, 1);
+            for ask in &asks {
+                engine.process_order(ask.clone());
+            }
+            fills(&engine.process_order(order(10, quantity, Side::Bid)))
+        };
+
+        assert_eq!(run(Allocation::Fifo, 35), vec![(1, 10), (2, 25)]);
+        assert_eq!(run(Allocation::SizePriority, 35), vec![(2, 30), (3, 5)]);
+        // The head fills whole, then 20 is shared 12 to 8 over the remaining 50
+        assert_eq!(
+            run(Allocation::TopOrder { min_allocation: 0 }, 30),
+            vec![(1, 10), (2, 12), (3, 8)]
+        );
+    }
+
+    /// Fills the newest order first and asks for more than the book can take.
+    struct Greedy;
+
+    impl MatchPolicy for Greedy {
+        fn allocate(&self, level: &[Order], quantity: u64, _lot: u64) -> Vec<(usize, u64)> {
+            let mut fills: Vec<(usize, u64)> =
+                (0..level.len()).rev().map(|i| (i, quantity)).collect();
+            fills.push((0, quantity));
+            fills.push((level.len(), quantity));
+            fills
+        }
+    }
+
+    #[test]
+    fn test_custom_policy_is_trimmed_to_the_book() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let mut market = Market::with_policy(pair, MarketConfig::default(), Arc::new(Greedy));
+        market.process_order(order(1, 10, Side::Ask));
+        market.process_order(order(2, 10, Side::Ask));
+
+        let trades = market.process_order(order(10, 15, Side::Bid)).fills;
+        assert_eq!(fills(&trades), vec![(2, 10), (1, 5)]);
+        let book = market.book_view();
+        assert_eq!(book.asks[0].quantity, Quantity::new(5));
+
+        // Back to the configured allocation
+        market.set_policy(Allocation::Fifo.policy());
+        market.process_order(order(3, 10, Side::Ask));
+        let trades = market.process_order(order(11, 10, Side::Bid)).fills;
+        assert_eq!(fills(&trades), vec![(1, 5), (3, 5)]);
+    }
+}
@@ -1,14 +1,124 @@
-use crate::order::{AccountId, Order, OrderId, Price, Quantity, Side};
-use crate::orderbook::OrderBook;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use crate::match_policy::{Allocation, Fifo, MatchPolicy};
+use crate::order::{AccountId, ClientOrderId, Order, OrderId, OrderTag, Price, Quantity, Side};
+use crate::orderbook::{BookBackend, OrderBook};
+
+/// Identifies a trade uniquely across every market of an exchange.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TradeId(u64);
+
+impl TradeId {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The sequence trade IDs are drawn from. Clones draw from the same sequence, which is how
+/// every market of an exchange shares one.
 #[derive(Debug, Clone)]
+pub struct TradeIds(Arc<AtomicU64>);
+
+impl TradeIds {
+    /// A sequence whose first ID is `next`.
+    pub fn starting_at(next: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(next)))
+    }
+
+    /// The ID the next trade will get.
+    pub fn peek(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn next(&self) -> TradeId {
+        TradeId(self.0.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for TradeIds {
+    fn default() -> Self {
+        Self::starting_at(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Trade {
+    /// Assigned when a market records the trade; zero until then.
+    pub id: TradeId,
+    /// Position of the trade among those of its market, from 1 and without gaps. Assigned
+    /// with `id`.
+    pub sequence: u64,
     pub ask_order_id: OrderId,
     pub bid_order_id: OrderId,
     pub ask_account_id: AccountId,
     pub bid_account_id: AccountId,
     pub price: Price,
     pub quantity: Quantity,
+    /// The side that took liquidity, or `None` if neither side did (e.g. an auction uncross).
+    pub aggressor: Option<Side>,
+    /// Client order IDs of the ask and bid orders, if they had one.
+    pub ask_client_order_id: Option<ClientOrderId>,
+    pub bid_client_order_id: Option<ClientOrderId>,
+    /// Tags of the ask and bid orders, if they had one.
+    pub ask_tag: Option<OrderTag>,
+    pub bid_tag: Option<OrderTag>,
+}
+
+/// Whether a side of a trade provided or took liquidity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+impl Trade {
+    /// Returns the liquidity role of the given side.
+    ///
+    /// The resting side is the maker and the aggressor is the taker. Trades without an
+    /// aggressor have no resting side, so both sides are treated as takers.
+    pub fn liquidity(&self, side: Side) -> Liquidity {
+        match self.aggressor {
+            Some(aggressor) if aggressor != side => Liquidity::Maker,
+            _ => Liquidity::Taker,
+        }
+    }
+
+    /// Returns the account on the given side of the trade.
+    pub fn account_id(&self, side: Side) -> &AccountId {
+        match side {
+            Side::Bid => &self.bid_account_id,
+            Side::Ask => &self.ask_account_id,
+        }
+    }
+
+    /// Returns the order on the given side of the trade.
+    pub fn order_id(&self, side: Side) -> OrderId {
+        match side {
+            Side::Bid => self.bid_order_id,
+            Side::Ask => self.ask_order_id,
+        }
+    }
+
+    /// Returns the side that provided liquidity, or `None` if neither side did.
+    pub fn maker_side(&self) -> Option<Side> {
+        self.aggressor.map(Side::opposite)
+    }
+
+    /// Returns the resting order of the trade, or `None` if neither side rested.
+    pub fn maker_order_id(&self) -> Option<OrderId> {
+        self.maker_side().map(|side| self.order_id(side))
+    }
+
+    /// Returns the aggressing order of the trade, or `None` if neither side aggressed.
+    pub fn taker_order_id(&self) -> Option<OrderId> {
+        self.aggressor.map(|side| self.order_id(side))
+    }
 }
 
 pub enum OrderUpdate {
@@ -16,205 +126,452 @@ pub enum OrderUpdate {
     Update(Quantity),
 }
 
+/// What happens when an order would trade against a resting order of the same account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    /// Cancel what is left of the incoming order. The trades it already made stand.
+    CancelNewest,
+    /// Cancel the resting order and keep matching.
+    CancelOldest,
+    /// Reduce both orders by the smaller of their quantities, without trading, and keep
+    /// matching what is left of the incoming order.
+    DecrementBoth,
+}
+
+/// Quantity taken out of an order by self-trade prevention.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTradeCancel {
+    /// The resting order as it was before, or the incoming order with the quantity it had
+    /// left at that point.
+    pub order: Order,
+    pub cancelled: Quantity,
+}
+
+/// Buffers kept across orders, so matching stops allocating once they have grown.
+#[derive(Default)]
+struct Scratch {
+    /// The resting order updates of the incoming order.
+    updates: Vec<(OrderId, Price, OrderUpdate)>,
+    /// The fills chosen within a level, by index into the level.
+    fills: Vec<(usize, u64)>,
+    /// The indices of the level already filled.
+    filled: HashSet<usize>,
+}
+
+/// Matches incoming orders against a single orderbook.
+///
+/// # Priority
+///
+/// Resting orders are matched in a strict total order: best price first, then earliest
+/// `Timestamp`, then lowest `OrderId`. The order ID is the final tie-break, so two orders can
+/// never share a position in the queue.
+///
+/// Within a level this is first in, first out as long as timestamps are arrival times. Fills
+/// and reductions change an order's quantity in place and keep its position. Paths that take
+/// an order out and insert it again, such as amends, failed replacements, peg re-pricing and
+/// snapshot restores, queue it by its timestamp: an order put back with its own timestamp
+/// regains its exact position, and one re-posted with a later timestamp goes behind every
+/// order that arrived before it.
+///
+/// # Determinism
+///
+/// Matching depends only on the sequence of calls made on the engine and the orders passed
+/// to them. Replaying the same sequence against a fresh engine with the same backend always
+/// produces the same trades, in the same order, and leaves an identical book. No wall-clock
+/// time, randomness, or hash iteration order is consulted.
+///
+/// # Allocation
+///
+/// The priority above decides the order in which price levels are walked. Within a level,
+/// the engine's `MatchPolicy` decides who fills: the default `Fifo` policy hands the
+/// quantity out in priority order, while pro-rata policies only use it for what their
+/// proportional pass leaves over. Built-in policies are as deterministic as the priority;
+/// custom ones must be too for replays to hold.
+///
+/// # Memory
+///
+/// The buffers matching works in belong to the engine and are reused from one order to the
+/// next. Once they have grown to the size of the largest order, processing an order with
+/// `process_order_buffered` or `process_order_with` allocates nothing beyond what the book
+/// needs to store a new level, as long as the policy implements `allocate_into`.
 pub struct MatchingEngine {
     orderbook: OrderBook,
+    policy: Arc<dyn MatchPolicy>,
+    /// Fills are whole multiples of this.
+    lot: u64,
+    scratch: Scratch,
+    /// The trades of the last order processed with `process_order_buffered`.
+    trades: Vec<Trade>,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MatchingEngine {
     pub fn new() -> Self {
+        Self::with_backend(BookBackend::default())
+    }
+
+    /// Creates a matching engine whose orderbook uses the given storage backend.
+    pub fn with_backend(backend: BookBackend) -> Self {
         Self {
-            orderbook: OrderBook::new(),
+            orderbook: OrderBook::with_backend(backend),
+            policy: Arc::new(Fifo),
+            lot: 1,
+            scratch: Scratch::default(),
+            trades: Vec::new(),
         }
     }
 
+    /// Uses one of the built-in allocation policies; see `set_policy`.
+    pub fn with_allocation(mut self, allocation: Allocation, lot: u64) -> Self {
+        self.set_policy(allocation.policy(), lot);
+        self
+    }
+
+    /// Sets how the engine shares an aggressor's quantity within a price level
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The allocation policy
+    /// * `lot` - Fills are rounded down to multiples of this, so a book of whole lots only
+    ///   ever trades whole lots
+    pub fn set_policy(&mut self, policy: Arc<dyn MatchPolicy>, lot: u64) {
+        self.policy = policy;
+        self.lot = lot.max(1);
+    }
+
+    /// Returns the engine's orderbook.
+    pub(crate) fn orderbook(&self) -> &OrderBook {
+        &self.orderbook
+    }
+
+    /// Places an order directly in the book without matching it, e.g. when restoring a
+    /// snapshot.
+    pub(crate) fn restore_order(&mut self, order: Order) {
+        self.orderbook.insert_order(order);
+    }
+
     /// Process a new order, attempting to match it against the orderbook
-    /// Returns true if the order was accepted, false if rejected
+    ///
+    /// Returns the trades. The unfilled remainder of a resting order type is inserted in the
+    /// book behind the orders already at its price. Market and immediate-or-cancel orders
+    /// never rest: whatever they cannot fill is dropped.
     pub fn process_order(&mut self, order: Order) -> Vec<Trade> {
-        match order.side {
-            Side::Bid => self.process_bid(order),
-            Side::Ask => self.process_ask(order),
-        }
+        self.process_order_with_stp(order, None).0
+    }
+
+    /// Process a new order like `process_order`, preventing it from trading with resting
+    /// orders of its own account
+    ///
+    /// Self-trade prevention applies to the orders the allocation policy picks to fill:
+    /// under `Fifo`, the incoming order trades with everything ahead of its own order
+    /// before prevention kicks in. Returns the trades and the quantities cancelled, in the
+    /// order they happened. An incoming order cancelled or decremented to nothing never
+    /// rests.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The incoming order
+    /// * `stp` - The prevention to apply, or `None` to allow self-trades
+    pub fn process_order_with_stp(
+        &mut self,
+        order: Order,
+        stp: Option<SelfTradePrevention>,
+    ) -> (Vec<Trade>, Vec<SelfTradeCancel>) {
+        let mut trades = Vec::new();
+        let cancels = self.execute(order, stp, |trade| trades.push(trade));
+        (trades, cancels)
+    }
+
+    /// Process a new order like `process_order`, collecting its trades in a buffer the
+    /// engine reuses
+    ///
+    /// Returns the trades, which stay available until the next call.
+    pub fn process_order_buffered(&mut self, order: Order) -> &[Trade] {
+        let mut trades = std::mem::take(&mut self.trades);
+        trades.clear();
+        self.execute(order, None, |trade| trades.push(trade));
+        self.trades = trades;
+        &self.trades
+    }
+
+    /// Process a new order like `process_order`, handing each trade to `on_trade` as it
+    /// executes instead of collecting them
+    ///
+    /// The trades are handed out before the book is updated, in the order `process_order`
+    /// would return them.
+    pub fn process_order_with<F: FnMut(&Trade)>(&mut self, order: Order, mut on_trade: F) {
+        self.execute(order, None, |trade| on_trade(&trade));
     }
 
-    /// Process a bid order, returns the executed trades.
-    fn process_bid(&mut self, mut bid: Order) -> Vec<Trade> {
+    /// Match an order, hand its trades to `on_trade`, then apply the matches to the book.
+    fn execute(
+        &mut self,
+        mut order: Order,
+        stp: Option<SelfTradePrevention>,
+        mut on_trade: impl FnMut(Trade),
+    ) -> Vec<SelfTradeCancel> {
         // First, collect all the matches and updates we need to make
-        let (trades, order_updates) = self.find_bid_matches(&mut bid);
-
-        // Then apply all updates atomically
-        if !trades.is_empty() {
-            for (order_id, update) in order_updates {
-                // If there's a partial fill, we need to update the order quantity
-                match update {
-                    OrderUpdate::Remove => {
-                        self.orderbook.remove_order(order_id, Side::Ask, bid.price);
-                    }
-                    OrderUpdate::Update(new_qty) => {
-                        self.orderbook
-                            .update_order_quantity(order_id, Side::Ask, new_qty);
-                    }
+        let mut traded = false;
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let cancels = self.find_matches(&mut order, stp, &mut scratch, &mut |trade| {
+            traded = true;
+            on_trade(trade);
+        });
+
+        // Then apply all updates atomically, in one pass over each level touched. The updates
+        // of a level are contiguous, since levels are walked one after the other
+        let resting_side = order.side.opposite();
+        let mut updates = scratch.updates.as_mut_slice();
+        while let Some(&(_, price, _)) = updates.first() {
+            let len = updates
+                .iter()
+                .take_while(|update| update.1 == price)
+                .count();
+            let (level, rest) = updates.split_at_mut(len);
+            level.sort_unstable_by_key(|update| update.0);
+            self.orderbook.retain_level(resting_side, price, |resting| {
+                match level.binary_search_by_key(&resting.id, |update| update.0) {
+                    Ok(i) => match level[i].2 {
+                        OrderUpdate::Remove => false,
+                        OrderUpdate::Update(new_qty) => {
+                            resting.quantity = new_qty;
+                            true
+                        }
+                    },
+                    Err(_) => true,
                 }
-            }
-        } else {
-            self.orderbook.insert_order(bid.clone());
+            });
+            updates = rest;
         }
-        trades
+        scratch.updates.clear();
+        self.scratch = scratch;
+        let touched = traded || !cancels.is_empty();
+        if order.rests() && (!touched || order.quantity.get() > 0) {
+            self.orderbook.insert_order(order);
+        }
+        cancels
     }
 
-    /// Separate matching logic from update logic
+    /// Returns the trades an order would execute if it were processed now, without
+    /// changing the book
     ///
-    /// Also updates the bid quantity to the remaining quantity
-    fn find_bid_matches(&self, bid: &mut Order) -> (Vec<Trade>, Vec<(OrderId, OrderUpdate)>) {
+    /// The trades are those `process_order` would return, except that they carry no trade
+    /// IDs or sequence numbers yet.
+    pub fn simulate_order(&self, mut order: Order) -> Vec<Trade> {
         let mut trades = Vec::new();
-        let mut updates = Vec::new();
-        let mut remaining_qty = bid.quantity.get();
-
-        // Find all matches until we run out of quantity or there are no more asks with a price <= bid.price
-        while remaining_qty > 0 {
-            let best_ask = match self.orderbook.get_asks().next() {
-                Some((price, orders)) if price.get() <= bid.price.get() => Some((*price, orders)),
-                _ => None,
-            };
+        self.find_matches(&mut order, None, &mut Scratch::default(), &mut |trade| {
+            trades.push(trade)
+        });
+        trades
+    }
 
-            match best_ask {
-                Some((ask_price, ask_orders)) => {
-                    for ask in ask_orders.iter() {
-                        let match_qty = std::cmp::min(remaining_qty, ask.quantity.get());
-                        if match_qty > 0 {
-                            trades.push(Trade {
-                                price: ask_price,
-                                quantity: Quantity::new(match_qty),
-                                ask_order_id: ask.id,
-                                bid_order_id: bid.id,
-                                ask_account_id: ask.account_id.clone(),
-                                bid_account_id: bid.account_id.clone(),
-                            });
-
-                            // Record the update needed
-                            if ask.quantity.get() == match_qty {
-                                updates.push((ask.id, OrderUpdate::Remove));
-                            } else {
-                                updates.push((
-                                    ask.id,
-                                    OrderUpdate::Update(Quantity::new(
-                                        ask.quantity.get() - match_qty,
-                                    )),
-                                ));
-                            }
+    /// Find the matches of an incoming order on either side, without changing the book
+    ///
+    /// Trades are handed to `on_trade` as they are found and the resting order updates are
+    /// left in `scratch`. Returns the self-trade cancellations. Also updates the order
+    /// quantity to the remaining quantity.
+    fn find_matches(
+        &self,
+        incoming: &mut Order,
+        stp: Option<SelfTradePrevention>,
+        scratch: &mut Scratch,
+        on_trade: &mut dyn FnMut(Trade),
+    ) -> Vec<SelfTradeCancel> {
+        let Scratch {
+            updates,
+            fills,
+            filled,
+        } = scratch;
+        updates.clear();
+        let mut cancels = Vec::new();
+        let mut remaining_qty = incoming.quantity.get();
 
+        // Walk the opposite side from the best price until we run out of quantity or it no
+        // longer crosses
+        for (price, resting_orders) in self.orderbook.levels(incoming.side.opposite()) {
+            if remaining_qty == 0 || !incoming.crosses(price) {
+                break;
+            }
+            // Own orders taken out of the level by self-trade prevention
+            let mut prevented: Vec<OrderId> = Vec::new();
+            while remaining_qty > 0 {
+                let unprevented: Vec<Order>;
+                let level = if prevented.is_empty() {
+                    resting_orders.as_slice()
+                } else {
+                    unprevented = resting_orders
+                        .iter()
+                        .filter(|order| !prevented.contains(&order.id))
+                        .cloned()
+                        .collect();
+                    &unprevented
+                };
+                self.allocate(level, remaining_qty, fills, filled);
+                let own = fills
+                    .iter()
+                    .position(|&(i, _)| level[i].account_id == incoming.account_id);
+                let (Some(stp), Some(own)) = (stp, own) else {
+                    for &(i, match_qty) in fills.iter() {
+                        remaining_qty -= match_qty;
+                        Self::fill(incoming, &level[i], price, match_qty, on_trade, updates);
+                    }
+                    break;
+                };
+
+                let resting = &level[fills[own].0];
+                match stp {
+                    SelfTradePrevention::CancelNewest => {
+                        for &(i, match_qty) in &fills[..own] {
                             remaining_qty -= match_qty;
+                            Self::fill(incoming, &level[i], price, match_qty, on_trade, updates);
                         }
-                        if remaining_qty == 0 {
-                            break;
-                        }
+                        cancels.push(SelfTradeCancel {
+                            order: Order {
+                                quantity: Quantity::new(remaining_qty),
+                                ..incoming.clone()
+                            },
+                            cancelled: Quantity::new(remaining_qty),
+                        });
+                        remaining_qty = 0;
+                    }
+                    SelfTradePrevention::CancelOldest => {
+                        updates.push((resting.id, price, OrderUpdate::Remove));
+                        cancels.push(SelfTradeCancel {
+                            order: resting.clone(),
+                            cancelled: resting.quantity,
+                        });
+                    }
+                    SelfTradePrevention::DecrementBoth => {
+                        let decrement = remaining_qty.min(resting.quantity.get());
+                        updates.push((resting.id, price, Self::reduced(resting, decrement)));
+                        cancels.push(SelfTradeCancel {
+                            order: resting.clone(),
+                            cancelled: Quantity::new(decrement),
+                        });
+                        cancels.push(SelfTradeCancel {
+                            order: Order {
+                                quantity: Quantity::new(remaining_qty),
+                                ..incoming.clone()
+                            },
+                            cancelled: Quantity::new(decrement),
+                        });
+                        remaining_qty -= decrement;
                     }
                 }
-                None => break,
+                prevented.push(resting.id);
             }
         }
 
-        // Update the bid quantity to the remaining quantity
-        bid.quantity = Quantity::new(remaining_qty);
+        // Update the order quantity to the remaining quantity
+        incoming.quantity = Quantity::new(remaining_qty);
 
-        (trades, updates)
+        cancels
     }
 
-    /// Find all the matches for an ask order
-    ///
-    /// Also updates the ask quantity to the remaining quantity
-    fn find_ask_matches(&self, ask: &mut Order) -> (Vec<Trade>, Vec<(OrderId, OrderUpdate)>) {
-        let mut trades = Vec::new();
-        let mut updates = Vec::new();
-        let mut remaining_qty = ask.quantity.get();
-
-        // Find all matches until we run out of quantity or there are no more bids with a price >= ask.price
-        while remaining_qty > 0 {
-            // Get best bid
-            let best_bid = match self.orderbook.get_bids().next() {
-                Some((price, _)) if price.to_price().get() >= ask.price.get() => Some(price),
-                _ => None,
-            };
+    /// Records a fill of a resting order by the incoming order.
+    fn fill(
+        incoming: &Order,
+        resting: &Order,
+        price: Price,
+        match_qty: u64,
+        on_trade: &mut dyn FnMut(Trade),
+        updates: &mut Vec<(OrderId, Price, OrderUpdate)>,
+    ) {
+        let (bid, ask) = match incoming.side {
+            Side::Bid => (incoming, resting),
+            Side::Ask => (resting, incoming),
+        };
+        on_trade(Trade {
+            id: TradeId::default(),
+            sequence: 0,
+            price,
+            quantity: Quantity::new(match_qty),
+            ask_order_id: ask.id,
+            bid_order_id: bid.id,
+            ask_account_id: ask.account_id.clone(),
+            bid_account_id: bid.account_id.clone(),
+            aggressor: Some(incoming.side),
+            ask_client_order_id: ask.client_order_id.clone(),
+            bid_client_order_id: bid.client_order_id.clone(),
+            ask_tag: ask.tag.clone(),
+            bid_tag: bid.tag.clone(),
+        });
+        updates.push((resting.id, price, Self::reduced(resting, match_qty)));
+    }
 
-            match best_bid {
-                Some(bid_price) => {
-                    // Match against orders at this price level
-                    let bid_orders = self.orderbook.get_bids().next().map(|(_, orders)| orders);
-                    if let Some(orders) = bid_orders {
-                        for bid in orders.iter() {
-                            let match_qty = std::cmp::min(remaining_qty, bid.quantity.get());
-                            if match_qty > 0 {
-                                trades.push(Trade {
-                                    price: bid_price.to_price(),
-                                    quantity: Quantity::new(match_qty),
-                                    ask_order_id: ask.id,
-                                    bid_order_id: bid.id,
-                                    ask_account_id: ask.account_id.clone(),
-                                    bid_account_id: bid.account_id.clone(),
-                                });
-
-                                // Record the update needed
-                                if bid.quantity.get() == match_qty {
-                                    updates.push((bid.id, OrderUpdate::Remove));
-                                } else {
-                                    updates.push((
-                                        bid.id,
-                                        OrderUpdate::Update(Quantity::new(
-                                            bid.quantity.get() - match_qty,
-                                        )),
-                                    ));
-                                }
-                                remaining_qty -= match_qty;
-                            }
-                            if remaining_qty == 0 {
-                                break;
-                            }
-                        }
-                    }
-                }
-                None => break,
-            }
+    /// The update that takes `quantity` out of a resting order.
+    fn reduced(resting: &Order, quantity: u64) -> OrderUpdate {
+        if resting.quantity.get() == quantity {
+            OrderUpdate::Remove
+        } else {
+            OrderUpdate::Update(Quantity::new(resting.quantity.get() - quantity))
         }
-
-        // Update the ask quantity to the remaining quantity
-        ask.quantity = Quantity::new(remaining_qty);
-
-        (trades, updates)
     }
 
-    /// Process an ask order
+    /// Share `quantity` among the orders of a level according to the policy
     ///
-    /// Returns the trades.
-    fn process_ask(&mut self, mut ask: Order) -> Vec<Trade> {
-        let (trades, updates) = self.find_ask_matches(&mut ask);
-
-        // Handle the results
-        if !trades.is_empty() {
-            for (order_id, update) in updates {
-                match update {
-                    OrderUpdate::Remove => {
-                        self.orderbook.remove_order(order_id, Side::Bid, ask.price);
-                    }
-                    OrderUpdate::Update(new_qty) => {
-                        self.orderbook
-                            .update_order_quantity(order_id, Side::Bid, new_qty);
-                    }
-                }
+    /// Leaves the indices of the orders that fill and their fill quantities in `fills`, in
+    /// the order the policy chose, with the fills the book cannot take trimmed as
+    /// `MatchPolicy` describes. `filled` is working space.
+    fn allocate(
+        &self,
+        level: &[Order],
+        quantity: u64,
+        fills: &mut Vec<(usize, u64)>,
+        filled: &mut HashSet<usize>,
+    ) {
+        fills.clear();
+        filled.clear();
+        self.policy.allocate_into(level, quantity, self.lot, fills);
+        let mut remaining = quantity;
+        // Trim in place, keeping the accepted fills at the front
+        let mut kept = 0;
+        for chosen in 0..fills.len() {
+            let (i, fill) = fills[chosen];
+            let Some(order) = level.get(i) else {
+                continue;
+            };
+            if filled.contains(&i) {
+                continue;
             }
-        } else {
-            self.orderbook.insert_order(ask.clone());
+            let fill = fill.min(remaining).min(order.quantity.get()) / self.lot * self.lot;
+            if fill == 0 || (order.all_or_none && fill < order.quantity.get()) {
+                continue;
+            }
+            filled.insert(i);
+            fills[kept] = (i, fill);
+            kept += 1;
+            remaining -= fill;
         }
-        trades
+        fills.truncate(kept);
     }
 
     /// Cancel an order by its ID. Returns the order if it was found and removed.
     pub fn cancel_order(&mut self, order_id: OrderId, side: Side, price: Price) -> Option<Order> {
         self.orderbook.remove_order(order_id, side, price)
     }
+
+    /// Shrink a resting order without losing its queue priority. Returns the order as it was
+    /// before, or `None` if it was not found or the quantity would not shrink.
+    pub fn reduce_order(
+        &mut self,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Option<Order> {
+        self.orderbook.reduce_order(order_id, side, price, quantity)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::order::{AccountId, OrderId, Timestamp};
+    use crate::order::{AccountId, OrderId, OrderType, TimeInForce, Timestamp};
 
     use super::*;
 
@@ -260,4 +617,554 @@ mod tests {
             Timestamp::new(4),
         ));
     }
+
+    fn order(id: u64, price: u64, qty: u64, side: Side, ts: u64) -> Order {
+        Order::new(
+            OrderId::new(id),
+            Price::new(price),
+            Quantity::new(qty),
+            side,
+            AccountId::new(format!("trader{}", id % 7)),
+            Timestamp::new(ts),
+        )
+    }
+
+    #[test]
+    fn test_priority_is_timestamp_then_order_id() {
+        let mut engine = MatchingEngine::new();
+
+        // Inserted out of priority order: (ts 2, id 1), (ts 1, id 3), (ts 1, id 2).
+        engine.process_order(order(1, 100, 1, Side::Ask, 2));
+        engine.process_order(order(3, 100, 1, Side::Ask, 1));
+        engine.process_order(order(2, 100, 1, Side::Ask, 1));
+
+        let trades = engine.process_order(order(4, 100, 3, Side::Bid, 3));
+        let filled: Vec<OrderId> = trades.iter().map(|t| t.ask_order_id).collect();
+        assert_eq!(
+            filled,
+            vec![OrderId::new(2), OrderId::new(3), OrderId::new(1)]
+        );
+    }
+
+    #[test]
+    fn test_market_order_sweeps_levels_and_drops_remainder() {
+        let mut engine = MatchingEngine::new();
+        engine.process_order(order(1, 100, 2, Side::Ask, 1));
+        engine.process_order(order(2, 105, 3, Side::Ask, 2));
+
+        let market = Order::market(
+            OrderId::new(3),
+            Quantity::new(10),
+            Side::Bid,
+            AccountId::new("taker".to_string()),
+            Timestamp::new(3),
+        );
+        let trades = engine.process_order(market.clone());
+        let fills: Vec<(u64, u64)> = trades
+            .iter()
+            .map(|t| (t.price.get(), t.quantity.get()))
+            .collect();
+        assert_eq!(fills, vec![(100, 2), (105, 3)]);
+
+        // Both levels are consumed and the remainder did not rest
+        assert_eq!(engine.orderbook().get_best_ask(), None);
+        assert_eq!(engine.orderbook().get_best_bid(), None);
+
+        // Against an empty book nothing trades and nothing rests
+        assert!(engine.process_order(market).is_empty());
+        assert_eq!(engine.orderbook().get_best_bid(), None);
+    }
+
+    #[test]
+    fn test_replay_is_deterministic() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let orders: Vec<Order> = (0..2_000)
+            .map(|i| {
+                let side = if rng.random_bool(0.5) {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                };
+                order(
+                    i,
+                    rng.random_range(95..105),
+                    rng.random_range(1..20),
+                    side,
+                    // Coarse timestamps so that many orders tie and fall back to the ID.
+                    i / 10,
+                )
+            })
+            .collect();
+
+        let replay = |backend: BookBackend| {
+            let mut engine = MatchingEngine::with_backend(backend);
+            let trades: Vec<Trade> = orders
+                .iter()
+                .cloned()
+                .flat_map(|o| engine.process_order(o))
+                .collect();
+            let bids: Vec<(u64, Vec<OrderId>)> = engine
+                .orderbook
+                .get_bids()
+                .map(|(p, os)| (p.to_price().get(), os.iter().map(|o| o.id).collect()))
+                .collect();
+            let asks: Vec<(u64, Vec<OrderId>)> = engine
+                .orderbook
+                .get_asks()
+                .map(|(p, os)| (p.get(), os.iter().map(|o| o.id).collect()))
+                .collect();
+            (trades, bids, asks)
+        };
+
+        let ladder = BookBackend::Ladder {
+            min_price: Price::new(90),
+            tick_size: 1,
+            num_ticks: 20,
+        };
+        assert_eq!(replay(BookBackend::BTree), replay(BookBackend::BTree));
+        assert_eq!(replay(ladder), replay(ladder));
+        assert_eq!(replay(BookBackend::BTree), replay(ladder));
+    }
+
+    #[test]
+    fn test_all_or_none_orders_are_skipped_by_small_aggressors() {
+        let mut engine = MatchingEngine::new();
+        engine.process_order(Order {
+            all_or_none: true,
+            ..order(1, 100, 5, Side::Ask, 1)
+        });
+        engine.process_order(order(2, 100, 2, Side::Ask, 2));
+        engine.process_order(order(3, 101, 2, Side::Ask, 3));
+
+        // Too small for the all-or-none order, so it trades behind it at the same level
+        let trades = engine.process_order(order(4, 101, 3, Side::Bid, 4));
+        let fills: Vec<(u64, u64)> = trades
+            .iter()
+            .map(|t| (t.ask_order_id.get(), t.quantity.get()))
+            .collect();
+        assert_eq!(fills, vec![(2, 2), (3, 1)]);
+
+        // The all-or-none order kept its place and fills once an aggressor can take it all
+        let trades = engine.process_order(order(5, 100, 5, Side::Bid, 5));
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].ask_order_id, OrderId::new(1));
+        assert_eq!(trades[0].quantity, Quantity::new(5));
+    }
+
+    #[test]
+    fn test_sides_match_symmetrically() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        // Mirroring every order's side and its price around 100 must mirror the trades and
+        // the book, whatever the mix of order kinds
+        let mirror = |order: &Order| Order {
+            side: order.side.opposite(),
+            price: Price::new(200 - order.price.get()),
+            protection_price: order
+                .protection_price
+                .map(|price| Price::new(200 - price.get())),
+            ..order.clone()
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        let orders: Vec<Order> = (0..2_000)
+            .map(|i| {
+                let side = if rng.random_bool(0.5) {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                };
+                let mut order = order(
+                    i,
+                    rng.random_range(95..=105),
+                    rng.random_range(1..20),
+                    side,
+                    i / 10,
+                );
+                match rng.random_range(0..8) {
+                    0 => order.order_type = OrderType::Market,
+                    1 => {
+                        order.order_type = OrderType::Market;
+                        order.protection_price = Some(order.price);
+                    }
+                    2 => order.time_in_force = Some(TimeInForce::Ioc),
+                    3 => order.all_or_none = true,
+                    _ => {}
+                }
+                order
+            })
+            .collect();
+
+        let ladder = BookBackend::Ladder {
+            min_price: Price::new(90),
+            tick_size: 1,
+            num_ticks: 20,
+        };
+        for backend in [BookBackend::BTree, ladder] {
+            let mut engine = MatchingEngine::with_backend(backend);
+            let mut mirrored = MatchingEngine::with_backend(backend);
+            for order in &orders {
+                let trades = engine.process_order(order.clone());
+                let expected: Vec<Trade> = trades
+                    .iter()
+                    .map(|trade| Trade {
+                        id: trade.id,
+                        sequence: trade.sequence,
+                        ask_order_id: trade.bid_order_id,
+                        bid_order_id: trade.ask_order_id,
+                        ask_account_id: trade.bid_account_id.clone(),
+                        bid_account_id: trade.ask_account_id.clone(),
+                        price: Price::new(200 - trade.price.get()),
+                        quantity: trade.quantity,
+                        aggressor: trade.aggressor.map(Side::opposite),
+                        ask_client_order_id: trade.bid_client_order_id.clone(),
+                        bid_client_order_id: trade.ask_client_order_id.clone(),
+                        ask_tag: trade.bid_tag.clone(),
+                        bid_tag: trade.ask_tag.clone(),
+                    })
+                    .collect();
+                assert_eq!(mirrored.process_order(mirror(order)), expected);
+            }
+            for side in [Side::Bid, Side::Ask] {
+                let levels: Vec<(Price, Vec<Order>)> = engine
+                    .orderbook()
+                    .levels(side)
+                    .map(|(price, orders)| {
+                        (
+                            Price::new(200 - price.get()),
+                            orders.iter().map(mirror).collect(),
+                        )
+                    })
+                    .collect();
+                let mirrored_levels: Vec<(Price, Vec<Order>)> = mirrored
+                    .orderbook()
+                    .levels(side.opposite())
+                    .map(|(price, orders)| (price, orders.clone()))
+                    .collect();
+                assert_eq!(levels, mirrored_levels);
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_level_sweeps_update_each_level() {
+        let ladder = BookBackend::Ladder {
+            min_price: Price::new(90),
+            tick_size: 1,
+            num_ticks: 20,
+        };
+        for backend in [BookBackend::BTree, ladder] {
+            for side in [Side::Bid, Side::Ask] {
+                // Resting orders 2, 3 and 4 ticks away from 100, swept by an order at 105 or 95
+                let away = |ticks: u64| match side {
+                    Side::Bid => 100 + ticks,
+                    Side::Ask => 100 - ticks,
+                };
+                let mut engine = MatchingEngine::with_backend(backend);
+                engine.process_order(order(1, away(2), 2, side.opposite(), 1));
+                engine.process_order(order(2, away(2), 1, side.opposite(), 2));
+                engine.process_order(order(3, away(3), 3, side.opposite(), 3));
+                engine.process_order(order(4, away(4), 4, side.opposite(), 4));
+
+                let trades = engine.process_order(order(5, away(5), 7, side, 5));
+                let fills: Vec<(u64, u64)> = trades
+                    .iter()
+                    .map(|t| (t.price.get(), t.quantity.get()))
+                    .collect();
+                assert_eq!(
+                    fills,
+                    vec![(away(2), 2), (away(2), 1), (away(3), 3), (away(4), 1)]
+                );
+
+                // Swept levels are gone and the partially filled order kept its own level
+                let levels: Vec<(u64, Vec<(u64, u64)>)> = engine
+                    .orderbook()
+                    .levels(side.opposite())
+                    .map(|(price, orders)| {
+                        let orders = orders
+                            .iter()
+                            .map(|o| (o.id.get(), o.quantity.get()))
+                            .collect();
+                        (price.get(), orders)
+                    })
+                    .collect();
+                assert_eq!(levels, vec![(away(4), vec![(4, 3)])]);
+                assert!(engine.orderbook().levels(side).next().is_none());
+
+                // The remaining order is found at its level price
+                assert!(
+                    engine
+                        .cancel_order(OrderId::new(4), side.opposite(), Price::new(away(4)))
+                        .is_some()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reduce_order_keeps_priority() {
+        for backend in [
+            BookBackend::BTree,
+            BookBackend::Ladder {
+                min_price: Price::new(0),
+                tick_size: 1,
+                num_ticks: 200,
+            },
+        ] {
+            let mut engine = MatchingEngine::with_backend(backend);
+            engine.process_order(order(1, 100, 5, Side::Bid, 1));
+            engine.process_order(order(2, 100, 5, Side::Bid, 2));
+
+            // Only shrinking an order that exists at that price and side works
+            for (id, side, price, qty) in [
+                (1, Side::Bid, 100, 5),
+                (1, Side::Bid, 100, 0),
+                (1, Side::Bid, 99, 2),
+                (1, Side::Ask, 100, 2),
+                (3, Side::Bid, 100, 2),
+            ] {
+                let reduced = engine.reduce_order(
+                    OrderId::new(id),
+                    side,
+                    Price::new(price),
+                    Quantity::new(qty),
+                );
+                assert_eq!(reduced, None);
+            }
+            let previous = engine
+                .reduce_order(
+                    OrderId::new(1),
+                    Side::Bid,
+                    Price::new(100),
+                    Quantity::new(2),
+                )
+                .unwrap();
+            assert_eq!(previous.quantity, Quantity::new(5));
+            assert_eq!(previous.price, Price::new(100));
+
+            let trades = engine.process_order(order(3, 100, 3, Side::Ask, 3));
+            let fills: Vec<(u64, u64)> = trades
+                .iter()
+                .map(|trade| (trade.bid_order_id.get(), trade.quantity.get()))
+                .collect();
+            assert_eq!(fills, vec![(1, 2), (2, 1)]);
+        }
+    }
+
+    #[test]
+    fn test_partially_filled_remainder_rests() {
+        for backend in [
+            BookBackend::BTree,
+            BookBackend::Ladder {
+                min_price: Price::new(0),
+                tick_size: 1,
+                num_ticks: 200,
+            },
+        ] {
+            let mut engine = MatchingEngine::with_backend(backend);
+            engine.process_order(order(1, 99, 4, Side::Bid, 1));
+            engine.process_order(order(2, 100, 3, Side::Ask, 2));
+            engine.process_order(order(3, 101, 2, Side::Ask, 3));
+
+            let trades = engine.process_order(order(4, 101, 10, Side::Bid, 4));
+            assert_eq!(trades.len(), 2);
+            assert_eq!(engine.orderbook().get_best_ask(), None);
+            assert_eq!(engine.orderbook().get_best_bid(), Some(101));
+
+            // The remainder of 5 is now the best bid
+            let trades = engine.process_order(order(5, 99, 9, Side::Ask, 5));
+            let filled: Vec<(OrderId, Quantity)> = trades
+                .iter()
+                .map(|trade| (trade.bid_order_id, trade.quantity))
+                .collect();
+            assert_eq!(
+                filled,
+                vec![
+                    (OrderId::new(4), Quantity::new(5)),
+                    (OrderId::new(1), Quantity::new(4))
+                ]
+            );
+            assert_eq!(engine.orderbook().get_best_bid(), None);
+        }
+    }
+
+    #[test]
+    fn test_fifo_within_a_crowded_level() {
+        for backend in [
+            BookBackend::BTree,
+            BookBackend::Ladder {
+                min_price: Price::new(0),
+                tick_size: 1,
+                num_ticks: 200,
+            },
+        ] {
+            let mut engine = MatchingEngine::with_backend(backend);
+            // 60 asks at one level, inserted out of arrival order; pairs of orders share
+            // a timestamp, and their IDs break the tie
+            let mut expected = Vec::new();
+            for i in 0..60u64 {
+                let slot = (i * 37) % 60;
+                let id = 100 + slot;
+                engine.process_order(order(id, 100, 2, Side::Ask, slot / 2));
+                expected.push((slot / 2, id));
+            }
+            expected.sort();
+            let expected: Vec<OrderId> = expected
+                .into_iter()
+                .map(|(_, id)| OrderId::new(id))
+                .collect();
+
+            // Each taker takes one unit, so every ask is hit twice in a row before the next
+            let mut filled = Vec::new();
+            for i in 0..120 {
+                let trades = engine.process_order(order(1_000 + i, 100, 1, Side::Bid, 100 + i));
+                assert_eq!(trades.len(), 1);
+                filled.push(trades[0].ask_order_id);
+            }
+            let heads: Vec<OrderId> = filled
+                .chunks(2)
+                .map(|pair| {
+                    assert_eq!(pair[0], pair[1]);
+                    pair[0]
+                })
+                .collect();
+            assert_eq!(heads, expected);
+            assert_eq!(engine.orderbook().get_best_ask(), None);
+        }
+    }
+
+    #[test]
+    fn test_pro_rata_allocation() {
+        for backend in [
+            BookBackend::BTree,
+            BookBackend::Ladder {
+                min_price: Price::new(0),
+                tick_size: 1,
+                num_ticks: 200,
+            },
+        ] {
+            let fills = |trades: &[Trade]| -> Vec<(u64, u64)> {
+                trades
+                    .iter()
+                    .map(|t| (t.ask_order_id.get(), t.quantity.get()))
+                    .collect()
+            };
+
+            let mut engine = MatchingEngine::with_backend(backend)
+                .with_allocation(Allocation::ProRata { min_allocation: 5 }, 1);
+            engine.process_order(order(1, 100, 60, Side::Ask, 1));
+            engine.process_order(order(2, 100, 30, Side::Ask, 2));
+            engine.process_order(order(3, 100, 10, Side::Ask, 3));
+            engine.process_order(Order {
+                all_or_none: true,
+                ..order(4, 100, 5, Side::Ask, 4)
+            });
+
+            // Shares of 12, 6 and 2 of the 20; the 2 is below the minimum and goes to the
+            // head of the queue with the rest of the rounding
+            let trades = engine.process_order(order(10, 100, 20, Side::Bid, 10));
+            assert_eq!(fills(&trades), vec![(1, 14), (2, 6)]);
+
+            // Enough for every divisible order; the all-or-none order fills in the priority
+            // pass and the remainder rests
+            let trades = engine.process_order(order(11, 100, 90, Side::Bid, 11));
+            assert_eq!(fills(&trades), vec![(1, 46), (2, 24), (3, 10), (4, 5)]);
+            assert_eq!(engine.orderbook().get_best_ask(), None);
+            assert_eq!(engine.orderbook().get_best_bid(), Some(100));
+
+            // Shares rounded down to whole lots of 10
+            let mut engine = MatchingEngine::with_backend(backend)
+                .with_allocation(Allocation::ProRata { min_allocation: 0 }, 10);
+            engine.process_order(order(1, 100, 50, Side::Ask, 1));
+            engine.process_order(order(2, 100, 50, Side::Ask, 2));
+            let trades = engine.process_order(order(10, 100, 30, Side::Bid, 10));
+            assert_eq!(fills(&trades), vec![(1, 20), (2, 10)]);
+        }
+    }
+
+    #[test]
+    fn test_trade_maker_taker_metadata() {
+        let mut engine = MatchingEngine::new();
+        engine.process_order(order(1, 100, 5, Side::Ask, 1));
+        let trades = engine.process_order(order(2, 100, 5, Side::Bid, 2));
+        let trade = &trades[0];
+        assert_eq!(trade.maker_side(), Some(Side::Ask));
+        assert_eq!(trade.maker_order_id(), Some(OrderId::new(1)));
+        assert_eq!(trade.taker_order_id(), Some(OrderId::new(2)));
+        assert_eq!(trade.liquidity(Side::Ask), Liquidity::Maker);
+        assert_eq!(trade.liquidity(Side::Bid), Liquidity::Taker);
+
+        // Neither side of an uncross trade rested
+        let uncross = Trade {
+            aggressor: None,
+            ..trade.clone()
+        };
+        assert_eq!(uncross.maker_order_id(), None);
+        assert_eq!(uncross.taker_order_id(), None);
+        assert_eq!(uncross.order_id(Side::Bid), OrderId::new(2));
+        assert_eq!(uncross.liquidity(Side::Ask), Liquidity::Taker);
+    }
+
+    #[test]
+    fn test_simulate_order_leaves_the_book_untouched() {
+        let mut engine = MatchingEngine::new();
+        engine.process_order(order(1, 100, 2, Side::Ask, 1));
+        engine.process_order(order(2, 101, 3, Side::Ask, 2));
+        let levels = |engine: &MatchingEngine| -> Vec<(Price, Vec<Order>)> {
+            engine
+                .orderbook()
+                .levels(Side::Ask)
+                .map(|(price, orders)| (price, orders.clone()))
+                .collect()
+        };
+        let before = levels(&engine);
+
+        let bid = order(3, 101, 4, Side::Bid, 3);
+        let simulated = engine.simulate_order(bid.clone());
+        assert_eq!(levels(&engine), before);
+        let trades = engine.process_order(bid);
+        assert_eq!(simulated, trades);
+        assert_eq!(simulated.len(), 2);
+    }
+
+    #[test]
+    fn test_process_order_with_hands_out_the_same_trades() {
+        let orders = [
+            order(1, 100, 2, Side::Ask, 1),
+            order(2, 101, 3, Side::Ask, 2),
+            order(3, 101, 4, Side::Bid, 3),
+            order(4, 99, 1, Side::Ask, 4),
+        ];
+        let mut collected = MatchingEngine::new();
+        let mut streamed = MatchingEngine::new();
+        for order in orders {
+            let trades = collected.process_order(order.clone());
+            let mut handed = Vec::new();
+            streamed.process_order_with(order, |trade| handed.push(trade.clone()));
+            assert_eq!(handed, trades);
+        }
+        let asks = |engine: &MatchingEngine| -> Vec<(Price, Vec<Order>)> {
+            engine
+                .orderbook()
+                .levels(Side::Ask)
+                .map(|(price, orders)| (price, orders.clone()))
+                .collect()
+        };
+        assert_eq!(asks(&streamed), asks(&collected));
+    }
+
+    #[test]
+    fn test_buffered_processing_matches_process_order() {
+        for allocation in [Allocation::Fifo, Allocation::ProRata { min_allocation: 1 }] {
+            let mut collected = MatchingEngine::new().with_allocation(allocation, 1);
+            let mut buffered = MatchingEngine::new().with_allocation(allocation, 1);
+            for i in 0..500 {
+                let side = if i % 3 == 0 { Side::Bid } else { Side::Ask };
+                let order = order(i, 95 + (i * 7) % 10, 1 + (i * 13) % 20, side, i);
+                let trades = collected.process_order(order.clone());
+                assert_eq!(buffered.process_order_buffered(order), trades.as_slice());
+            }
+        }
+    }
 }
@@ -0,0 +1,915 @@
+//! Versioning of the persisted binary formats.
+//!
+//! Every file starts with a four-byte magic identifying its format, followed by the `u32`
+//! format version and the encoded body. On load, bodies written by older versions of the
+//! crate are upgraded one version at a time until they reach the current version, so files
+//! remain readable after upgrades.
+//!
+//! Version 1 files were written before the version field existed: the magic (if any) is
+//! followed directly by the body.
+
+use std::borrow::Cow;
+
+use anyhow::Result;
+
+use crate::codec::{Decoder, Encoder};
+
+/// Upgrades a body from one version to the next.
+type Upgrade = fn(&[u8]) -> Result<Vec<u8>>;
+
+/// A persisted binary format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Snapshot,
+    Witness,
+    Journal,
+}
+
+impl Format {
+    pub fn magic(self) -> &'static [u8; 4] {
+        match self {
+            Format::Snapshot => b"EXSS",
+            Format::Witness => b"EXWT",
+            Format::Journal => b"EXJL",
+        }
+    }
+
+    /// The version written by this version of the crate.
+    pub fn current_version(self) -> u32 {
+        self.migrations().len() as u32 + 1
+    }
+
+    /// The upgrade from version `i + 1` to version `i + 2` is at index `i`.
+    ///
+    /// Witnesses and journals embed snapshot bodies and commands, so changing either
+    /// encoding needs a migration of all three formats.
+    fn migrations(self) -> &'static [Upgrade] {
+        match self {
+            // 1 -> 2: added the header; the body is unchanged
+            // 2 -> 3: added optional fields to orders
+            // 3 -> 4: added the minimum quantity shortfall to market configs
+            // 4 -> 5: added flat fees to market configs
+            // 5 -> 6: added client order IDs to trades; orders carry them as an optional
+            //         field, so snapshots are unchanged
+            // 6 -> 7: added order tags to trades; snapshots are unchanged likewise
+            // 7 -> 8: added odd lot handling to market configs
+            // 8 -> 9: added the allocation algorithm to market configs
+            // 9 -> 10: added trade IDs and sequence numbers to trades, and the next of each
+            //          to snapshots
+            // 10 -> 11: added positions to snapshots
+            Format::Snapshot => &[
+                unchanged,
+                v2::snapshot_to_v3,
+                v3::snapshot_to_v4,
+                v4::snapshot_to_v5,
+                unchanged,
+                unchanged,
+                v7::snapshot_to_v8,
+                v8::snapshot_to_v9,
+                v9::snapshot_to_v10,
+                v10::snapshot_to_v11,
+            ],
+            Format::Witness => &[
+                unchanged,
+                v2::witness_to_v3,
+                v3::witness_to_v4,
+                v4::witness_to_v5,
+                v5::witness_to_v6,
+                v6::witness_to_v7,
+                v7::witness_to_v8,
+                v8::witness_to_v9,
+                v9::witness_to_v10,
+                v10::witness_to_v11,
+            ],
+            // Journal versions 1, 2, 3 and 4 embed version 8, 9, 10 and 11 snapshots and
+            // commands
+            Format::Journal => &[v8::journal_to_v2, v9::journal_to_v3, v10::journal_to_v4],
+        }
+    }
+}
+
+fn unchanged(body: &[u8]) -> Result<Vec<u8>> {
+    Ok(body.to_vec())
+}
+
+/// Writes the header of a file in the current version of the format.
+pub(crate) fn write_header(encoder: &mut Encoder, format: Format) {
+    for byte in format.magic() {
+        encoder.u8(*byte);
+    }
+    encoder.u32(format.current_version());
+}
+
+/// Returns the version of a file and its body, without upgrading it.
+pub fn read_header(format: Format, bytes: &[u8]) -> Result<(u32, &[u8])> {
+    let magic = format.magic();
+    match format {
+        // Version 1 snapshots had no magic. Their first eight bytes are the number of
+        // balances, which can never spell the magic in a valid file.
+        Format::Snapshot if !bytes.starts_with(magic) => return Ok((1, bytes)),
+        _ if !bytes.starts_with(magic) => {
+            return Err(anyhow::anyhow!("Not a {:?} file", format));
+        }
+        _ => {}
+    }
+    let rest = &bytes[magic.len()..];
+    let version = rest
+        .get(..4)
+        .map(|v| u32::from_be_bytes(v.try_into().unwrap()))
+        .ok_or(anyhow::anyhow!("Unexpected end of input"))?;
+    // Version 1 witnesses continued with the `u64` balance count of the pre-state, whose
+    // high bytes are always zero
+    if version == 0 {
+        return Ok((1, rest));
+    }
+    Ok((version, &rest[4..]))
+}
+
+/// Returns the body of a file, upgraded to the current version of the format.
+pub fn load(format: Format, bytes: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let (version, body) = read_header(format, bytes)?;
+    let current = format.current_version();
+    if version > current {
+        return Err(anyhow::anyhow!(
+            "{:?} version {} is newer than supported version {}",
+            format,
+            version,
+            current
+        ));
+    }
+    let mut body = Cow::Borrowed(body);
+    for upgrade in &format.migrations()[version as usize - 1..] {
+        body = Cow::Owned(upgrade(&body)?);
+    }
+    Ok(body)
+}
+
+/// Copies values from an old body to an upgraded one.
+///
+/// Migrations describe the old layout with these primitives rather than the current
+/// decoders, so they keep working as the current encoding evolves.
+struct Transcoder<'a> {
+    from: Decoder<'a>,
+    to: Encoder,
+}
+
+impl<'a> Transcoder<'a> {
+    fn new(body: &'a [u8]) -> Self {
+        Self {
+            from: Decoder::new(body),
+            to: Encoder::new(),
+        }
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        self.from.finish()?;
+        Ok(self.to.finish())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        let value = self.from.u8()?;
+        self.to.u8(value);
+        Ok(value)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let value = self.from.u64()?;
+        self.to.u64(value);
+        Ok(value)
+    }
+
+    fn str(&mut self) -> Result<&'a str> {
+        let value = self.from.str()?;
+        self.to.str(value);
+        Ok(value)
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let len = self.from.len()?;
+        self.to.len(len);
+        Ok(len)
+    }
+
+    fn bytes(&mut self) -> Result<()> {
+        let value = self.from.bytes()?;
+        self.to.bytes(value);
+        Ok(())
+    }
+}
+
+/// Layout of version 2 bodies.
+mod v2 {
+    use super::*;
+
+    pub fn snapshot_to_v3(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        snapshot(&mut t)?;
+        t.finish()
+    }
+
+    pub fn witness_to_v3(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        snapshot(&mut t)?;
+        for _ in 0..t.len()? {
+            match t.u8()? {
+                0 | 1 => {
+                    t.str()?;
+                    t.str()?;
+                    t.u64()?;
+                }
+                2 => {
+                    t.str()?;
+                    t.str()?;
+                    order(&mut t)?;
+                }
+                3 => {
+                    t.str()?;
+                    t.str()?;
+                    t.u64()?;
+                    t.u8()?;
+                    t.u64()?;
+                }
+                tag => return Err(anyhow::anyhow!("Invalid command {}", tag)),
+            }
+        }
+        for _ in 0..t.len()? {
+            if t.u8()? == 1 {
+                for _ in 0..t.len()? {
+                    t.u64()?;
+                    t.u64()?;
+                    t.str()?;
+                    t.str()?;
+                    t.u64()?;
+                    t.u64()?;
+                    if t.u8()? == 1 {
+                        t.u8()?;
+                    }
+                }
+            }
+        }
+        snapshot(&mut t)?;
+        t.finish()
+    }
+
+    fn snapshot(t: &mut Transcoder<'_>) -> Result<()> {
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            t.u64()?;
+        }
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            if t.u8()? == 1 {
+                t.u64()?;
+                t.u64()?;
+                t.u64()?;
+            }
+            t.u64()?;
+            t.u64()?;
+            t.u8()?;
+            for _side in 0..2 {
+                for _ in 0..t.len()? {
+                    order(t)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Version 2 orders had no optional fields.
+    fn order(t: &mut Transcoder<'_>) -> Result<()> {
+        t.u64()?;
+        t.u64()?;
+        t.u64()?;
+        t.u8()?;
+        t.str()?;
+        t.u64()?;
+        t.to.u8(0);
+        Ok(())
+    }
+}
+
+/// Layout of version 3 bodies.
+mod v3 {
+    use super::*;
+
+    /// Copies a market config, leaving the encoder where later versions appended fields.
+    pub type Config = fn(&mut Transcoder<'_>) -> Result<()>;
+
+    /// Copies a trade, leaving the encoder where later versions appended fields.
+    pub type Trade = fn(&mut Transcoder<'_>) -> Result<()>;
+
+    pub fn snapshot_to_v4(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        snapshot(&mut t, config_to_v4)?;
+        t.finish()
+    }
+
+    pub fn witness_to_v4(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        witness(&mut t, config_to_v4, trade)?;
+        t.finish()
+    }
+
+    fn config_to_v4(t: &mut Transcoder<'_>) -> Result<()> {
+        config(t)?;
+        // Version 3 markets always rejected minimum quantity shortfalls
+        t.to.u8(0);
+        Ok(())
+    }
+
+    pub fn config(t: &mut Transcoder<'_>) -> Result<()> {
+        if t.u8()? == 1 {
+            t.u64()?;
+            t.u64()?;
+            t.u64()?;
+        }
+        t.u64()?;
+        t.u64()?;
+        t.u8()?;
+        Ok(())
+    }
+
+    /// Copies a witness in the version 3 layout, with `config` for the market configs of
+    /// its snapshots and `trade` for the trades of its outcomes.
+    pub fn witness(t: &mut Transcoder<'_>, config: Config, trade: Trade) -> Result<()> {
+        snapshot(t, config)?;
+        for _ in 0..t.len()? {
+            command(t)?;
+        }
+        for _ in 0..t.len()? {
+            outcome(t, trade)?;
+        }
+        snapshot(t, config)
+    }
+
+    pub fn command(t: &mut Transcoder<'_>) -> Result<()> {
+        match t.u8()? {
+            0 | 1 => {
+                t.str()?;
+                t.str()?;
+                t.u64()?;
+            }
+            2 => {
+                t.str()?;
+                t.str()?;
+                order(t)?;
+            }
+            3 => {
+                t.str()?;
+                t.str()?;
+                t.u64()?;
+                t.u8()?;
+                t.u64()?;
+            }
+            4 => {
+                t.u64()?;
+            }
+            tag => return Err(anyhow::anyhow!("Invalid command {}", tag)),
+        }
+        Ok(())
+    }
+
+    /// Copies the outcome of a command, with `trade` for its trades.
+    pub fn outcome(t: &mut Transcoder<'_>, trade: Trade) -> Result<()> {
+        if t.u8()? == 1 {
+            for _ in 0..t.len()? {
+                trade(t)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn trade(t: &mut Transcoder<'_>) -> Result<()> {
+        t.u64()?;
+        t.u64()?;
+        t.str()?;
+        t.str()?;
+        t.u64()?;
+        t.u64()?;
+        if t.u8()? == 1 {
+            t.u8()?;
+        }
+        Ok(())
+    }
+
+    /// Copies a snapshot in the version 3 layout with `config`.
+    pub fn snapshot(t: &mut Transcoder<'_>, config: Config) -> Result<()> {
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            t.u64()?;
+        }
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            config(t)?;
+            for _side in 0..2 {
+                for _ in 0..t.len()? {
+                    order(t)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn order(t: &mut Transcoder<'_>) -> Result<()> {
+        t.u64()?;
+        t.u64()?;
+        t.u64()?;
+        t.u8()?;
+        t.str()?;
+        t.u64()?;
+        for _ in 0..t.u8()? {
+            t.u8()?;
+            t.bytes()?;
+        }
+        Ok(())
+    }
+}
+
+/// Layout of version 4 bodies: version 3 with the minimum quantity shortfall appended to
+/// market configs.
+mod v4 {
+    use super::*;
+
+    pub fn snapshot_to_v5(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::snapshot(&mut t, config_to_v5)?;
+        t.finish()
+    }
+
+    pub fn witness_to_v5(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::witness(&mut t, config_to_v5, v3::trade)?;
+        t.finish()
+    }
+
+    fn config_to_v5(t: &mut Transcoder<'_>) -> Result<()> {
+        v3::config(t)?;
+        t.u8()?;
+        // No flat fee
+        t.to.u8(0);
+        Ok(())
+    }
+}
+
+/// Layout of version 5 bodies: version 4 with flat fees appended to market configs.
+mod v5 {
+    use super::*;
+
+    pub fn witness_to_v6(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::witness(&mut t, config, trade_to_v6)?;
+        t.finish()
+    }
+
+    pub fn config(t: &mut Transcoder<'_>) -> Result<()> {
+        v3::config(t)?;
+        t.u8()?;
+        if t.u8()? == 1 {
+            t.str()?;
+            t.u64()?;
+        }
+        Ok(())
+    }
+
+    fn trade_to_v6(t: &mut Transcoder<'_>) -> Result<()> {
+        v3::trade(t)?;
+        // Neither order had a client order ID
+        t.to.u8(0);
+        t.to.u8(0);
+        Ok(())
+    }
+}
+
+/// Layout of version 6 bodies: version 5 with client order IDs appended to trades.
+mod v6 {
+    use super::*;
+
+    pub fn witness_to_v7(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::witness(&mut t, v5::config, trade_to_v7)?;
+        t.finish()
+    }
+
+    fn trade_to_v7(t: &mut Transcoder<'_>) -> Result<()> {
+        v3::trade(t)?;
+        for _ in 0..2 {
+            if t.u8()? == 1 {
+                t.str()?;
+            }
+        }
+        // Neither order had a tag
+        t.to.u8(0);
+        t.to.u8(0);
+        Ok(())
+    }
+}
+
+/// Layout of version 7 bodies: version 6 with order tags appended to trades.
+mod v7 {
+    use super::*;
+
+    pub fn snapshot_to_v8(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::snapshot(&mut t, config_to_v8)?;
+        t.finish()
+    }
+
+    pub fn witness_to_v8(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::witness(&mut t, config_to_v8, trade)?;
+        t.finish()
+    }
+
+    fn config_to_v8(t: &mut Transcoder<'_>) -> Result<()> {
+        v5::config(t)?;
+        // Version 7 markets matched odd lots with everything else
+        t.to.u8(0);
+        Ok(())
+    }
+
+    pub fn trade(t: &mut Transcoder<'_>) -> Result<()> {
+        v3::trade(t)?;
+        // Client order IDs, then tags
+        for _ in 0..4 {
+            if t.u8()? == 1 {
+                t.str()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Layout of version 8 bodies: version 7 with odd lot handling appended to market configs.
+/// Version 1 journals embed commands, trades and snapshots in this layout.
+mod v8 {
+    use super::*;
+
+    pub fn snapshot_to_v9(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::snapshot(&mut t, config_to_v9)?;
+        t.finish()
+    }
+
+    pub fn witness_to_v9(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        v3::witness(&mut t, config_to_v9, v7::trade)?;
+        t.finish()
+    }
+
+    pub fn journal_to_v2(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        t.u64()?;
+        for _ in 0..t.len()? {
+            v3::command(&mut t)?;
+            v3::outcome(&mut t, v7::trade)?;
+        }
+        for _ in 0..t.len()? {
+            t.u64()?;
+            v3::snapshot(&mut t, config_to_v9)?;
+        }
+        t.finish()
+    }
+
+    fn config_to_v9(t: &mut Transcoder<'_>) -> Result<()> {
+        v5::config(t)?;
+        if t.u8()? == 1 {
+            t.u64()?;
+        }
+        // Version 8 markets allocated first in, first out
+        t.to.u8(0);
+        Ok(())
+    }
+}
+
+/// Layout of version 9 bodies: version 8 with the allocation algorithm appended to market
+/// configs. Version 2 journals embed commands, trades and snapshots in this layout.
+mod v9 {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// The next trade ID and the next trade sequence number of each market, by the symbols
+    /// of its pair.
+    ///
+    /// Version 9 trades had neither. They are numbered the way the engine numbers them,
+    /// starting from one in every file, so that re-executing an upgraded witness or journal
+    /// assigns the same numbers.
+    #[derive(Clone)]
+    struct Counters<'a> {
+        next_id: u64,
+        sequences: HashMap<(&'a str, &'a str), u64>,
+    }
+
+    impl Default for Counters<'_> {
+        fn default() -> Self {
+            Self {
+                next_id: 1,
+                sequences: HashMap::new(),
+            }
+        }
+    }
+
+    impl<'a> Counters<'a> {
+        fn sequence(&self, pair: (&'a str, &'a str)) -> u64 {
+            self.sequences.get(&pair).copied().unwrap_or(1)
+        }
+    }
+
+    pub fn snapshot_to_v10(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        snapshot(&mut t, &Counters::default())?;
+        t.finish()
+    }
+
+    pub fn witness_to_v10(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        let mut counters = Counters::default();
+        snapshot(&mut t, &counters)?;
+        let mut pairs = Vec::new();
+        for _ in 0..t.len()? {
+            pairs.push(command(&mut t)?);
+        }
+        for i in 0..t.len()? {
+            outcome(&mut t, pairs.get(i).copied().flatten(), &mut counters)?;
+        }
+        snapshot(&mut t, &counters)?;
+        t.finish()
+    }
+
+    pub fn journal_to_v3(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        t.u64()?;
+        let mut counters = Counters::default();
+        // The counters at each sequence number, for the checkpoints
+        let mut history = vec![counters.clone()];
+        for _ in 0..t.len()? {
+            let pair = command(&mut t)?;
+            outcome(&mut t, pair, &mut counters)?;
+            history.push(counters.clone());
+        }
+        for _ in 0..t.len()? {
+            let seq = t.u64()?;
+            let counters = history
+                .get(seq as usize)
+                .ok_or(anyhow::anyhow!("Invalid checkpoint {}", seq))?;
+            snapshot(&mut t, counters)?;
+        }
+        t.finish()
+    }
+
+    /// Copies a command, returning the pair of the market it posts an order to, if any.
+    fn command<'a>(t: &mut Transcoder<'a>) -> Result<Option<(&'a str, &'a str)>> {
+        match t.u8()? {
+            0 | 1 => {
+                t.str()?;
+                t.str()?;
+                t.u64()?;
+            }
+            2 => {
+                let pair = (t.str()?, t.str()?);
+                v3::order(t)?;
+                return Ok(Some(pair));
+            }
+            3 => {
+                t.str()?;
+                t.str()?;
+                t.u64()?;
+                t.u8()?;
+                t.u64()?;
+            }
+            4 => {
+                t.u64()?;
+            }
+            tag => return Err(anyhow::anyhow!("Invalid command {}", tag)),
+        }
+        Ok(None)
+    }
+
+    /// Copies the outcome of a command posting to `pair`, numbering its trades.
+    fn outcome<'a>(
+        t: &mut Transcoder<'a>,
+        pair: Option<(&'a str, &'a str)>,
+        counters: &mut Counters<'a>,
+    ) -> Result<()> {
+        if t.u8()? == 1 {
+            for _ in 0..t.len()? {
+                v7::trade(t)?;
+                let pair = pair.ok_or(anyhow::anyhow!("Trades without a market"))?;
+                let sequence = counters.sequence(pair);
+                t.to.u64(counters.next_id);
+                t.to.u64(sequence);
+                counters.next_id += 1;
+                counters.sequences.insert(pair, sequence + 1);
+            }
+        }
+        Ok(())
+    }
+
+    fn snapshot(t: &mut Transcoder<'_>, counters: &Counters<'_>) -> Result<()> {
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            t.u64()?;
+        }
+        for _ in 0..t.len()? {
+            let pair = (t.str()?, t.str()?);
+            config(t)?;
+            for _side in 0..2 {
+                for _ in 0..t.len()? {
+                    v3::order(t)?;
+                }
+            }
+            t.to.u64(counters.sequence(pair));
+        }
+        t.to.u64(counters.next_id);
+        Ok(())
+    }
+
+    pub fn config(t: &mut Transcoder<'_>) -> Result<()> {
+        v5::config(t)?;
+        if t.u8()? == 1 {
+            t.u64()?;
+        }
+        if matches!(t.u8()?, 1 | 3) {
+            t.u64()?;
+        }
+        Ok(())
+    }
+}
+
+/// Layout of version 10 bodies: version 9 with trade IDs and sequence numbers. Version 3
+/// journals embed commands, trades and snapshots in this layout.
+mod v10 {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    /// Positions by account and by the base and numeraire symbols of the market, in the
+    /// order snapshots list them.
+    ///
+    /// Version 10 snapshots had none. They are taken as zero in the snapshot a witness or
+    /// journal starts from, and accumulated from the trades of its outcomes, so that
+    /// re-executing an upgraded witness or journal reaches the same positions.
+    type Positions<'a> = BTreeMap<(&'a str, &'a str, &'a str), i64>;
+
+    pub fn snapshot_to_v11(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        snapshot(&mut t, &Positions::new())?;
+        t.finish()
+    }
+
+    pub fn witness_to_v11(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        let mut positions = Positions::new();
+        snapshot(&mut t, &positions)?;
+        let mut pairs = Vec::new();
+        for _ in 0..t.len()? {
+            pairs.push(command(&mut t)?);
+        }
+        for i in 0..t.len()? {
+            outcome(&mut t, pairs.get(i).copied().flatten(), &mut positions)?;
+        }
+        snapshot(&mut t, &positions)?;
+        t.finish()
+    }
+
+    pub fn journal_to_v4(body: &[u8]) -> Result<Vec<u8>> {
+        let mut t = Transcoder::new(body);
+        t.u64()?;
+        let mut positions = Positions::new();
+        // The positions at each sequence number, for the checkpoints
+        let mut history = vec![positions.clone()];
+        for _ in 0..t.len()? {
+            let pair = command(&mut t)?;
+            outcome(&mut t, pair, &mut positions)?;
+            history.push(positions.clone());
+        }
+        for _ in 0..t.len()? {
+            let seq = t.u64()?;
+            let positions = history
+                .get(seq as usize)
+                .ok_or(anyhow::anyhow!("Invalid checkpoint {}", seq))?;
+            snapshot(&mut t, positions)?;
+        }
+        t.finish()
+    }
+
+    /// Copies a command, returning the pair of the market whose trades it reports, if any.
+    fn command<'a>(t: &mut Transcoder<'a>) -> Result<Option<(&'a str, &'a str)>> {
+        match t.u8()? {
+            0 | 1 => {
+                t.str()?;
+                t.str()?;
+                t.u64()?;
+            }
+            2 => {
+                let pair = (t.str()?, t.str()?);
+                v3::order(t)?;
+                return Ok(Some(pair));
+            }
+            3 => {
+                t.str()?;
+                t.str()?;
+                t.u64()?;
+                t.u8()?;
+                t.u64()?;
+            }
+            4 => {
+                t.u64()?;
+            }
+            5 => {
+                let pair = (t.str()?, t.str()?);
+                t.u64()?;
+                return Ok(Some(pair));
+            }
+            6 => {
+                let pair = (t.str()?, t.str()?);
+                t.u64()?;
+                t.u8()?;
+                for _ in 0..4 {
+                    t.u64()?;
+                }
+                return Ok(Some(pair));
+            }
+            tag => return Err(anyhow::anyhow!("Invalid command {}", tag)),
+        }
+        Ok(None)
+    }
+
+    /// Copies the outcome of a command trading in `pair`, adding its trades to the
+    /// positions.
+    fn outcome<'a>(
+        t: &mut Transcoder<'a>,
+        pair: Option<(&'a str, &'a str)>,
+        positions: &mut Positions<'a>,
+    ) -> Result<()> {
+        if t.u8()? == 1 {
+            for _ in 0..t.len()? {
+                let (ask_account, bid_account, quantity) = trade(t)?;
+                let (base, numeraire) = pair.ok_or(anyhow::anyhow!("Trades without a market"))?;
+                let quantity = quantity as i64;
+                *positions.entry((bid_account, base, numeraire)).or_default() += quantity;
+                *positions.entry((ask_account, base, numeraire)).or_default() -= quantity;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies a trade, returning its ask and bid accounts and its quantity.
+    fn trade<'a>(t: &mut Transcoder<'a>) -> Result<(&'a str, &'a str, u64)> {
+        t.u64()?;
+        t.u64()?;
+        let (ask_account, bid_account) = (t.str()?, t.str()?);
+        t.u64()?;
+        let quantity = t.u64()?;
+        if t.u8()? == 1 {
+            t.u8()?;
+        }
+        // Client order IDs, then tags
+        for _ in 0..4 {
+            if t.u8()? == 1 {
+                t.str()?;
+            }
+        }
+        // Trade ID and sequence number
+        t.u64()?;
+        t.u64()?;
+        Ok((ask_account, bid_account, quantity))
+    }
+
+    fn snapshot(t: &mut Transcoder<'_>, positions: &Positions<'_>) -> Result<()> {
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            t.u64()?;
+        }
+        for _ in 0..t.len()? {
+            t.str()?;
+            t.str()?;
+            v9::config(t)?;
+            for _side in 0..2 {
+                for _ in 0..t.len()? {
+                    v3::order(t)?;
+                }
+            }
+            t.u64()?;
+        }
+        t.u64()?;
+        let positions: Vec<_> = positions
+            .iter()
+            .filter(|(_, position)| **position != 0)
+            .collect();
+        t.to.len(positions.len());
+        for ((account, base, numeraire), position) in positions {
+            t.to.str(account);
+            t.to.str(base);
+            t.to.str(numeraire);
+            t.to.u64(*position as u64);
+        }
+        Ok(())
+    }
+}
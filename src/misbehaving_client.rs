@@ -0,0 +1,305 @@
+//! A simulation agent that sends the malformed order flow of a buggy client.
+//!
+//! Each tick the `MisbehavingClient` trades once against the best price, so that it has
+//! client order IDs to reuse and filled orders to amend, and then sends one malformed
+//! request picked at random. Every response is logged, so a test can assert that each
+//! request got the typed rejection it deserves and that the exchange was left intact.
+
+use std::{cell::RefCell, rc::Rc};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    market::Pair,
+    matching::Trade,
+    order::{ClientOrderId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    reject::RejectReason,
+    simulation::{Strategy, StrategyContext},
+};
+
+/// A malformed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Post an order under the client order ID of an order already accepted.
+    DuplicateClientOrderId,
+    /// Cancel an order that filled or was never posted.
+    CancelUnknownOrder,
+    /// Amend an order that filled.
+    AmendFilledOrder,
+    /// Post a bid the account cannot pay for.
+    ExceedBalance,
+}
+
+impl Misbehavior {
+    /// The rejection the exchange must answer the request with.
+    pub fn expected_rejection(&self) -> RejectReason {
+        match self {
+            Misbehavior::DuplicateClientOrderId => RejectReason::DuplicateClientOrderId,
+            Misbehavior::CancelUnknownOrder | Misbehavior::AmendFilledOrder => {
+                RejectReason::UnknownOrder
+            }
+            Misbehavior::ExceedBalance => RejectReason::InsufficientBalance,
+        }
+    }
+}
+
+/// How the exchange answered a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Accepted,
+    Rejected(RejectReason),
+    /// Refused with an error that carries no `RejectReason`.
+    Failed(String),
+}
+
+impl From<anyhow::Result<()>> for Response {
+    fn from(result: anyhow::Result<()>) -> Self {
+        match result {
+            Ok(()) => Response::Accepted,
+            Err(error) => match RejectReason::of(&error) {
+                Some(reason) => Response::Rejected(reason),
+                None => Response::Failed(error.to_string()),
+            },
+        }
+    }
+}
+
+/// One malformed request and its response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MisbehaviorOutcome {
+    pub time: u64,
+    pub misbehavior: Misbehavior,
+    pub response: Response,
+}
+
+impl MisbehaviorOutcome {
+    /// Returns true if the request was refused with the rejection it deserves.
+    pub fn is_expected(&self) -> bool {
+        self.response == Response::Rejected(self.misbehavior.expected_rejection())
+    }
+}
+
+/// Where a `MisbehavingClient` logs its requests, shared with whoever inspects them.
+pub type MisbehaviorLog = Rc<RefCell<Vec<MisbehaviorOutcome>>>;
+
+/// A strategy that sends malformed requests to one market.
+///
+/// Its account needs numeraire to trade with, far below `u64::MAX`, and the market needs
+/// asks from other strategies for it to fill against.
+pub struct MisbehavingClient {
+    pair: Pair,
+    rng: StdRng,
+    log: MisbehaviorLog,
+    /// Client order IDs of accepted orders.
+    client_order_ids: Vec<ClientOrderId>,
+    /// Bids that may still rest, with their price and unfilled quantity.
+    open: Vec<(OrderId, Price, u64)>,
+    /// Bids that filled completely, with their price.
+    filled: Vec<(OrderId, Price)>,
+}
+
+impl MisbehavingClient {
+    /// Create a client for `pair` whose random choices are drawn from `seed`
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market the client trades in
+    /// * `seed` - Seed of the client's choices, so that runs can be repeated
+    /// * `log` - Where the client logs its malformed requests
+    pub fn new(pair: Pair, seed: u64, log: MisbehaviorLog) -> Self {
+        Self {
+            pair,
+            rng: StdRng::seed_from_u64(seed),
+            log,
+            client_order_ids: Vec::new(),
+            open: Vec::new(),
+            filled: Vec::new(),
+        }
+    }
+
+    /// Lift one unit of the best ask under a fresh client order ID.
+    fn trade(&mut self, ctx: &mut StrategyContext<'_>) {
+        let Some(ask) = ctx.top_of_book(self.pair).best_ask else {
+            return;
+        };
+        let client_order_id = ClientOrderId::new(format!("c{}", self.client_order_ids.len()));
+        let order = Order {
+            client_order_id: Some(client_order_id.clone()),
+            ..bid(Price::new(ask), 1)
+        };
+        if let Ok(order_id) = ctx.submit_order(self.pair, order) {
+            self.client_order_ids.push(client_order_id);
+            self.open.push((order_id, Price::new(ask), 1));
+        }
+    }
+
+    fn misbehave(&mut self, ctx: &mut StrategyContext<'_>, misbehavior: Misbehavior) -> Response {
+        let pair = self.pair;
+        match misbehavior {
+            Misbehavior::DuplicateClientOrderId => {
+                let index = self.rng.random_range(0..self.client_order_ids.len());
+                let order = Order {
+                    client_order_id: Some(self.client_order_ids[index].clone()),
+                    ..bid(Price::new(1), 1)
+                };
+                ctx.submit_order(pair, order).map(|_| ()).into()
+            }
+            Misbehavior::CancelUnknownOrder => {
+                let (order_id, price) = match self.filled.is_empty() || self.rng.random_bool(0.5) {
+                    true => (OrderId::new(u64::MAX - self.rng.random_range(0..1_000)), 1),
+                    false => {
+                        let (order_id, price) =
+                            self.filled[self.rng.random_range(0..self.filled.len())];
+                        (order_id, price.get())
+                    }
+                };
+                ctx.cancel_order(pair, order_id, Side::Bid, Price::new(price))
+                    .into()
+            }
+            Misbehavior::AmendFilledOrder => {
+                let (order_id, price) = self.filled[self.rng.random_range(0..self.filled.len())];
+                let new_price = Price::new(self.rng.random_range(1..=price.get()));
+                let new_quantity = Quantity::new(self.rng.random_range(1..=3));
+                ctx.amend_order(pair, order_id, Side::Bid, price, new_price, new_quantity)
+                    .into()
+            }
+            Misbehavior::ExceedBalance => {
+                let quantity = ctx.balance(pair.numeraire).saturating_add(1);
+                ctx.submit_order(pair, bid(Price::new(1), quantity))
+                    .map(|_| ())
+                    .into()
+            }
+        }
+    }
+}
+
+impl Strategy for MisbehavingClient {
+    fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+        self.trade(ctx);
+        let mut possible = vec![Misbehavior::CancelUnknownOrder, Misbehavior::ExceedBalance];
+        if !self.client_order_ids.is_empty() {
+            possible.push(Misbehavior::DuplicateClientOrderId);
+        }
+        if !self.filled.is_empty() {
+            possible.push(Misbehavior::AmendFilledOrder);
+        }
+        let misbehavior = possible[self.rng.random_range(0..possible.len())];
+        let response = self.misbehave(ctx, misbehavior);
+        self.log.borrow_mut().push(MisbehaviorOutcome {
+            time: ctx.time(),
+            misbehavior,
+            response,
+        });
+    }
+
+    fn on_fill(&mut self, _ctx: &mut StrategyContext<'_>, pair: Pair, trade: &Trade) {
+        if pair != self.pair {
+            return;
+        }
+        let Some(index) = self
+            .open
+            .iter()
+            .position(|(order_id, ..)| *order_id == trade.bid_order_id)
+        else {
+            return;
+        };
+        let (order_id, price, remaining) = &mut self.open[index];
+        *remaining = remaining.saturating_sub(trade.quantity.get());
+        if *remaining == 0 {
+            self.filled.push((*order_id, *price));
+            self.open.remove(index);
+        }
+    }
+}
+
+/// A bid whose ID, account and timestamp the simulator fills in.
+fn bid(price: Price, quantity: u64) -> Order {
+    Order::new(
+        OrderId::default(),
+        price,
+        Quantity::new(quantity),
+        Side::Bid,
+        Default::default(),
+        Timestamp::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        account::SystemAccount, asset::Asset, exchange::Exchange, market::Market, order::AccountId,
+        simulation::Simulator,
+    };
+
+    use super::*;
+
+    /// Quotes a fresh ask each tick, a tick above the last.
+    struct Quoter {
+        pair: Pair,
+    }
+
+    impl Strategy for Quoter {
+        fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+            let price = Price::new(100 + ctx.time() % 5);
+            ctx.post_order(self.pair, Side::Ask, price, Quantity::new(2))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_malformed_flow_gets_typed_rejects_and_leaves_state_intact() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let client = AccountId::new("client".to_string());
+        let quoter = AccountId::new("quoter".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(client.clone(), pair.numeraire, 1_000_000);
+        exchange.add_balance(quoter.clone(), pair.base, 1_000);
+        let log = MisbehaviorLog::default();
+        let mut simulator = Simulator::new(exchange);
+        simulator.enable_drills(50, 20);
+        simulator.register(quoter.clone(), Box::new(Quoter { pair }));
+        simulator.register(
+            client.clone(),
+            Box::new(MisbehavingClient::new(pair, 3, log.clone())),
+        );
+        simulator.run(200);
+
+        let log = log.borrow();
+        assert_eq!(log.len(), 200);
+        for outcome in log.iter() {
+            assert!(outcome.is_expected(), "{outcome:?}");
+        }
+        for misbehavior in [
+            Misbehavior::DuplicateClientOrderId,
+            Misbehavior::CancelUnknownOrder,
+            Misbehavior::AmendFilledOrder,
+            Misbehavior::ExceedBalance,
+        ] {
+            assert!(log.iter().any(|outcome| outcome.misbehavior == misbehavior));
+        }
+
+        // Nothing was created or lost, and the journal replays to the same state
+        let exchange = &simulator.exchange;
+        let accounts = [client, quoter, SystemAccount::Fees.id()];
+        for (asset, total) in [(pair.numeraire, 1_000_000), (pair.base, 1_000)] {
+            let held: u64 = accounts
+                .iter()
+                .map(|account| {
+                    exchange.get_balance(account.clone(), asset).unwrap_or(0)
+                        + exchange.locked_balance(account, asset)
+                })
+                .sum();
+            assert_eq!(held, total);
+        }
+        let book = exchange.orderbook(pair).unwrap();
+        assert!(book.bids.is_empty());
+        assert_eq!(simulator.drills().len(), 4);
+        for drill in simulator.drills() {
+            assert!(drill.is_clean(), "{drill:?}");
+        }
+    }
+}
@@ -1,4 +1,9 @@
 use std::ops::{Add, Sub};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::matching::SelfTradePrevention;
 
 /// Represents the side of an order - either a bid (buy) or ask (sell)
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
@@ -8,8 +13,80 @@ pub enum Side {
     Ask,
 }
 
+impl Side {
+    /// The side an order on this side trades against.
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Bid => Side::Ask,
+            Side::Ask => Side::Bid,
+        }
+    }
+}
+
+/// How an order is priced
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum OrderType {
+    /// Matches at its price or better; the remainder rests in the book.
+    #[default]
+    Limit,
+    /// Matches against the opposite side regardless of price; the remainder is rejected.
+    Market,
+}
+
+/// How long an order stays in the book
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum TimeInForce {
+    /// Good till cancelled: the remainder rests until filled or cancelled.
+    #[default]
+    Gtc,
+    /// Immediate or cancel: matches what it can on arrival and discards the remainder.
+    Ioc,
+}
+
+/// The price a stop order's stop price is compared with
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum StopTrigger {
+    /// The prices of the market's trades.
+    #[default]
+    LastTrade,
+    /// The index price supplied to the market from outside, so that stops work in markets
+    /// that rarely trade.
+    Index,
+}
+
+/// The book price a pegged order tracks
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PegReference {
+    BestBid,
+    BestAsk,
+    /// Midpoint of the best bid and ask, rounded down.
+    Mid,
+}
+
+/// Pegging instructions of an order
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Peg {
+    pub reference: PegReference,
+    /// Added to the reference price.
+    pub offset: i64,
+    /// The highest price of a pegged bid or the lowest price of a pegged ask.
+    pub cap: Option<Price>,
+}
+
+impl Peg {
+    /// The price of a pegged order on `side` for the given reference price.
+    pub fn price(&self, side: Side, reference: Price) -> Price {
+        let price = Price::new(reference.get().saturating_add_signed(self.offset));
+        match (side, self.cap) {
+            (Side::Bid, Some(cap)) => price.min(cap),
+            (Side::Ask, Some(cap)) => price.max(cap),
+            (_, None) => price,
+        }
+    }
+}
+
 /// Represents a single order in the orderbook
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Order {
     pub id: OrderId,
     pub price: Price,
@@ -17,10 +94,45 @@ pub struct Order {
     pub side: Side,
     pub account_id: AccountId,
     pub timestamp: Timestamp,
+    pub order_type: OrderType,
+    /// If unset, the account's default applies, and good-till-cancelled without one.
+    pub time_in_force: Option<TimeInForce>,
+    /// Post-only: the order is rejected rather than trade on arrival, so it only ever adds
+    /// liquidity. If unset, the account's default applies.
+    pub post_only: Option<bool>,
+    /// If set, the order is removed by the first expiry sweep at or after this time.
+    pub expires_at: Option<Timestamp>,
+    /// If set, the order waits off-book until the price selected by `stop_trigger` reaches
+    /// this price. It is then processed as a market or limit order depending on its type.
+    pub stop_price: Option<Price>,
+    /// What the stop price is compared with. Ignored without a stop price.
+    pub stop_trigger: StopTrigger,
+    /// If set, the order's price floats with the book and `price` is its current peg price.
+    pub peg: Option<Peg>,
+    /// If set, the order only executes if at least this quantity matches immediately.
+    pub min_qty: Option<Quantity>,
+    /// All-or-none: the order only trades against an order that fills it entirely. While it
+    /// rests, smaller aggressors skip it.
+    pub all_or_none: bool,
+    /// Reduce-only: the order is capped to the account's position in the market when it is
+    /// posted, so that it can only shrink the position, never flip it.
+    pub reduce_only: bool,
+    /// For market orders, the worst price the sweep may reach: bids stop above it and asks
+    /// below it, and the remainder is cancelled.
+    pub protection_price: Option<Price>,
+    /// The caller's own ID for the order, echoed in its trades. Unique per account among
+    /// recent orders.
+    pub client_order_id: Option<ClientOrderId>,
+    /// An opaque label, such as the strategy that sent the order, echoed in its trades.
+    pub tag: Option<OrderTag>,
+    /// What to do if the order would trade with a resting order of its account. If unset,
+    /// the account's self-trade prevention applies.
+    pub self_trade_prevention: Option<SelfTradePrevention>,
 }
 
 impl Order {
-    // TODO: OrderId should be generated by the exchange/market
+    /// Creates a limit order with an ID chosen by the caller. To have the exchange assign the
+    /// ID instead, post the order with `Exchange::place_order`.
     pub fn new(
         id: OrderId,
         price: Price,
@@ -36,17 +148,275 @@ impl Order {
             side,
             account_id,
             timestamp,
+            order_type: OrderType::Limit,
+            time_in_force: None,
+            post_only: None,
+            expires_at: None,
+            stop_price: None,
+            stop_trigger: StopTrigger::LastTrade,
+            peg: None,
+            min_qty: None,
+            all_or_none: false,
+            reduce_only: false,
+            protection_price: None,
+            client_order_id: None,
+            tag: None,
+            self_trade_prevention: None,
+        }
+    }
+
+    /// Creates a market order. Its price is ignored by matching.
+    pub fn market(
+        id: OrderId,
+        quantity: Quantity,
+        side: Side,
+        account_id: AccountId,
+        timestamp: Timestamp,
+    ) -> Self {
+        Self {
+            order_type: OrderType::Market,
+            ..Self::new(id, Price::new(0), quantity, side, account_id, timestamp)
+        }
+    }
+
+    /// Returns true if the unfilled remainder of the order rests in the book.
+    pub fn rests(&self) -> bool {
+        self.order_type == OrderType::Limit
+            && self.time_in_force.unwrap_or_default() == TimeInForce::Gtc
+    }
+
+    /// Returns true if the order must not trade on arrival.
+    pub fn is_post_only(&self) -> bool {
+        self.post_only == Some(true)
+    }
+
+    /// Returns true if the order has expired by `now`.
+    pub fn is_expired(&self, now: Timestamp) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns true if a stop order is triggered by a trade at `last_price`: bid stops trigger
+    /// at or above their stop price, ask stops at or below it. Orders without a stop price are
+    /// always triggered.
+    pub fn is_triggered(&self, last_price: Price) -> bool {
+        match (self.stop_price, self.side) {
+            (None, _) => true,
+            (Some(stop), Side::Bid) => last_price >= stop,
+            (Some(stop), Side::Ask) => last_price <= stop,
         }
     }
+
+    /// Returns true if this resting order can trade against an aggressor with `remaining`
+    /// quantity left. All-or-none orders are skipped unless they can be filled entirely.
+    pub fn can_fill_against(&self, remaining: u64) -> bool {
+        !self.all_or_none || self.quantity.get() <= remaining
+    }
+
+    /// Returns true if the order crosses a resting order at `price` on the opposite side.
+    pub fn crosses(&self, price: Price) -> bool {
+        match (self.order_type, self.side) {
+            (OrderType::Market, Side::Bid) => self.protection_price.is_none_or(|p| price <= p),
+            (OrderType::Market, Side::Ask) => self.protection_price.is_none_or(|p| price >= p),
+            (OrderType::Limit, Side::Bid) => price <= self.price,
+            (OrderType::Limit, Side::Ask) => price >= self.price,
+        }
+    }
+}
+
+/// Builds an order from named, typed fields, validating it on `build`.
+///
+/// Only the ID, side and account are required up front. Orders default to limit orders at
+/// timestamp zero with no optional instructions, which leaves the time in force, post-only
+/// flag and self-trade prevention to the account's defaults.
+#[derive(Debug, Clone)]
+pub struct OrderBuilder {
+    order: Order,
 }
 
+impl OrderBuilder {
+    pub fn new(id: OrderId, side: Side, account_id: AccountId) -> Self {
+        Self {
+            order: Order {
+                id,
+                side,
+                account_id,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn price(mut self, price: Price) -> Self {
+        self.order.price = price;
+        self
+    }
+
+    pub fn quantity(mut self, quantity: Quantity) -> Self {
+        self.order.quantity = quantity;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.order.timestamp = timestamp;
+        self
+    }
+
+    /// Make the order a market order. Its price is ignored.
+    pub fn market(mut self) -> Self {
+        self.order.order_type = OrderType::Market;
+        self
+    }
+
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.order.time_in_force = Some(time_in_force);
+        self
+    }
+
+    /// Make the order post-only, or not even if its account defaults to post-only.
+    pub fn post_only(mut self, post_only: bool) -> Self {
+        self.order.post_only = Some(post_only);
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Timestamp) -> Self {
+        self.order.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn stop_price(mut self, stop_price: Price) -> Self {
+        self.order.stop_price = Some(stop_price);
+        self
+    }
+
+    /// Compare the stop price with the market's index price instead of its trades.
+    pub fn index_trigger(mut self) -> Self {
+        self.order.stop_trigger = StopTrigger::Index;
+        self
+    }
+
+    pub fn peg(mut self, peg: Peg) -> Self {
+        self.order.peg = Some(peg);
+        self
+    }
+
+    pub fn min_qty(mut self, min_qty: Quantity) -> Self {
+        self.order.min_qty = Some(min_qty);
+        self
+    }
+
+    pub fn all_or_none(mut self) -> Self {
+        self.order.all_or_none = true;
+        self
+    }
+
+    pub fn reduce_only(mut self) -> Self {
+        self.order.reduce_only = true;
+        self
+    }
+
+    pub fn protection_price(mut self, protection_price: Price) -> Self {
+        self.order.protection_price = Some(protection_price);
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: ClientOrderId) -> Self {
+        self.order.client_order_id = Some(client_order_id);
+        self
+    }
+
+    pub fn tag(mut self, tag: OrderTag) -> Self {
+        self.order.tag = Some(tag);
+        self
+    }
+
+    pub fn self_trade_prevention(mut self, stp: SelfTradePrevention) -> Self {
+        self.order.self_trade_prevention = Some(stp);
+        self
+    }
+
+    /// Returns the order, or an error if its fields are inconsistent.
+    ///
+    /// Only checks the order itself; markets may still reject it when it is posted.
+    pub fn build(self) -> Result<Order> {
+        let order = self.order;
+        let is_market = order.order_type == OrderType::Market;
+        if order.quantity.get() == 0 {
+            return Err(anyhow::anyhow!("Order quantity must be positive"));
+        }
+        if !is_market && order.peg.is_none() && order.price.get() == 0 {
+            return Err(anyhow::anyhow!("Limit orders need a price"));
+        }
+        if order
+            .min_qty
+            .is_some_and(|min_qty| min_qty > order.quantity)
+        {
+            return Err(anyhow::anyhow!("Minimum quantity exceeds order quantity"));
+        }
+        if order.protection_price.is_some() && !is_market {
+            return Err(anyhow::anyhow!(
+                "Protection prices only apply to market orders"
+            ));
+        }
+        if order.peg.is_some() && (!order.rests() || order.stop_price.is_some()) {
+            return Err(anyhow::anyhow!(
+                "Pegged orders must be good-till-cancelled limit orders"
+            ));
+        }
+        if order.is_post_only() && !order.rests() {
+            return Err(anyhow::anyhow!(
+                "Post-only orders must be good-till-cancelled limit orders"
+            ));
+        }
+        if order.is_expired(order.timestamp) {
+            return Err(anyhow::anyhow!("Order already expired"));
+        }
+        Ok(order)
+    }
+}
+
+/// The account an order or balance belongs to.
+///
+/// The ID is shared rather than copied, so cloning one, as every trade and settlement does,
+/// never allocates.
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct AccountId(String);
+pub struct AccountId(Arc<str>);
 
 impl AccountId {
+    pub fn new(id: String) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// An order ID assigned by the client rather than the exchange.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientOrderId(String);
+
+impl ClientOrderId {
     pub fn new(id: String) -> Self {
         Self(id)
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A label the caller attaches to an order, e.g. to attribute its fills to a strategy.
+/// Tags mean nothing to the exchange and need not be unique.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrderTag(String);
+
+impl OrderTag {
+    pub fn new(tag: String) -> Self {
+        Self(tag)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -56,6 +426,20 @@ impl OrderId {
     pub fn new(id: u64) -> Self {
         Self(id)
     }
+
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Identifies a group of orders submitted atomically across markets.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupId(u64);
+
+impl GroupId {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -123,34 +507,122 @@ impl Timestamp {
     pub fn new(timestamp: u64) -> Self {
         Self(timestamp)
     }
+
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Create a good-till-cancelled limit order from plain values.
+///
+/// The ID, price, quantity and timestamp must be `u64`s and the side a `Side`; anything
+/// else fails to compile. The account accepts anything with a `to_string`.
+///
+/// # Example
+///
+/// ```
+/// use exchanges::create_order;
+/// use exchanges::order::{AccountId, Quantity, Side};
+///
+/// let order = create_order!(1, 100, 10, Side::Bid, "trader1", 1);
+/// assert_eq!(order.quantity, Quantity::new(10));
+/// assert_eq!(order.account_id, AccountId::new("trader1".to_string()));
+/// ```
+///
+/// ```compile_fail
+/// use exchanges::create_order;
+/// use exchanges::order::Side;
+///
+/// // Prices are integers
+/// let order = create_order!(1, 100.5, 10, Side::Bid, "trader1", 1);
+/// ```
+///
+/// # Arguments
+///
+/// * `id` - The unique identifier for the order.
+/// * `price` - The price of the order.
+/// * `qty` - The quantity of the order.
+/// * `side` - The side of the order.
+/// * `account` - The account of the order.
+/// * `ts` - The timestamp of the order.
+#[macro_export]
+macro_rules! create_order {
+    ($id:expr, $price:expr, $qty:expr, $side:expr, $account:expr, $ts:expr) => {{
+        let (id, price, qty, ts): (u64, u64, u64, u64) = ($id, $price, $qty, $ts);
+        let side: $crate::order::Side = $side;
+        $crate::order::Order::new(
+            $crate::order::OrderId::new(id),
+            $crate::order::Price::new(price),
+            $crate::order::Quantity::new(qty),
+            side,
+            $crate::order::AccountId::new($account.to_string()),
+            $crate::order::Timestamp::new(ts),
+        )
+    }};
 }
 
-// /// Create an order with the given parameters.
-// ///
-// /// # Example
-// ///
-// /// ```
-// /// let order = order!(1, 100, 10, Side::Bid, "trader1", 1);
-// /// ```
-// ///
-// /// # Arguments
-// ///
-// /// * `id` - The unique identifier for the order.
-// /// * `price` - The price of the order.
-// /// * `qty` - The quantity of the order.
-// /// * `side` - The side of the order.
-// /// * `account` - The account of the order.
-// /// * `ts` - The timestamp of the order.
-// #[macro_export]
-// macro_rules! create_order {
-//     ($id:expr, $price:expr, $qty:expr, $side:expr, $account:expr, $ts:expr) => {
-//         crate::order::Order {
-//             id: crate::order::OrderId($id),
-//             price: crate::order::Price($price),
-//             quantity: crate::order::Quantity($qty),
-//             side: $side,
-//             account: $account.to_string(),
-//             timestamp: crate::order::Timestamp($ts),
-//         }
-//     };
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_builder_defaults_and_validation() {
+        let builder = || {
+            OrderBuilder::new(
+                OrderId::new(1),
+                Side::Ask,
+                AccountId::new("alice".to_string()),
+            )
+            .quantity(Quantity::new(5))
+        };
+        assert_eq!(
+            builder().price(Price::new(100)).build().unwrap(),
+            Order::new(
+                OrderId::new(1),
+                Price::new(100),
+                Quantity::new(5),
+                Side::Ask,
+                AccountId::new("alice".to_string()),
+                Timestamp::new(0),
+            )
+        );
+        let market = builder()
+            .market()
+            .protection_price(Price::new(90))
+            .build()
+            .unwrap();
+        assert_eq!(market.order_type, OrderType::Market);
+
+        // No price, no quantity, or inconsistent instructions
+        assert!(builder().build().is_err());
+        assert!(
+            builder()
+                .price(Price::new(100))
+                .quantity(Quantity::new(0))
+                .build()
+                .is_err()
+        );
+        assert!(
+            builder()
+                .price(Price::new(100))
+                .min_qty(Quantity::new(6))
+                .build()
+                .is_err()
+        );
+        assert!(
+            builder()
+                .price(Price::new(100))
+                .protection_price(Price::new(90))
+                .build()
+                .is_err()
+        );
+        assert!(
+            builder()
+                .price(Price::new(100))
+                .timestamp(Timestamp::new(5))
+                .expires_at(Timestamp::new(5))
+                .build()
+                .is_err()
+        );
+    }
+}
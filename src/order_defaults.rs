@@ -0,0 +1,321 @@
+//! Per-account defaults for order instructions.
+//!
+//! Orders may leave their time in force and post-only flag unset, and the exchange fills
+//! them in from the account's `OrderDefaults` when the order is submitted, so clients that
+//! always send the same instructions do not have to repeat them. Unset instructions of
+//! accounts without a default keep their usual meaning: good-till-cancelled, and not
+//! post-only. The account's self-trade prevention, set with
+//! `Exchange::set_self_trade_prevention`, likewise applies to orders that do not set their
+//! own.
+//!
+//! An account can also choose the asset its orders pay flat fees in. Orders have no fee
+//! asset of their own, so it applies to all of them: their flat fees are priced in it at
+//! the cross rate instead of in the market's numeraire, and collected from it.
+//!
+//! Defaults are not part of snapshots, like self-trade prevention, so they have to be set
+//! again on an exchange restored from one.
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    exchange::Exchange,
+    market::Pair,
+    order::{AccountId, Order, TimeInForce},
+};
+
+/// Instructions applied to an account's orders that leave them unset.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OrderDefaults {
+    pub time_in_force: Option<TimeInForce>,
+    pub post_only: Option<bool>,
+    /// The asset flat fees are paid in, or `None` for the numeraire of each market.
+    pub fee_asset: Option<Asset>,
+}
+
+impl OrderDefaults {
+    /// Fill in the instructions the order leaves unset.
+    pub fn apply(&self, order: &mut Order) {
+        order.time_in_force = order.time_in_force.or(self.time_in_force);
+        order.post_only = order.post_only.or(self.post_only);
+    }
+}
+
+impl Exchange {
+    /// Set the instructions applied to an account's orders that leave them unset
+    ///
+    /// Applies to the orders the account submits from then on; orders already resting or
+    /// waiting for their stop keep the instructions they were submitted with.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account, which must exist
+    /// * `defaults` - The new defaults, replacing the previous ones
+    pub fn set_order_defaults(
+        &mut self,
+        account_id: &AccountId,
+        defaults: OrderDefaults,
+    ) -> Result<()> {
+        self.account_manager
+            .set_order_defaults(account_id, defaults)
+    }
+
+    /// Returns the order defaults of an account, empty if it has none or does not exist.
+    pub fn order_defaults(&self, account_id: &AccountId) -> OrderDefaults {
+        self.account_manager
+            .get_account(account_id)
+            .map(|account| account.order_defaults)
+            .unwrap_or_default()
+    }
+
+    /// Fill in the instructions an order leaves unset from its account's defaults.
+    pub(crate) fn apply_order_defaults(&self, order: &mut Order) {
+        self.order_defaults(&order.account_id).apply(order);
+    }
+
+    /// The asset an account's orders in a market pay flat fees in.
+    pub(crate) fn fee_asset(&self, account_id: &AccountId, pair: Pair) -> Asset {
+        self.order_defaults(account_id)
+            .fee_asset
+            .unwrap_or(pair.numeraire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        account::SystemAccount,
+        codec::{Decoder, Encoder},
+        market::{FeeSchedule, FlatFee, Market, MarketConfig},
+        matching::SelfTradePrevention,
+        order::{OrderBuilder, OrderId, Price, Quantity, Side, Timestamp},
+        reject::RejectReason,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_defaults_fill_in_unset_instructions() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        assert!(
+            exchange
+                .set_order_defaults(&alice, OrderDefaults::default())
+                .is_err()
+        );
+        exchange.add_balance(alice.clone(), pair.base, 10);
+        exchange.add_balance(alice.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(bob.clone(), pair.numeraire, 1_000);
+        let defaults = OrderDefaults {
+            time_in_force: Some(TimeInForce::Ioc),
+            post_only: Some(true),
+            fee_asset: None,
+        };
+        exchange.set_order_defaults(&alice, defaults).unwrap();
+        assert_eq!(exchange.order_defaults(&alice), defaults);
+        let order = |id: u64, price: u64, side: Side, account: &AccountId| {
+            OrderBuilder::new(OrderId::new(id), side, account.clone())
+                .price(Price::new(price))
+                .quantity(Quantity::new(1))
+                .timestamp(Timestamp::new(id))
+        };
+
+        // Alice defaults to post-only, which contradicts her default time in force
+        let error = exchange
+            .post_order(order(1, 100, Side::Ask, &alice).build().unwrap(), pair)
+            .unwrap_err();
+        assert_eq!(
+            RejectReason::of(&error),
+            Some(RejectReason::BadPostOnlyOrder)
+        );
+        let ask = order(2, 100, Side::Ask, &alice)
+            .time_in_force(TimeInForce::Gtc)
+            .build()
+            .unwrap();
+        exchange.post_order(ask.clone(), pair).unwrap();
+        let resting = exchange.markets[&pair]
+            .resting_order(ask.id, Side::Ask, ask.price)
+            .unwrap();
+        assert_eq!(resting.post_only, Some(true));
+        // Bob has no defaults, so his order is a plain limit order and rests
+        let bid = order(3, 90, Side::Bid, &bob).build().unwrap();
+        exchange.post_order(bid, pair).unwrap();
+
+        // A post-only order that would trade is rejected; opting out, it trades and its
+        // remainder is discarded by Alice's default time in force
+        let crossing = order(4, 90, Side::Ask, &alice)
+            .time_in_force(TimeInForce::Gtc)
+            .build()
+            .unwrap();
+        let error = exchange.post_order(crossing, pair).unwrap_err();
+        assert_eq!(
+            RejectReason::of(&error),
+            Some(RejectReason::PostOnlyWouldTrade)
+        );
+        let taker = OrderBuilder::new(OrderId::new(5), Side::Ask, alice.clone())
+            .price(Price::new(90))
+            .quantity(Quantity::new(2))
+            .post_only(false)
+            .build()
+            .unwrap();
+        let report = exchange.post_order(taker, pair).unwrap();
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.remaining, Quantity::new(0));
+
+        // An order's own self-trade prevention overrides the account's
+        exchange.set_self_trade_prevention(alice.clone(), Some(SelfTradePrevention::CancelNewest));
+        let own_bid = order(6, 100, Side::Bid, &alice)
+            .post_only(false)
+            .self_trade_prevention(SelfTradePrevention::CancelOldest)
+            .build()
+            .unwrap();
+        let report = exchange.post_order(own_bid.clone(), pair).unwrap();
+        assert!(report.fills.is_empty());
+        assert!(
+            exchange.markets[&pair]
+                .resting_order(ask.id, Side::Ask, ask.price)
+                .is_none()
+        );
+
+        // Set and unset instructions survive the codec
+        for order in [ask, own_bid] {
+            let mut encoder = Encoder::new();
+            encoder.order(&order);
+            let encoded = encoder.finish();
+            assert_eq!(Decoder::new(&encoded).order().unwrap(), order);
+        }
+    }
+
+    #[test]
+    fn test_flat_fees_paid_in_the_fee_asset() {
+        let (usd, btc, fee_token) = (Asset::new("USD"), Asset::new("BTC"), Asset::new("EXC"));
+        let btc_usd = Pair {
+            numeraire: usd,
+            base: btc,
+        };
+        let token_usd = Pair {
+            numeraire: usd,
+            base: fee_token,
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            btc_usd,
+            MarketConfig {
+                fees: FeeSchedule {
+                    flat_fee: Some(FlatFee {
+                        asset: usd,
+                        amount: 100,
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ));
+        exchange.add_market(Market::new(token_usd));
+        exchange.add_balance(account("quoter"), usd, 1);
+        exchange.add_balance(account("quoter"), fee_token, 1);
+        exchange.add_balance(account("maker"), btc, 1);
+        exchange.add_balance(account("maker"), usd, 1_000);
+        exchange.add_balance(account("taker"), usd, 1_000);
+        exchange.add_balance(account("taker"), btc, 1);
+        exchange.add_balance(account("taker"), fee_token, 99);
+        let defaults = OrderDefaults {
+            fee_asset: Some(fee_token),
+            ..OrderDefaults::default()
+        };
+        exchange
+            .set_order_defaults(&account("taker"), defaults)
+            .unwrap();
+        let order = |id: u64, price: u64, side: Side, name: &str| {
+            OrderBuilder::new(OrderId::new(id), side, account(name))
+                .price(Price::new(price))
+                .quantity(Quantity::new(1))
+                .timestamp(Timestamp::new(id))
+                .build()
+                .unwrap()
+        };
+
+        // Without a rate for the fee token the fee cannot be priced in it
+        let error = exchange
+            .post_order(order(1, 1_000, Side::Bid, "taker"), btc_usd)
+            .unwrap_err();
+        assert_eq!(RejectReason::of(&error), Some(RejectReason::NoFlatFeeRate));
+        // 1 EXC is worth 2 USD, so the fee is 50 EXC
+        exchange
+            .post_order(order(2, 1, Side::Bid, "quoter"), token_usd)
+            .unwrap();
+        exchange
+            .post_order(order(3, 3, Side::Ask, "quoter"), token_usd)
+            .unwrap();
+
+        // A bid that rests gets its reserved fee back
+        exchange
+            .post_order(order(4, 900, Side::Bid, "taker"), btc_usd)
+            .unwrap();
+        assert_eq!(
+            exchange.get_balance(account("taker"), fee_token).unwrap(),
+            99
+        );
+        exchange
+            .cancel_order(OrderId::new(4), Price::new(900), Side::Bid, btc_usd)
+            .unwrap();
+        exchange
+            .post_order(order(5, 1_000, Side::Ask, "maker"), btc_usd)
+            .unwrap();
+        exchange
+            .post_order(order(6, 1_000, Side::Bid, "taker"), btc_usd)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("taker"), usd).unwrap(), 0);
+        assert_eq!(
+            exchange.get_balance(account("taker"), fee_token).unwrap(),
+            49
+        );
+        // The treasury holds no USD to convert it, so the fees account keeps the token
+        let fees = SystemAccount::Fees.id();
+        assert_eq!(exchange.get_balance(fees.clone(), fee_token).unwrap(), 50);
+
+        // Asks reserve the fee too, rather than paying it from their USD proceeds
+        exchange
+            .post_order(order(7, 800, Side::Bid, "maker"), btc_usd)
+            .unwrap();
+        let error = exchange
+            .post_order(order(8, 800, Side::Ask, "taker"), btc_usd)
+            .unwrap_err();
+        assert_eq!(
+            RejectReason::of(&error),
+            Some(RejectReason::InsufficientBalance)
+        );
+        assert_eq!(exchange.get_balance(account("taker"), btc).unwrap(), 2);
+        exchange.add_balance(account("taker"), fee_token, 1);
+        exchange
+            .post_order(order(9, 800, Side::Ask, "taker"), btc_usd)
+            .unwrap();
+        assert_eq!(exchange.get_balance(account("taker"), usd).unwrap(), 800);
+        assert_eq!(
+            exchange.get_balance(account("taker"), fee_token).unwrap(),
+            0
+        );
+        assert_eq!(exchange.get_balance(fees, fee_token).unwrap(), 100);
+        // The token leg would move the rate the other leg's fee is priced at, so they cannot
+        // be grouped
+        let error = exchange
+            .post_order_group(vec![
+                (order(10, 2, Side::Bid, "taker"), token_usd),
+                (order(11, 700, Side::Bid, "taker"), btc_usd),
+            ])
+            .unwrap_err();
+        assert!(error.to_string().contains("cross rate"));
+        assert!(
+            exchange.markets[&token_usd]
+                .resting_order(OrderId::new(10), Side::Bid, Price::new(2))
+                .is_none()
+        );
+    }
+}
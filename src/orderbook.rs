@@ -1,6 +1,64 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, btree_map};
+use std::ops::ControlFlow;
 
-use crate::order::{NegatedPrice, Order, OrderId, Price, Quantity, Side};
+use crate::ladder::{LadderIter, PriceLadder};
+use crate::order::{NegatedPrice, Order, OrderId, PegReference, Price, Quantity, Side};
+
+/// Storage backend used by an `OrderBook`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BookBackend {
+    /// Price levels kept in a `BTreeMap`. Supports any price.
+    #[default]
+    BTree,
+    /// Direct-indexed price ladder covering `num_ticks` prices from `min_price` in steps of
+    /// `tick_size`. Only prices on the ladder can be stored, but best-price lookups and level
+    /// access are constant time, which makes it much faster for dense books.
+    Ladder {
+        min_price: Price,
+        tick_size: u64,
+        num_ticks: usize,
+    },
+}
+
+impl BookBackend {
+    /// Returns true if an order at this price can rest in a book using this backend.
+    pub fn supports_price(&self, price: Price) -> bool {
+        match *self {
+            BookBackend::BTree => true,
+            BookBackend::Ladder {
+                min_price,
+                tick_size,
+                num_ticks,
+            } => {
+                let Some(offset) = price.get().checked_sub(min_price.get()) else {
+                    return false;
+                };
+                offset % tick_size == 0 && offset / tick_size < num_ticks as u64
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Levels {
+    BTree {
+        bids: BTreeMap<NegatedPrice, Vec<Order>>, // negated price -> orders (ascending)
+        asks: BTreeMap<Price, Vec<Order>>,        // price -> orders (ascending)
+    },
+    Ladder {
+        bids: PriceLadder,
+        asks: PriceLadder,
+    },
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels::BTree {
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+}
 
 /// A double-sided orderbook that maintains sorted bids and asks
 ///
@@ -8,44 +66,61 @@ use crate::order::{NegatedPrice, Order, OrderId, Price, Quantity, Side};
 /// Asks are stored with natural prices to maintain ascending order (lowest first)
 #[derive(Debug, Default)]
 pub struct OrderBook {
-    bids: BTreeMap<NegatedPrice, Vec<Order>>, // negated price -> orders (ascending)
-    asks: BTreeMap<Price, Vec<Order>>,        // price -> orders (ascending)
+    levels: Levels,
 }
 
 impl OrderBook {
     /// Creates a new empty orderbook
     pub fn new() -> Self {
-        Self {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        }
+        Self::default()
+    }
+
+    /// Creates a new empty orderbook using the given storage backend
+    pub fn with_backend(backend: BookBackend) -> Self {
+        let levels = match backend {
+            BookBackend::BTree => Levels::default(),
+            BookBackend::Ladder {
+                min_price,
+                tick_size,
+                num_ticks,
+            } => Levels::Ladder {
+                bids: PriceLadder::new(min_price, tick_size, num_ticks),
+                asks: PriceLadder::new(min_price, tick_size, num_ticks),
+            },
+        };
+        Self { levels }
     }
 
     /// Inserts a new order into the orderbook
     ///
     /// For bids, the price is negated to maintain descending order
     /// For asks, the price is stored as-is to maintain ascending order
+    ///
+    /// Within a price level the order is placed by `(timestamp, id)`, so re-inserted orders
+    /// (amends, replenishments) land at the position their timestamp entitles them to.
+    ///
+    /// Panics if the book uses a ladder backend and the price is not on the ladder.
     pub fn insert_order(&mut self, order: Order) {
-        match order.side {
-            Side::Bid => {
-                let mut bid_order = order;
-                let negated_price: NegatedPrice = NegatedPrice::new(bid_order.price.get());
+        match &mut self.levels {
+            Levels::BTree { bids, asks } => match order.side {
+                Side::Bid => {
+                    let mut bid_order = order;
+                    let negated_price: NegatedPrice = NegatedPrice::new(bid_order.price.get());
 
-                // Update the order price to the negated price
-                bid_order.price = negated_price.to_price();
+                    // Update the order price to the negated price
+                    bid_order.price = negated_price.to_price();
 
-                // Insert the order into the orderbook
-                self.bids
-                    .entry(negated_price)
-                    .or_insert_with(Vec::new)
-                    .push(bid_order);
-            }
-            Side::Ask => {
-                self.asks
-                    .entry(order.price)
-                    .or_insert_with(Vec::new)
-                    .push(order);
-            }
+                    // Insert the order into the orderbook
+                    insert_by_priority(bids.entry(negated_price).or_default(), bid_order);
+                }
+                Side::Ask => {
+                    insert_by_priority(asks.entry(order.price).or_default(), order);
+                }
+            },
+            Levels::Ladder { bids, asks } => match order.side {
+                Side::Bid => bids.insert(order),
+                Side::Ask => asks.insert(order),
+            },
         }
     }
 
@@ -54,63 +129,173 @@ impl OrderBook {
     /// For bids, the price must be provided in its original form (not negated)
     /// Returns the removed order if found, None otherwise
     pub fn remove_order(&mut self, order_id: OrderId, side: Side, price: Price) -> Option<Order> {
-        let orders = match side {
-            Side::Bid => self.bids.get_mut(&NegatedPrice::from_price(price))?,
-            Side::Ask => self.asks.get_mut(&price)?,
-        };
+        match &mut self.levels {
+            Levels::BTree { bids, asks } => {
+                let orders = match side {
+                    Side::Bid => bids.get_mut(&NegatedPrice::from_price(price))?,
+                    Side::Ask => asks.get_mut(&price)?,
+                };
 
-        if let Some(pos) = orders.iter().position(|o| o.id == order_id) {
-            // Remove the order from the orderbook
-            let order = orders.remove(pos);
+                let pos = orders.iter().position(|o| o.id == order_id)?;
+                // Remove the order from the orderbook
+                let order = orders.remove(pos);
 
-            // If there are no more orders at this price, remove the price from the orderbook
-            if orders.is_empty() {
-                match order.side {
-                    Side::Bid => self.bids.remove(&NegatedPrice::from_price(order.price)),
-                    Side::Ask => self.asks.remove(&order.price),
+                // If there are no more orders at this price, remove the price from the orderbook
+                if orders.is_empty() {
+                    match order.side {
+                        Side::Bid => bids.remove(&NegatedPrice::from_price(order.price)),
+                        Side::Ask => asks.remove(&order.price),
+                    };
+                }
+                Some(order)
+            }
+            Levels::Ladder { bids, asks } => {
+                let ladder = match side {
+                    Side::Bid => bids,
+                    Side::Ask => asks,
                 };
+                let orders = ladder.level_mut(price)?;
+                let pos = orders.iter().position(|o| o.id == order_id)?;
+                let order = orders.remove(pos);
+                ladder.release_if_empty(price);
+                Some(order)
             }
-            Some(order)
-        } else {
-            None
         }
     }
 
-    /// Updates the quantity of an order in the orderbook
-    pub fn update_order_quantity(&mut self, order_id: OrderId, side: Side, new_qty: Quantity) {
-        // Check bids first
-        match side {
-            Side::Bid => {
-                for orders_at_price in self.bids.values_mut() {
-                    if let Some(order) = orders_at_price.iter_mut().find(|o| o.id == order_id) {
-                        order.quantity = new_qty;
-                        break;
+    /// Shrinks a resting order in place, keeping its position in the queue
+    ///
+    /// For bids, the price must be provided in its original form (not negated).
+    /// Returns the order as it was before, or None if it was not found or the new quantity is
+    /// zero or not below its current quantity.
+    pub fn reduce_order(
+        &mut self,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Option<Order> {
+        let orders = match (&mut self.levels, side) {
+            (Levels::BTree { bids, .. }, Side::Bid) => {
+                bids.get_mut(&NegatedPrice::from_price(price))?
+            }
+            (Levels::BTree { asks, .. }, Side::Ask) => asks.get_mut(&price)?,
+            (Levels::Ladder { bids, .. }, Side::Bid) => bids.level_mut(price)?,
+            (Levels::Ladder { asks, .. }, Side::Ask) => asks.level_mut(price)?,
+        };
+        let order = orders.iter_mut().find(|o| o.id == order_id)?;
+        if quantity.get() == 0 || quantity >= order.quantity {
+            return None;
+        }
+        let previous = order.clone();
+        order.quantity = quantity;
+        Some(previous)
+    }
+
+    /// Walks the orders of one price level once, keeping those `keep` returns true for
+    ///
+    /// `keep` may change the orders it keeps, e.g. their quantity. The level is dropped if no
+    /// order is left. Bid prices are given in their original form (not negated); the orders
+    /// `keep` sees carry their stored price.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The side of the level
+    /// * `price` - The price of the level
+    /// * `keep` - Called with each order of the level in priority order
+    pub fn retain_level(&mut self, side: Side, price: Price, keep: impl FnMut(&mut Order) -> bool) {
+        match (&mut self.levels, side) {
+            (Levels::BTree { bids, .. }, Side::Bid) => {
+                let negated = NegatedPrice::from_price(price);
+                if let Some(orders) = bids.get_mut(&negated) {
+                    orders.retain_mut(keep);
+                    if orders.is_empty() {
+                        bids.remove(&negated);
                     }
                 }
             }
-            Side::Ask => {
-                for orders_at_price in self.asks.values_mut() {
-                    if let Some(order) = orders_at_price.iter_mut().find(|o| o.id == order_id) {
-                        order.quantity = new_qty;
-                        break;
+            (Levels::BTree { asks, .. }, Side::Ask) => {
+                if let Some(orders) = asks.get_mut(&price) {
+                    orders.retain_mut(keep);
+                    if orders.is_empty() {
+                        asks.remove(&price);
                     }
                 }
             }
+            (Levels::Ladder { bids: ladder, .. }, Side::Bid)
+            | (Levels::Ladder { asks: ladder, .. }, Side::Ask) => {
+                if let Some(orders) = ladder.level_mut(price) {
+                    orders.retain_mut(keep);
+                    ladder.release_if_empty(price);
+                }
+            }
+        }
+    }
+
+    /// Updates the quantity of an order in the orderbook
+    pub fn update_order_quantity(&mut self, order_id: OrderId, side: Side, new_qty: Quantity) {
+        match (&mut self.levels, side) {
+            (Levels::BTree { bids, .. }, Side::Bid) => {
+                set_quantity(bids.values_mut(), order_id, new_qty)
+            }
+            (Levels::BTree { asks, .. }, Side::Ask) => {
+                set_quantity(asks.values_mut(), order_id, new_qty)
+            }
+            (Levels::Ladder { bids, .. }, Side::Bid) => {
+                set_quantity(bids.levels_mut(), order_id, new_qty)
+            }
+            (Levels::Ladder { asks, .. }, Side::Ask) => {
+                set_quantity(asks.levels_mut(), order_id, new_qty)
+            }
         }
     }
 
     /// Get all bids.
     ///
     /// The prices in the bids are negated.
-    pub fn get_bids(&self) -> impl Iterator<Item = (&NegatedPrice, &Vec<Order>)> {
-        self.bids.iter()
+    pub fn get_bids(&self) -> BidLevels<'_> {
+        match &self.levels {
+            Levels::BTree { bids, .. } => BidLevels::BTree(bids.iter()),
+            Levels::Ladder { bids, .. } => BidLevels::Ladder(bids.iter_descending()),
+        }
     }
 
     /// Get all asks.
     ///
     /// The prices are in their original form (not negated).
-    pub fn get_asks(&self) -> impl Iterator<Item = (&Price, &Vec<Order>)> {
-        self.asks.iter()
+    pub fn get_asks(&self) -> AskLevels<'_> {
+        match &self.levels {
+            Levels::BTree { asks, .. } => AskLevels::BTree(asks.iter()),
+            Levels::Ladder { asks, .. } => AskLevels::Ladder(asks.iter_ascending()),
+        }
+    }
+
+    /// Get the levels of one side, best price first, with their original prices.
+    pub fn levels(&self, side: Side) -> SideLevels<'_> {
+        match side {
+            Side::Bid => SideLevels::Bids(self.get_bids()),
+            Side::Ask => SideLevels::Asks(self.get_asks()),
+        }
+    }
+
+    /// Visit the levels of one side, best price first, until `visit` breaks
+    ///
+    /// Returns the value `visit` broke with, or `Continue` if every level was visited.
+    /// Nothing is allocated, so this suits checks that usually stop after a few levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `side` - The side to walk
+    /// * `visit` - Called with each level in turn
+    pub fn walk_side<B>(
+        &self,
+        side: Side,
+        mut visit: impl FnMut(&PriceLevel<'_>) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
+        for (price, orders) in self.levels(side) {
+            visit(&PriceLevel { price, orders })?;
+        }
+        ControlFlow::Continue(())
     }
 
     /// Get the best bid price.
@@ -118,19 +303,199 @@ impl OrderBook {
     /// The bid prices are stored negated (so that the BTreeMap is a min-heap).
     /// Returns the original price.
     pub fn get_best_bid(&self) -> Option<u64> {
-        self.bids.first_key_value().map(|(k, _)| k.to_price().get())
+        match &self.levels {
+            Levels::BTree { bids, .. } => bids.first_key_value().map(|(k, _)| k.to_price().get()),
+            Levels::Ladder { bids, .. } => bids.highest().map(|p| p.get()),
+        }
     }
 
     /// Get the best ask price.
     pub fn get_best_ask(&self) -> Option<u64> {
-        self.asks.first_key_value().map(|(k, _)| k.get())
+        match &self.levels {
+            Levels::BTree { asks, .. } => asks.first_key_value().map(|(k, _)| k.get()),
+            Levels::Ladder { asks, .. } => asks.lowest().map(|p| p.get()),
+        }
+    }
+
+    /// The quantity an incoming order would match immediately, up to its own quantity.
+    ///
+    /// Walks the book like the matching engine, skipping all-or-none orders it cannot fill.
+    pub fn matchable_quantity(&self, order: &Order) -> Quantity {
+        let crossing = self
+            .levels(order.side.opposite())
+            .take_while(|(price, _)| order.crosses(*price))
+            .map(|(_, orders)| orders);
+        let mut remaining = order.quantity.get();
+        for resting in crossing.flatten() {
+            if remaining == 0 {
+                break;
+            }
+            if resting.can_fill_against(remaining) {
+                remaining -= resting.quantity.get().min(remaining);
+            }
+        }
+        order.quantity - Quantity::new(remaining)
+    }
+
+    /// The reference price of a peg, taken from unpegged orders only so that pegged orders
+    /// never peg to each other.
+    pub fn peg_reference(&self, reference: PegReference) -> Option<Price> {
+        let unpegged = |orders: &Vec<Order>| orders.iter().any(|order| order.peg.is_none());
+        let best_bid = || {
+            self.get_bids()
+                .find(|(_, orders)| unpegged(orders))
+                .map(|(price, _)| price.to_price())
+        };
+        let best_ask = || {
+            self.get_asks()
+                .find(|(_, orders)| unpegged(orders))
+                .map(|(price, _)| price)
+        };
+        match reference {
+            PegReference::BestBid => best_bid(),
+            PegReference::BestAsk => best_ask(),
+            PegReference::Mid => {
+                let (bid, ask) = (best_bid()?, best_ask()?);
+                Some(Price::new(bid.get() + (ask.get() - bid.get()) / 2))
+            }
+        }
+    }
+
+    /// The price a pegged order would rest at now, or `None` for unpegged orders and when
+    /// the reference price is missing.
+    ///
+    /// Pegged orders are always passive: the peg price is kept strictly inside the best
+    /// opposite price.
+    pub fn peg_price(&self, order: &Order) -> Option<Price> {
+        let peg = order.peg?;
+        let price = peg.price(order.side, self.peg_reference(peg.reference)?);
+        Some(match order.side {
+            Side::Bid => match self.get_best_ask() {
+                Some(ask) => price.min(Price::new(ask.saturating_sub(1))),
+                None => price,
+            },
+            Side::Ask => match self.get_best_bid() {
+                Some(bid) => price.max(Price::new(bid + 1)),
+                None => price,
+            },
+        })
+    }
+
+    /// Resting pegged orders whose price differs from their current peg price.
+    pub fn stale_pegs(&self) -> Vec<Order> {
+        self.get_bids()
+            .flat_map(|(_, orders)| orders.iter())
+            .chain(self.get_asks().flat_map(|(_, orders)| orders.iter()))
+            .filter(|order| {
+                self.peg_price(order)
+                    .is_some_and(|price| price != order.price)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// One price level of a book side, with its original price and its orders in time priority.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceLevel<'a> {
+    pub price: Price,
+    pub orders: &'a [Order],
+}
+
+impl PriceLevel<'_> {
+    /// The total open quantity resting at the level.
+    pub fn quantity(&self) -> Quantity {
+        Quantity::new(self.orders.iter().map(|order| order.quantity.get()).sum())
+    }
+}
+
+/// Inserts an order into a price level, keeping the level sorted by `(timestamp, id)`.
+///
+/// Orders normally arrive in timestamp order, so this is a push in the common case.
+pub(crate) fn insert_by_priority(orders: &mut Vec<Order>, order: Order) {
+    let key = (order.timestamp, order.id);
+    match orders.last() {
+        Some(last) if (last.timestamp, last.id) > key => {
+            let pos = orders.partition_point(|o| (o.timestamp, o.id) < key);
+            orders.insert(pos, order);
+        }
+        _ => orders.push(order),
+    }
+}
+
+/// Sets the quantity of the first order with a matching ID in the given levels.
+fn set_quantity<'a>(
+    levels: impl Iterator<Item = &'a mut Vec<Order>>,
+    order_id: OrderId,
+    new_qty: Quantity,
+) {
+    for orders_at_price in levels {
+        if let Some(order) = orders_at_price.iter_mut().find(|o| o.id == order_id) {
+            order.quantity = new_qty;
+            break;
+        }
+    }
+}
+
+/// Iterator over bid levels, best (highest) price first.
+pub enum BidLevels<'a> {
+    BTree(btree_map::Iter<'a, NegatedPrice, Vec<Order>>),
+    Ladder(LadderIter<'a>),
+}
+
+impl<'a> Iterator for BidLevels<'a> {
+    type Item = (NegatedPrice, &'a Vec<Order>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            BidLevels::BTree(iter) => iter.next().map(|(price, orders)| (*price, orders)),
+            BidLevels::Ladder(iter) => iter
+                .next()
+                .map(|(price, orders)| (NegatedPrice::from_price(price), orders)),
+        }
+    }
+}
+
+/// Iterator over ask levels, best (lowest) price first.
+pub enum AskLevels<'a> {
+    BTree(btree_map::Iter<'a, Price, Vec<Order>>),
+    Ladder(LadderIter<'a>),
+}
+
+impl<'a> Iterator for AskLevels<'a> {
+    type Item = (Price, &'a Vec<Order>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            AskLevels::BTree(iter) => iter.next().map(|(price, orders)| (*price, orders)),
+            AskLevels::Ladder(iter) => iter.next(),
+        }
+    }
+}
+
+/// Iterator over the levels of either side, best price first.
+pub enum SideLevels<'a> {
+    Bids(BidLevels<'a>),
+    Asks(AskLevels<'a>),
+}
+
+impl<'a> Iterator for SideLevels<'a> {
+    type Item = (Price, &'a Vec<Order>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SideLevels::Bids(iter) => iter
+                .next()
+                .map(|(price, orders)| (price.to_price(), orders)),
+            SideLevels::Asks(iter) => iter.next(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::order::{AccountId, OrderId, Quantity, Timestamp};
+    use crate::order::{AccountId, OrderId, Peg, Quantity, Timestamp};
 
     use super::*;
 
@@ -187,4 +552,270 @@ mod tests {
         let bids: Vec<u64> = ob.get_bids().map(|(k, _)| k.to_price().get()).collect();
         assert_eq!(bids, vec![102, 101]);
     }
+
+    #[test]
+    fn test_order_sorting_across_levels() {
+        let mut ob = OrderBook::new();
+
+        // Two levels on each side
+        ob.insert_order(Order::new(
+            OrderId::new(1),
+            Price::new(100),
+            Quantity::new(10),
+            Side::Bid,
+            AccountId::new("trader1".to_string()),
+            Timestamp::new(1),
+        ));
+        ob.insert_order(Order::new(
+            OrderId::new(2),
+            Price::new(101),
+            Quantity::new(5),
+            Side::Bid,
+            AccountId::new("trader2".to_string()),
+            Timestamp::new(2),
+        ));
+
+        // Best bid should be 101
+        assert_eq!(ob.get_best_bid(), Some(101));
+
+        // Add some asks
+        ob.insert_order(Order::new(
+            OrderId::new(3),
+            Price::new(102),
+            Quantity::new(7),
+            Side::Ask,
+            AccountId::new("trader3".to_string()),
+            Timestamp::new(3),
+        ));
+        ob.insert_order(Order::new(
+            OrderId::new(4),
+            Price::new(103),
+            Quantity::new(3),
+            Side::Ask,
+            AccountId::new("trader4".to_string()),
+            Timestamp::new(4),
+        ));
+
+        // Best ask should be 102
+        assert_eq!(ob.get_best_ask(), Some(102));
+
+        // Verify no crossing
+        assert!(ob.get_best_bid().unwrap() < ob.get_best_ask().unwrap());
+
+        // Add crossing order
+        ob.insert_order(Order::new(
+            OrderId::new(5),
+            Price::new(102),
+            Quantity::new(2),
+            Side::Bid,
+            AccountId::new("trader5".to_string()),
+            Timestamp::new(5),
+        ));
+        // Best bid should now be 102, crossing with best ask
+        assert_eq!(ob.get_best_bid(), Some(102));
+        assert_eq!(ob.get_best_ask(), Some(102));
+
+        // Remove an order
+        ob.remove_order(OrderId::new(1), Side::Bid, Price::new(100));
+
+        // Verify remaining bid at 101 and 102
+        let bids: Vec<u64> = ob.get_bids().map(|(k, _)| k.to_price().get()).collect();
+        assert_eq!(bids, vec![102, 101]);
+    }
+
+    #[test]
+    fn test_ladder_backend() {
+        let mut ob = OrderBook::with_backend(BookBackend::Ladder {
+            min_price: Price::new(90),
+            tick_size: 1,
+            num_ticks: 100,
+        });
+
+        for (id, price, side) in [
+            (1, 100, Side::Bid),
+            (2, 101, Side::Bid),
+            (3, 102, Side::Ask),
+            (4, 105, Side::Ask),
+        ] {
+            ob.insert_order(Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(10),
+                side,
+                AccountId::new(format!("trader{}", id)),
+                Timestamp::new(id),
+            ));
+        }
+
+        assert_eq!(ob.get_best_bid(), Some(101));
+        assert_eq!(ob.get_best_ask(), Some(102));
+
+        ob.update_order_quantity(OrderId::new(3), Side::Ask, Quantity::new(4));
+        let (_, orders) = ob.get_asks().next().unwrap();
+        assert_eq!(orders[0].quantity, Quantity::new(4));
+
+        assert!(
+            ob.remove_order(OrderId::new(2), Side::Bid, Price::new(101))
+                .is_some()
+        );
+        assert!(
+            ob.remove_order(OrderId::new(2), Side::Bid, Price::new(101))
+                .is_none()
+        );
+        assert_eq!(ob.get_best_bid(), Some(100));
+
+        let asks: Vec<u64> = ob.get_asks().map(|(k, _)| k.get()).collect();
+        assert_eq!(asks, vec![102, 105]);
+    }
+
+    #[test]
+    fn test_backend_supports_price() {
+        let backend = BookBackend::Ladder {
+            min_price: Price::new(100),
+            tick_size: 5,
+            num_ticks: 10,
+        };
+        assert!(backend.supports_price(Price::new(100)));
+        assert!(backend.supports_price(Price::new(145)));
+        assert!(!backend.supports_price(Price::new(150)));
+        assert!(!backend.supports_price(Price::new(102)));
+        assert!(!backend.supports_price(Price::new(95)));
+        assert!(BookBackend::BTree.supports_price(Price::new(u64::MAX)));
+    }
+
+    #[test]
+    fn test_peg_price_stays_passive() {
+        let mut ob = OrderBook::new();
+        let order = |id: u64, side: Side, price: u64| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                AccountId::new("trader".to_string()),
+                Timestamp::new(id),
+            )
+        };
+        ob.insert_order(order(1, Side::Bid, 100));
+        ob.insert_order(order(2, Side::Ask, 105));
+        let pegged = |side: Side, reference: PegReference, offset: i64| Order {
+            peg: Some(Peg {
+                reference,
+                offset,
+                cap: None,
+            }),
+            ..order(3, side, 0)
+        };
+
+        assert_eq!(ob.peg_reference(PegReference::Mid), Some(Price::new(102)));
+        assert_eq!(
+            ob.peg_price(&pegged(Side::Bid, PegReference::Mid, 0)),
+            Some(Price::new(102))
+        );
+        // Pegging to the opposite side is kept inside the spread
+        assert_eq!(
+            ob.peg_price(&pegged(Side::Bid, PegReference::BestAsk, 0)),
+            Some(Price::new(104))
+        );
+        assert_eq!(
+            ob.peg_price(&pegged(Side::Ask, PegReference::BestBid, -3)),
+            Some(Price::new(101))
+        );
+        assert_eq!(ob.peg_price(&order(4, Side::Bid, 100)), None);
+
+        // Pegged orders do not move the reference price of other pegs
+        let mut resting = pegged(Side::Bid, PegReference::BestBid, 2);
+        resting.price = Price::new(101);
+        ob.insert_order(resting.clone());
+        assert_eq!(
+            ob.peg_reference(PegReference::BestBid),
+            Some(Price::new(100))
+        );
+        assert_eq!(ob.stale_pegs(), vec![resting]);
+    }
+
+    #[test]
+    fn test_walk_side_stops_early() {
+        let mut ob = OrderBook::new();
+        for (id, price, qty) in [(1, 101, 2), (2, 102, 3), (3, 102, 1), (4, 105, 10)] {
+            ob.insert_order(Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(qty),
+                Side::Ask,
+                AccountId::new("account".to_string()),
+                Timestamp::new(id),
+            ));
+        }
+
+        // Cost to fill 5, stopping at the level that completes it
+        let mut remaining = 5;
+        let mut cost = 0;
+        let mut visited = 0;
+        let done = ob.walk_side(Side::Ask, |level| {
+            visited += 1;
+            let fill = remaining.min(level.quantity().get());
+            cost += fill * level.price.get();
+            remaining -= fill;
+            if remaining == 0 {
+                ControlFlow::Break(level.price)
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(done, ControlFlow::Break(Price::new(102)));
+        assert_eq!((cost, visited), (2 * 101 + 3 * 102, 2));
+
+        let prices = ob.walk_side(Side::Bid, |level| ControlFlow::Break(level.price));
+        assert_eq!(prices, ControlFlow::Continue(()));
+    }
+
+    #[test]
+    fn test_retain_level_drops_emptied_levels() {
+        let backends = [
+            BookBackend::BTree,
+            BookBackend::Ladder {
+                min_price: Price::new(90),
+                tick_size: 1,
+                num_ticks: 20,
+            },
+        ];
+        for backend in backends {
+            let mut ob = OrderBook::with_backend(backend);
+            for (id, price) in [(1, 100), (2, 100), (3, 100), (4, 99)] {
+                ob.insert_order(Order::new(
+                    OrderId::new(id),
+                    Price::new(price),
+                    Quantity::new(5),
+                    Side::Bid,
+                    AccountId::new("account".to_string()),
+                    Timestamp::new(id),
+                ));
+            }
+
+            ob.retain_level(Side::Bid, Price::new(100), |order| match order.id.get() {
+                1 => false,
+                2 => {
+                    order.quantity = Quantity::new(2);
+                    true
+                }
+                _ => true,
+            });
+            let level: Vec<(OrderId, Quantity)> = ob
+                .levels(Side::Bid)
+                .next()
+                .map(|(_, orders)| orders.iter().map(|o| (o.id, o.quantity)).collect())
+                .unwrap();
+            assert_eq!(
+                level,
+                vec![
+                    (OrderId::new(2), Quantity::new(2)),
+                    (OrderId::new(3), Quantity::new(5))
+                ]
+            );
+
+            ob.retain_level(Side::Bid, Price::new(100), |_| false);
+            assert_eq!(ob.get_best_bid(), Some(99));
+        }
+    }
 }
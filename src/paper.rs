@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    exchange::Exchange,
+    market::{FeeSchedule, Pair},
+    matching::{Liquidity, Trade},
+    order::{AccountId, Order, OrderId, Price, Quantity, Side},
+    reject::RejectReason,
+};
+
+/// A simulated execution of a paper order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaperFill {
+    pub order_id: OrderId,
+    pub pair: Pair,
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub liquidity: Liquidity,
+}
+
+/// An account that trades against the real books without affecting them.
+///
+/// Marketable paper orders fill against the liquidity resting in the exchange's books at the
+/// time they are posted, without consuming it. The rest of the order waits in the paper
+/// account and fills from the real trade feed: a resting paper order fills, up to the traded
+/// quantity, whenever a real trade prints at or through its price.
+///
+/// Paper balances, holds and fills are kept entirely separate from the exchange's accounts.
+#[derive(Debug)]
+pub struct PaperAccount {
+    pub id: AccountId,
+    balances: HashMap<Asset, u64>,
+    open_orders: Vec<(Pair, Order)>,
+    fills: Vec<PaperFill>,
+}
+
+impl PaperAccount {
+    pub fn new(id: AccountId) -> Self {
+        Self {
+            id,
+            balances: HashMap::new(),
+            open_orders: Vec::new(),
+            fills: Vec::new(),
+        }
+    }
+
+    /// Add paper funds to the account.
+    pub fn deposit(&mut self, asset: Asset, amount: u64) {
+        *self.balances.entry(asset).or_insert(0) += amount;
+    }
+
+    /// The available paper balance of an asset.
+    pub fn balance(&self, asset: Asset) -> u64 {
+        self.balances.get(&asset).copied().unwrap_or(0)
+    }
+
+    /// Every fill of the account, oldest first.
+    pub fn fills(&self) -> &[PaperFill] {
+        &self.fills
+    }
+
+    /// The paper orders still waiting for fills.
+    pub fn open_orders(&self) -> impl Iterator<Item = &(Pair, Order)> {
+        self.open_orders.iter()
+    }
+
+    /// Post a paper order, returning the fills it received from the current book.
+    ///
+    /// The order is funded from paper balances like a real order. Any unfilled quantity
+    /// rests in the paper account.
+    pub fn post_order(
+        &mut self,
+        exchange: &Exchange,
+        pair: Pair,
+        mut order: Order,
+    ) -> Result<Vec<PaperFill>> {
+        if !order.rests() {
+            return Err(anyhow::anyhow!(
+                "Paper accounts only support good-till-cancelled limit orders"
+            ));
+        }
+        if order.stop_price.is_some() {
+            return Err(anyhow::anyhow!("Paper accounts do not support stop orders"));
+        }
+        if order.all_or_none {
+            return Err(anyhow::anyhow!(
+                "Paper accounts do not support all-or-none orders"
+            ));
+        }
+        if order.reduce_only {
+            return Err(anyhow::anyhow!(
+                "Paper accounts do not support reduce-only orders"
+            ));
+        }
+        if order.min_qty.is_some() {
+            return Err(anyhow::anyhow!(
+                "Paper accounts do not support minimum quantities"
+            ));
+        }
+        if order.peg.is_some() {
+            return Err(anyhow::anyhow!(
+                "Paper accounts do not support pegged orders"
+            ));
+        }
+        // Paper fills pay no fees
+        let (asset, amount) = Exchange::checked_hold_for(&order, pair, FeeSchedule::default())
+            .ok_or(RejectReason::HoldOverflow)?;
+        let balance = self.balances.entry(asset).or_insert(0);
+        if *balance < amount {
+            return Err(anyhow::anyhow!("Insufficient balance"));
+        }
+        *balance -= amount;
+
+        let mut fills = Vec::new();
+        if let Some(market) = exchange.markets.get(&pair) {
+            let book = market.engine_for(order.quantity).orderbook();
+            let levels: Box<dyn Iterator<Item = (Price, u64)>> = match order.side {
+                Side::Bid => Box::new(
+                    book.get_asks()
+                        .take_while(|(price, _)| *price <= order.price)
+                        .map(|(price, orders)| (price, level_quantity(orders))),
+                ),
+                Side::Ask => Box::new(
+                    book.get_bids()
+                        .map(|(price, orders)| (price.to_price(), level_quantity(orders)))
+                        .take_while(|(price, _)| *price >= order.price),
+                ),
+            };
+            for (price, available) in levels {
+                if order.quantity.get() == 0 {
+                    break;
+                }
+                let quantity = Quantity::new(available.min(order.quantity.get()));
+                order.quantity = order.quantity - quantity;
+                fills.push(PaperFill {
+                    order_id: order.id,
+                    pair,
+                    side: order.side,
+                    price,
+                    quantity,
+                    liquidity: Liquidity::Taker,
+                });
+            }
+        }
+
+        for fill in &fills {
+            self.settle(fill, order.price);
+        }
+        if order.quantity.get() > 0 {
+            self.open_orders.push((pair, order));
+        }
+        Ok(fills)
+    }
+
+    /// Cancel a resting paper order, releasing its paper hold.
+    pub fn cancel_order(&mut self, pair: Pair, order_id: OrderId) -> Result<()> {
+        let pos = self
+            .open_orders
+            .iter()
+            .position(|(p, o)| *p == pair && o.id == order_id)
+            .ok_or(anyhow::anyhow!("Order not found"))?;
+        let (pair, order) = self.open_orders.remove(pos);
+        let (asset, amount) = Exchange::hold_for(&order, pair, FeeSchedule::default());
+        self.deposit(asset, amount);
+        Ok(())
+    }
+
+    /// Feed a real trade to the account, returning the paper fills it caused.
+    pub fn on_trade(&mut self, pair: Pair, trade: &Trade) -> Vec<PaperFill> {
+        let mut fills = Vec::new();
+        let mut remaining = trade.quantity.get();
+        for (order_pair, order) in &mut self.open_orders {
+            if remaining == 0 {
+                break;
+            }
+            let crosses = match order.side {
+                Side::Bid => trade.price <= order.price,
+                Side::Ask => trade.price >= order.price,
+            };
+            if *order_pair != pair || !crosses {
+                continue;
+            }
+            let quantity = order.quantity.get().min(remaining);
+            remaining -= quantity;
+            order.quantity = Quantity::new(order.quantity.get() - quantity);
+            fills.push((
+                order.price,
+                PaperFill {
+                    order_id: order.id,
+                    pair,
+                    side: order.side,
+                    price: order.price,
+                    quantity: Quantity::new(quantity),
+                    liquidity: Liquidity::Maker,
+                },
+            ));
+        }
+        self.open_orders
+            .retain(|(_, order)| order.quantity.get() > 0);
+
+        fills
+            .into_iter()
+            .map(|(limit, fill)| {
+                self.settle(&fill, limit);
+                fill
+            })
+            .collect()
+    }
+
+    /// The profit and loss of the account's fills in a market, marking the net position at
+    /// `mark`.
+    pub fn pnl(&self, pair: Pair, mark: Price) -> i128 {
+        let mut cash: i128 = 0;
+        let mut position: i128 = 0;
+        for fill in self.fills.iter().filter(|f| f.pair == pair) {
+            let notional = fill.price.get() as i128 * fill.quantity.get() as i128;
+            match fill.side {
+                Side::Bid => {
+                    cash -= notional;
+                    position += fill.quantity.get() as i128;
+                }
+                Side::Ask => {
+                    cash += notional;
+                    position -= fill.quantity.get() as i128;
+                }
+            }
+        }
+        cash + position * mark.get() as i128
+    }
+
+    /// Credit a fill, refunding any difference between the hold at `limit` and the fill price.
+    fn settle(&mut self, fill: &PaperFill, limit: Price) {
+        let quantity = fill.quantity.get();
+        match fill.side {
+            Side::Bid => {
+                self.deposit(fill.pair.base, quantity);
+                self.deposit(
+                    fill.pair.numeraire,
+                    quantity * (limit.get() - fill.price.get()),
+                );
+            }
+            Side::Ask => self.deposit(fill.pair.numeraire, quantity * fill.price.get()),
+        }
+        self.fills.push(fill.clone());
+    }
+}
+
+fn level_quantity(orders: &[Order]) -> u64 {
+    orders.iter().map(|order| order.quantity.get()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{market::Market, matching::TradeId, order::Timestamp};
+
+    use super::*;
+
+    fn order(id: u64, price: u64, qty: u64, side: Side, account: &str) -> Order {
+        Order::new(
+            OrderId::new(id),
+            Price::new(price),
+            Quantity::new(qty),
+            side,
+            AccountId::new(account.to_string()),
+            Timestamp::new(id),
+        )
+    }
+
+    #[test]
+    fn test_paper_fills_do_not_consume_liquidity() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(AccountId::new("mm".to_string()), pair.base, 20);
+        exchange
+            .post_order(order(1, 100, 10, Side::Ask, "mm"), pair)
+            .unwrap();
+        exchange
+            .post_order(order(2, 101, 10, Side::Ask, "mm"), pair)
+            .unwrap();
+
+        let mut paper = PaperAccount::new(AccountId::new("paper".to_string()));
+        paper.deposit(pair.numeraire, 2_000);
+
+        let fills = paper
+            .post_order(&exchange, pair, order(1, 101, 15, Side::Bid, "paper"))
+            .unwrap();
+        let filled: Vec<(u64, u64)> = fills
+            .iter()
+            .map(|f| (f.price.get(), f.quantity.get()))
+            .collect();
+        assert_eq!(filled, vec![(100, 10), (101, 5)]);
+
+        // The real book is untouched
+        let book = exchange.markets[&pair].matching_engine.orderbook();
+        assert_eq!(book.get_best_ask(), Some(100));
+        assert_eq!(
+            book.get_asks().next().unwrap().1[0].quantity,
+            Quantity::new(10)
+        );
+
+        assert_eq!(paper.balance(pair.base), 15);
+        assert_eq!(paper.balance(pair.numeraire), 2_000 - 1_505);
+        assert_eq!(paper.pnl(pair, Price::new(102)), 25);
+
+        // A resting paper bid fills from the trade feed
+        paper
+            .post_order(&exchange, pair, order(2, 99, 5, Side::Bid, "paper"))
+            .unwrap();
+        let trade = Trade {
+            id: TradeId::default(),
+            sequence: 0,
+            ask_order_id: OrderId::new(7),
+            bid_order_id: OrderId::new(8),
+            ask_account_id: AccountId::new("a".to_string()),
+            bid_account_id: AccountId::new("b".to_string()),
+            price: Price::new(99),
+            quantity: Quantity::new(3),
+            aggressor: Some(Side::Ask),
+            ask_client_order_id: None,
+            bid_client_order_id: None,
+            ask_tag: None,
+            bid_tag: None,
+        };
+        let fills = paper.on_trade(pair, &trade);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].quantity, Quantity::new(3));
+        assert_eq!(
+            paper.open_orders().next().unwrap().1.quantity,
+            Quantity::new(2)
+        );
+        assert_eq!(paper.balance(pair.base), 18);
+    }
+}
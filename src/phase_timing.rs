@@ -0,0 +1,105 @@
+//! Where the exchange spends its time on each order, for targeting optimization work.
+//!
+//! With `Exchange::phase_timings` set, every order that reaches the matching engine adds
+//! the time it spent in each phase of `post_order`: validation covers the checks and the
+//! hold taken before matching, matching covers the matching engine, and settlement covers
+//! refunds, balance transfers, fees and the settlement hook. Stops the order triggers are
+//! timed as orders of their own, and rejected orders are not timed. The `loadtest` binary
+//! reports the split for a synthetic order flow.
+
+use std::time::{Duration, Instant};
+
+/// Time spent in each phase of order processing, summed over the timed orders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    /// Number of orders timed.
+    pub orders: u64,
+    pub validation: Duration,
+    pub matching: Duration,
+    pub settlement: Duration,
+}
+
+impl PhaseTimings {
+    /// Total time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.validation + self.matching + self.settlement
+    }
+
+    /// Average time per timed order of a phase total, such as `self.matching`.
+    pub fn per_order(&self, phase: Duration) -> Duration {
+        let nanos = phase.as_nanos() / u128::from(self.orders.max(1));
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Add one order, given the instants each phase started at and the instant it finished.
+    pub(crate) fn record(
+        &mut self,
+        validation: Instant,
+        matching: Instant,
+        settlement: Instant,
+        done: Instant,
+    ) {
+        self.orders += 1;
+        self.validation += matching - validation;
+        self.matching += settlement - matching;
+        self.settlement += done - settlement;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        exchange::Exchange,
+        market::{Market, Pair},
+        order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_only_matched_orders_are_timed() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.base, 5);
+        exchange.add_balance(bob.clone(), pair.numeraire, 100);
+        let order = |id: u64, quantity: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(10),
+                Quantity::new(quantity),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+        exchange
+            .post_order(order(1, 5, Side::Ask, &alice), pair)
+            .unwrap();
+        assert_eq!(exchange.phase_timings, None);
+
+        exchange.phase_timings = Some(PhaseTimings::default());
+        exchange
+            .post_order(order(2, 2, Side::Bid, &bob), pair)
+            .unwrap();
+        // Bob cannot fund this one
+        assert!(
+            exchange
+                .post_order(order(3, 20, Side::Bid, &bob), pair)
+                .is_err()
+        );
+        let timings = exchange.phase_timings.unwrap();
+        assert_eq!(timings.orders, 1);
+        assert_eq!(
+            timings.total(),
+            timings.validation + timings.matching + timings.settlement
+        );
+        assert_eq!(timings.per_order(timings.matching), timings.matching);
+    }
+}
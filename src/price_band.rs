@@ -0,0 +1,285 @@
+//! Price bands: keeping fat-finger limit prices out of the book.
+//!
+//! A market's `PriceBand`s bound the limit price of every order it takes to a percentage
+//! around a reference: a static price set by the operator, such as the previous close, or
+//! the market's last trade price, which moves with the market. Orders priced outside a band
+//! are rejected or clamped to its edge, by the band's `BandAction`. Bands are applied in
+//! order, so an order must pass every one of them.
+//!
+//! Bands apply to limit orders when they reach the book: stop-limit orders when they
+//! trigger, and not at all to market and pegged orders, which have no limit price of their
+//! own. A band on the last trade price applies once the market has traded. Bands are not
+//! part of snapshots.
+
+use anyhow::Result;
+
+use crate::{
+    exchange::Exchange,
+    market::Pair,
+    order::{Order, OrderType, Price},
+    orderbook::BookBackend,
+    reject::RejectReason,
+};
+
+/// What a band is centred on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandReference {
+    /// A fixed price, set by the operator.
+    Static(Price),
+    /// The market's last trade price.
+    LastTrade,
+}
+
+/// What happens to an order priced outside a band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandAction {
+    /// The order is rejected with `RejectReason::PriceOutsideBand`.
+    Reject,
+    /// The order's price is moved to the nearest price within the band on the market's tick.
+    Clamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceBand {
+    pub reference: BandReference,
+    /// The largest deviation from the reference allowed, in basis points of it.
+    pub max_deviation_bps: u64,
+    pub action: BandAction,
+}
+
+impl PriceBand {
+    /// The lowest and highest prices the band allows, or `None` if its reference is unknown.
+    pub fn limits(&self, last_trade_price: Option<Price>) -> Option<(Price, Price)> {
+        let reference = match self.reference {
+            BandReference::Static(price) => price,
+            BandReference::LastTrade => last_trade_price?,
+        }
+        .get();
+        let width = (reference as u128 * self.max_deviation_bps as u128 / 10_000)
+            .min(u64::MAX as u128) as u64;
+        Some((
+            Price::new(reference.saturating_sub(width)),
+            Price::new(reference.saturating_add(width)),
+        ))
+    }
+}
+
+/// The price within `low..=high` nearest to `price` that the backend supports.
+fn clamp(price: Price, low: Price, high: Price, backend: BookBackend) -> Option<Price> {
+    let clamped = price.clamp(low, high);
+    let BookBackend::Ladder {
+        min_price,
+        tick_size,
+        ..
+    } = backend
+    else {
+        return Some(clamped);
+    };
+    let offset = clamped.get().checked_sub(min_price.get())?;
+    let mut snapped = min_price.get() + offset / tick_size * tick_size;
+    if snapped < low.get() {
+        snapped = snapped.checked_add(tick_size)?;
+    }
+    let snapped = Price::new(snapped);
+    (low..=high).contains(&snapped).then_some(snapped)
+}
+
+impl Exchange {
+    /// Set the price bands of a market
+    ///
+    /// Applies to the orders the market takes from then on; resting orders are not touched.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market
+    /// * `bands` - The bands, applied in order, replacing the previous ones; empty to remove
+    ///   them
+    pub fn set_price_bands(&mut self, pair: Pair, bands: Vec<PriceBand>) -> Result<()> {
+        let market = self
+            .markets
+            .get_mut(&pair)
+            .ok_or(anyhow::anyhow!("Market not found"))?;
+        market.set_price_bands(bands);
+        Ok(())
+    }
+
+    /// The price bands of a market, empty if it has none or does not exist.
+    pub fn price_bands(&self, pair: Pair) -> &[PriceBand] {
+        self.markets
+            .get(&pair)
+            .map_or(&[], |market| market.price_bands())
+    }
+
+    /// Reject or clamp a limit order priced outside the bands of its market.
+    pub(crate) fn apply_price_bands(
+        &self,
+        order: &mut Order,
+        pair: Pair,
+    ) -> Result<(), RejectReason> {
+        let Some(market) = self.markets.get(&pair) else {
+            return Ok(());
+        };
+        if order.order_type != OrderType::Limit || order.peg.is_some() || order.stop_price.is_some()
+        {
+            return Ok(());
+        }
+        for band in market.price_bands() {
+            let Some((low, high)) = band.limits(market.last_trade_price()) else {
+                continue;
+            };
+            if (low..=high).contains(&order.price) {
+                continue;
+            }
+            order.price = match band.action {
+                BandAction::Reject => None,
+                BandAction::Clamp => clamp(order.price, low, high, market.config.book_backend),
+            }
+            .ok_or(RejectReason::PriceOutsideBand)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::{Market, MarketConfig},
+        order::{AccountId, OrderId, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_bands_reject_or_clamp_fat_fingers() {
+        let btc = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let eth = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("ETH"),
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let ladder = MarketConfig {
+            book_backend: BookBackend::Ladder {
+                min_price: Price::new(5),
+                tick_size: 10,
+                num_ticks: 100,
+            },
+            ..MarketConfig::default()
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(btc));
+        exchange.add_market(Market::with_config(eth, ladder));
+        for name in ["alice", "bob"] {
+            exchange.add_balance(account(name), btc.base, 100);
+            exchange.add_balance(account(name), eth.base, 100);
+            exchange.add_balance(account(name), btc.numeraire, 100_000);
+        }
+        let unlisted = Pair {
+            numeraire: btc.base,
+            base: eth.base,
+        };
+        assert!(exchange.set_price_bands(unlisted, Vec::new()).is_err());
+        // BTC has a wide static band and a tight band on the last trade
+        let bands = vec![
+            PriceBand {
+                reference: BandReference::Static(Price::new(100)),
+                max_deviation_bps: 6_000,
+                action: BandAction::Reject,
+            },
+            PriceBand {
+                reference: BandReference::LastTrade,
+                max_deviation_bps: 1_000,
+                action: BandAction::Clamp,
+            },
+        ];
+        exchange.set_price_bands(btc, bands.clone()).unwrap();
+        assert_eq!(exchange.price_bands(btc), bands.as_slice());
+        let limit = |id: u64, price: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+
+        // Before the market trades only the static band applies
+        let error = exchange
+            .post_order(limit(1, 161, Side::Bid, "alice"), btc)
+            .unwrap_err();
+        assert_eq!(
+            RejectReason::of(&error),
+            Some(RejectReason::PriceOutsideBand)
+        );
+        exchange
+            .post_order(limit(2, 140, Side::Ask, "alice"), btc)
+            .unwrap();
+        exchange
+            .post_order(limit(3, 140, Side::Bid, "bob"), btc)
+            .unwrap();
+        // Then a bid far through the last trade is clamped to its band and rests there
+        let report = exchange
+            .post_order(limit(4, 159, Side::Bid, "bob"), btc)
+            .unwrap();
+        assert!(report.fills.is_empty());
+        assert!(
+            exchange.markets[&btc]
+                .resting_order(OrderId::new(4), Side::Bid, Price::new(154))
+                .is_some()
+        );
+        // An ask below the static band is rejected before the last trade could clamp it
+        let error = exchange
+            .post_order(limit(5, 39, Side::Ask, "alice"), btc)
+            .unwrap_err();
+        assert_eq!(
+            RejectReason::of(&error),
+            Some(RejectReason::PriceOutsideBand)
+        );
+
+        // Legs of a group would move each other's band
+        let error = exchange
+            .post_order_group(vec![
+                (limit(6, 150, Side::Bid, "alice"), btc),
+                (limit(7, 150, Side::Bid, "alice"), btc),
+            ])
+            .unwrap_err();
+        assert!(error.to_string().contains("cannot share a group"));
+
+        // Market orders are not banded, and clamped prices land on the ladder's tick
+        let market_order = Order::market(
+            OrderId::new(8),
+            Quantity::new(1),
+            Side::Ask,
+            account("alice"),
+            Timestamp::new(8),
+        );
+        let report = exchange.post_order(market_order, btc).unwrap();
+        assert_eq!(report.fills[0].price, Price::new(154));
+        exchange
+            .set_price_bands(
+                eth,
+                vec![PriceBand {
+                    reference: BandReference::Static(Price::new(200)),
+                    max_deviation_bps: 1_000,
+                    action: BandAction::Clamp,
+                }],
+            )
+            .unwrap();
+        exchange
+            .post_order(limit(9, 305, Side::Bid, "bob"), eth)
+            .unwrap();
+        let ask = Order {
+            quantity: Quantity::new(2),
+            ..limit(10, 105, Side::Ask, "alice")
+        };
+        let report = exchange.post_order(ask, eth).unwrap();
+        assert_eq!(report.fills[0].price, Price::new(215));
+        let book = exchange.orderbook(eth).unwrap();
+        assert_eq!(book.asks[0].price, Price::new(185));
+    }
+}
@@ -0,0 +1,450 @@
+//! Python bindings for driving the engine from notebooks, enabled by the `python` feature.
+//!
+//! Build the extension module with `maturin develop --features python`, then
+//! `import exchanges`. Assets, accounts and sides are passed as strings. Depth queries return
+//! numpy arrays that take ownership of the engine's buffers instead of copying them.
+//!
+//! Python strategies are objects with any of the methods `on_tick(ctx)`,
+//! `on_fill(ctx, trade)` and `on_book(ctx, numeraire, base, best_bid, best_ask)`. Orders placed
+//! through `ctx` are executed once the callback returns.
+
+use numpy::{PyArray1, PyArray2, PyArrayMethods};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    asset::Asset,
+    command::Command,
+    exchange::Exchange,
+    market::{Market, Pair},
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    orderbook::OrderBook,
+    simulation::{Simulator, Strategy, StrategyContext, TopOfBook},
+};
+
+/// An exchange driven from Python.
+#[pyclass(name = "Exchange", unsendable)]
+#[derive(Default)]
+pub struct PyExchange {
+    exchange: Exchange,
+}
+
+#[pymethods]
+impl PyExchange {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_market(&mut self, numeraire: &str, base: &str) {
+        self.exchange.add_market(Market::new(pair(numeraire, base)));
+    }
+
+    fn deposit(&mut self, account: &str, asset: &str, amount: u64) -> PyResult<()> {
+        self.exchange
+            .deposit(account_id(account), Asset::intern(asset), amount)
+            .map_err(py_error)
+    }
+
+    fn withdraw(&mut self, account: &str, asset: &str, amount: u64) -> PyResult<()> {
+        self.exchange
+            .withdraw(account_id(account), Asset::intern(asset), amount)
+            .map_err(py_error)
+    }
+
+    /// The balance of an account, zero for unknown accounts.
+    fn balance(&self, account: &str, asset: &str) -> u64 {
+        balance(&self.exchange, account, asset)
+    }
+
+    /// Post a limit order, returning its trades as
+    /// `(ask_order_id, bid_order_id, price, quantity)` tuples.
+    #[allow(clippy::too_many_arguments)]
+    fn post_limit_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+        quantity: u64,
+        account: &str,
+        timestamp: u64,
+    ) -> PyResult<Vec<(u64, u64, u64, u64)>> {
+        let order = Order::new(
+            OrderId::new(id),
+            Price::new(price),
+            Quantity::new(quantity),
+            parse_side(side)?,
+            account_id(account),
+            Timestamp::new(timestamp),
+        );
+        self.post(order, pair(numeraire, base))
+    }
+
+    /// Post a market order, returning its trades like `post_limit_order`.
+    #[allow(clippy::too_many_arguments)]
+    fn post_market_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        quantity: u64,
+        account: &str,
+        timestamp: u64,
+    ) -> PyResult<Vec<(u64, u64, u64, u64)>> {
+        let order = Order::market(
+            OrderId::new(id),
+            Quantity::new(quantity),
+            parse_side(side)?,
+            account_id(account),
+            Timestamp::new(timestamp),
+        );
+        self.post(order, pair(numeraire, base))
+    }
+
+    fn cancel_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+    ) -> PyResult<()> {
+        self.exchange
+            .cancel_order(
+                OrderId::new(id),
+                Price::new(price),
+                parse_side(side)?,
+                pair(numeraire, base),
+            )
+            .map_err(py_error)
+    }
+
+    fn reduce_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+        quantity: u64,
+    ) -> PyResult<()> {
+        self.exchange
+            .execute(Command::ReduceOrder {
+                pair: pair(numeraire, base),
+                order_id: OrderId::new(id),
+                side: parse_side(side)?,
+                price: Price::new(price),
+                quantity: Quantity::new(quantity),
+            })
+            .map(|_| ())
+            .map_err(py_error)
+    }
+
+    /// The depth of one side of a market as an `(levels, 2)` array of price and quantity,
+    /// best level first.
+    fn depth<'py>(
+        &self,
+        py: Python<'py>,
+        numeraire: &str,
+        base: &str,
+        side: &str,
+    ) -> PyResult<Bound<'py, PyArray2<u64>>> {
+        depth(py, &self.exchange, pair(numeraire, base), parse_side(side)?)
+    }
+}
+
+impl PyExchange {
+    fn post(&mut self, order: Order, pair: Pair) -> PyResult<Vec<(u64, u64, u64, u64)>> {
+        let report = self.exchange.post_order(order, pair).map_err(py_error)?;
+        Ok(report.into_trades().iter().map(trade_tuple).collect())
+    }
+}
+
+/// A simulator driven from Python.
+#[pyclass(name = "Simulator", unsendable)]
+pub struct PySimulator {
+    simulator: Simulator,
+}
+
+#[pymethods]
+impl PySimulator {
+    /// Create a simulator starting from a copy of the balances and books of `exchange`.
+    #[new]
+    fn new(exchange: PyRef<'_, PyExchange>) -> Self {
+        let exchange = Exchange::from_snapshot(&exchange.exchange.snapshot());
+        Self {
+            simulator: Simulator::new(exchange),
+        }
+    }
+
+    /// Register a Python strategy trading for `account`.
+    fn register(&mut self, account: &str, strategy: Py<PyAny>) {
+        self.simulator
+            .register(account_id(account), Box::new(PyStrategy { strategy }));
+    }
+
+    fn run(&mut self, steps: u64) {
+        self.simulator.run(steps);
+    }
+
+    fn step(&mut self) {
+        self.simulator.step();
+    }
+
+    #[getter]
+    fn time(&self) -> u64 {
+        self.simulator.time()
+    }
+
+    fn balance(&self, account: &str, asset: &str) -> u64 {
+        balance(&self.simulator.exchange, account, asset)
+    }
+
+    fn depth<'py>(
+        &self,
+        py: Python<'py>,
+        numeraire: &str,
+        base: &str,
+        side: &str,
+    ) -> PyResult<Bound<'py, PyArray2<u64>>> {
+        depth(
+            py,
+            &self.simulator.exchange,
+            pair(numeraire, base),
+            parse_side(side)?,
+        )
+    }
+}
+
+/// An order requested by a Python strategy, executed once its callback returns.
+enum Action {
+    Post {
+        pair: Pair,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    },
+    Cancel {
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+    },
+}
+
+/// The view of the simulation given to a Python strategy callback.
+#[pyclass(name = "StrategyContext", unsendable)]
+pub struct PyStrategyContext {
+    #[pyo3(get)]
+    time: u64,
+    #[pyo3(get)]
+    account: String,
+    actions: Vec<Action>,
+}
+
+#[pymethods]
+impl PyStrategyContext {
+    fn post_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        side: &str,
+        price: u64,
+        quantity: u64,
+    ) -> PyResult<()> {
+        self.actions.push(Action::Post {
+            pair: pair(numeraire, base),
+            side: parse_side(side)?,
+            price: Price::new(price),
+            quantity: Quantity::new(quantity),
+        });
+        Ok(())
+    }
+
+    fn cancel_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+    ) -> PyResult<()> {
+        self.actions.push(Action::Cancel {
+            pair: pair(numeraire, base),
+            order_id: OrderId::new(id),
+            side: parse_side(side)?,
+            price: Price::new(price),
+        });
+        Ok(())
+    }
+}
+
+/// Adapts a Python object to the `Strategy` trait.
+struct PyStrategy {
+    strategy: Py<PyAny>,
+}
+
+impl PyStrategy {
+    /// Calls `method` on the Python strategy if it defines it, then executes the actions it
+    /// requested. Exceptions raised by the strategy are printed and its actions discarded.
+    fn call<'py>(
+        &self,
+        py: Python<'py>,
+        ctx: &mut StrategyContext<'_>,
+        method: &str,
+        args: impl FnOnce(Py<PyStrategyContext>) -> PyResult<Bound<'py, pyo3::types::PyTuple>>,
+    ) {
+        let strategy = self.strategy.bind(py);
+        if !strategy.hasattr(method).unwrap_or(false) {
+            return;
+        }
+        let result = Py::new(
+            py,
+            PyStrategyContext {
+                time: ctx.time(),
+                account: ctx.account_id().as_str().to_string(),
+                actions: Vec::new(),
+            },
+        )
+        .and_then(|py_ctx| {
+            strategy.call_method1(method, args(py_ctx.clone_ref(py))?)?;
+            Ok(py_ctx)
+        });
+        let py_ctx = match result {
+            Ok(py_ctx) => py_ctx,
+            Err(error) => {
+                error.print(py);
+                return;
+            }
+        };
+        let actions = std::mem::take(&mut py_ctx.borrow_mut(py).actions);
+        for action in actions {
+            // Rejected orders are dropped, as they would be for a Rust strategy ignoring errors
+            let _ = match action {
+                Action::Post {
+                    pair,
+                    side,
+                    price,
+                    quantity,
+                } => ctx.post_order(pair, side, price, quantity).map(|_| ()),
+                Action::Cancel {
+                    pair,
+                    order_id,
+                    side,
+                    price,
+                } => ctx.cancel_order(pair, order_id, side, price),
+            };
+        }
+    }
+}
+
+impl Strategy for PyStrategy {
+    fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+        Python::attach(|py| {
+            self.call(py, ctx, "on_tick", |py_ctx| (py_ctx,).into_pyobject(py));
+        });
+    }
+
+    fn on_fill(&mut self, ctx: &mut StrategyContext<'_>, _pair: Pair, trade: &Trade) {
+        Python::attach(|py| {
+            self.call(py, ctx, "on_fill", |py_ctx| {
+                (py_ctx, trade_tuple(trade)).into_pyobject(py)
+            });
+        });
+    }
+
+    fn on_book(&mut self, ctx: &mut StrategyContext<'_>, pair: Pair, top: TopOfBook) {
+        Python::attach(|py| {
+            self.call(py, ctx, "on_book", |py_ctx| {
+                (
+                    py_ctx,
+                    pair.numeraire.symbol,
+                    pair.base.symbol,
+                    top.best_bid,
+                    top.best_ask,
+                )
+                    .into_pyobject(py)
+            });
+        });
+    }
+}
+
+#[pymodule]
+fn exchanges(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyExchange>()?;
+    module.add_class::<PySimulator>()?;
+    module.add_class::<PyStrategyContext>()?;
+    Ok(())
+}
+
+fn depth<'py>(
+    py: Python<'py>,
+    exchange: &Exchange,
+    pair: Pair,
+    side: Side,
+) -> PyResult<Bound<'py, PyArray2<u64>>> {
+    let levels = exchange
+        .markets
+        .get(&pair)
+        .map(|market| levels(market.matching_engine.orderbook(), side))
+        .unwrap_or_default();
+    let rows = levels.len() / 2;
+    // The array takes ownership of the buffer; reshaping a contiguous array does not copy
+    PyArray1::from_vec(py, levels).reshape([rows, 2])
+}
+
+/// Flattened `[price, quantity, ...]` pairs of one side of a book, best level first.
+fn levels(book: &OrderBook, side: Side) -> Vec<u64> {
+    let quantity = |orders: &[Order]| orders.iter().map(|o| o.quantity.get()).sum::<u64>();
+    match side {
+        Side::Bid => book
+            .get_bids()
+            .flat_map(|(price, orders)| [price.to_price().get(), quantity(orders)])
+            .collect(),
+        Side::Ask => book
+            .get_asks()
+            .flat_map(|(price, orders)| [price.get(), quantity(orders)])
+            .collect(),
+    }
+}
+
+fn trade_tuple(trade: &Trade) -> (u64, u64, u64, u64) {
+    (
+        trade.ask_order_id.get(),
+        trade.bid_order_id.get(),
+        trade.price.get(),
+        trade.quantity.get(),
+    )
+}
+
+fn balance(exchange: &Exchange, account: &str, asset: &str) -> u64 {
+    exchange
+        .get_balance(account_id(account), Asset::intern(asset))
+        .unwrap_or(0)
+}
+
+fn pair(numeraire: &str, base: &str) -> Pair {
+    Pair {
+        numeraire: Asset::intern(numeraire),
+        base: Asset::intern(base),
+    }
+}
+
+fn account_id(account: &str) -> AccountId {
+    AccountId::new(account.to_string())
+}
+
+fn parse_side(side: &str) -> PyResult<Side> {
+    match side {
+        "bid" => Ok(Side::Bid),
+        "ask" => Ok(Side::Ask),
+        _ => Err(PyValueError::new_err("Side must be \"bid\" or \"ask\"")),
+    }
+}
+
+fn py_error(error: anyhow::Error) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
@@ -0,0 +1,210 @@
+//! Why the exchange refuses an order, or a request about an order.
+//!
+//! Order entry fails with a `RejectReason` inside the returned `anyhow::Error`, so the error
+//! still reads as a plain message while callers that need to act on the reason can recover
+//! it with `RejectReason::of`.
+
+use std::fmt;
+
+/// The reason an order was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The client order ID was used by one of the account's recent orders.
+    DuplicateClientOrderId,
+    AccountClosed,
+    /// System accounts cannot trade.
+    SystemAccount,
+    /// The order expired before it arrived.
+    Expired,
+    /// A reduce-only order would not reduce the account's position.
+    WouldNotReduce,
+    /// The minimum quantity exceeds the order quantity.
+    MinQtyAboveQuantity,
+    /// Protection prices only apply to market orders.
+    ProtectionPriceNotMarket,
+    /// Surveillance throttled the account for its order-to-trade ratio.
+    Throttled,
+    /// The quantity is neither an odd lot nor a whole number of round lots.
+    BadLotSize,
+    /// Pegged orders must be good-till-cancelled limit orders.
+    BadPeggedOrder,
+    /// Pegged orders must be round lots.
+    PeggedOddLot,
+    /// The book has no reference price for a pegged order without a cap.
+    NoPegReference,
+    /// The price is off the market's tick size or price bounds.
+    BadTickSize,
+    /// The stop price is off the market's tick size or price bounds.
+    BadStopTickSize,
+    /// The book cannot fill the order's minimum quantity right away.
+    MinQtyUnavailable,
+    /// The book can fill an all-or-none order only partially.
+    AllOrNoneUnfillable,
+    /// The market's flat fee cannot be priced for lack of a cross rate.
+    NoFlatFeeRate,
+    /// The lending pool cannot lend the base a short sale lacks.
+    ShortSaleNotLocated,
+    /// The market is in pre-open, which only accepts plain limit orders that can rest.
+    PreOpen,
+    /// The order's hold, its quantity times its price and fees, does not fit in a `u64`.
+    HoldOverflow,
+    /// The account has never held a balance.
+    UnknownAccount,
+    /// The account cannot cover the order's hold or fees.
+    InsufficientBalance,
+    /// Post-only orders must be good-till-cancelled limit orders.
+    BadPostOnlyOrder,
+    /// The post-only order would trade on arrival.
+    PostOnlyWouldTrade,
+    /// The price is outside one of the market's price bands.
+    PriceOutsideBand,
+    /// The order to cancel or amend is not resting: it filled, was cancelled, or never
+    /// existed.
+    UnknownOrder,
+}
+
+impl RejectReason {
+    /// The reason an error rejected an order, or `None` if it is not a rejection.
+    pub fn of(error: &anyhow::Error) -> Option<RejectReason> {
+        error.downcast_ref().copied()
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RejectReason::DuplicateClientOrderId => "Duplicate client order ID",
+            RejectReason::AccountClosed => "Account closed",
+            RejectReason::SystemAccount => "System accounts cannot trade",
+            RejectReason::Expired => "Order already expired",
+            RejectReason::WouldNotReduce => "Reduce-only order would not reduce the position",
+            RejectReason::MinQtyAboveQuantity => "Minimum quantity exceeds order quantity",
+            RejectReason::ProtectionPriceNotMarket => {
+                "Protection prices only apply to market orders"
+            }
+            RejectReason::Throttled => "Account throttled by surveillance",
+            RejectReason::BadLotSize => {
+                "Quantity must be an odd lot or a whole number of round lots"
+            }
+            RejectReason::BadPeggedOrder => {
+                "Pegged orders must be good-till-cancelled limit orders"
+            }
+            RejectReason::PeggedOddLot => "Pegged orders must be round lots",
+            RejectReason::NoPegReference => "No reference price for pegged order",
+            RejectReason::BadTickSize => "Price not supported by market",
+            RejectReason::BadStopTickSize => "Stop price not supported by market",
+            RejectReason::MinQtyUnavailable => "Minimum quantity not available",
+            RejectReason::AllOrNoneUnfillable => "All-or-none order cannot be filled entirely",
+            RejectReason::NoFlatFeeRate => "No cross rate for the flat fee",
+            RejectReason::ShortSaleNotLocated => "Short sale could not be located",
+            RejectReason::HoldOverflow => "Order value too large",
+            RejectReason::PreOpen => "Only resting limit orders are accepted before the open",
+            RejectReason::UnknownAccount => "Account not found",
+            RejectReason::InsufficientBalance => "Insufficient balance",
+            RejectReason::UnknownOrder => "Order not found",
+            RejectReason::BadPostOnlyOrder => {
+                "Post-only orders must be good-till-cancelled limit orders"
+            }
+            RejectReason::PostOnlyWouldTrade => "Post-only order would trade",
+            RejectReason::PriceOutsideBand => "Price outside band",
+        })
+    }
+}
+
+impl std::error::Error for RejectReason {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        event::ExchangeEvent,
+        exchange::Exchange,
+        market::{Market, MarketConfig, Pair},
+        order::{AccountId, ClientOrderId, Order, OrderId, Price, Quantity, Side, Timestamp},
+        orderbook::BookBackend,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_rejections_carry_their_reason() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            pair,
+            MarketConfig {
+                book_backend: BookBackend::Ladder {
+                    min_price: Price::new(0),
+                    tick_size: 5,
+                    num_ticks: 100,
+                },
+                ..MarketConfig::default()
+            },
+        ));
+        exchange.add_balance(alice.clone(), pair.base, 5);
+        exchange.add_balance(bob.clone(), pair.numeraire, 100);
+        let order = |id: u64, price: u64, side: Side, account: &AccountId| Order {
+            client_order_id: Some(ClientOrderId::new(format!("order-{}", id % 2))),
+            ..Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+        let reason = |exchange: &mut Exchange, order: Order| {
+            RejectReason::of(&exchange.post_order(order, pair).unwrap_err())
+        };
+
+        assert_eq!(
+            reason(&mut exchange, order(1, 102, Side::Ask, &alice)),
+            Some(RejectReason::BadTickSize)
+        );
+        assert_eq!(
+            reason(&mut exchange, order(1, 200, Side::Bid, &bob)),
+            Some(RejectReason::InsufficientBalance)
+        );
+        exchange
+            .post_order(order(1, 100, Side::Ask, &alice), pair)
+            .unwrap();
+        let error = exchange
+            .post_order(order(3, 100, Side::Ask, &alice), pair)
+            .unwrap_err();
+        assert_eq!(
+            RejectReason::of(&error),
+            Some(RejectReason::DuplicateClientOrderId)
+        );
+        // The message reads as before
+        assert_eq!(error.to_string(), "Duplicate client order ID");
+
+        // A stop that cannot be funded when it triggers
+        let stop = Order {
+            stop_price: Some(Price::new(100)),
+            ..order(4, 100, Side::Bid, &bob)
+        };
+        exchange.post_order(stop, pair).unwrap();
+        exchange
+            .remove_balance(bob.clone(), pair.numeraire, 100)
+            .unwrap();
+        exchange.add_balance(alice.clone(), pair.numeraire, 100);
+        exchange
+            .post_order(order(6, 100, Side::Bid, &alice), pair)
+            .unwrap();
+        assert_eq!(
+            exchange.drain_events(),
+            vec![ExchangeEvent::StopRejected {
+                pair,
+                order_id: OrderId::new(4),
+                account_id: bob,
+                reason: RejectReason::InsufficientBalance,
+            }]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Bounded in-memory history.
+//!
+//! Markets keep every trade on their tape and the exchange queues events until they are
+//! drained, so a long-running process grows without bound unless history is evicted.
+//! Retention policies bound both, and an `Archiver` sees every evicted entry first so it
+//! can be flushed to durable storage.
+
+use crate::{
+    event::ExchangeEvent, exchange::Exchange, market::Pair, matching::Trade, order::Timestamp,
+};
+
+/// How much history to keep. Entries beyond either limit are evicted, oldest first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Most entries kept, or `None` for no limit.
+    pub max_count: Option<usize>,
+    /// Entries older than this are evicted, or `None` for no limit.
+    pub max_age: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Number of entries to evict from the front of a history with these timestamps,
+    /// oldest first.
+    fn expired(&self, times: &[Timestamp], now: Timestamp) -> usize {
+        let over_count = self
+            .max_count
+            .map_or(0, |max_count| times.len().saturating_sub(max_count));
+        let too_old = self.max_age.map_or(0, |max_age| {
+            times.partition_point(|time| time.get().saturating_add(max_age) < now.get())
+        });
+        over_count.max(too_old)
+    }
+}
+
+/// Receives history evicted from memory, before it is dropped.
+pub trait Archiver: Send {
+    /// Trades evicted from a market's tape, oldest first, with their execution times.
+    fn archive_trades(&mut self, pair: Pair, trades: &[(Timestamp, Trade)]);
+
+    /// Undrained events evicted from the event queue, oldest first.
+    fn archive_events(&mut self, events: &[ExchangeEvent]);
+}
+
+/// The exchange's retention settings.
+#[derive(Default)]
+pub(crate) struct Retention {
+    tape: RetentionPolicy,
+    /// Events carry no timestamp, so only their number is bounded.
+    max_events: Option<usize>,
+    archiver: Option<Box<dyn Archiver>>,
+}
+
+impl Exchange {
+    /// Set how many trades each market keeps on its tape
+    ///
+    /// The tape backs public and private trade queries and reports built from them, which
+    /// only see what is retained.
+    pub fn set_tape_retention(&mut self, policy: RetentionPolicy) {
+        self.retention.tape = policy;
+    }
+
+    /// Set how many undrained events are kept, or `None` to keep them all
+    pub fn set_event_retention(&mut self, max_events: Option<usize>) {
+        self.retention.max_events = max_events;
+    }
+
+    /// Set the archiver that receives evicted history, or `None` to drop it
+    pub fn set_archiver(&mut self, archiver: Option<Box<dyn Archiver>>) {
+        self.retention.archiver = archiver;
+    }
+
+    /// Evict the history that exceeds the retention policies, archiving it first
+    ///
+    /// Markets are swept in pair order, then the event queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time, which trade ages are measured against
+    pub fn enforce_retention(&mut self, now: Timestamp) {
+        let mut pairs: Vec<Pair> = self.markets.keys().copied().collect();
+        pairs.sort_by_key(|pair| (pair.base.symbol, pair.numeraire.symbol));
+        for pair in pairs {
+            let market = self.markets.get_mut(&pair).unwrap();
+            let expired = self.retention.tape.expired(market.trade_times(), now);
+            if expired == 0 {
+                continue;
+            }
+            let evicted = market.evict_trades(expired);
+            if let Some(archiver) = &mut self.retention.archiver {
+                archiver.archive_trades(pair, &evicted);
+            }
+        }
+
+        let expired = self
+            .retention
+            .max_events
+            .map_or(0, |max_events| self.events.len().saturating_sub(max_events));
+        if expired > 0 {
+            let evicted: Vec<ExchangeEvent> = self.events.drain(..expired).collect();
+            if let Some(archiver) = &mut self.retention.archiver {
+                archiver.archive_events(&evicted);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        asset::Asset,
+        market::Market,
+        order::{AccountId, Order, OrderId, Price, Quantity, Side},
+        reject::RejectReason,
+    };
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Archive {
+        trades: Vec<(Pair, Timestamp)>,
+        events: Vec<ExchangeEvent>,
+    }
+
+    struct SharedArchive(Arc<Mutex<Archive>>);
+
+    impl Archiver for SharedArchive {
+        fn archive_trades(&mut self, pair: Pair, trades: &[(Timestamp, Trade)]) {
+            let mut archive = self.0.lock().unwrap();
+            archive
+                .trades
+                .extend(trades.iter().map(|(time, _)| (pair, *time)));
+        }
+
+        fn archive_events(&mut self, events: &[ExchangeEvent]) {
+            self.0.lock().unwrap().events.extend_from_slice(events);
+        }
+    }
+
+    #[test]
+    fn test_retention_archives_before_evicting() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(bob.clone(), pair.base, 5);
+        for time in 1..=5 {
+            let order = |id: u64, side: Side, account: &AccountId| {
+                Order::new(
+                    OrderId::new(id),
+                    Price::new(100),
+                    Quantity::new(1),
+                    side,
+                    account.clone(),
+                    Timestamp::new(time),
+                )
+            };
+            exchange
+                .post_order(order(time * 2, Side::Ask, &bob), pair)
+                .unwrap();
+            exchange
+                .post_order(order(time * 2 + 1, Side::Bid, &alice), pair)
+                .unwrap();
+        }
+        for id in 0..3 {
+            exchange.events.push(ExchangeEvent::StopRejected {
+                pair,
+                order_id: OrderId::new(id),
+                account_id: alice.clone(),
+                reason: RejectReason::InsufficientBalance,
+            });
+        }
+
+        let archive = Arc::new(Mutex::new(Archive::default()));
+        exchange.set_archiver(Some(Box::new(SharedArchive(archive.clone()))));
+        exchange.set_tape_retention(RetentionPolicy {
+            max_count: Some(3),
+            max_age: Some(2),
+        });
+        exchange.set_event_retention(Some(1));
+
+        exchange.enforce_retention(Timestamp::new(5));
+        assert_eq!(exchange.markets[&pair].trades().len(), 3);
+        assert_eq!(exchange.drain_events().len(), 1);
+        // Only the age limit applies by now
+        exchange.enforce_retention(Timestamp::new(7));
+        let market = &exchange.markets[&pair];
+        assert_eq!(market.trades().len(), 1);
+        assert_eq!(market.last_trade_price(), Some(Price::new(100)));
+
+        let archive = archive.lock().unwrap();
+        let times: Vec<u64> = archive.trades.iter().map(|(_, time)| time.get()).collect();
+        assert_eq!(times, vec![1, 2, 3, 4]);
+        assert_eq!(archive.events.len(), 2);
+    }
+}
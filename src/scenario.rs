@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    asset::Asset,
+    exchange::Exchange,
+    market::{Market, Pair},
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+};
+
+/// A scenario: the markets and starting balances of an exchange, followed by timed steps of
+/// actions and the assertions that must hold after each step.
+///
+/// Scenarios are written in YAML. Markets are named `BASE/NUMERAIRE`.
+///
+/// ```yaml
+/// name: simple fill
+/// markets: [BTC/USD]
+/// balances:
+///   alice: { USD: 1000 }
+///   bob: { BTC: 10 }
+/// steps:
+///   - time: 1
+///     actions:
+///       - order: { id: 1, account: bob, market: BTC/USD, side: ask, price: 100, quantity: 5 }
+///     expect:
+///       - best_ask: { market: BTC/USD, price: 100 }
+///   - time: 2
+///     actions:
+///       - order: { id: 2, account: alice, market: BTC/USD, side: bid, price: 100, quantity: 5 }
+///     expect:
+///       - trade: { market: BTC/USD, price: 100, quantity: 5 }
+///       - balance: { account: alice, asset: BTC, amount: 5 }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub markets: Vec<String>,
+    /// Starting balances per account and asset symbol.
+    #[serde(default)]
+    pub balances: BTreeMap<String, BTreeMap<String, u64>>,
+    pub steps: Vec<Step>,
+}
+
+/// A point in scenario time: actions are applied in order, then every expectation is checked.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub time: u64,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub expect: Vec<Expectation>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScenarioSide {
+    Bid,
+    Ask,
+}
+
+impl From<ScenarioSide> for Side {
+    fn from(side: ScenarioSide) -> Self {
+        match side {
+            ScenarioSide::Bid => Side::Bid,
+            ScenarioSide::Ask => Side::Ask,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Post an order. If `reject` is set the exchange must refuse it.
+    Order {
+        id: u64,
+        account: String,
+        market: String,
+        side: ScenarioSide,
+        price: u64,
+        quantity: u64,
+        #[serde(default)]
+        reject: bool,
+    },
+    /// Cancel a resting order.
+    Cancel {
+        id: u64,
+        market: String,
+        side: ScenarioSide,
+        price: u64,
+    },
+    Deposit {
+        account: String,
+        asset: String,
+        amount: u64,
+    },
+    Withdraw {
+        account: String,
+        asset: String,
+        amount: u64,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expectation {
+    Balance {
+        account: String,
+        asset: String,
+        amount: u64,
+    },
+    /// The best bid, or an empty bid side if `price` is omitted.
+    BestBid {
+        market: String,
+        #[serde(default)]
+        price: Option<u64>,
+    },
+    /// The best ask, or an empty ask side if `price` is omitted.
+    BestAsk {
+        market: String,
+        #[serde(default)]
+        price: Option<u64>,
+    },
+    /// A trade with this price and quantity was executed during the step.
+    Trade {
+        market: String,
+        price: u64,
+        quantity: u64,
+    },
+    /// The number of trades executed during the step, across all markets.
+    TradeCount { count: usize },
+}
+
+impl Scenario {
+    /// Parses a scenario from YAML.
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        // Actions and expectations are written as single-key maps rather than YAML tags
+        serde_yaml::with::singleton_map_recursive::deserialize(serde_yaml::Deserializer::from_str(
+            yaml,
+        ))
+        .context("Invalid scenario")
+    }
+
+    /// Runs the scenario against a fresh exchange, returning the final exchange state.
+    ///
+    /// Fails with the step time and a description of the first action or expectation that
+    /// did not behave as described.
+    pub fn run(&self) -> Result<Exchange> {
+        let mut exchange = Exchange::new();
+        for market in &self.markets {
+            exchange.add_market(Market::new(parse_pair(market)?));
+        }
+        for (account, balances) in &self.balances {
+            for (asset, amount) in balances {
+                exchange.add_balance(account_id(account), Asset::intern(asset), *amount);
+            }
+        }
+
+        for step in &self.steps {
+            let mut trades = Vec::new();
+            for action in &step.actions {
+                apply(&mut exchange, action, step.time, &mut trades)
+                    .with_context(|| format!("{}: step {}", self.name, step.time))?;
+            }
+            for expectation in &step.expect {
+                check(&exchange, expectation, &trades)
+                    .with_context(|| format!("{}: step {}", self.name, step.time))?;
+            }
+        }
+        Ok(exchange)
+    }
+}
+
+fn apply(
+    exchange: &mut Exchange,
+    action: &Action,
+    time: u64,
+    trades: &mut Vec<(Pair, Trade)>,
+) -> Result<()> {
+    match action {
+        Action::Order {
+            id,
+            account,
+            market,
+            side,
+            price,
+            quantity,
+            reject,
+        } => {
+            let pair = parse_pair(market)?;
+            let order = Order::new(
+                OrderId::new(*id),
+                Price::new(*price),
+                Quantity::new(*quantity),
+                (*side).into(),
+                account_id(account),
+                Timestamp::new(time),
+            );
+            match (exchange.post_order(order, pair), reject) {
+                (Ok(executed), false) => trades.extend(
+                    executed
+                        .into_trades()
+                        .into_iter()
+                        .map(|trade| (pair, trade)),
+                ),
+                (Err(_), true) => {}
+                (Ok(_), true) => return Err(anyhow::anyhow!("order {} was not rejected", id)),
+                (Err(e), false) => return Err(e.context(format!("order {} was rejected", id))),
+            }
+        }
+        Action::Cancel {
+            id,
+            market,
+            side,
+            price,
+        } => exchange.cancel_order(
+            OrderId::new(*id),
+            Price::new(*price),
+            (*side).into(),
+            parse_pair(market)?,
+        )?,
+        Action::Deposit {
+            account,
+            asset,
+            amount,
+        } => exchange.deposit(account_id(account), Asset::intern(asset), *amount)?,
+        Action::Withdraw {
+            account,
+            asset,
+            amount,
+        } => exchange.withdraw(account_id(account), Asset::intern(asset), *amount)?,
+    }
+    Ok(())
+}
+
+fn check(exchange: &Exchange, expectation: &Expectation, trades: &[(Pair, Trade)]) -> Result<()> {
+    match expectation {
+        Expectation::Balance {
+            account,
+            asset,
+            amount,
+        } => {
+            let actual = exchange
+                .get_balance(account_id(account), Asset::intern(asset))
+                .unwrap_or(0);
+            if actual != *amount {
+                return Err(anyhow::anyhow!(
+                    "expected {} balance of {} to be {}, got {}",
+                    account,
+                    asset,
+                    amount,
+                    actual
+                ));
+            }
+        }
+        Expectation::BestBid { market, price } | Expectation::BestAsk { market, price } => {
+            let pair = parse_pair(market)?;
+            let actual = exchange.markets.get(&pair).and_then(|m| {
+                let book = m.matching_engine.orderbook();
+                match expectation {
+                    Expectation::BestBid { .. } => book.get_best_bid(),
+                    _ => book.get_best_ask(),
+                }
+            });
+            if actual != *price {
+                return Err(anyhow::anyhow!(
+                    "expected {:?} in {} to be {:?}, got {:?}",
+                    expectation,
+                    market,
+                    price,
+                    actual
+                ));
+            }
+        }
+        Expectation::Trade {
+            market,
+            price,
+            quantity,
+        } => {
+            let pair = parse_pair(market)?;
+            let found = trades.iter().any(|(p, t)| {
+                *p == pair && t.price.get() == *price && t.quantity.get() == *quantity
+            });
+            if !found {
+                return Err(anyhow::anyhow!(
+                    "expected a trade of {} at {} in {}",
+                    quantity,
+                    price,
+                    market
+                ));
+            }
+        }
+        Expectation::TradeCount { count } => {
+            if trades.len() != *count {
+                return Err(anyhow::anyhow!(
+                    "expected {} trades, got {}",
+                    count,
+                    trades.len()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses a market name of the form `BASE/NUMERAIRE`.
+pub fn parse_pair(market: &str) -> Result<Pair> {
+    let (base, numeraire) = market
+        .split_once('/')
+        .ok_or(anyhow::anyhow!("Invalid market {}", market))?;
+    Ok(Pair {
+        numeraire: Asset::intern(numeraire),
+        base: Asset::intern(base),
+    })
+}
+
+fn account_id(account: &str) -> AccountId {
+    AccountId::new(account.to_string())
+}
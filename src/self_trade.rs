@@ -0,0 +1,189 @@
+//! Self-trade prevention.
+//!
+//! Accounts can opt out of trading with their own resting orders. The matching engine
+//! takes the prevented quantity out of the orders involved instead, and the exchange
+//! releases its holds and reports it with `SelfTradePrevented` events.
+
+use std::collections::HashMap;
+
+use crate::{
+    event::ExchangeEvent,
+    exchange::Exchange,
+    market::{FeeSchedule, Pair},
+    matching::{SelfTradeCancel, SelfTradePrevention},
+    order::{AccountId, Order, Side},
+};
+
+/// The self-trade prevention of each account that has one.
+#[derive(Debug, Default)]
+pub(crate) struct SelfTradePolicies {
+    policies: HashMap<AccountId, SelfTradePrevention>,
+}
+
+impl Exchange {
+    /// Set or clear the self-trade prevention of an account
+    ///
+    /// Applies to the orders the account posts from then on, including its stop orders
+    /// when they trigger, unless they set their own. Orders of accounts without one may trade
+    /// with each other freely.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `stp` - What to do when an order of the account would trade with another of its
+    ///   resting orders, or `None` to allow it
+    pub fn set_self_trade_prevention(
+        &mut self,
+        account_id: AccountId,
+        stp: Option<SelfTradePrevention>,
+    ) {
+        match stp {
+            Some(stp) => {
+                self.self_trade.policies.insert(account_id, stp);
+            }
+            None => {
+                self.self_trade.policies.remove(&account_id);
+            }
+        }
+    }
+
+    /// Returns the self-trade prevention of an account, if it has one.
+    pub fn self_trade_prevention(&self, account_id: &AccountId) -> Option<SelfTradePrevention> {
+        self.self_trade.policies.get(account_id).copied()
+    }
+
+    /// Release the holds of the resting quantities self-trade prevention cancelled and
+    /// raise an event for every cancellation, resting or incoming.
+    ///
+    /// The incoming order's hold is released by its submission, with the rest of its
+    /// unfilled quantity.
+    pub(crate) fn release_self_trade_cancels(
+        &mut self,
+        pair: Pair,
+        fees: FeeSchedule,
+        incoming_side: Side,
+        cancels: Vec<SelfTradeCancel>,
+    ) {
+        for SelfTradeCancel { order, cancelled } in cancels {
+            if order.side != incoming_side {
+                let (asset, amount) = Self::hold_for(
+                    &Order {
+                        quantity: cancelled,
+                        ..order.clone()
+                    },
+                    pair,
+                    fees,
+                );
+                self.add_balance(order.account_id.clone(), asset, amount);
+            }
+            self.events.push(ExchangeEvent::SelfTradePrevented {
+                pair,
+                order_id: order.id,
+                account_id: order.account_id,
+                side: order.side,
+                quantity: cancelled,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::Market,
+        order::{OrderId, Price, Quantity, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_self_trade_prevention() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let order = |id: u64, quantity: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(quantity),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        // (policy, traded, bid left resting, bob's ask left, prevented quantities)
+        for (stp, traded, resting_bid, bob_left, prevented) in [
+            (SelfTradePrevention::CancelNewest, 0, 0, 5, vec![(3, 8)]),
+            (SelfTradePrevention::CancelOldest, 5, 3, 0, vec![(1, 5)]),
+            (
+                SelfTradePrevention::DecrementBoth,
+                3,
+                0,
+                2,
+                vec![(1, 5), (3, 5)],
+            ),
+        ] {
+            let mut exchange = Exchange::new();
+            exchange.add_market(Market::new(pair));
+            exchange.add_balance(alice.clone(), pair.numeraire, 1_000);
+            exchange.add_balance(alice.clone(), pair.base, 5);
+            exchange.add_balance(bob.clone(), pair.base, 5);
+            exchange.set_self_trade_prevention(alice.clone(), Some(stp));
+            exchange
+                .post_order(order(1, 5, Side::Ask, &alice), pair)
+                .unwrap();
+            exchange
+                .post_order(order(2, 5, Side::Ask, &bob), pair)
+                .unwrap();
+
+            let trades = exchange
+                .post_order(order(3, 8, Side::Bid, &alice), pair)
+                .unwrap()
+                .fills;
+            assert!(trades.iter().all(|trade| trade.ask_account_id == bob));
+            let filled: u64 = trades.iter().map(|trade| trade.quantity.get()).sum();
+            assert_eq!(filled, traded, "{:?}", stp);
+
+            let book = exchange.orderbook(pair).unwrap();
+            let bids: u64 = book.bids.iter().map(|level| level.quantity.get()).sum();
+            assert_eq!(bids, resting_bid, "{:?}", stp);
+            let market = &exchange.markets[&pair];
+            let bob_ask = market.resting_order(OrderId::new(2), Side::Ask, Price::new(100));
+            assert_eq!(bob_ask.map_or(0, |order| order.quantity.get()), bob_left);
+            assert!(
+                market
+                    .resting_order(OrderId::new(1), Side::Ask, Price::new(100))
+                    .is_none()
+                    || stp == SelfTradePrevention::CancelNewest
+            );
+
+            // Cancelled quantity gets its hold back
+            assert_eq!(
+                exchange.get_balance(alice.clone(), pair.numeraire).unwrap()
+                    + exchange.locked_balance(&alice, pair.numeraire),
+                1_000 - traded * 100
+            );
+            assert_eq!(
+                exchange.get_balance(alice.clone(), pair.base).unwrap()
+                    + exchange.locked_balance(&alice, pair.base),
+                5 + traded
+            );
+            let events: Vec<(u64, u64)> = exchange
+                .drain_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    ExchangeEvent::SelfTradePrevented {
+                        order_id, quantity, ..
+                    } => Some((order_id.get(), quantity.get())),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(events, prevented, "{:?}", stp);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Hooks that see every settled batch of trades.
+//!
+//! The trades of an order, or of an auction uncross, are settled together as one batch. A
+//! `SettlementHook` is called once per batch, after every balance movement of the batch has
+//! been booked, so an embedder can mirror fills into an external risk or accounting system.
+//! Hooks run synchronously inside the call that executed the trades; to process batches
+//! elsewhere, install the sending half of a channel, which queues a copy of each batch.
+
+use std::sync::mpsc::Sender;
+
+use crate::{
+    exchange::Exchange, ledger::LedgerEntry, market::Pair, matching::Trade, order::Timestamp,
+};
+
+/// A batch of trades in one market and the ledger entries that settled them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settlement {
+    pub pair: Pair,
+    /// The time the trades were executed.
+    pub time: Timestamp,
+    /// The trades, in execution order.
+    pub trades: Vec<Trade>,
+    /// Every balance movement booked while settling the trades, fees included, in ledger
+    /// order.
+    pub entries: Vec<LedgerEntry>,
+}
+
+/// Receives every settled batch of trades.
+pub trait SettlementHook: Send {
+    fn settled(&mut self, settlement: &Settlement);
+}
+
+impl SettlementHook for Sender<Settlement> {
+    /// Queues a copy of the batch. Batches settled after the receiver is dropped are lost.
+    fn settled(&mut self, settlement: &Settlement) {
+        let _ = self.send(settlement.clone());
+    }
+}
+
+impl Exchange {
+    /// Set the hook that receives every settled batch of trades, or `None` to remove it
+    pub fn set_settlement_hook(&mut self, hook: Option<Box<dyn SettlementHook>>) {
+        self.settlement_hook = hook;
+    }
+
+    /// Hand a settled batch to the hook, with the ledger entries booked since the ledger
+    /// held `mark` live entries.
+    pub(crate) fn notify_settlement(
+        &mut self,
+        pair: Pair,
+        trades: &[Trade],
+        time: Timestamp,
+        mark: usize,
+    ) {
+        let Some(hook) = &mut self.settlement_hook else {
+            return;
+        };
+        if trades.is_empty() {
+            return;
+        }
+        hook.settled(&Settlement {
+            pair,
+            time,
+            trades: trades.to_vec(),
+            entries: self.account_manager.ledger().entries()[mark..].to_vec(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use crate::{
+        asset::Asset,
+        ledger::Direction,
+        market::Market,
+        order::{AccountId, Order, OrderId, Price, Quantity, Side},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_settled_batches_are_queued() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.base, 5);
+        exchange.add_balance(bob.clone(), pair.numeraire, 1_000);
+        let (sender, receiver) = mpsc::channel();
+        exchange.set_settlement_hook(Some(Box::new(sender)));
+        let order = |id: u64, price: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(2),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+
+        exchange
+            .post_order(order(1, 100, Side::Ask, &alice), pair)
+            .unwrap();
+        assert!(receiver.try_recv().is_err());
+        let report = exchange
+            .post_order(order(2, 105, Side::Bid, &bob), pair)
+            .unwrap();
+
+        let settlement = receiver.try_recv().unwrap();
+        assert_eq!(
+            (settlement.pair, settlement.time),
+            (pair, Timestamp::new(2))
+        );
+        assert_eq!(settlement.trades, report.fills);
+        let entries: Vec<(&AccountId, Asset, Direction, u64)> = settlement
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    &entry.account_id,
+                    entry.asset,
+                    entry.direction,
+                    entry.amount,
+                )
+            })
+            .collect();
+        // Proceeds, the base bought and the price improvement on the hold
+        assert_eq!(
+            entries,
+            vec![
+                (&alice, pair.numeraire, Direction::Credit, 200),
+                (&bob, pair.base, Direction::Credit, 2),
+                (&bob, pair.numeraire, Direction::Credit, 10),
+            ]
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
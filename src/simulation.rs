@@ -0,0 +1,801 @@
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    command::Command,
+    diff::SnapshotDiff,
+    exchange::Exchange,
+    journal::Journal,
+    market::Pair,
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    snapshot::Unsnapshotted,
+    speed_bump::{DelayedOrder, SpeedBump, SpeedBumps},
+};
+
+/// The best prices of a market, delivered to strategies after every tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopOfBook {
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+}
+
+/// A trading strategy driven by the `Simulator`.
+///
+/// Every callback receives a context bound to the strategy's account, through which it can
+/// place and cancel orders and query its portfolio. All callbacks default to doing nothing.
+pub trait Strategy {
+    /// Called once per simulator step, before any market data of that step.
+    fn on_tick(&mut self, _ctx: &mut StrategyContext<'_>) {}
+
+    /// Called for every trade executed in any market.
+    fn on_trade(&mut self, _ctx: &mut StrategyContext<'_>, _pair: Pair, _trade: &Trade) {}
+
+    /// Called for every trade in which the strategy's account took part.
+    fn on_fill(&mut self, _ctx: &mut StrategyContext<'_>, _pair: Pair, _trade: &Trade) {}
+
+    /// Called for every market at the end of each step.
+    fn on_book(&mut self, _ctx: &mut StrategyContext<'_>, _pair: Pair, _top: TopOfBook) {}
+}
+
+/// The view of the exchange given to a strategy callback.
+pub struct StrategyContext<'a> {
+    exchange: &'a mut Exchange,
+    account_id: &'a AccountId,
+    time: u64,
+    next_order_id: &'a mut u64,
+    trades: &'a mut Vec<(Pair, Trade)>,
+    journal: Option<&'a mut Journal>,
+    speed_bumps: &'a mut SpeedBumps,
+}
+
+impl StrategyContext<'_> {
+    /// The account the strategy trades for.
+    pub fn account_id(&self) -> &AccountId {
+        self.account_id
+    }
+
+    /// The current simulation time.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Post a limit order, returning the ID assigned to it by the simulator.
+    ///
+    /// Trades executed by the order are delivered to strategies once the current callback
+    /// returns. An order held back by the market's speed bump is accepted without being
+    /// checked; if the exchange rejects it on release, it is dropped.
+    pub fn post_order(
+        &mut self,
+        pair: Pair,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Result<OrderId> {
+        let order = Order::new(
+            OrderId::default(),
+            price,
+            quantity,
+            side,
+            self.account_id.clone(),
+            Timestamp::default(),
+        );
+        self.submit_order(pair, order)
+    }
+
+    /// Post an order with any instructions, such as a client order ID, returning the ID
+    /// assigned to it by the simulator.
+    ///
+    /// The order's ID, account and timestamp are replaced by the simulator's; otherwise it
+    /// is posted like `post_order` posts limit orders.
+    pub fn submit_order(&mut self, pair: Pair, order: Order) -> Result<OrderId> {
+        let order_id = OrderId::new(*self.next_order_id);
+        *self.next_order_id += 1;
+        let order = Order {
+            id: order_id,
+            account_id: self.account_id.clone(),
+            timestamp: Timestamp::new(self.time),
+            ..order
+        };
+        let side = order.side;
+        let top = self.top_of_book(pair);
+        let best_opposite = match side {
+            Side::Bid => top.best_ask,
+            Side::Ask => top.best_bid,
+        };
+        let Some(order) =
+            self.speed_bumps
+                .intercept(pair, order, best_opposite.map(Price::new), self.time)
+        else {
+            return Ok(order_id);
+        };
+        let trades = self.execute(Command::PostOrder { pair, order })?;
+        self.trades
+            .extend(trades.into_iter().map(|trade| (pair, trade)));
+        Ok(order_id)
+    }
+
+    /// Cancel a resting order of the strategy's account.
+    pub fn cancel_order(
+        &mut self,
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+    ) -> Result<()> {
+        self.execute(Command::CancelOrder {
+            pair,
+            order_id,
+            side,
+            price,
+        })
+        .map(|_| ())
+    }
+
+    /// Change the price or quantity of a resting order of the strategy's account; see
+    /// `Exchange::amend_order`.
+    pub fn amend_order(
+        &mut self,
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        new_price: Price,
+        new_quantity: Quantity,
+    ) -> Result<()> {
+        let trades = self.execute(Command::AmendOrder {
+            pair,
+            order_id,
+            side,
+            price,
+            new_price,
+            new_quantity,
+            timestamp: Timestamp::new(self.time),
+        })?;
+        self.trades
+            .extend(trades.into_iter().map(|trade| (pair, trade)));
+        Ok(())
+    }
+
+    /// Shrink a resting order of the strategy's account in place; see
+    /// `Exchange::reduce_order`.
+    pub fn reduce_order(
+        &mut self,
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+    ) -> Result<()> {
+        self.execute(Command::ReduceOrder {
+            pair,
+            order_id,
+            side,
+            price,
+            quantity,
+        })
+        .map(|_| ())
+    }
+
+    /// Cancel a resting order of the strategy's account and post `replacement` in its place,
+    /// returning the ID assigned to the replacement; see `Exchange::cancel_replace`.
+    ///
+    /// The replacement's ID, account and timestamp are replaced by the simulator's, as by
+    /// `submit_order`, but it is not held back by a speed bump.
+    pub fn cancel_replace(
+        &mut self,
+        pair: Pair,
+        order_id: OrderId,
+        side: Side,
+        price: Price,
+        replacement: Order,
+    ) -> Result<OrderId> {
+        let replacement_id = OrderId::new(*self.next_order_id);
+        *self.next_order_id += 1;
+        let replacement = Order {
+            id: replacement_id,
+            account_id: self.account_id.clone(),
+            timestamp: Timestamp::new(self.time),
+            ..replacement
+        };
+        let trades = self.execute(Command::CancelReplace {
+            pair,
+            order_id,
+            side,
+            price,
+            replacement,
+        })?;
+        self.trades
+            .extend(trades.into_iter().map(|trade| (pair, trade)));
+        Ok(replacement_id)
+    }
+
+    /// The available balance of the strategy's account.
+    pub fn balance(&self, asset: Asset) -> u64 {
+        self.exchange
+            .get_balance(self.account_id.clone(), asset)
+            .unwrap_or(0)
+    }
+
+    /// The balance of the strategy's account locked by resting orders.
+    pub fn locked_balance(&self, asset: Asset) -> u64 {
+        self.exchange.locked_balance(self.account_id, asset)
+    }
+
+    /// The best prices of a market.
+    pub fn top_of_book(&self, pair: Pair) -> TopOfBook {
+        top_of_book(self.exchange, pair)
+    }
+
+    fn execute(&mut self, command: Command) -> Result<Vec<Trade>> {
+        match self.journal.as_deref_mut() {
+            Some(journal) => journal.execute(self.exchange, command),
+            None => self.exchange.execute(command),
+        }
+    }
+}
+
+/// The outcome of an outage drill.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrillReport {
+    /// The simulation time of the outage.
+    pub time: u64,
+    /// The journal sequence number the exchange was recovered at.
+    pub seq: u64,
+    /// Resting orders across all markets at the time of the outage.
+    pub resting_orders: usize,
+    /// Balances, positions and orders the recovered exchange disagrees on.
+    pub lost: SnapshotDiff,
+    /// Live state outside snapshots, which the recovered exchange does not have.
+    pub unsnapshotted: Vec<Unsnapshotted>,
+}
+
+impl DrillReport {
+    /// Returns true if the exchange would be recovered without losing anything.
+    pub fn is_clean(&self) -> bool {
+        self.lost.is_empty() && self.unsnapshotted.is_empty()
+    }
+}
+
+/// Runs strategies against an exchange in discrete time steps.
+///
+/// Each step, every strategy's `on_tick` runs in registration order. Trades are then
+/// delivered through `on_trade` and `on_fill` until no strategy reacts with new trades,
+/// and finally every strategy sees `on_book` for every market. Trades caused by orders
+/// placed from `on_book` are delivered in the following step. If drills are enabled, an
+/// outage drill runs at the end of every drill step.
+///
+/// Orders held back by a speed bump are posted at the start of the step they are due, before
+/// any `on_tick`, stamped with the time of that step; their trades are delivered in that
+/// step.
+pub struct Simulator {
+    pub exchange: Exchange,
+    strategies: Vec<(AccountId, Box<dyn Strategy>)>,
+    time: u64,
+    next_order_id: u64,
+    /// Trades executed from `on_book`, delivered at the start of the next step.
+    pending_trades: Vec<(Pair, Trade)>,
+    /// Journal of strategy commands, kept while drills are enabled.
+    journal: Option<Journal>,
+    /// Steps between outage drills, if enabled.
+    drill_interval: Option<u64>,
+    drills: Vec<DrillReport>,
+    speed_bumps: SpeedBumps,
+}
+
+impl Simulator {
+    pub fn new(exchange: Exchange) -> Self {
+        Self {
+            exchange,
+            strategies: Vec::new(),
+            time: 0,
+            next_order_id: 0,
+            pending_trades: Vec::new(),
+            journal: None,
+            drill_interval: None,
+            drills: Vec::new(),
+            speed_bumps: SpeedBumps::default(),
+        }
+    }
+
+    /// Enable outage drills: every `interval` steps, after the step completes, the exchange
+    /// is recovered from its journal and compared with the live one (see `outage`).
+    ///
+    /// Strategy commands are journaled from now on, starting from the current state.
+    /// Changes made to `exchange` directly are not journaled, and drills report them lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Number of steps between drills, at least one
+    /// * `checkpoint_interval` - Number of commands between journal snapshots, at least one
+    pub fn enable_drills(&mut self, interval: u64, checkpoint_interval: u64) {
+        self.journal = Some(Journal::new(&self.exchange, checkpoint_interval));
+        self.drill_interval = Some(interval.max(1));
+    }
+
+    /// The journal of strategy commands, if drills are enabled.
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// The reports of the drills run so far, oldest first.
+    pub fn drills(&self) -> &[DrillReport] {
+        &self.drills
+    }
+
+    /// Recover the exchange from the journal as if it had gone down, and report what the
+    /// recovery would lose
+    ///
+    /// The recovered exchange is compared with the live one, which keeps running either way.
+    /// Besides the snapshot state the recovery disagrees on, the report lists the live state
+    /// snapshots leave out, such as pending stops, so a drill is only clean if nothing at all
+    /// would be lost.
+    pub fn outage(&self) -> Result<DrillReport> {
+        let journal = self
+            .journal
+            .as_ref()
+            .ok_or(anyhow::anyhow!("Drills are not enabled"))?;
+        let seq = journal.len();
+        let live = self.exchange.snapshot();
+        let recovered = Exchange::state_at(journal, seq)?;
+        Ok(DrillReport {
+            time: self.time,
+            seq,
+            resting_orders: live
+                .markets
+                .iter()
+                .map(|market| market.bids.len() + market.asks.len())
+                .sum(),
+            lost: live.diff(&recovered.snapshot()),
+            unsnapshotted: self.exchange.unsnapshotted_state(),
+        })
+    }
+
+    /// Hold back the marketable orders strategies post in a market, or `None` to remove
+    /// the market's speed bump
+    ///
+    /// Orders held back when the speed bump is removed are still released when due.
+    ///
+    /// # Arguments
+    ///
+    /// * `pair` - The market
+    /// * `bump` - How long orders that would trade on arrival are held back
+    pub fn set_speed_bump(&mut self, pair: Pair, bump: Option<SpeedBump>) {
+        self.speed_bumps.set(pair, bump);
+    }
+
+    /// The orders held back by speed bumps, in the order they will be released.
+    pub fn delayed_orders(&self) -> &[DelayedOrder] {
+        self.speed_bumps.delayed()
+    }
+
+    /// Register a strategy trading for the given account.
+    pub fn register(&mut self, account_id: AccountId, strategy: Box<dyn Strategy>) {
+        self.strategies.push((account_id, strategy));
+    }
+
+    /// The current simulation time.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Run `steps` simulation steps.
+    pub fn run(&mut self, steps: u64) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    /// Advance time by one and run a single step.
+    pub fn step(&mut self) {
+        self.time += 1;
+
+        let mut trades = std::mem::take(&mut self.pending_trades);
+        for delayed in self.speed_bumps.release(self.time) {
+            let order = Order {
+                timestamp: Timestamp::new(self.time),
+                ..delayed.order
+            };
+            let command = Command::PostOrder {
+                pair: delayed.pair,
+                order,
+            };
+            let executed = match self.journal.as_mut() {
+                Some(journal) => journal.execute(&mut self.exchange, command),
+                None => self.exchange.execute(command),
+            };
+            // The strategy was told the order was accepted, so a rejection only drops it
+            if let Ok(executed) = executed {
+                trades.extend(executed.into_iter().map(|trade| (delayed.pair, trade)));
+            }
+        }
+        for i in 0..self.strategies.len() {
+            self.dispatch(i, &mut trades, |strategy, ctx| strategy.on_tick(ctx));
+        }
+
+        while !trades.is_empty() {
+            let mut reactions = Vec::new();
+            for (pair, trade) in &trades {
+                for i in 0..self.strategies.len() {
+                    self.dispatch(i, &mut reactions, |strategy, ctx| {
+                        strategy.on_trade(ctx, *pair, trade)
+                    });
+                    let account_id = &self.strategies[i].0;
+                    if trade.bid_account_id == *account_id || trade.ask_account_id == *account_id {
+                        self.dispatch(i, &mut reactions, |strategy, ctx| {
+                            strategy.on_fill(ctx, *pair, trade)
+                        });
+                    }
+                }
+            }
+            trades = reactions;
+        }
+
+        let mut pairs: Vec<Pair> = self.exchange.markets.keys().copied().collect();
+        pairs.sort_by_key(|pair| (pair.base.symbol, pair.numeraire.symbol));
+        for pair in pairs {
+            let top = top_of_book(&self.exchange, pair);
+            for i in 0..self.strategies.len() {
+                // Trades from orders placed in `on_book` are delivered at the next step
+                let mut late = std::mem::take(&mut self.pending_trades);
+                self.dispatch(i, &mut late, |strategy, ctx| {
+                    strategy.on_book(ctx, pair, top)
+                });
+                self.pending_trades = late;
+            }
+        }
+
+        if self
+            .drill_interval
+            .is_some_and(|interval| self.time.is_multiple_of(interval))
+        {
+            let report = self.outage().expect("drills are enabled");
+            self.drills.push(report);
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        index: usize,
+        trades: &mut Vec<(Pair, Trade)>,
+        callback: impl FnOnce(&mut dyn Strategy, &mut StrategyContext<'_>),
+    ) {
+        let (account_id, strategy) = &mut self.strategies[index];
+        let mut ctx = StrategyContext {
+            exchange: &mut self.exchange,
+            account_id,
+            time: self.time,
+            next_order_id: &mut self.next_order_id,
+            trades,
+            journal: self.journal.as_mut(),
+            speed_bumps: &mut self.speed_bumps,
+        };
+        callback(strategy.as_mut(), &mut ctx);
+    }
+}
+
+fn top_of_book(exchange: &Exchange, pair: Pair) -> TopOfBook {
+    let book = exchange
+        .markets
+        .get(&pair)
+        .map(|market| market.matching_engine.orderbook());
+    TopOfBook {
+        best_bid: book.and_then(|book| book.get_best_bid()),
+        best_ask: book.and_then(|book| book.get_best_ask()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::market::Market;
+
+    use super::*;
+
+    fn pair() -> Pair {
+        Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        }
+    }
+
+    /// Quotes one ask at 100 on the first tick.
+    struct Quoter;
+
+    impl Strategy for Quoter {
+        fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+            if ctx.time() == 1 {
+                ctx.post_order(pair(), Side::Ask, Price::new(100), Quantity::new(5))
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Lifts the best ask whenever one is shown, recording its fills.
+    struct Lifter {
+        fills: Rc<RefCell<Vec<Trade>>>,
+    }
+
+    impl Strategy for Lifter {
+        fn on_book(&mut self, ctx: &mut StrategyContext<'_>, pair: Pair, top: TopOfBook) {
+            if let Some(ask) = top.best_ask {
+                ctx.post_order(pair, Side::Bid, Price::new(ask), Quantity::new(5))
+                    .unwrap();
+            }
+        }
+
+        fn on_fill(&mut self, _ctx: &mut StrategyContext<'_>, _pair: Pair, trade: &Trade) {
+            self.fills.borrow_mut().push(trade.clone());
+        }
+    }
+
+    #[test]
+    fn test_strategies_receive_fills() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(AccountId::new("maker".to_string()), pair.base, 5);
+        exchange.add_balance(AccountId::new("taker".to_string()), pair.numeraire, 500);
+
+        let fills = Rc::new(RefCell::new(Vec::new()));
+        let mut simulator = Simulator::new(exchange);
+        simulator.register(AccountId::new("maker".to_string()), Box::new(Quoter));
+        simulator.register(
+            AccountId::new("taker".to_string()),
+            Box::new(Lifter {
+                fills: fills.clone(),
+            }),
+        );
+
+        // The ask is shown at the end of step 1; the lift is delivered in step 2
+        simulator.step();
+        assert!(fills.borrow().is_empty());
+        simulator.step();
+        assert_eq!(fills.borrow().len(), 1);
+        assert_eq!(fills.borrow()[0].price, Price::new(100));
+        assert_eq!(
+            simulator
+                .exchange
+                .get_balance(AccountId::new("taker".to_string()), pair.base)
+                .unwrap(),
+            5
+        );
+    }
+
+    /// Requotes one ask every tick, cancelling the previous one.
+    struct Requoter {
+        resting: Option<(OrderId, Price)>,
+    }
+
+    impl Strategy for Requoter {
+        fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+            if let Some((order_id, price)) = self.resting.take() {
+                // The ask may have been lifted already
+                let _ = ctx.cancel_order(pair(), order_id, Side::Ask, price);
+            }
+            let price = Price::new(100 + ctx.time() % 3);
+            let order_id = ctx
+                .post_order(pair(), Side::Ask, price, Quantity::new(2))
+                .unwrap();
+            self.resting = Some((order_id, price));
+        }
+    }
+
+    /// Takes one unit whenever the best ask is at 100.
+    struct Dipper;
+
+    impl Strategy for Dipper {
+        fn on_book(&mut self, ctx: &mut StrategyContext<'_>, pair: Pair, top: TopOfBook) {
+            if top.best_ask == Some(100) {
+                ctx.post_order(pair, Side::Bid, Price::new(100), Quantity::new(1))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_outage_drills_recover_without_loss() {
+        let pair = pair();
+        let simulator = || {
+            let mut exchange = Exchange::new();
+            exchange.add_market(Market::new(pair));
+            exchange.add_balance(AccountId::new("maker".to_string()), pair.base, 100);
+            exchange.add_balance(AccountId::new("taker".to_string()), pair.numeraire, 10_000);
+            let mut simulator = Simulator::new(exchange);
+            simulator.register(
+                AccountId::new("maker".to_string()),
+                Box::new(Requoter { resting: None }),
+            );
+            simulator.register(AccountId::new("taker".to_string()), Box::new(Dipper));
+            simulator
+        };
+
+        let mut drilled = simulator();
+        drilled.enable_drills(2, 3);
+        drilled.run(9);
+        let mut reference = simulator();
+        reference.run(9);
+
+        assert_eq!(drilled.drills().len(), 4);
+        assert!(drilled.drills().iter().all(DrillReport::is_clean));
+        assert!(
+            drilled
+                .drills()
+                .iter()
+                .any(|drill| drill.resting_orders > 0)
+        );
+        // The journal carries on through the drills
+        let seqs: Vec<u64> = drilled.drills().iter().map(|drill| drill.seq).collect();
+        assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]));
+        let journal = drilled.journal().unwrap();
+        assert_eq!(
+            Exchange::state_at(journal, journal.len())
+                .unwrap()
+                .snapshot(),
+            drilled.exchange.snapshot()
+        );
+        assert_eq!(drilled.exchange.snapshot(), reference.exchange.snapshot());
+
+        assert!(reference.outage().is_err());
+    }
+
+    /// Posts an ask, shrinks it, then replaces it at a higher price.
+    struct Resizer {
+        resting: Option<OrderId>,
+    }
+
+    impl Strategy for Resizer {
+        fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+            let price = Price::new(100);
+            match (ctx.time(), self.resting) {
+                (1, _) => {
+                    let order_id = ctx
+                        .post_order(pair(), Side::Ask, price, Quantity::new(4))
+                        .unwrap();
+                    self.resting = Some(order_id);
+                }
+                (2, Some(order_id)) => ctx
+                    .reduce_order(pair(), order_id, Side::Ask, price, Quantity::new(2))
+                    .unwrap(),
+                (3, Some(order_id)) => {
+                    let replacement = Order::new(
+                        OrderId::default(),
+                        Price::new(101),
+                        Quantity::new(3),
+                        Side::Ask,
+                        ctx.account_id().clone(),
+                        Timestamp::default(),
+                    );
+                    let order_id = ctx
+                        .cancel_replace(pair(), order_id, Side::Ask, price, replacement)
+                        .unwrap();
+                    self.resting = Some(order_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_strategy_reductions_and_replacements_are_journaled() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(AccountId::new("maker".to_string()), pair.base, 5);
+        let mut simulator = Simulator::new(exchange);
+        simulator.register(
+            AccountId::new("maker".to_string()),
+            Box::new(Resizer { resting: None }),
+        );
+        simulator.enable_drills(10, 10);
+        simulator.run(3);
+
+        let journal = simulator.journal().unwrap();
+        assert!(matches!(
+            journal.commands(),
+            [
+                Command::PostOrder { .. },
+                Command::ReduceOrder { .. },
+                Command::CancelReplace { .. },
+            ]
+        ));
+        assert_eq!(
+            Exchange::state_at(journal, journal.len())
+                .unwrap()
+                .snapshot(),
+            simulator.exchange.snapshot()
+        );
+        assert_eq!(top_of_book(&simulator.exchange, pair).best_ask, Some(101));
+    }
+
+    /// Places one stop ask below the market on the first tick.
+    struct StopSetter;
+
+    impl Strategy for StopSetter {
+        fn on_tick(&mut self, ctx: &mut StrategyContext<'_>) {
+            if ctx.time() == 1 {
+                let order = Order {
+                    stop_price: Some(Price::new(90)),
+                    ..Order::new(
+                        OrderId::default(),
+                        Price::new(90),
+                        Quantity::new(1),
+                        Side::Ask,
+                        ctx.account_id().clone(),
+                        Timestamp::default(),
+                    )
+                };
+                ctx.submit_order(pair(), order).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_outage_drills_report_unsnapshotted_state() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(AccountId::new("maker".to_string()), pair.base, 100);
+        let mut simulator = Simulator::new(exchange);
+        simulator.register(AccountId::new("maker".to_string()), Box::new(StopSetter));
+        simulator.enable_drills(2, 3);
+        simulator.run(2);
+
+        let drill = &simulator.drills()[0];
+        assert!(drill.lost.is_empty());
+        assert_eq!(
+            drill.unsnapshotted,
+            vec![Unsnapshotted::PendingStops { pair, count: 1 }]
+        );
+        assert!(!drill.is_clean());
+        // The live exchange keeps its stop
+        assert_eq!(simulator.exchange.markets[&pair].pending_stops().len(), 1);
+    }
+
+    #[test]
+    fn test_speed_bump_delays_marketable_orders() {
+        let pair = pair();
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(AccountId::new("maker".to_string()), pair.base, 5);
+        exchange.add_balance(AccountId::new("taker".to_string()), pair.numeraire, 500);
+
+        let fills = Rc::new(RefCell::new(Vec::new()));
+        let mut simulator = Simulator::new(exchange);
+        simulator.set_speed_bump(pair, Some(SpeedBump::fixed(2)));
+        simulator.register(AccountId::new("maker".to_string()), Box::new(Quoter));
+        simulator.register(
+            AccountId::new("taker".to_string()),
+            Box::new(Lifter {
+                fills: fills.clone(),
+            }),
+        );
+
+        // The resting ask goes straight to the book; each lift waits two steps
+        simulator.run(2);
+        assert!(fills.borrow().is_empty());
+        let delayed: Vec<(u64, u64)> = simulator
+            .delayed_orders()
+            .iter()
+            .map(|delayed| (delayed.posted_at, delayed.release_at))
+            .collect();
+        assert_eq!(delayed, vec![(1, 3), (2, 4)]);
+
+        simulator.step();
+        assert_eq!(fills.borrow().len(), 1);
+        assert_eq!(fills.borrow()[0].price, Price::new(100));
+
+        // The second lift can no longer be funded and is dropped on release
+        simulator.step();
+        assert!(simulator.delayed_orders().is_empty());
+        assert_eq!(fills.borrow().len(), 1);
+        assert_eq!(
+            simulator.exchange.markets[&pair]
+                .matching_engine
+                .orderbook()
+                .get_best_bid(),
+            None
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    codec::{Decoder, Encoder},
+    exchange::Exchange,
+    market::{Market, MarketConfig, Pair},
+    matching::TradeIds,
+    migration::{self, Format},
+    order::{AccountId, Order},
+};
+
+/// The resting state of one market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketSnapshot {
+    pub pair: Pair,
+    pub config: MarketConfig,
+    /// Resting bids, best first and in priority order within a level, followed by those of
+    /// the odd-lot book in the same order.
+    pub bids: Vec<Order>,
+    /// Resting asks, ordered like the bids.
+    pub asks: Vec<Order>,
+    /// The sequence number of the market's next trade.
+    pub next_trade_sequence: u64,
+}
+
+/// A canonical copy of the exchange's balances, books and positions.
+///
+/// A snapshot is not the whole state of the exchange, and an exchange restored from one
+/// only behaves like the original for commands that do not depend on what it leaves out.
+/// A restore loses:
+///
+/// - pending stop orders, and the last trade and index prices that trigger them;
+/// - each market's price bands, circuit breaker, and custom `MatchPolicy`, which falls
+///   back to the allocation of its config;
+/// - open auctions, short-sale borrows, and the markets open to short selling;
+/// - order groups, baskets, closed accounts, and balance thresholds;
+/// - resting spread orders, whose implied orders are restored as plain orders;
+/// - each account's order defaults, self-trade prevention, beneficial owner, and recent
+///   client order IDs;
+/// - surveillance, the ledger, and the trade history and tape;
+/// - the sequences of exchange-assigned order IDs, which a restored exchange continues
+///   after the highest resting order ID;
+/// - the exchange's own settings, such as its clock, retention, hooks and logs.
+///
+/// Cross rates are implied by the books, so they are restored with them. Trade IDs and
+/// sequence numbers are covered, so a restored exchange continues them where they left off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Every balance, sorted by account and asset symbol.
+    pub balances: Vec<(AccountId, Asset, u64)>,
+    /// Every market, sorted by base and numeraire symbol.
+    pub markets: Vec<MarketSnapshot>,
+    /// The ID of the exchange's next trade.
+    pub next_trade_id: u64,
+    /// Every nonzero position, sorted by account and by base and numeraire symbol.
+    pub positions: Vec<(AccountId, Pair, i64)>,
+}
+
+/// Live state that a snapshot leaves out and that changes what later commands do.
+///
+/// Histories, such as the trade tape and the ledger, are left out too but are not listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unsnapshotted {
+    /// Stop orders waiting for their trigger.
+    PendingStops {
+        pair: Pair,
+        count: usize,
+    },
+    PriceBands {
+        pair: Pair,
+    },
+    CircuitBreaker {
+        pair: Pair,
+    },
+    /// Fills are allocated by a custom `MatchPolicy`.
+    CustomPolicy {
+        pair: Pair,
+    },
+    Auction {
+        pair: Pair,
+    },
+    /// Order groups whose resting legs cancel together.
+    OrderGroups {
+        count: usize,
+    },
+    /// Resting spread orders, whose implied orders a restore leaves as plain orders.
+    SpreadOrders {
+        count: usize,
+    },
+}
+
+impl fmt::Display for Unsnapshotted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let market = |pair: &Pair| format!("{}/{}", pair.base.symbol, pair.numeraire.symbol);
+        match self {
+            Unsnapshotted::PendingStops { pair, count } => {
+                write!(f, "{} pending stops in {}", count, market(pair))
+            }
+            Unsnapshotted::PriceBands { pair } => write!(f, "price bands in {}", market(pair)),
+            Unsnapshotted::CircuitBreaker { pair } => {
+                write!(f, "circuit breaker in {}", market(pair))
+            }
+            Unsnapshotted::CustomPolicy { pair } => {
+                write!(f, "custom match policy in {}", market(pair))
+            }
+            Unsnapshotted::Auction { pair } => write!(f, "auction in {}", market(pair)),
+            Unsnapshotted::OrderGroups { count } => write!(f, "{} order groups", count),
+            Unsnapshotted::SpreadOrders { count } => write!(f, "{} spread orders", count),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Encodes the snapshot in the current version of the canonical binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        migration::write_header(&mut encoder, Format::Snapshot);
+        self.encode(&mut encoder);
+        encoder.finish()
+    }
+
+    /// Decodes a snapshot from any version of the canonical binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let body = migration::load(Format::Snapshot, bytes)?;
+        let mut decoder = Decoder::new(&body);
+        let snapshot = Self::decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(snapshot)
+    }
+
+    pub(crate) fn encode(&self, encoder: &mut Encoder) {
+        encoder.len(self.balances.len());
+        for (account_id, asset, amount) in &self.balances {
+            encoder.str(account_id.as_str());
+            encoder.str(asset.symbol);
+            encoder.u64(*amount);
+        }
+        encoder.len(self.markets.len());
+        for market in &self.markets {
+            encoder.pair(market.pair);
+            encoder.market_config(&market.config);
+            for orders in [&market.bids, &market.asks] {
+                encoder.len(orders.len());
+                for order in orders {
+                    encoder.order(order);
+                }
+            }
+            encoder.u64(market.next_trade_sequence);
+        }
+        encoder.u64(self.next_trade_id);
+        encoder.len(self.positions.len());
+        for (account_id, pair, position) in &self.positions {
+            encoder.str(account_id.as_str());
+            encoder.pair(*pair);
+            encoder.u64(*position as u64);
+        }
+    }
+
+    pub(crate) fn decode(decoder: &mut Decoder<'_>) -> Result<Self> {
+        let mut balances = Vec::new();
+        for _ in 0..decoder.len()? {
+            balances.push((decoder.account_id()?, decoder.asset()?, decoder.u64()?));
+        }
+        let mut markets = Vec::new();
+        for _ in 0..decoder.len()? {
+            let pair = decoder.pair()?;
+            let config = decoder.market_config()?;
+            let mut sides = [Vec::new(), Vec::new()];
+            for orders in &mut sides {
+                for _ in 0..decoder.len()? {
+                    orders.push(decoder.order()?);
+                }
+            }
+            let [bids, asks] = sides;
+            markets.push(MarketSnapshot {
+                pair,
+                config,
+                bids,
+                asks,
+                next_trade_sequence: decoder.u64()?,
+            });
+        }
+        let next_trade_id = decoder.u64()?;
+        let mut positions = Vec::new();
+        for _ in 0..decoder.len()? {
+            positions.push((
+                decoder.account_id()?,
+                decoder.pair()?,
+                decoder.u64()? as i64,
+            ));
+        }
+        Ok(Self {
+            balances,
+            markets,
+            next_trade_id,
+            positions,
+        })
+    }
+}
+
+impl Exchange {
+    /// Take a canonical snapshot of the exchange's balances, books and positions
+    pub fn snapshot(&self) -> Snapshot {
+        let mut balances: Vec<(AccountId, Asset, u64)> = self
+            .account_manager
+            .balances()
+            .map(|(account_id, asset, amount)| (account_id.clone(), asset, amount))
+            .collect();
+        balances.sort_by(|a, b| (&a.0, a.1.symbol).cmp(&(&b.0, b.1.symbol)));
+
+        let mut markets: Vec<MarketSnapshot> = self
+            .markets
+            .iter()
+            .map(|(pair, market)| MarketSnapshot {
+                pair: *pair,
+                config: market.config,
+                bids: market
+                    .books()
+                    .flat_map(|book| book.get_bids().flat_map(|(_, orders)| orders.iter()))
+                    .cloned()
+                    .collect(),
+                asks: market
+                    .books()
+                    .flat_map(|book| book.get_asks().flat_map(|(_, orders)| orders.iter()))
+                    .cloned()
+                    .collect(),
+                next_trade_sequence: market.next_trade_sequence(),
+            })
+            .collect();
+        markets.sort_by_key(|market| (market.pair.base.symbol, market.pair.numeraire.symbol));
+
+        let mut positions: Vec<(AccountId, Pair, i64)> = self
+            .positions
+            .iter()
+            .filter(|(_, position)| **position != 0)
+            .map(|((account_id, pair), position)| (account_id.clone(), *pair, *position))
+            .collect();
+        positions.sort_by(|a, b| {
+            (&a.0, a.1.base.symbol, a.1.numeraire.symbol).cmp(&(
+                &b.0,
+                b.1.base.symbol,
+                b.1.numeraire.symbol,
+            ))
+        });
+
+        Snapshot {
+            balances,
+            markets,
+            next_trade_id: self.trade_ids.peek(),
+            positions,
+        }
+    }
+
+    /// The live state a snapshot of the exchange would leave out, by market and then for
+    /// the whole exchange. Empty if restoring a snapshot loses nothing later commands
+    /// depend on.
+    pub fn unsnapshotted_state(&self) -> Vec<Unsnapshotted> {
+        let mut pairs: Vec<Pair> = self.markets.keys().copied().collect();
+        pairs.sort_by_key(|pair| (pair.base.symbol, pair.numeraire.symbol));
+        let mut state = Vec::new();
+        for pair in pairs {
+            let market = &self.markets[&pair];
+            let count = market.pending_stops().len();
+            if count > 0 {
+                state.push(Unsnapshotted::PendingStops { pair, count });
+            }
+            if !market.price_bands().is_empty() {
+                state.push(Unsnapshotted::PriceBands { pair });
+            }
+            if market.circuit_breaker().is_some() {
+                state.push(Unsnapshotted::CircuitBreaker { pair });
+            }
+            if market.has_custom_policy() {
+                state.push(Unsnapshotted::CustomPolicy { pair });
+            }
+            if self.auctions.contains_key(&pair) {
+                state.push(Unsnapshotted::Auction { pair });
+            }
+        }
+        let count = self.linked_order_groups();
+        if count > 0 {
+            state.push(Unsnapshotted::OrderGroups { count });
+        }
+        let count = self.spreads.len();
+        if count > 0 {
+            state.push(Unsnapshotted::SpreadOrders { count });
+        }
+        state
+    }
+
+    /// Create an exchange from a snapshot
+    ///
+    /// Resting orders are placed back in their books without matching, and their holds are
+    /// not taken again: the snapshot's balances already exclude them. Odd lots go back to
+    /// the odd-lot book of markets that segregate them.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The snapshot to restore
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut exchange = Exchange::new();
+        exchange.trade_ids = TradeIds::starting_at(snapshot.next_trade_id);
+        for (account_id, asset, amount) in &snapshot.balances {
+            exchange.add_balance(account_id.clone(), *asset, *amount);
+        }
+        for market_snapshot in &snapshot.markets {
+            let mut market = Market::with_config(market_snapshot.pair, market_snapshot.config);
+            market.set_next_trade_sequence(market_snapshot.next_trade_sequence);
+            for order in market_snapshot.bids.iter().chain(&market_snapshot.asks) {
+                market.restore_order(order.clone());
+                exchange.record_order_id(order.id, market_snapshot.pair);
+            }
+            exchange.add_market(market);
+        }
+        for (account_id, pair, position) in &snapshot.positions {
+            exchange
+                .positions
+                .insert((account_id.clone(), *pair), *position);
+        }
+        exchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::order::{OrderId, Price, Quantity, Side, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(AccountId::new("alice".to_string()), pair.numeraire, 1_000);
+        exchange.add_balance(AccountId::new("bob".to_string()), pair.base, 10);
+        for (id, side, price) in [(1, Side::Bid, 99), (2, Side::Bid, 99), (3, Side::Ask, 101)] {
+            let account = if side == Side::Bid { "alice" } else { "bob" };
+            exchange
+                .post_order(
+                    Order::new(
+                        OrderId::new(id),
+                        Price::new(price),
+                        Quantity::new(2),
+                        side,
+                        AccountId::new(account.to_string()),
+                        Timestamp::new(id),
+                    ),
+                    pair,
+                )
+                .unwrap();
+        }
+
+        // Alice lifts part of bob's ask, opening a position for each of them
+        exchange
+            .post_order(
+                Order::new(
+                    OrderId::new(4),
+                    Price::new(101),
+                    Quantity::new(1),
+                    Side::Bid,
+                    AccountId::new("alice".to_string()),
+                    Timestamp::new(4),
+                ),
+                pair,
+            )
+            .unwrap();
+
+        let snapshot = exchange.snapshot();
+        assert_eq!(snapshot.positions.len(), 2);
+        let decoded = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let restored = Exchange::from_snapshot(&decoded);
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(
+            restored.markets[&pair]
+                .matching_engine
+                .orderbook()
+                .get_best_bid(),
+            Some(99)
+        );
+
+        let mut truncated = snapshot.to_bytes();
+        truncated.pop();
+        assert!(Snapshot::from_bytes(&truncated).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Speed bumps: delaying marketable orders in simulated time before they are matched.
+//!
+//! A speed bump gives resting liquidity a head start on whoever trades against it: an order
+//! that would trade on arrival is held back for a short interval, fixed or drawn at random,
+//! while orders that only add liquidity reach the book right away. The `Simulator` applies
+//! the speed bumps set on it, so the effect on spreads, fills and strategy profits can be
+//! studied with the analytics module.
+
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    market::Pair,
+    order::{Order, Price},
+};
+
+/// How long a market holds back marketable orders, in simulation steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeedBump {
+    pub min_delay: u64,
+    /// The delay is drawn uniformly from `min_delay` to `max_delay`, both included.
+    pub max_delay: u64,
+    /// Seed of the random delays, so that runs can be repeated.
+    pub seed: u64,
+}
+
+impl SpeedBump {
+    /// Holds every marketable order back by the same delay.
+    pub fn fixed(delay: u64) -> Self {
+        Self {
+            min_delay: delay,
+            max_delay: delay,
+            seed: 0,
+        }
+    }
+
+    /// Holds marketable orders back by a delay drawn from `min_delay..=max_delay`.
+    pub fn random(min_delay: u64, max_delay: u64, seed: u64) -> Self {
+        Self {
+            min_delay: min_delay.min(max_delay),
+            max_delay: max_delay.max(min_delay),
+            seed,
+        }
+    }
+}
+
+/// An order held back by a speed bump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelayedOrder {
+    pub pair: Pair,
+    pub order: Order,
+    /// The time the order was posted.
+    pub posted_at: u64,
+    /// The time the order reaches the book.
+    pub release_at: u64,
+}
+
+/// The speed bumps of a simulation and the orders they hold back.
+#[derive(Debug, Default)]
+pub(crate) struct SpeedBumps {
+    bumps: HashMap<Pair, (SpeedBump, StdRng)>,
+    /// Ordered by release time, then by the time the orders were held back.
+    delayed: Vec<DelayedOrder>,
+}
+
+impl SpeedBumps {
+    pub(crate) fn set(&mut self, pair: Pair, bump: Option<SpeedBump>) {
+        match bump {
+            Some(bump) => {
+                self.bumps
+                    .insert(pair, (bump, StdRng::seed_from_u64(bump.seed)));
+            }
+            None => {
+                self.bumps.remove(&pair);
+            }
+        }
+    }
+
+    pub(crate) fn delayed(&self) -> &[DelayedOrder] {
+        &self.delayed
+    }
+
+    /// Hold an order back if its market has a speed bump and the order would trade against
+    /// `best_opposite`, the best price on the other side of the book. Returns the order if
+    /// it was not held back.
+    pub(crate) fn intercept(
+        &mut self,
+        pair: Pair,
+        order: Order,
+        best_opposite: Option<Price>,
+        now: u64,
+    ) -> Option<Order> {
+        let Some((bump, rng)) = self.bumps.get_mut(&pair) else {
+            return Some(order);
+        };
+        if !best_opposite.is_some_and(|price| order.crosses(price)) {
+            return Some(order);
+        }
+        let release_at = now + rng.random_range(bump.min_delay..=bump.max_delay);
+        let position = self
+            .delayed
+            .partition_point(|delayed| delayed.release_at <= release_at);
+        self.delayed.insert(
+            position,
+            DelayedOrder {
+                pair,
+                order,
+                posted_at: now,
+                release_at,
+            },
+        );
+        None
+    }
+
+    /// Take the orders due by `now`, in the order they are released.
+    pub(crate) fn release(&mut self, now: u64) -> Vec<DelayedOrder> {
+        let due = self
+            .delayed
+            .partition_point(|delayed| delayed.release_at <= now);
+        self.delayed.drain(..due).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        order::{AccountId, OrderId, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_random_delays_are_seeded_and_bounded() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let bid = |id: u64| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(1),
+                Side::Bid,
+                AccountId::new("taker".to_string()),
+                Timestamp::new(id),
+            )
+        };
+        let delays = |seed: u64| {
+            let mut bumps = SpeedBumps::default();
+            bumps.set(pair, Some(SpeedBump::random(3, 1, seed)));
+            // Only orders that would trade are held back
+            assert!(
+                bumps
+                    .intercept(pair, bid(0), Some(Price::new(101)), 0)
+                    .is_some()
+            );
+            assert!(bumps.intercept(pair, bid(0), None, 0).is_some());
+            for id in 1..=50 {
+                assert!(
+                    bumps
+                        .intercept(pair, bid(id), Some(Price::new(100)), 10)
+                        .is_none()
+                );
+            }
+            assert!(bumps.release(10).is_empty());
+            let released = bumps.release(13);
+            assert!(bumps.delayed().is_empty());
+            assert!(
+                released
+                    .windows(2)
+                    .all(|window| window[0].release_at <= window[1].release_at)
+            );
+            released
+                .iter()
+                .map(|delayed| (delayed.order.id, delayed.release_at - 10))
+                .collect::<Vec<_>>()
+        };
+
+        let first = delays(7);
+        assert_eq!(first, delays(7));
+        assert!(first.iter().all(|(_, delay)| (1..=3).contains(delay)));
+        assert!(first.iter().any(|(_, delay)| *delay != first[0].1));
+    }
+}
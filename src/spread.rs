@@ -0,0 +1,810 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{
+    asset::Asset,
+    event::ExchangeEvent,
+    exchange::Exchange,
+    market::{Market, Pair},
+    matching::Trade,
+    order::{
+        AccountId, Order, OrderBuilder, OrderId, Price, Quantity, Side, TimeInForce, Timestamp,
+    },
+    reject::RejectReason,
+};
+
+/// A synthetic instrument that buys `buy_leg` and sells `sell_leg` in equal base quantity.
+///
+/// Both legs must be quoted in the same numeraire. The spread price is the buy leg's price
+/// minus the sell leg's price, so it can be negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Spread {
+    pub buy_leg: Pair,
+    pub sell_leg: Pair,
+}
+
+/// A spread price and the quantity available at it, implied from the legs' books.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImpliedQuote {
+    pub price: i64,
+    pub quantity: Quantity,
+}
+
+/// An order to buy (bid) or sell (ask) a spread.
+///
+/// Posted with `Exchange::post_spread_order`, an order executes in full against the implied
+/// price of the legs' top levels (implied-out) or is rejected, and the same order ID is used
+/// for the order posted to each leg's market. Rested with `Exchange::rest_spread_order`, it
+/// waits in the spread's book and trades through the implied orders it generates into the
+/// legs' books (implied-in).
+#[derive(Debug, Clone)]
+pub struct SpreadOrder {
+    pub id: OrderId,
+    pub price: i64,
+    pub quantity: Quantity,
+    pub side: Side,
+    pub account_id: AccountId,
+    pub timestamp: Timestamp,
+}
+
+impl Spread {
+    /// Returns the implied quote for trading the spread on the given side.
+    ///
+    /// Buying the spread (`Side::Bid`) lifts the buy leg's best ask and hits the sell leg's best
+    /// bid, so it is quoted at the implied ask, and vice versa. Only the top level of each
+    /// leg is used. Returns `None` if either side is empty or the implied price does not fit
+    /// an `i64`.
+    pub fn implied_quote(
+        &self,
+        buy_market: &Market,
+        sell_market: &Market,
+        side: Side,
+    ) -> Option<ImpliedQuote> {
+        let buy_book = buy_market.matching_engine.orderbook();
+        let sell_book = sell_market.matching_engine.orderbook();
+        let ((buy_price, buy_qty), (sell_price, sell_qty)) = match side {
+            Side::Bid => {
+                let (ask, asks) = buy_book.get_asks().next()?;
+                let (bid, bids) = sell_book.get_bids().next()?;
+                (
+                    (ask, level_quantity(asks)),
+                    (bid.to_price(), level_quantity(bids)),
+                )
+            }
+            Side::Ask => {
+                let (bid, bids) = buy_book.get_bids().next()?;
+                let (ask, asks) = sell_book.get_asks().next()?;
+                (
+                    (bid.to_price(), level_quantity(bids)),
+                    (ask, level_quantity(asks)),
+                )
+            }
+        };
+        let buy_price = i64::try_from(buy_price.get()).ok()?;
+        let sell_price = i64::try_from(sell_price.get()).ok()?;
+        Some(ImpliedQuote {
+            price: buy_price.checked_sub(sell_price)?,
+            quantity: buy_qty.min(sell_qty),
+        })
+    }
+
+    /// Builds the leg orders that execute a spread order against the implied quote.
+    ///
+    /// The legs are immediate-or-cancel, so a leg never rests in its book. Returns an error
+    /// if the implied price is worse than the order's limit or the legs' top levels cannot
+    /// fill the full quantity.
+    pub fn leg_orders(
+        &self,
+        buy_market: &Market,
+        sell_market: &Market,
+        order: &SpreadOrder,
+    ) -> anyhow::Result<[(Order, Pair); 2]> {
+        if self.buy_leg.numeraire != self.sell_leg.numeraire {
+            return Err(anyhow::anyhow!("Spread legs must share a numeraire"));
+        }
+        let quote = self
+            .implied_quote(buy_market, sell_market, order.side)
+            .ok_or(anyhow::anyhow!("No implied liquidity"))?;
+        let crosses = match order.side {
+            Side::Bid => quote.price <= order.price,
+            Side::Ask => quote.price >= order.price,
+        };
+        if !crosses {
+            return Err(anyhow::anyhow!("Implied price does not cross the limit"));
+        }
+        if quote.quantity < order.quantity {
+            return Err(anyhow::anyhow!("Insufficient implied liquidity"));
+        }
+
+        let (buy_side, sell_side) = match order.side {
+            Side::Bid => (Side::Bid, Side::Ask),
+            Side::Ask => (Side::Ask, Side::Bid),
+        };
+        let leg = |market: &Market, side: Side| {
+            let book = market.matching_engine.orderbook();
+            let price = match side {
+                Side::Bid => book.get_best_ask(),
+                Side::Ask => book.get_best_bid(),
+            };
+            OrderBuilder::new(order.id, side, order.account_id.clone())
+                .price(Price::new(price.unwrap_or_default()))
+                .quantity(order.quantity)
+                .timestamp(order.timestamp)
+                .time_in_force(TimeInForce::Ioc)
+                .build()
+        };
+        Ok([
+            (leg(buy_market, buy_side)?, self.buy_leg),
+            (leg(sell_market, sell_side)?, self.sell_leg),
+        ])
+    }
+}
+
+/// Returns the total quantity resting at a price level, saturating at `u64::MAX`.
+fn level_quantity(orders: &[Order]) -> Quantity {
+    Quantity::new(orders.iter().fold(0u64, |total, order| {
+        total.saturating_add(order.quantity.get())
+    }))
+}
+
+/// The resting spread orders of an exchange, and the implied orders they generate.
+#[derive(Debug, Default)]
+pub(crate) struct SpreadBooks {
+    books: HashMap<Spread, SpreadBook>,
+    /// Implied orders resting in the legs' books, in the order they were posted.
+    implied: Vec<ImpliedOrder>,
+    /// Fills of implied orders not hedged yet, in the order they traded.
+    fills: Vec<(Quantity, ImpliedLeg)>,
+    /// Set while implied orders are refreshed, so that the orders posted meanwhile do not
+    /// refresh them again.
+    refreshing: bool,
+}
+
+/// The resting orders of one spread.
+#[derive(Debug, Default)]
+struct SpreadBook {
+    /// Resting bids, best first and in arrival order within a price.
+    bids: Vec<SpreadOrder>,
+    /// Resting asks, ordered like the bids.
+    asks: Vec<SpreadOrder>,
+}
+
+impl SpreadBook {
+    fn side(&self, side: Side) -> &[SpreadOrder] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut Vec<SpreadOrder> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+
+    fn insert(&mut self, order: SpreadOrder) {
+        let side = order.side;
+        let orders = self.side_mut(side);
+        let index = orders.partition_point(|resting| match side {
+            Side::Bid => resting.price >= order.price,
+            Side::Ask => resting.price <= order.price,
+        });
+        orders.insert(index, order);
+    }
+}
+
+/// An implied order and the ID it rests under in its leg's book.
+#[derive(Debug, Clone)]
+struct ImpliedOrder {
+    order_id: OrderId,
+    leg: ImpliedLeg,
+}
+
+/// An order generated into one leg's book by a resting spread order
+///
+/// When it fills, the spread order trades the same quantity in the other leg, against the
+/// top level the implied price was derived from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ImpliedLeg {
+    spread: Spread,
+    spread_side: Side,
+    spread_order_id: OrderId,
+    account_id: AccountId,
+    timestamp: Timestamp,
+    pair: Pair,
+    side: Side,
+    price: Price,
+    /// Quantity resting in the leg's book.
+    quantity: Quantity,
+    /// The other leg, and the price of its top level the hedge trades against.
+    hedge_pair: Pair,
+    hedge_price: Price,
+    /// Held per unit so that the hedge is funded when the implied order fills.
+    hedge_hold: (Asset, u64),
+}
+
+impl SpreadBooks {
+    /// Returns true if the implied orders may need hedging or refreshing after a change of
+    /// the market's book.
+    fn affects(&self, pair: Pair) -> bool {
+        !self.fills.is_empty()
+            || self
+                .books
+                .keys()
+                .any(|spread| spread.buy_leg == pair || spread.sell_leg == pair)
+            || self
+                .implied
+                .iter()
+                .any(|implied| implied.leg.pair == pair || implied.leg.hedge_pair == pair)
+    }
+
+    /// Queue the fills of implied orders among settled trades for hedging.
+    pub(crate) fn record_fills(&mut self, pair: Pair, trades: &[Trade]) {
+        if self.implied.is_empty() {
+            return;
+        }
+        for trade in trades {
+            for side in [Side::Bid, Side::Ask] {
+                let Some(index) = self.implied.iter().position(|implied| {
+                    implied.order_id == trade.order_id(side)
+                        && implied.leg.pair == pair
+                        && implied.leg.side == side
+                        && implied.leg.account_id == *trade.account_id(side)
+                }) else {
+                    continue;
+                };
+                let leg = &mut self.implied[index].leg;
+                leg.quantity = leg.quantity - trade.quantity;
+                self.fills.push((trade.quantity, leg.clone()));
+                if leg.quantity.get() == 0 {
+                    self.implied.remove(index);
+                }
+            }
+        }
+    }
+
+    /// Remove filled quantity from a resting spread order, and the order once it is done.
+    fn fill(&mut self, spread: Spread, side: Side, order_id: OrderId, quantity: Quantity) {
+        let Some(book) = self.books.get_mut(&spread) else {
+            return;
+        };
+        let orders = book.side_mut(side);
+        if let Some(index) = orders.iter().position(|order| order.id == order_id) {
+            let order = &mut orders[index];
+            order.quantity = Quantity::new(order.quantity.get().saturating_sub(quantity.get()));
+            if order.quantity.get() == 0 {
+                orders.remove(index);
+            }
+        }
+        if book.bids.is_empty() && book.asks.is_empty() {
+            self.books.remove(&spread);
+        }
+    }
+
+    /// Number of resting spread orders.
+    pub(crate) fn len(&self) -> usize {
+        self.books
+            .values()
+            .map(|book| book.bids.len() + book.asks.len())
+            .sum()
+    }
+}
+
+impl Exchange {
+    /// Rest a spread order in its spread's book, where it trades through implied orders
+    ///
+    /// The best bid and the best ask of a spread generate implied orders into the legs'
+    /// books. A spread bid at `p` bids `p` above the sell leg's best bid in the buy leg, and
+    /// offers `p` below the buy leg's best ask in the sell leg, each for as much as both the
+    /// spread order and the other leg's top level hold; a spread ask is the mirror image.
+    /// Implied orders trade like any other order. When one fills, the spread order trades
+    /// the same quantity against the other leg's top level with an immediate-or-cancel
+    /// hedge, so the spread executes at its price or better as two leg trades. An implied
+    /// order that crosses its book when generated trades on arrival, so a resting spread
+    /// order also executes once the legs' top levels imply its price (implied-out).
+    ///
+    /// Implied orders are regenerated whenever a leg's book changes, behind the orders
+    /// already resting at their price. A spread order holds nothing itself: each implied
+    /// order holds what it and its hedge can settle, and is not generated while the account
+    /// cannot fund both or either leg is not trading continuously. Implied and hedge orders
+    /// draw their IDs from the sequence `place_order` uses. A hedge that does not fill in
+    /// full, for instance because of self-trade prevention, raises an
+    /// `ImpliedHedgeShortfall` event.
+    ///
+    /// # Arguments
+    ///
+    /// * `spread` - The spread to trade
+    /// * `order` - The spread order
+    pub fn rest_spread_order(&mut self, spread: Spread, order: SpreadOrder) -> Result<()> {
+        if spread.buy_leg.numeraire != spread.sell_leg.numeraire {
+            return Err(anyhow::anyhow!("Spread legs must share a numeraire"));
+        }
+        if spread.buy_leg == spread.sell_leg {
+            return Err(anyhow::anyhow!("Spread legs must differ"));
+        }
+        if !self.markets.contains_key(&spread.buy_leg)
+            || !self.markets.contains_key(&spread.sell_leg)
+        {
+            return Err(anyhow::anyhow!("Market not found"));
+        }
+        if order.quantity.get() == 0 {
+            return Err(anyhow::anyhow!("Order quantity must be positive"));
+        }
+        if self.account_manager.is_closed(&order.account_id) {
+            return Err(RejectReason::AccountClosed.into());
+        }
+        let book = self.spreads.books.entry(spread).or_default();
+        if book
+            .bids
+            .iter()
+            .chain(&book.asks)
+            .any(|resting| resting.id == order.id)
+        {
+            return Err(anyhow::anyhow!("Duplicate spread order ID"));
+        }
+        book.insert(order);
+        self.refresh_spread_books();
+        Ok(())
+    }
+
+    /// Cancel a resting spread order and withdraw its implied orders, returning what was
+    /// left of it
+    ///
+    /// # Arguments
+    ///
+    /// * `spread` - The spread of the order
+    /// * `order_id` - The ID of the order to cancel
+    /// * `side` - The side of the order
+    pub fn cancel_spread_order(
+        &mut self,
+        spread: Spread,
+        order_id: OrderId,
+        side: Side,
+    ) -> Result<SpreadOrder> {
+        let book = self
+            .spreads
+            .books
+            .get_mut(&spread)
+            .ok_or(RejectReason::UnknownOrder)?;
+        let orders = book.side_mut(side);
+        let index = orders
+            .iter()
+            .position(|order| order.id == order_id)
+            .ok_or(RejectReason::UnknownOrder)?;
+        let order = orders.remove(index);
+        if book.bids.is_empty() && book.asks.is_empty() {
+            self.spreads.books.remove(&spread);
+        }
+        self.refresh_spread_books();
+        Ok(order)
+    }
+
+    /// The resting orders of one side of a spread's book, best first.
+    pub fn spread_orders(&self, spread: Spread, side: Side) -> &[SpreadOrder] {
+        self.spreads
+            .books
+            .get(&spread)
+            .map_or(&[], |book| book.side(side))
+    }
+
+    /// Hedge the implied orders that filled and regenerate those a change of the market's
+    /// book made stale.
+    pub(crate) fn refresh_implied_orders(&mut self, pair: Pair) {
+        if self.spreads.affects(pair) {
+            self.refresh_spread_books();
+        }
+    }
+
+    fn refresh_spread_books(&mut self) {
+        if self.spreads.refreshing {
+            return;
+        }
+        self.spreads.refreshing = true;
+        loop {
+            for (quantity, leg) in std::mem::take(&mut self.spreads.fills) {
+                self.hedge_implied_fill(quantity, leg);
+            }
+            self.update_implied_orders();
+            // Implied orders that traded on arrival are hedged in turn
+            if self.spreads.fills.is_empty() {
+                break;
+            }
+        }
+        self.spreads.refreshing = false;
+    }
+
+    /// Trade the other leg of a filled implied order, and take the fill off its spread order.
+    fn hedge_implied_fill(&mut self, quantity: Quantity, leg: ImpliedLeg) {
+        let (asset, unit) = leg.hedge_hold;
+        self.add_balance(leg.account_id.clone(), asset, unit * quantity.get());
+        let order_id = self.assigned_order_id(leg.hedge_pair);
+        let hedge = OrderBuilder::new(order_id, leg.side.opposite(), leg.account_id.clone())
+            .price(leg.hedge_price)
+            .quantity(quantity)
+            .timestamp(leg.timestamp)
+            .time_in_force(TimeInForce::Ioc)
+            .build();
+        let filled = match hedge.map(|hedge| self.submit_order(hedge, leg.hedge_pair)) {
+            Ok(Ok(report)) => {
+                self.record_order_id(order_id, leg.hedge_pair);
+                report.filled()
+            }
+            _ => Quantity::new(0),
+        };
+        if filled < quantity {
+            self.events.push(ExchangeEvent::ImpliedHedgeShortfall {
+                pair: leg.hedge_pair,
+                account_id: leg.account_id.clone(),
+                quantity: quantity - filled,
+            });
+        }
+        self.spreads
+            .fill(leg.spread, leg.spread_side, leg.spread_order_id, quantity);
+    }
+
+    /// Withdraw the implied orders that no longer match the spread books and the legs' top
+    /// levels, and post the missing ones, stopping at the first that trades on arrival.
+    fn update_implied_orders(&mut self) {
+        let mut spreads: Vec<Spread> = self.spreads.books.keys().copied().collect();
+        spreads.sort_by_key(|spread| {
+            (
+                spread.buy_leg.base.symbol,
+                spread.sell_leg.base.symbol,
+                spread.buy_leg.numeraire.symbol,
+            )
+        });
+        let wanted: Vec<ImpliedLeg> = spreads
+            .into_iter()
+            .flat_map(|spread| self.implied_legs(spread))
+            .collect();
+
+        for implied in std::mem::take(&mut self.spreads.implied) {
+            let resting = self.markets[&implied.leg.pair]
+                .resting_order(implied.order_id, implied.leg.side, implied.leg.price)
+                .is_some_and(|order| order.quantity == implied.leg.quantity);
+            if resting && wanted.contains(&implied.leg) {
+                self.spreads.implied.push(implied);
+            } else {
+                self.withdraw_implied_order(implied);
+            }
+        }
+        for leg in wanted {
+            if self
+                .spreads
+                .implied
+                .iter()
+                .all(|implied| implied.leg != leg)
+            {
+                self.post_implied_order(leg);
+                if !self.spreads.fills.is_empty() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The implied orders the best bid and ask of a spread should have in the legs' books.
+    fn implied_legs(&self, spread: Spread) -> Vec<ImpliedLeg> {
+        let Some(book) = self.spreads.books.get(&spread) else {
+            return Vec::new();
+        };
+        if self.is_pre_open(spread.buy_leg) || self.is_pre_open(spread.sell_leg) {
+            return Vec::new();
+        }
+        let mut legs = Vec::new();
+        for order in [book.bids.first(), book.asks.first()].into_iter().flatten() {
+            // Into the buy leg on the spread order's side, priced off the sell leg, and into
+            // the sell leg on the other side, priced off the buy leg
+            let placements = [
+                (spread.buy_leg, order.side, spread.sell_leg, 1),
+                (spread.sell_leg, order.side.opposite(), spread.buy_leg, -1),
+            ];
+            for (pair, side, hedge_pair, sign) in placements {
+                let Some((hedge_price, available)) = self.outright_top(hedge_pair, side) else {
+                    continue;
+                };
+                let price = i128::from(hedge_price.get()) + sign * i128::from(order.price);
+                let Some(price) = u64::try_from(price).ok().map(Price::new) else {
+                    continue;
+                };
+                if !self.markets[&pair].supports_price(price) {
+                    continue;
+                }
+                let quantity = order.quantity.min(available);
+                let hedge = Order::new(
+                    OrderId::default(),
+                    hedge_price,
+                    Quantity::new(1),
+                    side.opposite(),
+                    order.account_id.clone(),
+                    order.timestamp,
+                );
+                let fees = self.markets[&hedge_pair].config.fees;
+                let Some(hedge_hold) = Self::checked_hold_for(&hedge, hedge_pair, fees) else {
+                    continue;
+                };
+                legs.push(ImpliedLeg {
+                    spread,
+                    spread_side: order.side,
+                    spread_order_id: order.id,
+                    account_id: order.account_id.clone(),
+                    timestamp: order.timestamp,
+                    pair,
+                    side,
+                    price,
+                    quantity,
+                    hedge_pair,
+                    hedge_price,
+                    hedge_hold,
+                });
+            }
+        }
+        legs
+    }
+
+    /// The best price on one side of a market's book and the quantity resting there,
+    /// leaving out implied orders.
+    fn outright_top(&self, pair: Pair, side: Side) -> Option<(Price, Quantity)> {
+        let book = self.markets.get(&pair)?.matching_engine.orderbook();
+        book.levels(side).find_map(|(price, orders)| {
+            let quantity = orders
+                .iter()
+                .filter(|order| {
+                    !self.spreads.implied.iter().any(|implied| {
+                        implied.order_id == order.id
+                            && implied.leg.pair == pair
+                            && implied.leg.side == side
+                    })
+                })
+                .fold(0u64, |total, order| {
+                    total.saturating_add(order.quantity.get())
+                });
+            (quantity > 0).then_some((price, Quantity::new(quantity)))
+        })
+    }
+
+    /// Post an implied order after taking the hold of its hedge. Orders the account cannot
+    /// fund, or the market does not accept, are left out.
+    fn post_implied_order(&mut self, leg: ImpliedLeg) {
+        let (asset, unit) = leg.hedge_hold;
+        let Some(reserve) = unit.checked_mul(leg.quantity.get()) else {
+            return;
+        };
+        if self
+            .take_for_order(&leg.account_id, asset, reserve)
+            .is_err()
+        {
+            return;
+        }
+        let order_id = self.assigned_order_id(leg.pair);
+        let order = Order::new(
+            order_id,
+            leg.price,
+            leg.quantity,
+            leg.side,
+            leg.account_id.clone(),
+            leg.timestamp,
+        );
+        let pair = leg.pair;
+        // Registered first, so that fills on arrival are queued for hedging
+        self.spreads.implied.push(ImpliedOrder {
+            order_id,
+            leg: leg.clone(),
+        });
+        if self.submit_order(order, pair).is_ok() {
+            self.record_order_id(order_id, pair);
+        } else {
+            self.spreads
+                .implied
+                .retain(|implied| implied.order_id != order_id || implied.leg.pair != pair);
+            self.add_balance(leg.account_id, asset, reserve);
+        }
+    }
+
+    /// Cancel an implied order and release its hold and the hold of its hedge.
+    fn withdraw_implied_order(&mut self, implied: ImpliedOrder) {
+        let leg = implied.leg;
+        let market = self.markets.get_mut(&leg.pair).unwrap();
+        let fees = market.config.fees;
+        // The order may have left the book already, releasing its own hold
+        if let Some(order) = market.cancel_order(implied.order_id, leg.side, leg.price) {
+            let (asset, amount) = Self::hold_for(&order, leg.pair, fees);
+            self.add_balance(order.account_id, asset, amount);
+        }
+        let (asset, unit) = leg.hedge_hold;
+        self.add_balance(leg.account_id, asset, unit * leg.quantity.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::Unsnapshotted;
+
+    use super::*;
+
+    fn account(name: &str) -> AccountId {
+        AccountId::new(name.to_string())
+    }
+
+    /// A calendar spread between two months, with the front month bid at 100 and the back
+    /// month offered at 115.
+    fn calendar() -> (Exchange, Spread) {
+        let usd = Asset::new("USD");
+        let front = Pair {
+            numeraire: usd,
+            base: Asset::new("BTC-MAR"),
+        };
+        let back = Pair {
+            numeraire: usd,
+            base: Asset::new("BTC-JUN"),
+        };
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(front));
+        exchange.add_market(Market::new(back));
+        for name in ["mm", "trader"] {
+            exchange.add_balance(account(name), usd, 10_000);
+            exchange.add_balance(account(name), front.base, 10);
+        }
+        exchange.add_balance(account("mm"), back.base, 10);
+        let quotes = [(front, Side::Bid, 100), (back, Side::Ask, 115)];
+        for (id, (pair, side, price)) in quotes.into_iter().enumerate() {
+            let order = Order::new(
+                OrderId::new(id as u64 + 1),
+                Price::new(price),
+                Quantity::new(10),
+                side,
+                account("mm"),
+                Timestamp::new(1),
+            );
+            exchange.post_order(order, pair).unwrap();
+        }
+        let spread = Spread {
+            buy_leg: back,
+            sell_leg: front,
+        };
+        (exchange, spread)
+    }
+
+    fn spread_bid(price: i64, quantity: u64) -> SpreadOrder {
+        SpreadOrder {
+            id: OrderId::new(1),
+            price,
+            quantity: Quantity::new(quantity),
+            side: Side::Bid,
+            account_id: account("trader"),
+            timestamp: Timestamp::new(2),
+        }
+    }
+
+    fn best(exchange: &Exchange, pair: Pair, side: Side) -> Option<u64> {
+        let book = exchange.markets[&pair].matching_engine.orderbook();
+        match side {
+            Side::Bid => book.get_best_bid(),
+            Side::Ask => book.get_best_ask(),
+        }
+    }
+
+    #[test]
+    fn test_resting_spread_order_trades_through_implied_orders() {
+        let (mut exchange, spread) = calendar();
+        let (back, front) = (spread.buy_leg, spread.sell_leg);
+        exchange
+            .rest_spread_order(spread, spread_bid(10, 5))
+            .unwrap();
+
+        // Bids 10 over the front month's bid in the back month, and offers 10 under the
+        // back month's offer in the front month
+        assert_eq!(best(&exchange, back, Side::Bid), Some(110));
+        assert_eq!(best(&exchange, front, Side::Ask), Some(105));
+        assert_eq!(
+            exchange.unsnapshotted_state(),
+            vec![Unsnapshotted::SpreadOrders { count: 1 }]
+        );
+
+        // Selling into the implied bid sells the front month to its bid at the same time
+        exchange.add_balance(account("seller"), back.base, 3);
+        let ask = Order::new(
+            OrderId::new(3),
+            Price::new(110),
+            Quantity::new(3),
+            Side::Ask,
+            account("seller"),
+            Timestamp::new(3),
+        );
+        let report = exchange.post_order(ask, back).unwrap();
+        assert_eq!(report.filled(), Quantity::new(3));
+        assert_eq!(exchange.position(&account("trader"), back), 3);
+        assert_eq!(exchange.position(&account("trader"), front), -3);
+        assert_eq!(
+            exchange.spread_orders(spread, Side::Bid)[0].quantity,
+            Quantity::new(2)
+        );
+        assert_eq!(best(&exchange, back, Side::Bid), Some(110));
+
+        // Buying from the implied offer buys the back month from its offer
+        let bid = Order::new(
+            OrderId::new(4),
+            Price::new(105),
+            Quantity::new(5),
+            Side::Bid,
+            account("mm"),
+            Timestamp::new(4),
+        );
+        let report = exchange.post_order(bid, front).unwrap();
+        assert_eq!(report.filled(), Quantity::new(2));
+        assert_eq!(exchange.position(&account("trader"), back), 5);
+        assert_eq!(exchange.position(&account("trader"), front), -5);
+        assert!(exchange.spread_orders(spread, Side::Bid).is_empty());
+        assert!(exchange.unsnapshotted_state().is_empty());
+
+        // Every unit of the spread cost 10, and nothing is left held
+        let usd = back.numeraire;
+        assert_eq!(exchange.get_balance(account("trader"), usd).unwrap(), 9_950);
+        assert_eq!(exchange.locked_balance(&account("trader"), usd), 0);
+        assert_eq!(
+            exchange.get_balance(account("trader"), front.base).unwrap(),
+            5
+        );
+        assert_eq!(best(&exchange, back, Side::Bid), None);
+        assert_eq!(best(&exchange, front, Side::Bid), Some(105));
+    }
+
+    #[test]
+    fn test_resting_spread_order_executes_at_the_implied_price() {
+        let (mut exchange, spread) = calendar();
+        let (back, front) = (spread.buy_leg, spread.sell_leg);
+
+        // The legs imply 15, better than the order's 20
+        exchange
+            .rest_spread_order(spread, spread_bid(20, 4))
+            .unwrap();
+        assert!(exchange.spread_orders(spread, Side::Bid).is_empty());
+        assert_eq!(exchange.position(&account("trader"), back), 4);
+        assert_eq!(exchange.position(&account("trader"), front), -4);
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), back.numeraire)
+                .unwrap(),
+            9_940
+        );
+    }
+
+    #[test]
+    fn test_cancelling_a_spread_order_withdraws_its_implied_orders() {
+        let (mut exchange, spread) = calendar();
+        let (back, front) = (spread.buy_leg, spread.sell_leg);
+        exchange
+            .rest_spread_order(spread, spread_bid(10, 5))
+            .unwrap();
+        assert!(
+            exchange
+                .rest_spread_order(spread, spread_bid(9, 1))
+                .is_err()
+        );
+
+        let cancelled = exchange
+            .cancel_spread_order(spread, OrderId::new(1), Side::Bid)
+            .unwrap();
+        assert_eq!(cancelled.quantity, Quantity::new(5));
+        assert_eq!(best(&exchange, back, Side::Bid), None);
+        assert_eq!(best(&exchange, front, Side::Ask), None);
+        assert_eq!(
+            exchange
+                .get_balance(account("trader"), back.numeraire)
+                .unwrap(),
+            10_000
+        );
+        assert_eq!(
+            exchange.get_balance(account("trader"), front.base).unwrap(),
+            10
+        );
+        assert!(
+            exchange
+                .cancel_spread_order(spread, OrderId::new(1), Side::Bid)
+                .is_err()
+        );
+    }
+}
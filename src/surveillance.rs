@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::order::AccountId;
+
+/// Thresholds of the order-to-trade monitor.
+#[derive(Debug, Clone, Copy)]
+pub struct SurveillanceConfig {
+    /// Length of the rolling window, in timestamp units.
+    pub window: u64,
+    /// Maximum orders per trade within the window.
+    pub max_order_to_trade: u64,
+    /// Maximum cancels per fill within the window.
+    pub max_cancel_to_fill: u64,
+    /// Ratios are only evaluated once an account has sent this many orders in the window.
+    pub min_orders: u64,
+    /// Reject new orders from accounts in breach until their ratios recover.
+    pub throttle: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    OrderToTrade,
+    CancelToFill,
+}
+
+/// Raised when an account starts breaching a threshold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurveillanceAlert {
+    pub account_id: AccountId,
+    pub time: u64,
+    pub kind: AlertKind,
+    /// Events in the window: orders or cancels.
+    pub numerator: u64,
+    /// Events in the window: trades or fills.
+    pub denominator: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Activity {
+    Order,
+    Cancel,
+    Fill,
+}
+
+/// Monitors per-account order-to-trade and cancel-to-fill ratios over a rolling window,
+/// raising alerts and optionally throttling accounts that breach the configured thresholds.
+///
+/// Time is taken from the events themselves; events without a timestamp (cancels) are
+/// recorded at the latest time seen.
+#[derive(Debug)]
+pub struct Surveillance {
+    config: SurveillanceConfig,
+    now: u64,
+    activity: HashMap<AccountId, VecDeque<(u64, Activity)>>,
+    breaches: HashSet<(AccountId, AlertKind)>,
+    alerts: Vec<SurveillanceAlert>,
+}
+
+impl Surveillance {
+    pub fn new(config: SurveillanceConfig) -> Self {
+        Self {
+            config,
+            now: 0,
+            activity: HashMap::new(),
+            breaches: HashSet::new(),
+            alerts: Vec::new(),
+        }
+    }
+
+    /// Record an order sent by an account.
+    pub fn record_order(&mut self, account_id: &AccountId, time: u64) {
+        self.record(account_id, time, Activity::Order);
+    }
+
+    /// Record a cancel sent by an account.
+    pub fn record_cancel(&mut self, account_id: &AccountId) {
+        self.record(account_id, self.now, Activity::Cancel);
+    }
+
+    /// Record a fill received by an account.
+    pub fn record_fill(&mut self, account_id: &AccountId, time: u64) {
+        self.record(account_id, time, Activity::Fill);
+    }
+
+    /// Returns true if new orders from the account should be rejected.
+    pub fn is_throttled(&self, account_id: &AccountId) -> bool {
+        self.config.throttle
+            && self
+                .breaches
+                .iter()
+                .any(|(breaching, _)| breaching == account_id)
+    }
+
+    /// Every alert raised so far, oldest first.
+    pub fn alerts(&self) -> &[SurveillanceAlert] {
+        &self.alerts
+    }
+
+    /// Remove and return the alerts raised so far.
+    pub fn drain_alerts(&mut self) -> Vec<SurveillanceAlert> {
+        std::mem::take(&mut self.alerts)
+    }
+
+    fn record(&mut self, account_id: &AccountId, time: u64, activity: Activity) {
+        self.now = self.now.max(time);
+        let events = self.activity.entry(account_id.clone()).or_default();
+        events.push_back((time, activity));
+
+        let start = self.now.saturating_sub(self.config.window);
+        while events.front().is_some_and(|(t, _)| *t < start) {
+            events.pop_front();
+        }
+
+        let count = |kind| events.iter().filter(|(_, a)| *a == kind).count() as u64;
+        let (orders, cancels, fills) = (
+            count(Activity::Order),
+            count(Activity::Cancel),
+            count(Activity::Fill),
+        );
+        if orders < self.config.min_orders {
+            self.clear(account_id);
+            return;
+        }
+        self.evaluate(
+            account_id,
+            AlertKind::OrderToTrade,
+            orders,
+            fills,
+            self.config.max_order_to_trade,
+        );
+        self.evaluate(
+            account_id,
+            AlertKind::CancelToFill,
+            cancels,
+            fills,
+            self.config.max_cancel_to_fill,
+        );
+    }
+
+    fn evaluate(
+        &mut self,
+        account_id: &AccountId,
+        kind: AlertKind,
+        numerator: u64,
+        denominator: u64,
+        max_ratio: u64,
+    ) {
+        let key = (account_id.clone(), kind);
+        if numerator > max_ratio.saturating_mul(denominator.max(1)) {
+            if self.breaches.insert(key) {
+                self.alerts.push(SurveillanceAlert {
+                    account_id: account_id.clone(),
+                    time: self.now,
+                    kind,
+                    numerator,
+                    denominator,
+                });
+            }
+        } else {
+            self.breaches.remove(&key);
+        }
+    }
+
+    fn clear(&mut self, account_id: &AccountId) {
+        self.breaches
+            .retain(|(breaching, _)| breaching != account_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_to_trade_breach_throttles_until_window_rolls() {
+        let mut surveillance = Surveillance::new(SurveillanceConfig {
+            window: 10,
+            max_order_to_trade: 3,
+            max_cancel_to_fill: 100,
+            min_orders: 2,
+            throttle: true,
+        });
+        let spoofer = AccountId::new("spoofer".to_string());
+
+        for time in 0..3 {
+            surveillance.record_order(&spoofer, time);
+        }
+        surveillance.record_fill(&spoofer, 3);
+        assert!(!surveillance.is_throttled(&spoofer));
+
+        surveillance.record_order(&spoofer, 4);
+        assert!(surveillance.is_throttled(&spoofer));
+        assert_eq!(
+            surveillance.alerts(),
+            &[SurveillanceAlert {
+                account_id: spoofer.clone(),
+                time: 4,
+                kind: AlertKind::OrderToTrade,
+                numerator: 4,
+                denominator: 1,
+            }]
+        );
+
+        // Still breaching: no duplicate alert
+        surveillance.record_order(&spoofer, 5);
+        assert_eq!(surveillance.alerts().len(), 1);
+
+        // Once the burst leaves the window the account recovers
+        surveillance.record_order(&spoofer, 20);
+        surveillance.record_order(&spoofer, 21);
+        surveillance.record_fill(&spoofer, 21);
+        assert!(!surveillance.is_throttled(&spoofer));
+    }
+}
@@ -0,0 +1,198 @@
+//! Fills, fees and profit and loss of an account, broken down by order tag.
+
+use crate::{
+    exchange::Exchange,
+    market::Pair,
+    matching::Trade,
+    order::{AccountId, OrderTag, Price, Side, Timestamp},
+};
+
+/// What one tag of an account traded in a market over a period.
+///
+/// Fees are the market's percentage fees; flat fees are charged per order rather than per
+/// fill and are not attributed to tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagReport {
+    /// The tag, or `None` for the account's untagged orders.
+    pub tag: Option<OrderTag>,
+    pub fills: usize,
+    /// Base quantity bought.
+    pub bought: u64,
+    /// Base quantity sold.
+    pub sold: u64,
+    /// Numeraire paid for the base bought, before fees.
+    pub spent: u64,
+    /// Numeraire received for the base sold, before fees.
+    pub received: u64,
+    /// Numeraire paid in fees.
+    pub fees: u64,
+}
+
+impl TagReport {
+    /// The base position the tag built over the period: positive when it bought more than
+    /// it sold.
+    pub fn position(&self) -> i64 {
+        self.bought as i64 - self.sold as i64
+    }
+
+    /// Profit and loss in numeraire, after fees, with the position valued at `mark`.
+    pub fn pnl(&self, mark: Price) -> i128 {
+        self.received as i128 - self.spent as i128 - self.fees as i128
+            + self.position() as i128 * mark.get() as i128
+    }
+
+    fn record(&mut self, side: Side, trade: &Trade, fee: u64) {
+        let notional = trade.quantity.get() * trade.price.get();
+        self.fills += 1;
+        self.fees += fee;
+        match side {
+            Side::Bid => {
+                self.bought += trade.quantity.get();
+                self.spent += notional;
+            }
+            Side::Ask => {
+                self.sold += trade.quantity.get();
+                self.received += notional;
+            }
+        }
+    }
+}
+
+impl Exchange {
+    /// Break down an account's fills in a market by order tag
+    ///
+    /// Covers the trades executed from `from` until before `to`, by the time they executed.
+    /// Returns one report per tag that filled, untagged fills first and then in tag order.
+    /// A trade between two orders of the account counts once on each side.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The ID of the account
+    /// * `pair` - The market to report on
+    /// * `from` - The start of the period
+    /// * `to` - The end of the period, excluded
+    pub fn tag_reports(
+        &self,
+        account_id: &AccountId,
+        pair: Pair,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Vec<TagReport> {
+        let Some(market) = self.markets.get(&pair) else {
+            return Vec::new();
+        };
+        let fees = market.config.fees;
+        let mut reports: Vec<TagReport> = Vec::new();
+        let trades = market
+            .timed_trades()
+            .filter(|(time, _)| from <= *time && *time < to);
+        for (_, trade) in trades {
+            for (side, tag) in [(Side::Bid, &trade.bid_tag), (Side::Ask, &trade.ask_tag)] {
+                if trade.account_id(side) != account_id {
+                    continue;
+                }
+                let fee = fees.fee(
+                    trade.liquidity(side),
+                    trade.quantity.get() * trade.price.get(),
+                );
+                let index = match reports.binary_search_by(|report| report.tag.cmp(tag)) {
+                    Ok(index) => index,
+                    Err(index) => {
+                        reports.insert(
+                            index,
+                            TagReport {
+                                tag: tag.clone(),
+                                ..TagReport::default()
+                            },
+                        );
+                        index
+                    }
+                };
+                reports[index].record(side, trade, fee);
+            }
+        }
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::{FeeSchedule, Market, MarketConfig},
+        order::{Order, OrderId, Quantity},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_tag_reports() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::with_config(
+            pair,
+            MarketConfig {
+                fees: FeeSchedule {
+                    maker_fee_bps: 0,
+                    taker_fee_bps: 100,
+                    ..FeeSchedule::default()
+                },
+                ..MarketConfig::default()
+            },
+        ));
+        exchange.add_balance(alice.clone(), pair.numeraire, 10_000);
+        exchange.add_balance(alice.clone(), pair.base, 10);
+        exchange.add_balance(bob.clone(), pair.numeraire, 10_000);
+        exchange.add_balance(bob.clone(), pair.base, 10);
+        let order =
+            |id: u64, price: u64, side: Side, account_id: &AccountId, tag: Option<&str>| Order {
+                tag: tag.map(|tag| OrderTag::new(tag.to_string())),
+                ..Order::new(
+                    OrderId::new(id),
+                    Price::new(price),
+                    Quantity::new(2),
+                    side,
+                    account_id.clone(),
+                    Timestamp::new(id),
+                )
+            };
+        let orders = [
+            // Alice's maker strategy buys at 100 and sells at 110
+            order(1, 100, Side::Bid, &alice, Some("maker")),
+            order(2, 100, Side::Ask, &bob, None),
+            order(3, 110, Side::Ask, &alice, Some("maker")),
+            order(4, 110, Side::Bid, &bob, None),
+            // Her momentum strategy takes an ask
+            order(5, 120, Side::Ask, &bob, None),
+            order(6, 120, Side::Bid, &alice, Some("momentum")),
+            // After the period
+            order(7, 130, Side::Ask, &bob, None),
+            order(8, 130, Side::Bid, &alice, None),
+        ];
+        for order in orders {
+            exchange.post_order(order, pair).unwrap();
+        }
+
+        let reports = exchange.tag_reports(&alice, pair, Timestamp::new(0), Timestamp::new(7));
+        assert_eq!(reports.len(), 2);
+        let maker = &reports[0];
+        assert_eq!(maker.tag, Some(OrderTag::new("maker".to_string())));
+        assert_eq!((maker.fills, maker.position(), maker.fees), (2, 0, 0));
+        assert_eq!(maker.pnl(Price::new(1_000)), 20);
+        let momentum = &reports[1];
+        assert_eq!(momentum.tag, Some(OrderTag::new("momentum".to_string())));
+        assert_eq!((momentum.fills, momentum.position()), (1, 2));
+        // Paid 240 plus 1% taker fee, now worth 250
+        assert_eq!(momentum.fees, 2);
+        assert_eq!(momentum.pnl(Price::new(125)), 8);
+
+        let all = exchange.tag_reports(&alice, pair, Timestamp::new(0), Timestamp::new(100));
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].tag, None);
+    }
+}
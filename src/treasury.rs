@@ -0,0 +1,302 @@
+//! Automated rebalancing of system-account inventory, mainly for simulation studies.
+//!
+//! System accounts such as the fees account accumulate whatever assets their markets pay
+//! out. A `Treasury` keeps each watched account near a target share of its value in the
+//! base of a market by trading on the exchange's own books. System accounts cannot trade, so
+//! orders go through a desk account: the account's funds are moved to the desk, an
+//! immediate-or-cancel order is posted, and everything the desk holds in the market's
+//! assets is moved back. Every attempt is written to an audit log.
+
+use crate::{
+    account::SystemAccount,
+    exchange::Exchange,
+    market::Pair,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, TimeInForce, Timestamp},
+};
+
+/// The allocation a system account is kept at in one market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreasuryTarget {
+    pub account: SystemAccount,
+    pub pair: Pair,
+    /// Share of the account's value in the market to hold in base, in basis points.
+    pub base_share_bps: u64,
+    /// How far the base share may drift from the target before it is rebalanced, in basis
+    /// points of the account's value.
+    pub tolerance_bps: u64,
+}
+
+/// Limits every rebalancing order must respect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreasuryLimits {
+    /// Most base traded by a single order.
+    pub max_quantity: u64,
+    /// How far from the last trade price an order may execute, in basis points.
+    pub max_slippage_bps: u64,
+}
+
+/// An entry of the audit log: one rebalancing attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebalanceRecord {
+    pub time: Timestamp,
+    pub target: TreasuryTarget,
+    /// The last trade price the account was valued at.
+    pub reference_price: Price,
+    pub side: Side,
+    /// The quantity ordered, after the limits were applied.
+    pub quantity: Quantity,
+    pub limit_price: Price,
+    pub filled: Quantity,
+    /// Why the order was not posted or was rejected, if it was not accepted.
+    pub rejection: Option<String>,
+}
+
+/// Rebalances system accounts toward their targets.
+#[derive(Debug)]
+pub struct Treasury {
+    /// The account orders are posted from. It must not be used for anything else, since its
+    /// balances in the traded assets are swept back after every order.
+    desk: AccountId,
+    targets: Vec<TreasuryTarget>,
+    limits: TreasuryLimits,
+    log: Vec<RebalanceRecord>,
+}
+
+impl Treasury {
+    /// Create a treasury trading through `desk`, a regular account of its own.
+    pub fn new(desk: AccountId, limits: TreasuryLimits) -> Self {
+        Self {
+            desk,
+            targets: Vec::new(),
+            limits,
+            log: Vec::new(),
+        }
+    }
+
+    /// Watch a system account in a market. Targets are rebalanced in the order they were
+    /// added.
+    pub fn add_target(&mut self, target: TreasuryTarget) {
+        self.targets.push(target);
+    }
+
+    /// Every rebalancing attempt so far, oldest first.
+    pub fn audit_log(&self) -> &[RebalanceRecord] {
+        &self.log
+    }
+
+    /// Trade every target that drifted beyond its tolerance back toward it
+    ///
+    /// Accounts are valued at the market's last trade price; markets that have not traded
+    /// are skipped. Returns the records added to the audit log.
+    ///
+    /// # Arguments
+    ///
+    /// * `exchange` - The exchange holding the accounts and markets
+    /// * `now` - The time of the orders
+    pub fn rebalance(&mut self, exchange: &mut Exchange, now: Timestamp) -> &[RebalanceRecord] {
+        let start = self.log.len();
+        for target in self.targets.clone() {
+            if let Some(record) = self.rebalance_target(exchange, target, now) {
+                self.log.push(record);
+            }
+        }
+        &self.log[start..]
+    }
+
+    fn rebalance_target(
+        &self,
+        exchange: &mut Exchange,
+        target: TreasuryTarget,
+        now: Timestamp,
+    ) -> Option<RebalanceRecord> {
+        let pair = target.pair;
+        let price = exchange.markets.get(&pair)?.last_trade_price()?;
+        let account = target.account.id();
+        let balance = |exchange: &Exchange, asset| {
+            exchange.get_balance(account.clone(), asset).unwrap_or(0) as u128
+        };
+        let (base, numeraire) = (
+            balance(exchange, pair.base),
+            balance(exchange, pair.numeraire),
+        );
+        let p = price.get() as u128;
+        let value = base * p + numeraire;
+        if value == 0 {
+            return None;
+        }
+        let target_base = value * target.base_share_bps as u128 / 10_000 / p;
+        let drift = target_base.abs_diff(base);
+        if drift * p * 10_000 <= value * target.tolerance_bps as u128 {
+            return None;
+        }
+
+        let slippage = p * self.limits.max_slippage_bps as u128 / 10_000;
+        let (side, limit, quantity) = if target_base > base {
+            let limit = p + slippage;
+            (Side::Bid, limit, drift.min(numeraire / limit))
+        } else {
+            (Side::Ask, p.saturating_sub(slippage), drift)
+        };
+        let quantity = quantity.min(self.limits.max_quantity as u128) as u64;
+        let mut record = RebalanceRecord {
+            time: now,
+            target,
+            reference_price: price,
+            side,
+            quantity: Quantity::new(quantity),
+            limit_price: Price::new(limit as u64),
+            filled: Quantity::new(0),
+            rejection: None,
+        };
+        if quantity == 0 {
+            record.rejection = Some("Nothing to trade within the limits".to_string());
+            return Some(record);
+        }
+
+        // Fund the desk with everything the order may spend, then sweep it all back
+        let (spent, amount) = match side {
+            Side::Bid => (pair.numeraire, numeraire as u64),
+            Side::Ask => (pair.base, quantity),
+        };
+        if let Err(error) = exchange.remove_balance(account.clone(), spent, amount) {
+            record.rejection = Some(error.to_string());
+            return Some(record);
+        }
+        exchange.add_balance(self.desk.clone(), spent, amount);
+        let order = Order {
+            time_in_force: Some(TimeInForce::Ioc),
+            ..Order::new(
+                OrderId::new(0),
+                record.limit_price,
+                record.quantity,
+                side,
+                self.desk.clone(),
+                now,
+            )
+        };
+        match exchange.place_order(order, pair) {
+            Ok(report) => record.filled = report.filled(),
+            Err(error) => record.rejection = Some(error.to_string()),
+        }
+        for asset in [pair.base, pair.numeraire] {
+            let held = exchange.get_balance(self.desk.clone(), asset).unwrap_or(0);
+            if held > 0
+                && exchange
+                    .remove_balance(self.desk.clone(), asset, held)
+                    .is_ok()
+            {
+                exchange.add_balance(account.clone(), asset, held);
+            }
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{asset::Asset, market::Market};
+
+    use super::*;
+
+    #[test]
+    fn test_treasury_rebalances_within_limits() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let fees = SystemAccount::Fees;
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let desk = AccountId::new("desk".to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(alice.clone(), pair.base, 20);
+        exchange.add_balance(bob.clone(), pair.numeraire, 1_000);
+        exchange.add_balance(fees.id(), pair.numeraire, 2_000);
+        let order = |id: u64, price: u64, side: Side, account: &AccountId| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(5),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            )
+        };
+        for (id, price, side, account) in [
+            (1, 100, Side::Ask, &alice),
+            (2, 100, Side::Bid, &bob),
+            (3, 101, Side::Ask, &alice),
+            (4, 120, Side::Ask, &alice),
+        ] {
+            exchange
+                .post_order(order(id, price, side, account), pair)
+                .unwrap();
+        }
+        let balance = |exchange: &Exchange, account: &AccountId, asset| {
+            exchange.get_balance(account.clone(), asset).unwrap()
+        };
+
+        let mut treasury = Treasury::new(
+            desk.clone(),
+            TreasuryLimits {
+                max_quantity: 8,
+                max_slippage_bps: 200,
+            },
+        );
+        let target = TreasuryTarget {
+            account: fees,
+            pair,
+            base_share_bps: 5_000,
+            tolerance_bps: 500,
+        };
+        treasury.add_target(target);
+
+        // Half of 2,000 is 10 BTC at 100, capped at 8 and at 102
+        let records = treasury
+            .rebalance(&mut exchange, Timestamp::new(10))
+            .to_vec();
+        assert_eq!(
+            records,
+            vec![RebalanceRecord {
+                time: Timestamp::new(10),
+                target,
+                reference_price: Price::new(100),
+                side: Side::Bid,
+                quantity: Quantity::new(8),
+                limit_price: Price::new(102),
+                filled: Quantity::new(5),
+                rejection: None,
+            }]
+        );
+        // Everything the desk held went back to the fees account
+        assert_eq!(balance(&exchange, &fees.id(), pair.base), 5);
+        assert_eq!(balance(&exchange, &fees.id(), pair.numeraire), 1_495);
+        assert_eq!(balance(&exchange, &desk, pair.numeraire), 0);
+
+        // The ask at 120 is outside the slippage band
+        let records = treasury
+            .rebalance(&mut exchange, Timestamp::new(11))
+            .to_vec();
+        assert_eq!(
+            (records[0].quantity, records[0].filled),
+            (Quantity::new(4), Quantity::new(0))
+        );
+        exchange
+            .post_order(order(12, 102, Side::Ask, &alice), pair)
+            .unwrap();
+        let records = treasury
+            .rebalance(&mut exchange, Timestamp::new(13))
+            .to_vec();
+        assert_eq!(records[0].filled, Quantity::new(4));
+        assert_eq!(balance(&exchange, &fees.id(), pair.base), 9);
+
+        // Back within tolerance, so nothing is traded
+        assert!(
+            treasury
+                .rebalance(&mut exchange, Timestamp::new(14))
+                .is_empty()
+        );
+        assert_eq!(treasury.audit_log().len(), 3);
+    }
+}
@@ -0,0 +1,245 @@
+//! Stop triggers driven by the trades a market executes.
+//!
+//! A market feeds the price of every trade it records to its `StopTriggers`, which keeps the
+//! range the prices covered since stops were last released. A buy stop fires once any trade
+//! printed at or above its stop price and a sell stop once any trade printed at or below it,
+//! so a sweep through several levels fires the stops of every level it crossed, not only
+//! those of the level it ended at. The exchange keeps releasing stops until no trade fires
+//! another, so cascades complete within the order that started them.
+//!
+//! Stops with an index trigger ignore trades and are fired the same way by the index prices
+//! supplied to the market, which lets stops work in markets that rarely trade.
+
+use crate::order::{Order, OrderId, Price, Side, StopTrigger};
+
+/// Pending stop orders and the trade prices that may fire them.
+#[derive(Debug, Clone, Default)]
+pub struct StopTriggers {
+    /// Stop orders waiting for their trigger, in arrival order.
+    pending: Vec<Order>,
+    /// Lowest and highest trade price since stops were last released.
+    trades: Option<(Price, Price)>,
+    /// Lowest and highest index price since stops were last released.
+    index: Option<(Price, Price)>,
+}
+
+impl StopTriggers {
+    /// Stop orders waiting for their trigger, in arrival order.
+    pub fn pending(&self) -> &[Order] {
+        &self.pending
+    }
+
+    /// Queue a stop order until a trade fires it.
+    pub fn queue(&mut self, order: Order) {
+        self.pending.push(order);
+    }
+
+    /// Put stops released but not yet posted back at the front of the queue. Their stop price
+    /// is cleared, so the next release returns them first whatever the prices.
+    pub fn requeue(&mut self, stops: Vec<Order>) {
+        self.pending.splice(0..0, stops);
+    }
+
+    /// Take a pending stop out of the queue.
+    pub fn cancel(&mut self, order_id: OrderId, side: Side) -> Option<Order> {
+        let index = self
+            .pending
+            .iter()
+            .position(|order| order.id == order_id && order.side == side)?;
+        Some(self.pending.remove(index))
+    }
+
+    /// Record the price of an executed trade.
+    pub fn observe(&mut self, price: Price) {
+        widen(&mut self.trades, price);
+    }
+
+    /// Record an index price.
+    pub fn observe_index(&mut self, price: Price) {
+        widen(&mut self.index, price);
+    }
+
+    /// Remove and return the stops fired by the prices observed since the last release or by
+    /// the current prices, in arrival order, with their stop price cleared so they match when
+    /// processed
+    ///
+    /// # Arguments
+    ///
+    /// * `last_price` - The market's last trade price, which also fires stops queued after
+    ///   the trade
+    /// * `index_price` - The market's index price, which also fires index stops queued after
+    ///   it was supplied
+    pub fn release(&mut self, last_price: Option<Price>, index_price: Option<Price>) -> Vec<Order> {
+        if let Some(last) = last_price {
+            self.observe(last);
+        }
+        if let Some(index) = index_price {
+            self.observe_index(index);
+        }
+        let (trades, index) = (self.trades.take(), self.index.take());
+        let mut triggered: Vec<Order> = self
+            .pending
+            .extract_if(.., |order| {
+                let range = match order.stop_trigger {
+                    StopTrigger::LastTrade => trades,
+                    StopTrigger::Index => index,
+                };
+                range.is_some_and(|(low, high)| order.is_triggered(low) || order.is_triggered(high))
+            })
+            .collect();
+        for order in &mut triggered {
+            order.stop_price = None;
+        }
+        triggered
+    }
+}
+
+/// Widen a price range to include `price`.
+fn widen(range: &mut Option<(Price, Price)>, price: Price) {
+    *range = Some(match *range {
+        Some((low, high)) => (low.min(price), high.max(price)),
+        None => (price, price),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        codec::{Decoder, Encoder},
+        exchange::Exchange,
+        market::{Market, Pair},
+        matching::Trade,
+        order::{AccountId, Quantity, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_stops_fire_on_any_trade_and_cascade() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 3);
+        exchange.add_balance(account("bob"), pair.numeraire, 1_000);
+        exchange.add_balance(account("carol"), pair.base, 1);
+        exchange.add_balance(account("dave"), pair.numeraire, 1_000);
+        exchange.add_balance(account("erin"), pair.base, 1);
+        let limit = |id: u64, price: u64, side: Side, name: &str| {
+            Order::new(
+                OrderId::new(id),
+                Price::new(price),
+                Quantity::new(1),
+                side,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+        let sell_stop = |id: u64, stop: u64, name: &str| Order {
+            stop_price: Some(Price::new(stop)),
+            ..Order::market(
+                OrderId::new(id),
+                Quantity::new(1),
+                Side::Ask,
+                account(name),
+                Timestamp::new(id),
+            )
+        };
+        for order in [
+            limit(1, 100, Side::Ask, "alice"),
+            limit(2, 101, Side::Ask, "alice"),
+            limit(3, 102, Side::Ask, "alice"),
+            limit(4, 90, Side::Bid, "dave"),
+            limit(5, 89, Side::Bid, "dave"),
+            sell_stop(6, 100, "carol"),
+            sell_stop(7, 95, "erin"),
+        ] {
+            exchange.post_order(order, pair).unwrap();
+        }
+
+        // The sweep ends at 102, but its first trade at 100 fires carol's stop, whose trade
+        // at 90 fires erin's
+        let report = exchange
+            .post_order(
+                Order {
+                    quantity: Quantity::new(3),
+                    ..limit(8, 102, Side::Bid, "bob")
+                },
+                pair,
+            )
+            .unwrap();
+        let prices = |trades: &[Trade]| -> Vec<u64> {
+            trades.iter().map(|trade| trade.price.get()).collect()
+        };
+        assert_eq!(prices(&report.fills), vec![100, 101, 102]);
+        assert_eq!(prices(&report.triggered), vec![90, 89]);
+        assert_eq!(report.triggered[1].ask_order_id, OrderId::new(7));
+        assert!(exchange.markets[&pair].pending_stops().is_empty());
+    }
+
+    #[test]
+    fn test_index_stops_fire_on_index_price() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let account = |name: &str| AccountId::new(name.to_string());
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(account("alice"), pair.base, 2);
+        exchange.add_balance(account("bob"), pair.numeraire, 1_000);
+        let bid = Order::new(
+            OrderId::new(1),
+            Price::new(90),
+            Quantity::new(1),
+            Side::Bid,
+            account("bob"),
+            Timestamp::new(1),
+        );
+        exchange.post_order(bid, pair).unwrap();
+        let stop = Order {
+            stop_price: Some(Price::new(95)),
+            stop_trigger: StopTrigger::Index,
+            ..Order::market(
+                OrderId::new(2),
+                Quantity::new(1),
+                Side::Ask,
+                account("alice"),
+                Timestamp::new(2),
+            )
+        };
+        exchange.post_order(stop.clone(), pair).unwrap();
+
+        // Stop orders survive the codec with their trigger
+        let mut encoder = Encoder::new();
+        encoder.order(&stop);
+        let encoded = encoder.finish();
+        assert_eq!(Decoder::new(&encoded).order().unwrap(), stop);
+
+        // The market never traded; only the index reaching the stop price fires it
+        let trades = exchange.update_index_price(pair, Price::new(96)).unwrap();
+        assert!(trades.is_empty());
+        let trades = exchange.update_index_price(pair, Price::new(94)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(
+            (trades[0].price, trades[0].ask_order_id),
+            (Price::new(90), stop.id)
+        );
+        assert!(exchange.markets[&pair].pending_stops().is_empty());
+        assert!(
+            exchange
+                .update_index_price(
+                    Pair {
+                        numeraire: Asset::new("USD"),
+                        base: Asset::new("ETH"),
+                    },
+                    Price::new(1),
+                )
+                .is_err()
+        );
+    }
+}
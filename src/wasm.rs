@@ -0,0 +1,217 @@
+//! JavaScript bindings for running the engine in the browser, enabled by the `wasm` feature.
+//!
+//! Build with `cargo build --lib --target wasm32-unknown-unknown --features wasm` and generate
+//! the JS glue with `wasm-bindgen`. Assets, accounts and sides are passed as strings; prices,
+//! quantities and IDs are `u64`, which JS sees as `BigInt`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    asset::Asset,
+    command::Command,
+    exchange::Exchange,
+    market::{Market, Pair},
+    matching::Trade,
+    order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+};
+
+/// A trade, flattened for JS.
+#[wasm_bindgen(getter_with_clone)]
+pub struct WasmTrade {
+    pub ask_order_id: u64,
+    pub bid_order_id: u64,
+    pub ask_account: String,
+    pub bid_account: String,
+    pub price: u64,
+    pub quantity: u64,
+    pub trade_id: u64,
+    pub sequence: u64,
+}
+
+impl From<Trade> for WasmTrade {
+    fn from(trade: Trade) -> Self {
+        Self {
+            ask_order_id: trade.ask_order_id.get(),
+            bid_order_id: trade.bid_order_id.get(),
+            ask_account: trade.ask_account_id.as_str().to_string(),
+            bid_account: trade.bid_account_id.as_str().to_string(),
+            price: trade.price.get(),
+            quantity: trade.quantity.get(),
+            trade_id: trade.id.get(),
+            sequence: trade.sequence,
+        }
+    }
+}
+
+/// An exchange driven from JS.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct WasmExchange {
+    exchange: Exchange,
+}
+
+#[wasm_bindgen]
+impl WasmExchange {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_market(&mut self, numeraire: &str, base: &str) {
+        self.exchange.add_market(Market::new(pair(numeraire, base)));
+    }
+
+    pub fn deposit(&mut self, account: &str, asset: &str, amount: u64) -> Result<(), JsError> {
+        self.exchange
+            .deposit(account_id(account), Asset::intern(asset), amount)
+            .map_err(js_error)
+    }
+
+    pub fn withdraw(&mut self, account: &str, asset: &str, amount: u64) -> Result<(), JsError> {
+        self.exchange
+            .withdraw(account_id(account), Asset::intern(asset), amount)
+            .map_err(js_error)
+    }
+
+    /// The balance of an account, zero for unknown accounts.
+    pub fn balance(&self, account: &str, asset: &str) -> u64 {
+        self.exchange
+            .get_balance(account_id(account), Asset::intern(asset))
+            .unwrap_or(0)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn post_limit_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+        quantity: u64,
+        account: &str,
+        timestamp: u64,
+    ) -> Result<Vec<WasmTrade>, JsError> {
+        let order = Order::new(
+            OrderId::new(id),
+            Price::new(price),
+            Quantity::new(quantity),
+            parse_side(side)?,
+            account_id(account),
+            Timestamp::new(timestamp),
+        );
+        self.post(order, pair(numeraire, base))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn post_market_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        quantity: u64,
+        account: &str,
+        timestamp: u64,
+    ) -> Result<Vec<WasmTrade>, JsError> {
+        let order = Order::market(
+            OrderId::new(id),
+            Quantity::new(quantity),
+            parse_side(side)?,
+            account_id(account),
+            Timestamp::new(timestamp),
+        );
+        self.post(order, pair(numeraire, base))
+    }
+
+    pub fn cancel_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+    ) -> Result<(), JsError> {
+        self.exchange
+            .cancel_order(
+                OrderId::new(id),
+                Price::new(price),
+                parse_side(side)?,
+                pair(numeraire, base),
+            )
+            .map_err(js_error)
+    }
+
+    pub fn reduce_order(
+        &mut self,
+        numeraire: &str,
+        base: &str,
+        id: u64,
+        side: &str,
+        price: u64,
+        quantity: u64,
+    ) -> Result<(), JsError> {
+        self.exchange
+            .execute(Command::ReduceOrder {
+                pair: pair(numeraire, base),
+                order_id: OrderId::new(id),
+                side: parse_side(side)?,
+                price: Price::new(price),
+                quantity: Quantity::new(quantity),
+            })
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    pub fn best_bid(&self, numeraire: &str, base: &str) -> Option<u64> {
+        self.exchange
+            .markets
+            .get(&pair(numeraire, base))?
+            .matching_engine
+            .orderbook()
+            .get_best_bid()
+    }
+
+    pub fn best_ask(&self, numeraire: &str, base: &str) -> Option<u64> {
+        self.exchange
+            .markets
+            .get(&pair(numeraire, base))?
+            .matching_engine
+            .orderbook()
+            .get_best_ask()
+    }
+}
+
+impl WasmExchange {
+    fn post(&mut self, order: Order, pair: Pair) -> Result<Vec<WasmTrade>, JsError> {
+        let report = self.exchange.post_order(order, pair).map_err(js_error)?;
+        Ok(report
+            .into_trades()
+            .into_iter()
+            .map(WasmTrade::from)
+            .collect())
+    }
+}
+
+fn pair(numeraire: &str, base: &str) -> Pair {
+    Pair {
+        numeraire: Asset::intern(numeraire),
+        base: Asset::intern(base),
+    }
+}
+
+fn account_id(account: &str) -> AccountId {
+    AccountId::new(account.to_string())
+}
+
+fn parse_side(side: &str) -> Result<Side, JsError> {
+    match side {
+        "bid" => Ok(Side::Bid),
+        "ask" => Ok(Side::Ask),
+        _ => Err(JsError::new("Side must be \"bid\" or \"ask\"")),
+    }
+}
+
+fn js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&error.to_string())
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+
+use crate::{
+    codec::{Decoder, Encoder},
+    command::Command,
+    exchange::Exchange,
+    matching::Trade,
+    migration::{self, Format},
+    snapshot::Snapshot,
+};
+
+/// The result of executing one command of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandOutcome {
+    Executed(Vec<Trade>),
+    /// The exchange refused the command without changing any state.
+    Rejected,
+}
+
+/// Everything an external prover needs to prove a batch: the state before the batch, the
+/// ordered commands, the outcome of each command and the state after the batch.
+///
+/// Witnesses only cover the state captured by `Snapshot`; batches run against exchanges
+/// with order groups, surveillance or pending stop orders cannot be re-executed from their
+/// witness.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchWitness {
+    pub pre_state: Snapshot,
+    pub commands: Vec<Command>,
+    pub outcomes: Vec<CommandOutcome>,
+    pub post_state: Snapshot,
+}
+
+impl BatchWitness {
+    /// Executes a batch of commands against the exchange, recording its witness.
+    pub fn record(exchange: &mut Exchange, commands: Vec<Command>) -> Self {
+        let pre_state = exchange.snapshot();
+        let outcomes = commands
+            .iter()
+            .map(|command| outcome(exchange.execute(command.clone())))
+            .collect();
+        BatchWitness {
+            pre_state,
+            commands,
+            outcomes,
+            post_state: exchange.snapshot(),
+        }
+    }
+
+    /// Re-executes the batch from its pre-state, failing if any outcome or the post-state
+    /// differs from the witness.
+    pub fn verify(&self) -> Result<()> {
+        if self.commands.len() != self.outcomes.len() {
+            return Err(anyhow::anyhow!(
+                "Witness has {} commands but {} outcomes",
+                self.commands.len(),
+                self.outcomes.len()
+            ));
+        }
+        let mut exchange = Exchange::from_snapshot(&self.pre_state);
+        for (i, (command, expected)) in self.commands.iter().zip(&self.outcomes).enumerate() {
+            let actual = outcome(exchange.execute(command.clone()));
+            if actual != *expected {
+                return Err(anyhow::anyhow!(
+                    "Command {} produced {:?}, witness expects {:?}",
+                    i,
+                    actual,
+                    expected
+                ));
+            }
+        }
+        if exchange.snapshot() != self.post_state {
+            return Err(anyhow::anyhow!("Post-state does not match witness"));
+        }
+        Ok(())
+    }
+
+    /// Encodes the witness in the current version of the canonical binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        migration::write_header(&mut encoder, Format::Witness);
+        self.pre_state.encode(&mut encoder);
+        encoder.len(self.commands.len());
+        for command in &self.commands {
+            encoder.command(command);
+        }
+        encoder.len(self.outcomes.len());
+        for outcome in &self.outcomes {
+            encoder.outcome(outcome);
+        }
+        self.post_state.encode(&mut encoder);
+        encoder.finish()
+    }
+
+    /// Decodes a witness from any version of the canonical binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let body = migration::load(Format::Witness, bytes)?;
+        let mut decoder = Decoder::new(&body);
+        let pre_state = Snapshot::decode(&mut decoder)?;
+        let mut commands = Vec::new();
+        for _ in 0..decoder.len()? {
+            commands.push(decoder.command()?);
+        }
+        let mut outcomes = Vec::new();
+        for _ in 0..decoder.len()? {
+            outcomes.push(decoder.outcome()?);
+        }
+        let post_state = Snapshot::decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(Self {
+            pre_state,
+            commands,
+            outcomes,
+            post_state,
+        })
+    }
+}
+
+fn outcome(result: Result<Vec<Trade>>) -> CommandOutcome {
+    match result {
+        Ok(trades) => CommandOutcome::Executed(trades),
+        Err(_) => CommandOutcome::Rejected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        asset::Asset,
+        market::{Market, Pair},
+        order::{AccountId, Order, OrderId, Price, Quantity, Side, Timestamp},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_witness_round_trip_and_verify() {
+        let pair = Pair {
+            numeraire: Asset::new("USD"),
+            base: Asset::new("BTC"),
+        };
+        let alice = AccountId::new("alice".to_string());
+        let bob = AccountId::new("bob".to_string());
+        let post = |id: u64, side: Side, account: &AccountId| Command::PostOrder {
+            pair,
+            order: Order::new(
+                OrderId::new(id),
+                Price::new(100),
+                Quantity::new(5),
+                side,
+                account.clone(),
+                Timestamp::new(id),
+            ),
+        };
+
+        let mut exchange = Exchange::new();
+        exchange.add_market(Market::new(pair));
+        exchange.add_balance(bob.clone(), pair.base, 5);
+        let witness = BatchWitness::record(
+            &mut exchange,
+            vec![
+                post(1, Side::Ask, &bob),
+                // Unfunded: rejected
+                post(2, Side::Bid, &alice),
+                Command::Deposit {
+                    account_id: alice.clone(),
+                    asset: pair.numeraire,
+                    amount: 500,
+                },
+                post(3, Side::Bid, &alice),
+            ],
+        );
+        assert_eq!(witness.outcomes[1], CommandOutcome::Rejected);
+        assert!(matches!(&witness.outcomes[3], CommandOutcome::Executed(t) if t.len() == 1));
+
+        let decoded = BatchWitness::from_bytes(&witness.to_bytes()).unwrap();
+        assert_eq!(decoded, witness);
+        decoded.verify().unwrap();
+
+        // A tampered post-state is caught
+        let mut tampered = decoded;
+        tampered.post_state.balances[0].2 += 1;
+        assert!(tampered.verify().is_err());
+    }
+}
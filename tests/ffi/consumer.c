@@ -0,0 +1,70 @@
+/* Example consumer of the C interface, built and run by tests/ffi_tests.rs. */
+#include <stdio.h>
+#include <string.h>
+
+#include "exchanges.h"
+
+#define CHECK(call)                                                                         \
+    do {                                                                                    \
+        if ((call) != EX_OK) {                                                              \
+            fprintf(stderr, "%s failed: %s\n", #call, ex_last_error(exchange));             \
+            return 1;                                                                       \
+        }                                                                                   \
+    } while (0)
+
+static void on_trade(void *user_data, const ExTrade *trade) {
+    int *trades = user_data;
+    ++*trades;
+    printf("trade %llu@%llu\n", (unsigned long long)trade->quantity,
+           (unsigned long long)trade->price);
+}
+
+static void on_event(void *user_data, const ExEvent *event) {
+    int *events = user_data;
+    ++*events;
+    printf("event %d %s\n", (int)event->kind, event->account_id);
+}
+
+int main(void) {
+    int trades = 0;
+    int events = 0;
+    uint64_t balance = 0;
+    ExExchange *exchange = ex_exchange_new();
+
+    CHECK(ex_set_trade_callback(exchange, on_trade, &trades));
+    CHECK(ex_set_event_callback(exchange, on_event, &events));
+    CHECK(ex_add_market(exchange, "USD", "BTC"));
+    CHECK(ex_deposit(exchange, "maker", "BTC", 5));
+    CHECK(ex_deposit(exchange, "taker", "USD", 1000));
+    CHECK(ex_post_limit_order(exchange, "USD", "BTC", 1, EX_SIDE_ASK, 100, 5, "maker", 1));
+    CHECK(ex_post_limit_order(exchange, "USD", "BTC", 2, EX_SIDE_BID, 100, 5, "taker", 2));
+    CHECK(ex_balance(exchange, "taker", "BTC", &balance));
+    if (trades != 1 || balance != 5) {
+        fprintf(stderr, "unexpected fill: %d trades, balance %llu\n", trades,
+                (unsigned long long)balance);
+        return 1;
+    }
+
+    /* Rejections carry a message */
+    if (ex_post_limit_order(exchange, "USD", "BTC", 3, EX_SIDE_BID, 100, 1, "nobody", 3) !=
+            EX_REJECTED ||
+        strlen(ex_last_error(exchange)) == 0) {
+        fprintf(stderr, "unfunded order was not rejected\n");
+        return 1;
+    }
+    if (ex_post_limit_order(exchange, "USD", "BTC", 3, 7, 100, 1, "taker", 3) !=
+        EX_INVALID_ARGUMENT) {
+        fprintf(stderr, "invalid side was not rejected\n");
+        return 1;
+    }
+
+    CHECK(ex_close_account(exchange, "maker", 1000));
+    if (events != 1) {
+        fprintf(stderr, "expected one event, got %d\n", events);
+        return 1;
+    }
+
+    ex_exchange_free(exchange);
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,36 @@
+use std::{env, path::PathBuf, process::Command};
+
+/// Builds the example C consumer against the crate's shared library and runs it.
+#[test]
+fn test_c_consumer() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // The shared library is built next to the test binary
+    let lib_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let binary = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ffi_consumer");
+
+    let status = Command::new(env::var("CC").unwrap_or("cc".to_string()))
+        .arg(manifest_dir.join("tests/ffi/consumer.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lexchanges")
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .expect("C compiler not found");
+    assert!(status.success(), "failed to build the C consumer");
+
+    let output = Command::new(&binary).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "C consumer failed: {}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("trade 5@100"));
+    assert!(stdout.contains("event 0 maker"));
+    assert!(stdout.ends_with("ok\n"));
+}
@@ -0,0 +1,45 @@
+use std::fs;
+
+use exchanges::scenario::Scenario;
+
+#[test]
+fn test_scenarios() {
+    let mut paths: Vec<_> = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yaml"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let yaml = fs::read_to_string(&path).unwrap();
+        let scenario = Scenario::from_yaml(&yaml).unwrap();
+        if let Err(e) = scenario.run() {
+            panic!("{}: {:?}", path.display(), e);
+        }
+    }
+}
+
+#[test]
+fn test_failed_expectation_reports_step() {
+    let scenario = Scenario::from_yaml(
+        r#"
+name: wrong balance
+balances:
+  alice: { USD: 10 }
+steps:
+  - time: 7
+    expect:
+      - balance: { account: alice, asset: USD, amount: 11 }
+"#,
+    )
+    .unwrap();
+
+    let Err(err) = scenario.run() else {
+        panic!("scenario should fail");
+    };
+    let err = format!("{:#}", err);
+    assert!(err.contains("step 7"), "{}", err);
+    assert!(err.contains("got 10"), "{}", err);
+}